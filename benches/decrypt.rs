@@ -0,0 +1,80 @@
+//! Compares decrypting 10,000 values with a cipher prepared for each value against one
+//! `CipherContext` reused for all of them, as `decrypt_env_to_map` does.
+//!
+//! Preparing an AES-GCM key computes its key schedule and GHASH key, which the context does
+//! once; ChaCha20-Poly1305 only copies its key, so both of its timings match.
+//!
+//! Run with `cargo bench --bench decrypt`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use envenc::{
+    decrypt_env_to_map, decrypt_with_aad, encrypt_with_aad, keys_generation, CipherContext,
+    CipherType, Envelope, Key, Nonce,
+};
+use std::collections::HashMap;
+
+const VALUES: usize = 10_000;
+
+fn decrypt_10k(c: &mut Criterion) {
+    for cipher_type in [CipherType::AES256GCM, CipherType::ChaCha20Poly1305] {
+        let (key, _) = keys_generation(cipher_type).unwrap();
+        let typed_key = Key::new(cipher_type, key.clone()).unwrap();
+        let values: Vec<(String, Nonce, Vec<u8>)> = (0..VALUES)
+            .map(|index| {
+                let name = format!("VAR_{}", index);
+                let (_, nonce) = keys_generation(cipher_type).unwrap();
+                let nonce = Nonce::new(cipher_type, nonce).unwrap();
+                let plaintext = format!("postgres://app:secret-{}@db/app", index);
+                let ciphertext = encrypt_with_aad(
+                    cipher_type,
+                    &typed_key,
+                    &nonce,
+                    plaintext.as_bytes(),
+                    name.as_bytes(),
+                )
+                .unwrap();
+                (name, nonce, ciphertext)
+            })
+            .collect();
+        let env_vars: HashMap<String, String> = values
+            .iter()
+            .map(|(name, nonce, ciphertext)| {
+                let nonce = nonce.as_bytes().to_vec();
+                let envelope = Envelope::new(cipher_type, nonce, ciphertext.clone());
+                (name.clone(), envelope.to_string())
+            })
+            .collect();
+
+        let mut group = c.benchmark_group(format!("decrypt 10k values with {}", cipher_type));
+        group.sample_size(20);
+        group.bench_function("cipher prepared per value", |b| {
+            b.iter(|| {
+                for (name, nonce, ciphertext) in &values {
+                    decrypt_with_aad(cipher_type, &typed_key, nonce, ciphertext, name.as_bytes())
+                        .unwrap();
+                }
+            })
+        });
+        group.bench_function("one CipherContext", |b| {
+            b.iter(|| {
+                let context = CipherContext::new(cipher_type, &key).unwrap();
+                for (name, nonce, ciphertext) in &values {
+                    context
+                        .open(nonce.as_bytes(), ciphertext, name.as_bytes())
+                        .unwrap();
+                }
+            })
+        });
+        group.bench_function("decrypt_env_to_map", |b| {
+            b.iter_batched(
+                || env_vars.clone(),
+                |env_vars| decrypt_env_to_map(env_vars, cipher_type, &key).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, decrypt_10k);
+criterion_main!(benches);
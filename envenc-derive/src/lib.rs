@@ -0,0 +1,156 @@
+//! The `#[derive(EnvEncConfig)]` macro of envenc, enabled with its `derive` feature.
+//!
+//! Use it through `envenc::EnvEncConfig`; `envenc::EnvConfig` documents the attributes it
+//! accepts.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, GenericArgument, LitStr,
+    PathArguments, Type,
+};
+
+/// Implements `envenc::EnvConfig` for a struct with named fields, reading each field from the
+/// decrypted variable named after it in upper case.
+///
+/// Fields accept `#[envenc(rename = "NAME")]`, `#[envenc(default = "value")]`, and
+/// `#[envenc(from_str)]`. A field of type `Option<T>` is optional.
+#[proc_macro_derive(EnvEncConfig, attributes(envenc))]
+pub fn derive_env_enc_config(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// The attributes of a field.
+#[derive(Default)]
+struct FieldAttrs {
+    /// The variable the field is read from, if not the name of the field in upper case.
+    rename: Option<LitStr>,
+    /// The value parsed when the variable is not set.
+    default: Option<LitStr>,
+    /// Whether the field is parsed with `FromStr` rather than `FromEnvValue`.
+    from_str: bool,
+}
+
+/// Returns the implementation of `EnvConfig` for `input`.
+fn expand(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "EnvEncConfig can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "EnvEncConfig can only be derived for structs",
+            ))
+        }
+    };
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut reads = Vec::new();
+    let mut inits = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named fields have names");
+        let attrs = field_attrs(field)?;
+        let var_name = match &attrs.rename {
+            Some(rename) => rename.value(),
+            None => ident
+                .to_string()
+                .trim_start_matches("r#")
+                .to_ascii_uppercase(),
+        };
+        let default = match &attrs.default {
+            Some(default) => quote!(::core::option::Option::Some(#default)),
+            None => quote!(::core::option::Option::None),
+        };
+        let (optional, ty) = match option_inner(&field.ty) {
+            Some(inner) => (true, inner),
+            None => (false, &field.ty),
+        };
+        let parse = match attrs.from_str {
+            true => quote!(::envenc::ConfigLoader::from_str::<#ty>),
+            false => quote!(<#ty as ::envenc::FromEnvValue>::from_env_value),
+        };
+        match optional {
+            true => {
+                reads.push(quote! {
+                    let #ident = loader.optional::<#ty>(#var_name, #default, #parse);
+                });
+                inits.push(quote!(#ident));
+            }
+            false => {
+                reads.push(quote! {
+                    let #ident = loader.required::<#ty>(#var_name, #default, #parse);
+                });
+                inits.push(quote! {
+                    #ident: #ident.expect("a required field has a value once the loader finished")
+                });
+            }
+        }
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::envenc::EnvConfig for #name #ty_generics #where_clause {
+            fn from_env(
+                env: &::envenc::DecryptedEnv,
+            ) -> ::core::result::Result<Self, ::envenc::EnvEncError> {
+                let mut loader = ::envenc::ConfigLoader::new(env);
+                #(#reads)*
+                loader.finish()?;
+                ::core::result::Result::Ok(#name { #(#inits),* })
+            }
+        }
+    })
+}
+
+/// Reads the `#[envenc(...)]` attributes of `field`.
+fn field_attrs(field: &syn::Field) -> Result<FieldAttrs, Error> {
+    let mut attrs = FieldAttrs::default();
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("envenc"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                attrs.rename = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("default") {
+                attrs.default = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("from_str") {
+                attrs.from_str = true;
+            } else {
+                return Err(meta.error("expected `rename`, `default`, or `from_str`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attrs)
+}
+
+/// Returns `T` if `ty` is `Option<T>`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if path.qself.is_some() || segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    match arguments.args.first()? {
+        GenericArgument::Type(inner) if arguments.args.len() == 1 => Some(inner),
+        _ => None,
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{env_file::write_atomic_private, secret, EnvEncError, Key, KeyMaterial};
+use age::{Decryptor, Encryptor, Identity, Recipient};
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// How `save_keys_protected` protects a key file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyFileProtection {
+    /// Encrypt the key file with age to every one of `recipients`, so that any of their
+    /// identities can load it. Each recipient is an age public key, as in `age1...`, or an SSH
+    /// public key, as in `ssh-ed25519 AAAA...`.
+    Age { recipients: Vec<String> },
+}
+
+/// The identity `load_keys_with_identity` decrypts an age-encrypted key file with.
+#[derive(Clone)]
+pub enum AgeIdentity {
+    /// An age identity file, as written by `age-keygen`, holding one or more
+    /// `AGE-SECRET-KEY-1...` lines.
+    File(PathBuf),
+    /// An SSH private key file, such as `~/.ssh/id_ed25519`, that is not protected by a
+    /// passphrase.
+    Ssh(PathBuf),
+    /// An `AGE-SECRET-KEY-1...` identity itself, such as one passed in an environment variable.
+    Key(String),
+}
+
+impl AgeIdentity {
+    /// Reads the identities to decrypt with.
+    fn identities(&self) -> Result<Vec<Box<dyn Identity>>, EnvEncError> {
+        let failed = |reason: String| EnvEncError::AgeFailed { reason };
+        match self {
+            AgeIdentity::File(path) => {
+                let file = age::IdentityFile::from_file(path.to_string_lossy().into_owned())?;
+                let identities = file
+                    .into_identities()
+                    .map_err(|err| failed(format!("invalid identity file: {}", err)))?;
+                Ok(identities
+                    .into_iter()
+                    .map(|identity| identity as Box<dyn Identity>)
+                    .collect())
+            }
+            AgeIdentity::Ssh(path) => {
+                let reader = BufReader::new(File::open(path)?);
+                let identity =
+                    age::ssh::Identity::from_buffer(reader, Some(path.display().to_string()))
+                        .map_err(|err| failed(format!("invalid SSH key: {}", err)))?;
+                match identity {
+                    age::ssh::Identity::Unencrypted(_) => Ok(vec![Box::new(identity)]),
+                    age::ssh::Identity::Encrypted(_) => Err(failed(
+                        "SSH keys protected by a passphrase are not supported".to_string(),
+                    )),
+                    age::ssh::Identity::Unsupported(_) => {
+                        Err(failed("unsupported SSH key type".to_string()))
+                    }
+                }
+            }
+            AgeIdentity::Key(identity) => {
+                let identity = age::x25519::Identity::from_str(identity.trim())
+                    .map_err(|err| failed(format!("invalid identity: {}", err)))?;
+                Ok(vec![Box::new(identity)])
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for AgeIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AgeIdentity::File(path) => f.debug_tuple("File").field(path).finish(),
+            AgeIdentity::Ssh(path) => f.debug_tuple("Ssh").field(path).finish(),
+            AgeIdentity::Key(_) => f.debug_tuple("Key").field(&"[REDACTED]").finish(),
+        }
+    }
+}
+
+/// Parses an age or SSH public key.
+fn parse_recipient(recipient: &str) -> Result<Box<dyn Recipient>, EnvEncError> {
+    let recipient = recipient.trim();
+    if let Ok(parsed) = age::x25519::Recipient::from_str(recipient) {
+        return Ok(Box::new(parsed));
+    }
+    age::ssh::Recipient::from_str(recipient)
+        .map(|parsed| Box::new(parsed) as Box<dyn Recipient>)
+        .map_err(|_| EnvEncError::InvalidAgeRecipient {
+            recipient: recipient.to_string(),
+        })
+}
+
+/// Encrypts `plaintext` to every one of `recipients` in the age format.
+fn encrypt_to(recipients: &[String], plaintext: &[u8]) -> Result<Vec<u8>, EnvEncError> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| parse_recipient(recipient))
+        .collect::<Result<Vec<_>, _>>()?;
+    let encryptor = Encryptor::with_recipients(recipients.iter().map(|recipient| &**recipient))
+        .map_err(|err| EnvEncError::AgeFailed {
+            reason: err.to_string(),
+        })?;
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?;
+    Ok(encrypted)
+}
+
+/// Decrypts the age-encrypted key file at `path` with `identity`.
+fn decrypt_with(path: &Path, identity: &AgeIdentity) -> Result<Key, EnvEncError> {
+    let identities = identity.identities()?;
+    let encrypted = fs::read(path)?;
+    let failed = |err: age::DecryptError| EnvEncError::AgeFailed {
+        reason: format!("cannot decrypt {}: {}", path.display(), err),
+    };
+    let decryptor = Decryptor::new_buffered(&encrypted[..]).map_err(failed)?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|identity| &**identity))
+        .map_err(failed)?;
+    let mut decrypted = Vec::new();
+    if let Err(err) = reader.read_to_end(&mut decrypted) {
+        secret::wipe(decrypted);
+        return Err(err.into());
+    }
+    Ok(Key::from_bytes(decrypted))
+}
+
+/// Saves `keys` to a key file at `path` like `save_keys`, encrypted as `protection` says.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::InvalidAgeRecipient` if a recipient is neither an age nor an SSH
+/// public key, `EnvEncError::AgeFailed` if there are no recipients, and `EnvEncError::Io` if
+/// the file cannot be written.
+///
+/// # Example
+///
+/// ```
+/// use age::secrecy::ExposeSecret;
+/// use envenc::{
+///     load_keys, load_keys_with_identity, rewrap_key_file, save_keys_protected, AgeIdentity,
+///     CipherType, EnvEncError, KeyFileError, KeyFileProtection, KeyMaterial,
+/// };
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("envenc.key.age");
+/// let alice = age::x25519::Identity::generate();
+/// let bob = age::x25519::Identity::generate();
+/// let alice_key = AgeIdentity::Key(alice.to_string().expose_secret().to_string());
+///
+/// let keys = KeyMaterial::generate(CipherType::AES256GCM);
+/// let protection = KeyFileProtection::Age { recipients: vec![alice.to_public().to_string()] };
+/// save_keys_protected(&path, &keys, &protection)?;
+/// assert_eq!(load_keys_with_identity(&path, &alice_key)?, keys);
+/// assert!(matches!(
+///     load_keys(&path),
+///     Err(EnvEncError::InvalidKeyFile { reason: KeyFileError::AgeEncrypted, .. })
+/// ));
+///
+/// // An identity file, as written by `age-keygen`, works too.
+/// let identity_file = dir.path().join("bob.txt");
+/// std::fs::write(&identity_file, format!("# bob\n{}\n", bob.to_string().expose_secret()))?;
+/// let bob_file = AgeIdentity::File(identity_file);
+/// assert!(matches!(load_keys_with_identity(&path, &bob_file), Err(EnvEncError::AgeFailed { .. })));
+///
+/// // Adding bob and then removing alice keeps the master key.
+/// let both = KeyFileProtection::Age {
+///     recipients: vec![alice.to_public().to_string(), bob.to_public().to_string()],
+/// };
+/// rewrap_key_file(&path, &alice_key, &both)?;
+/// assert_eq!(load_keys_with_identity(&path, &bob_file)?, keys);
+/// let bob_only = KeyFileProtection::Age { recipients: vec![bob.to_public().to_string()] };
+/// rewrap_key_file(&path, &bob_file, &bob_only)?;
+/// assert_eq!(load_keys_with_identity(&path, &bob_file)?, keys);
+/// assert!(matches!(load_keys_with_identity(&path, &alice_key), Err(EnvEncError::AgeFailed { .. })));
+///
+/// let invalid = KeyFileProtection::Age { recipients: vec!["age1notakey".to_string()] };
+/// assert!(matches!(
+///     save_keys_protected(&path, &keys, &invalid),
+///     Err(EnvEncError::InvalidAgeRecipient { recipient }) if recipient == "age1notakey"
+/// ));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn save_keys_protected(
+    path: impl AsRef<Path>,
+    keys: &KeyMaterial,
+    protection: &KeyFileProtection,
+) -> Result<(), EnvEncError> {
+    let encoded = Key::from_bytes(keys.encode());
+    let encrypted = match protection {
+        KeyFileProtection::Age { recipients } => encrypt_to(recipients, encoded.as_bytes())?,
+    };
+    write_atomic_private(path.as_ref(), &encrypted)
+}
+
+/// Loads key material from the age-encrypted key file at `path`, as written by
+/// `save_keys_protected`, decrypting it with `identity`. See `save_keys_protected` for an
+/// example.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::AgeFailed` if `identity` cannot be read or cannot decrypt the file,
+/// the same errors as `load_keys` if the decrypted key file is invalid, and `EnvEncError::Io`
+/// if a file cannot be read.
+pub fn load_keys_with_identity(
+    path: impl AsRef<Path>,
+    identity: &AgeIdentity,
+) -> Result<KeyMaterial, EnvEncError> {
+    let path = path.as_ref();
+    let decrypted = decrypt_with(path, identity)?;
+    KeyMaterial::from_key_file(path, decrypted.as_bytes())
+}
+
+/// Encrypts the age-encrypted key file at `path` anew as `protection` says, decrypting it with
+/// `identity`, to add or remove recipients without changing the master key.
+///
+/// # Errors
+///
+/// Returns the same errors as `load_keys_with_identity` and `save_keys_protected`.
+pub fn rewrap_key_file(
+    path: impl AsRef<Path>,
+    identity: &AgeIdentity,
+    protection: &KeyFileProtection,
+) -> Result<(), EnvEncError> {
+    let path = path.as_ref();
+    let keys = load_keys_with_identity(path, identity)?;
+    save_keys_protected(path, &keys, protection)
+}
@@ -0,0 +1,160 @@
+use crate::{EnvEncError, KeyProvider, KeyProviderError};
+use aws_sdk_kms::{
+    error::{DisplayErrorContext, ProvideErrorMetadata, SdkError},
+    primitives::Blob,
+    Client,
+};
+use tokio::runtime::{Builder, Runtime};
+
+/// The prefix of the identifier an `AwsKmsProvider` records in env files, before the key ARN.
+const ID_PREFIX: &str = "aws-kms:";
+
+/// A `KeyProvider` that wraps data keys with a key in AWS KMS, identified by its ARN.
+///
+/// The provider calls KMS through its own single-threaded Tokio runtime, so its methods block
+/// and must not be called from within an async runtime. Failures to reach KMS are reported as
+/// `KeyProviderError::Network`, and KMS errors by their kind.
+///
+/// # Example
+///
+/// ```no_run
+/// use envenc::{decrypt_env_from_provider, AwsKmsProvider, CipherType, EnvEnc};
+///
+/// let key_arn = "arn:aws:kms:eu-west-1:111122223333:key/1234abcd-12ab-34cd-56ef-1234567890ab";
+/// let mut session = EnvEnc::builder()
+///     .cipher(CipherType::AES256GCM)
+///     .key_provider(AwsKmsProvider::new(key_arn)?)
+///     .env_file("secrets.env")
+///     .build()?;
+/// session.set("API_KEY", "secret")?;
+///
+/// decrypt_env_from_provider("secrets.env", CipherType::AES256GCM, &AwsKmsProvider::new(key_arn)?)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct AwsKmsProvider {
+    id: String,
+    key_arn: String,
+    client: Client,
+    runtime: Runtime,
+}
+
+impl AwsKmsProvider {
+    /// Creates a provider for the KMS key `key_arn`, with a client configured from the
+    /// environment: the usual AWS credential chain and region settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::Io` if the runtime for calling KMS cannot be started.
+    pub fn new(key_arn: impl Into<String>) -> Result<Self, EnvEncError> {
+        let runtime = new_runtime()?;
+        let config = runtime.block_on(aws_config::load_from_env());
+        Ok(Self::with_runtime(
+            key_arn.into(),
+            Client::new(&config),
+            runtime,
+        ))
+    }
+
+    /// Creates a provider for the KMS key `key_arn` that calls KMS through `client`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::Io` if the runtime for calling KMS cannot be started.
+    pub fn with_client(key_arn: impl Into<String>, client: Client) -> Result<Self, EnvEncError> {
+        Ok(Self::with_runtime(key_arn.into(), client, new_runtime()?))
+    }
+
+    /// Returns the ARN of the KMS key.
+    pub fn key_arn(&self) -> &str {
+        &self.key_arn
+    }
+
+    fn with_runtime(key_arn: String, client: Client, runtime: Runtime) -> Self {
+        AwsKmsProvider {
+            id: format!("{}{}", ID_PREFIX, key_arn),
+            key_arn,
+            client,
+            runtime,
+        }
+    }
+}
+
+impl KeyProvider for AwsKmsProvider {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn wrap(&self, data_key: &[u8]) -> Result<Vec<u8>, KeyProviderError> {
+        let request = self
+            .client
+            .encrypt()
+            .key_id(&self.key_arn)
+            .plaintext(Blob::new(data_key));
+        let output = self
+            .runtime
+            .block_on(request.send())
+            .map_err(provider_error)?;
+        output
+            .ciphertext_blob
+            .map(Blob::into_inner)
+            .ok_or_else(|| KeyProviderError::Other("KMS returned no ciphertext".to_string()))
+    }
+
+    fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>, KeyProviderError> {
+        let request = self
+            .client
+            .decrypt()
+            .key_id(&self.key_arn)
+            .ciphertext_blob(Blob::new(wrapped));
+        let output = self
+            .runtime
+            .block_on(request.send())
+            .map_err(provider_error)?;
+        output
+            .plaintext
+            .map(Blob::into_inner)
+            .ok_or_else(|| KeyProviderError::Other("KMS returned no plaintext".to_string()))
+    }
+}
+
+impl std::fmt::Debug for AwsKmsProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("AwsKmsProvider")
+            .field("key_arn", &self.key_arn)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Starts the runtime an `AwsKmsProvider` calls KMS through.
+fn new_runtime() -> Result<Runtime, EnvEncError> {
+    Ok(Builder::new_current_thread().enable_all().build()?)
+}
+
+/// Converts an error of a KMS call into a `KeyProviderError` of the matching kind.
+fn provider_error<E, R>(err: SdkError<E, R>) -> KeyProviderError
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    let message = DisplayErrorContext(&err).to_string();
+    if matches!(
+        err,
+        SdkError::DispatchFailure(_) | SdkError::TimeoutError(_)
+    ) {
+        return KeyProviderError::Network(message);
+    }
+    match err.code() {
+        Some("AccessDeniedException") => KeyProviderError::AccessDenied(message),
+        Some(
+            "NotFoundException"
+            | "DisabledException"
+            | "KeyUnavailableException"
+            | "KMSInvalidStateException",
+        ) => KeyProviderError::KeyUnavailable(message),
+        Some("InvalidCiphertextException" | "IncorrectKeyException") => {
+            KeyProviderError::InvalidCiphertext
+        }
+        Some("DependencyTimeoutException") => KeyProviderError::Network(message),
+        _ => KeyProviderError::Other(message),
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{
+    env_file::{lock_env_file, write_atomic_bytes, DEFAULT_LOCK_TIMEOUT},
+    EnvEncError,
+};
+use std::{
+    borrow::Cow,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The number of timestamped backups `BackupOptions::default` keeps.
+const DEFAULT_KEEP: usize = 10;
+
+/// How `backup_env_file` names, places, and keeps the copies of an env file it makes before
+/// the file is rewritten.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{backup_env_file, list_backups, restore_backup_with, BackupOptions};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join(".env");
+/// let options = BackupOptions { keep: Some(2), dir: Some(dir.path().join("backups")), ..BackupOptions::default() };
+///
+/// for version in 1..=3 {
+///     std::fs::write(&path, format!("VERSION={}\n", version))?;
+///     let backup = backup_env_file(&path, &options)?.unwrap();
+///     // Named after the time in UTC, such as `.env.20240101T120000.bak`.
+///     let name = backup.file_name().unwrap().to_string_lossy().into_owned();
+///     assert!(name.starts_with(".env.2") && name.ends_with(".bak"), "{}", name);
+///     assert_eq!(backup.parent(), Some(dir.path().join("backups").as_path()));
+/// }
+/// // Only the last two backups are kept.
+/// let backups = list_backups(&path, &options)?;
+/// assert_eq!(backups.len(), 2);
+/// assert_eq!(std::fs::read_to_string(&backups[1])?, "VERSION=3\n");
+///
+/// std::fs::write(&path, "VERSION=broken\n")?;
+/// assert_eq!(restore_backup_with(&path, &options)?, backups[1]);
+/// assert_eq!(std::fs::read_to_string(&path)?, "VERSION=3\n");
+///
+/// // Without timestamps, each backup replaces `.env.bak`.
+/// let single = BackupOptions { timestamped: false, ..BackupOptions::default() };
+/// assert_eq!(backup_env_file(&path, &single)?, Some(dir.path().join(".env.bak")));
+/// assert_eq!(backup_env_file(dir.path().join("missing.env"), &single)?, None);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupOptions {
+    /// Whether each backup is named after the time it was made, in UTC, as
+    /// `.env.20240101T120000.bak`, rather than replacing a single `.env.bak`.
+    pub timestamped: bool,
+    /// The number of timestamped backups to keep. Older ones are removed once a new backup is
+    /// made, but the new backup always is kept. `None` keeps every backup.
+    pub keep: Option<usize>,
+    /// The directory backups are written to, created if it does not exist. Defaults to the
+    /// directory of the env file.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for BackupOptions {
+    /// Returns options for timestamped backups next to the env file, of which the last 10 are
+    /// kept.
+    fn default() -> Self {
+        BackupOptions {
+            timestamped: true,
+            keep: Some(DEFAULT_KEEP),
+            dir: None,
+        }
+    }
+}
+
+/// Copies the env file at `path` to a backup as `options` describe, with the same permissions,
+/// and returns the path of the backup, or `None` if there is no file to back up.
+///
+/// A timestamped backup made earlier in the same second is never replaced: a counter is added
+/// to the name instead, as in `.env.20240101T120000.1.bak`. See `BackupOptions` for an example.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::Io` if the env file cannot be read, or the backup cannot be written.
+pub fn backup_env_file(
+    path: impl AsRef<Path>,
+    options: &BackupOptions,
+) -> Result<Option<PathBuf>, EnvEncError> {
+    let path = path.as_ref();
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let permissions = fs::metadata(path)?.permissions();
+    let dir = backup_dir(path, options);
+    fs::create_dir_all(&dir)?;
+    let name = file_name(path);
+    let backup = match options.timestamped {
+        true => create_timestamped(&dir, &name, &contents)?,
+        false => {
+            let backup = dir.join(format!("{}.bak", name));
+            write_atomic_bytes(&backup, &contents)?;
+            backup
+        }
+    };
+    fs::set_permissions(&backup, permissions)?;
+    log::info!("backed up {} to {}", path.display(), backup.display());
+
+    if let (true, Some(keep)) = (options.timestamped, options.keep) {
+        let backups = list_backups(path, options)?;
+        for old in &backups[..backups.len().saturating_sub(keep.max(1))] {
+            fs::remove_file(old)?;
+            log::debug!("removed the old backup {}", old.display());
+        }
+    }
+    Ok(Some(backup))
+}
+
+/// Returns the timestamped backups of the env file at `path` in the directory `options`
+/// names, oldest first.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::Io` if the directory exists but cannot be read.
+pub fn list_backups(
+    path: impl AsRef<Path>,
+    options: &BackupOptions,
+) -> Result<Vec<PathBuf>, EnvEncError> {
+    let path = path.as_ref();
+    let dir = backup_dir(path, options);
+    let name = file_name(path);
+    let read_from = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => &dir,
+    };
+    let entries = match fs::read_dir(read_from) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let file_name = entry?.file_name();
+        if let Some(order) = backup_order(&name, &file_name.to_string_lossy()) {
+            backups.push((order, dir.join(file_name)));
+        }
+    }
+    backups.sort();
+    Ok(backups.into_iter().map(|(_, backup)| backup).collect())
+}
+
+/// Replaces the env file at `path` with its newest backup next to it, and returns the path of
+/// the backup, which is kept.
+///
+/// See `restore_backup_with` for backups in another directory.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::NoBackup` if the env file has no backup, `EnvEncError::LockTimeout`
+/// if another writer holds the lock on the file, and `EnvEncError::Io` if the backup cannot be
+/// read or the env file cannot be written.
+pub fn restore_backup(path: impl AsRef<Path>) -> Result<PathBuf, EnvEncError> {
+    restore_backup_with(path, &BackupOptions::default())
+}
+
+/// Replaces the env file at `path` with its newest backup in the directory `options` names,
+/// with the permissions of the backup, and returns the path of the backup, which is kept. The
+/// newest timestamped backup is restored if there is one, and `.env.bak` otherwise.
+///
+/// # Errors
+///
+/// Returns the same errors as `restore_backup`.
+pub fn restore_backup_with(
+    path: impl AsRef<Path>,
+    options: &BackupOptions,
+) -> Result<PathBuf, EnvEncError> {
+    let path = path.as_ref();
+    let single = backup_dir(path, options).join(format!("{}.bak", file_name(path)));
+    let backup = match list_backups(path, options)?.pop() {
+        Some(backup) => backup,
+        None if single.exists() => single,
+        None => {
+            return Err(EnvEncError::NoBackup {
+                path: path.to_path_buf(),
+            })
+        }
+    };
+    let contents = fs::read(&backup)?;
+    let _lock = lock_env_file(path, DEFAULT_LOCK_TIMEOUT)?;
+    write_atomic_bytes(path, &contents)?;
+    fs::set_permissions(path, fs::metadata(&backup)?.permissions())?;
+    log::info!("restored {} from {}", path.display(), backup.display());
+    Ok(backup)
+}
+
+/// Returns the directory the backups of the env file at `path` are written to.
+fn backup_dir(path: &Path, options: &BackupOptions) -> PathBuf {
+    match &options.dir {
+        Some(dir) => dir.clone(),
+        None => path.parent().unwrap_or(Path::new("")).to_path_buf(),
+    }
+}
+
+/// Returns the file name of the env file at `path`.
+fn file_name(path: &Path) -> Cow<'_, str> {
+    path.file_name().unwrap_or_default().to_string_lossy()
+}
+
+/// Writes `contents` to a new timestamped backup of the env file `name` in `dir`, only
+/// accessible by its owner until its permissions are set, and returns its path.
+fn create_timestamped(dir: &Path, name: &str, contents: &[u8]) -> Result<PathBuf, EnvEncError> {
+    let stamp = timestamp(SystemTime::now());
+    for attempt in 0u32.. {
+        let backup = match attempt {
+            0 => dir.join(format!("{}.{}.bak", name, stamp)),
+            _ => dir.join(format!("{}.{}.{}.bak", name, stamp, attempt)),
+        };
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = match options.open(&backup) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        };
+        file.write_all(contents)?;
+        file.sync_all()?;
+        return Ok(backup);
+    }
+    unreachable!("a free backup name is found before the counter overflows")
+}
+
+/// Returns the time and counter of `file_name` if it is a timestamped backup of the env file
+/// `name`, for sorting backups from oldest to newest.
+fn backup_order(name: &str, file_name: &str) -> Option<(String, u32)> {
+    let rest = file_name.strip_prefix(name)?.strip_prefix('.')?;
+    let rest = rest.strip_suffix(".bak")?;
+    let (stamp, counter) = match rest.split_once('.') {
+        Some((stamp, counter)) => (stamp, counter.parse().ok()?),
+        None => (rest, 0),
+    };
+    let is_stamp = stamp.len() == 15
+        && stamp.char_indices().all(|(index, c)| match index {
+            8 => c == 'T',
+            _ => c.is_ascii_digit(),
+        });
+    is_stamp.then(|| (stamp.to_string(), counter))
+}
+
+/// Formats `time` in UTC as `20240101T120000`.
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (year, month, day) = civil_from_days(seconds / 86_400);
+    let seconds = seconds % 86_400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+/// Returns the year, month, and day of the Gregorian calendar that is `days` days after 1970-01-01,
+/// with the algorithm of Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = match shifted_month < 10 {
+        true => shifted_month + 3,
+        false => shifted_month - 9,
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
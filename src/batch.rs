@@ -0,0 +1,265 @@
+use crate::{
+    edit_env_file,
+    env_file::{
+        content_hash, read_contents, rewrite_env_file, ContentHash, LineEnding, WriteOptions,
+        DEFAULT_LOCK_TIMEOUT,
+    },
+    sealed::read_sealed,
+    secret::check_cipher,
+    CipherType, EnvEnc, EnvEncError, Key, MemoryStore, Overwrite, SetOutcome, DEFAULT_ENV_FILE,
+};
+use std::path::{Path, PathBuf};
+
+/// An env file opened for a batch of changes, which are made in memory and written with one
+/// atomic rewrite by `save`.
+///
+/// `set_enc_env_in` and the other one-off writers read, parse, and rewrite the whole file on
+/// every call, so seeding a file with a few hundred variables rewrites it as many times. An
+/// `EnvFile` parses the file once in `open`, and `set`, `upsert`, and `remove` only change
+/// the copy in memory, keeping comments and the order of the entries as a session does.
+/// Dropping it without calling `save` discards the changes and leaves the file alone.
+///
+/// `save` fails with `EnvEncError::ConcurrentModification` if another writer changed the file
+/// since it was opened or last saved, rather than overwriting the change; open it again to
+/// pick up the change. Sealed env files are changed through an `EnvEnc` session instead.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, read_env_enc_from, CipherType, EnvFile};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// std::fs::write(&path, "# Seeded by the fixtures script\n")?;
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+///
+/// let mut file = EnvFile::open(&path, cipher_type, &key)?;
+/// for index in 0..200 {
+///     file.set(&format!("VAR_{}", index), &format!("value {}", index))?;
+/// }
+/// assert!(file.remove("VAR_0")?);
+/// assert_eq!(file.get("VAR_199")?.as_deref(), Some("value 199"));
+///
+/// // Nothing is written until `save`, which rewrites the file once.
+/// assert_eq!(std::fs::read_to_string(&path)?, "# Seeded by the fixtures script\n");
+/// assert!(file.is_modified());
+/// file.save()?;
+/// assert!(!file.is_modified());
+/// assert_eq!(read_env_enc_from(&path)?.len(), 199);
+/// assert!(std::fs::read_to_string(&path)?.contains("# Seeded by the fixtures script\n"));
+///
+/// // Changes dropped without `save` are discarded.
+/// let before = std::fs::read(&path)?;
+/// let mut file = EnvFile::open(&path, cipher_type, &key)?;
+/// file.set("DISCARDED", "value")?;
+/// drop(file);
+/// assert_eq!(std::fs::read(&path)?, before);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct EnvFile {
+    path: PathBuf,
+    /// A session over a copy of the file in `store`, which takes the changes.
+    session: EnvEnc,
+    store: MemoryStore,
+    /// The hash of the contents of the file as it was opened or last saved.
+    content_hash: ContentHash,
+    modified: bool,
+}
+
+impl EnvFile {
+    /// Reads and parses the env file at `path`, which need not exist yet, with the cipher and
+    /// key its values are encrypted with.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::SealedFile` if the file is a sealed env file, and the errors of
+    /// `EnvEncBuilder::build`.
+    pub fn open(
+        path: impl AsRef<Path>,
+        cipher_type: CipherType,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Self, EnvEncError> {
+        let path = path.as_ref();
+        if read_sealed(path)?.is_some() {
+            return Err(EnvEncError::SealedFile {
+                path: path.to_path_buf(),
+            });
+        }
+        let contents = read_contents(path)?.unwrap_or_default();
+        let content_hash = content_hash(&contents);
+        let store = MemoryStore::with_contents(contents);
+        let session = EnvEnc::builder()
+            .cipher(cipher_type)
+            .key(key.as_ref())
+            .env_file(path)
+            .store(Box::new(store.clone()))
+            .build()?;
+        Ok(EnvFile {
+            path: path.to_path_buf(),
+            session,
+            store,
+            content_hash,
+            modified: false,
+        })
+    }
+
+    /// Returns the path of the env file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Decrypts the value of `var_name`, including changes that are not saved yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `EnvEnc::get`.
+    pub fn get(&self, var_name: &str) -> Result<Option<String>, EnvEncError> {
+        self.session.get(var_name)
+    }
+
+    /// Encrypts `value` and stores it under `var_name` in memory, unless the variable is
+    /// already set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if encryption fails.
+    pub fn set(&mut self, var_name: &str, value: &str) -> Result<SetOutcome, EnvEncError> {
+        self.upsert(var_name, value, Overwrite::No)
+    }
+
+    /// Encrypts `value` and stores it under `var_name` in memory, replacing an existing value
+    /// according to `overwrite`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if encryption fails.
+    pub fn upsert(
+        &mut self,
+        var_name: &str,
+        value: &str,
+        overwrite: Overwrite,
+    ) -> Result<SetOutcome, EnvEncError> {
+        let outcome = self.session.upsert(var_name, value, overwrite)?;
+        self.modified |= matches!(outcome, SetOutcome::Created | SetOutcome::Updated);
+        Ok(outcome)
+    }
+
+    /// Removes `var_name` in memory, returning `true` if it was set.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `EnvEnc::remove`.
+    pub fn remove(&mut self, var_name: &str) -> Result<bool, EnvEncError> {
+        let removed = self.session.remove(var_name)?;
+        self.modified |= removed;
+        Ok(removed)
+    }
+
+    /// Returns whether there are changes that `save` has not written yet.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Writes the changes to the env file with one atomic rewrite, under the lock on the file.
+    /// Does nothing if there are none.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::ConcurrentModification` if the file was changed since it was
+    /// opened or last saved, `EnvEncError::LockTimeout` if another writer holds the lock for
+    /// too long, and `EnvEncError::Io` if it cannot be written. The changes are kept for
+    /// another attempt.
+    pub fn save(&mut self) -> Result<(), EnvEncError> {
+        if !self.modified {
+            return Ok(());
+        }
+        let options = WriteOptions {
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            line_ending: LineEnding::default(),
+            expected: Some(self.content_hash),
+        };
+        let contents = self.store.contents().unwrap_or_default();
+        self.content_hash = rewrite_env_file(&self.path, options, |_| Ok(Some(contents)))?;
+        self.modified = false;
+        log::info!("saved {}", self.path.display());
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for EnvFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("EnvFile")
+            .field("path", &self.path)
+            .field("modified", &self.modified)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Encrypts and stores several environment variables in the `.env` file with one rewrite.
+///
+/// See `set_enc_env_many_in`.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::CipherMismatch` if the key was created for another cipher than
+/// `cipher_type`, and the errors of `set_enc_env_many_in`.
+pub fn set_enc_env_many(
+    vars: &[(&str, &str)],
+    cipher_type: CipherType,
+    key: &Key,
+) -> Result<Vec<SetOutcome>, EnvEncError> {
+    check_cipher(cipher_type, key, None)?;
+    set_enc_env_many_in(DEFAULT_ENV_FILE, vars, cipher_type, key)
+}
+
+/// Encrypts and stores several environment variables in the env file at `path`, as
+/// `set_enc_env_in` does for each, but reading and rewriting the file once.
+///
+/// Variables that are already set are left unchanged. The file stays locked from reading it
+/// to writing it, so the batch is applied as a whole and concurrent writers never lose each
+/// other's values.
+///
+/// # Returns
+///
+/// The `SetOutcome` of each variable, in the order of `vars`.
+///
+/// # Errors
+///
+/// Returns an error if encryption fails or the env file cannot be read or written, and
+/// `EnvEncError::LockTimeout` if another writer holds the lock for too long, in which case
+/// none of the variables are stored.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, read_env_enc_from, set_enc_env_in, set_enc_env_many_in, CipherType, SetOutcome};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "API_KEY", "sk_live_123", cipher_type, &key)?;
+///
+/// let outcomes = set_enc_env_many_in(
+///     &path,
+///     &[("DATABASE_URL", "postgres://localhost/app"), ("API_KEY", "sk_live_456")],
+///     cipher_type,
+///     &key,
+/// )?;
+/// assert_eq!(outcomes, [SetOutcome::Created, SetOutcome::AlreadyExists]);
+/// assert_eq!(read_env_enc_from(&path)?.len(), 2);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn set_enc_env_many_in(
+    path: impl AsRef<Path>,
+    vars: &[(&str, &str)],
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+) -> Result<Vec<SetOutcome>, EnvEncError> {
+    edit_env_file(path.as_ref(), cipher_type, key.as_ref(), |session| {
+        vars.iter()
+            .map(|(var_name, value)| session.upsert(var_name, value, Overwrite::No))
+            .collect()
+    })
+}
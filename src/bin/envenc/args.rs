@@ -0,0 +1,156 @@
+use clap::Args;
+use envenc::{read_kdf_metadata, CipherType, EnvEnc, EnvEncError, KdfKind, KeySource};
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+};
+
+/// The environment variable a passphrase is read from instead of prompting for it, for
+/// non-interactive use.
+pub(crate) const PASSPHRASE_VAR: &str = "ENVENC_PASSPHRASE";
+
+/// The environment variable a new passphrase is read from, when changing it.
+pub(crate) const NEW_PASSPHRASE_VAR: &str = "ENVENC_NEW_PASSPHRASE";
+
+/// The env file a command works on.
+#[derive(Args)]
+pub(crate) struct FileArgs {
+    /// The env file.
+    #[arg(long, short, default_value = ".env")]
+    pub(crate) file: PathBuf,
+}
+
+/// Where the key of the env file comes from. Without any of these flags, the key is read from
+/// the file named by `ENVENC_KEY_FILE`, or else from `ENVENC_KEY`.
+#[derive(Args, Clone)]
+pub(crate) struct KeyArgs {
+    /// The cipher values are encrypted with.
+    #[arg(long, default_value = "aes256gcm")]
+    pub(crate) cipher: CipherType,
+    /// Read the key from this file, as hex, base64, or raw bytes.
+    #[arg(long, value_name = "PATH")]
+    key_file: Option<PathBuf>,
+    /// Read the key from this environment variable, as hex or base64.
+    #[arg(long, value_name = "VAR", conflicts_with = "key_file")]
+    key_env: Option<String>,
+    /// Read the key from the OS keyring, under this service name.
+    #[cfg(feature = "keyring")]
+    #[arg(long, value_name = "SERVICE", conflicts_with_all = ["key_file", "key_env"])]
+    keyring: Option<String>,
+    /// Derive the key from a passphrase, prompted for or read from `ENVENC_PASSPHRASE`.
+    #[arg(long, conflicts_with_all = ["key_file", "key_env"])]
+    passphrase: bool,
+}
+
+impl KeyArgs {
+    /// Opens a session on the env file at `env_file` with the key these flags select.
+    ///
+    /// With `--passphrase`, the key is derived with the salt recorded in the header of the env
+    /// file by `envenc init --passphrase`, or else the passphrase unwraps the data key of the
+    /// file.
+    pub(crate) fn session(&self, env_file: &Path) -> Result<EnvEnc, EnvEncError> {
+        let builder = EnvEnc::builder().cipher(self.cipher).env_file(env_file);
+        let builder = match self.passphrase && read_kdf_metadata(env_file)?.is_none() {
+            true => builder.passphrase(self.passphrase()?, KdfKind::default()),
+            false => builder.key(self.load(env_file)?),
+        };
+        builder.build()
+    }
+
+    /// Loads the key of the env file at `env_file`. With `--passphrase`, the key is derived
+    /// with the salt recorded in its header.
+    pub(crate) fn load(&self, env_file: &Path) -> Result<Vec<u8>, EnvEncError> {
+        if !self.passphrase {
+            return self.source().load(self.cipher);
+        }
+        let metadata =
+            read_kdf_metadata(env_file)?.ok_or_else(|| EnvEncError::MissingKdfHeader {
+                path: env_file.to_path_buf(),
+            })?;
+        metadata.derive_key(&self.passphrase()?, self.cipher)
+    }
+
+    /// Reads the passphrase given with `--passphrase`.
+    pub(crate) fn passphrase(&self) -> io::Result<String> {
+        read_passphrase(PASSPHRASE_VAR, "Passphrase: ", false)
+    }
+
+    /// Returns the source the key is loaded from, unless it is derived from a passphrase.
+    fn source(&self) -> KeySource {
+        #[cfg(feature = "keyring")]
+        if let Some(service) = &self.keyring {
+            return keyring_source(service);
+        }
+        match (&self.key_file, &self.key_env) {
+            (Some(path), _) => KeySource::file(path),
+            (None, Some(var)) => KeySource::EnvVar(var.clone()),
+            (None, None) => KeySource::from_env(),
+        }
+    }
+}
+
+/// Where the key of the second env file of a command on two files comes from. Without any of
+/// these flags, it is the key of the first file.
+#[derive(Args)]
+pub(crate) struct SecondKeyArgs {
+    /// The cipher values of the second file are encrypted with, if not the one of the first.
+    #[arg(long, value_name = "CIPHER")]
+    cipher2: Option<CipherType>,
+    /// Read the key of the second file from this file, as hex, base64, or raw bytes.
+    #[arg(long, value_name = "PATH")]
+    key_file2: Option<PathBuf>,
+    /// Read the key of the second file from this environment variable, as hex or base64.
+    #[arg(long, value_name = "VAR", conflicts_with = "key_file2")]
+    key_env2: Option<String>,
+    /// Read the key of the second file from the OS keyring, under this service name.
+    #[cfg(feature = "keyring")]
+    #[arg(long, value_name = "SERVICE", conflicts_with_all = ["key_file2", "key_env2"])]
+    keyring2: Option<String>,
+}
+
+impl SecondKeyArgs {
+    /// Returns the key flags of the second file, falling back to the ones of the first file,
+    /// `first`.
+    pub(crate) fn or(&self, first: &KeyArgs) -> KeyArgs {
+        let own_key = self.key_file2.is_some() || self.key_env2.is_some();
+        #[cfg(feature = "keyring")]
+        let own_key = own_key || self.keyring2.is_some();
+        let second = match own_key {
+            true => KeyArgs {
+                cipher: first.cipher,
+                key_file: self.key_file2.clone(),
+                key_env: self.key_env2.clone(),
+                #[cfg(feature = "keyring")]
+                keyring: self.keyring2.clone(),
+                passphrase: false,
+            },
+            false => first.clone(),
+        };
+        KeyArgs {
+            cipher: self.cipher2.unwrap_or(first.cipher),
+            ..second
+        }
+    }
+}
+
+/// Returns the OS keyring entry of the key for `service`.
+#[cfg(feature = "keyring")]
+pub(crate) fn keyring_source(service: &str) -> KeySource {
+    KeySource::OsKeyring {
+        service: service.to_string(),
+        user: "envenc".to_string(),
+    }
+}
+
+/// Reads a passphrase from the environment variable `var`, or else prompts for it on the
+/// terminal without echoing it, twice if `confirm` is set.
+pub(crate) fn read_passphrase(var: &str, prompt: &str, confirm: bool) -> io::Result<String> {
+    if let Ok(passphrase) = env::var(var) {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password(prompt)?;
+    if confirm && rpassword::prompt_password("Repeat the passphrase: ")? != passphrase {
+        return Err(io::Error::other("the passphrases do not match"));
+    }
+    Ok(passphrase)
+}
@@ -0,0 +1,84 @@
+use crate::{args::FileArgs, CliResult};
+use clap::Args;
+use envenc::{backup_env_file, restore_backup, BackupOptions, EnvEncError};
+use rand::Rng;
+use std::{env, fs, io, path::Path, process::ExitCode};
+
+/// How `rewrite` applies a change to an env file.
+#[derive(Clone, Copy)]
+pub(crate) enum Mode {
+    /// Apply the change to a copy of the env file, which is then removed.
+    DryRun,
+    /// Apply the change after copying the env file to `{name}.<timestamp>.bak`, keeping the
+    /// last 10 backups.
+    Backup,
+    /// Apply the change without a backup.
+    NoBackup,
+}
+
+impl Mode {
+    /// Returns the mode selected by the `--dry-run` and `--no-backup` flags.
+    pub(crate) fn from_flags(dry_run: bool, no_backup: bool) -> Self {
+        match (dry_run, no_backup) {
+            (true, _) => Mode::DryRun,
+            (false, false) => Mode::Backup,
+            (false, true) => Mode::NoBackup,
+        }
+    }
+}
+
+/// Applies `change`, which rewrites the env file it is given atomically, to the env file at
+/// `path` according to `mode`.
+///
+/// The backup is removed again if `change` fails, since the env file is then left untouched.
+pub(crate) fn rewrite<T, E: From<io::Error> + From<EnvEncError>>(
+    path: &Path,
+    mode: Mode,
+    change: impl FnOnce(&Path) -> Result<T, E>,
+) -> Result<T, E> {
+    match mode {
+        Mode::DryRun => {
+            let dir = env::temp_dir().join(format!(
+                "envenc-dry-run-{:016x}",
+                rand::thread_rng().gen::<u64>()
+            ));
+            fs::create_dir(&dir)?;
+            let copy = dir.join(path.file_name().unwrap_or_default());
+            let result = fs::copy(path, &copy)
+                .map_err(E::from)
+                .and_then(|_| change(&copy));
+            let _ = fs::remove_dir_all(&dir);
+            result
+        }
+        Mode::Backup => {
+            let Some(backup) = backup_env_file(path, &BackupOptions::default())? else {
+                return change(path);
+            };
+            let result = change(path);
+            match &result {
+                Ok(_) => eprintln!("Backed up the previous file to {}.", backup.display()),
+                Err(_) => {
+                    let _ = fs::remove_file(&backup);
+                }
+            }
+            result
+        }
+        Mode::NoBackup => change(path),
+    }
+}
+
+/// The arguments of `envenc restore`.
+#[derive(Args)]
+pub(crate) struct RestoreArgs {
+    #[command(flatten)]
+    file: FileArgs,
+}
+
+/// Runs `envenc restore`, which replaces the env file with its newest backup. No key is
+/// needed, and the backup is kept.
+pub(crate) fn restore(args: RestoreArgs) -> CliResult {
+    let path = &args.file.file;
+    let backup = restore_backup(path)?;
+    eprintln!("Restored {} from {}.", path.display(), backup.display());
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,145 @@
+use crate::{
+    args::{KeyArgs, SecondKeyArgs},
+    CliResult,
+};
+use clap::Args;
+use serde_json::json;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// The arguments of `envenc diff`.
+#[derive(Args)]
+pub(crate) struct DiffArgs {
+    /// The env file to compare from.
+    old: PathBuf,
+    /// The env file to compare to.
+    new: PathBuf,
+    /// Print the decrypted values of the variables that were added, removed, or changed.
+    #[arg(long)]
+    show_values: bool,
+    /// Print the differences as JSON.
+    #[arg(long)]
+    json: bool,
+    #[command(flatten)]
+    key: KeyArgs,
+    #[command(flatten)]
+    key2: SecondKeyArgs,
+}
+
+/// How a variable differs between the two env files.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+impl Change {
+    fn name(self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Changed => "changed",
+            Change::Unchanged => "unchanged",
+        }
+    }
+
+    fn marker(self) -> char {
+        match self {
+            Change::Added => '+',
+            Change::Removed => '-',
+            Change::Changed => '~',
+            Change::Unchanged => ' ',
+        }
+    }
+}
+
+/// Runs `envenc diff`, which exits with a failure if the files differ, as `diff` does.
+///
+/// Both files are decrypted, each with its own key and cipher, and their plaintext values are
+/// compared, since the ciphertext of equal values differs by its nonce. Only names are printed
+/// unless `--show-values` is given.
+pub(crate) fn run(args: DiffArgs) -> CliResult {
+    let old = decrypt(&args.old, &args.key)?;
+    let new = decrypt(&args.new, &args.key2.or(&args.key))?;
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let changes: Vec<(&String, Change)> = names
+        .into_iter()
+        .map(|name| {
+            let change = match (old.get(name), new.get(name)) {
+                (None, _) => Change::Added,
+                (_, None) => Change::Removed,
+                (Some(old), Some(new)) if old != new => Change::Changed,
+                _ => Change::Unchanged,
+            };
+            (name, change)
+        })
+        .collect();
+
+    match args.json {
+        true => {
+            let names = |wanted: Change| -> Vec<&String> {
+                changes
+                    .iter()
+                    .filter(|(_, change)| *change == wanted)
+                    .map(|(name, _)| *name)
+                    .collect()
+            };
+            let mut output = json!({
+                "old": args.old.display().to_string(),
+                "new": args.new.display().to_string(),
+                "added": names(Change::Added),
+                "removed": names(Change::Removed),
+                "changed": names(Change::Changed),
+                "unchanged": names(Change::Unchanged),
+            });
+            if args.show_values {
+                output["values"] = changes
+                    .iter()
+                    .filter(|(_, change)| *change != Change::Unchanged)
+                    .map(|(name, _)| {
+                        let values = json!({ "old": old.get(*name), "new": new.get(*name) });
+                        (name.to_string(), values)
+                    })
+                    .collect();
+            }
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+        false => {
+            for (name, change) in &changes {
+                println!("{} {} ({})", change.marker(), name, change.name());
+                if args.show_values && *change != Change::Unchanged {
+                    if let Some(value) = old.get(*name) {
+                        println!("    - {}", serde_json::to_string(value)?);
+                    }
+                    if let Some(value) = new.get(*name) {
+                        println!("    + {}", serde_json::to_string(value)?);
+                    }
+                }
+            }
+        }
+    }
+    match changes
+        .iter()
+        .any(|(_, change)| *change != Change::Unchanged)
+    {
+        true => Ok(ExitCode::FAILURE),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// Decrypts every variable of the env file at `path` with the key `key` selects, failing if
+/// the file does not exist rather than comparing it as empty.
+fn decrypt(
+    path: &Path,
+    key: &KeyArgs,
+) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    if !path.exists() {
+        return Err(format!("{} does not exist", path.display()).into());
+    }
+    Ok(key.session(path)?.decrypt_to_map()?)
+}
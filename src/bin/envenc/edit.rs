@@ -0,0 +1,239 @@
+use crate::{
+    args::{FileArgs, KeyArgs},
+    CliResult,
+};
+use clap::Args;
+use envenc::{list_enc_env, Overwrite};
+use rand::Rng;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+/// The prefix of the comment lines `envenc edit` adds to the temporary file to report a parse
+/// error. They are dropped when the file is parsed.
+const ERROR_PREFIX: &str = "# envenc error:";
+
+/// The arguments of `envenc edit`.
+#[derive(Args)]
+pub(crate) struct EditArgs {
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc edit`.
+///
+/// The decrypted variables are written as `NAME=value` lines to a temporary file only the
+/// owner can read, which is opened with `$VISUAL`, `$EDITOR`, or `vi`. Values that hold
+/// newlines, quotes, or surrounding whitespace are written as double-quoted strings with
+/// escapes. Once the editor exits, only the variables whose value changed are encrypted
+/// again, with fresh nonces, so the unchanged lines of the env file keep their ciphertext.
+///
+/// If the edited file cannot be parsed, the editor is opened again with the error on its
+/// first line. Saving it again without changes aborts, as an editor that exits non-zero does.
+pub(crate) fn run(args: EditArgs) -> CliResult {
+    let path = &args.file.file;
+    let mut session = args.key.session(path)?;
+    let original = session.decrypt_to_map()?;
+    let order: Vec<String> = list_enc_env(path)?
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+    let mut names: Vec<&String> = original.keys().collect();
+    names.sort_by_key(|name| (order.iter().position(|n| n == *name), *name));
+
+    let mut text = format!(
+        "# Decrypted variables of {}. Save and quit to encrypt changed values again.\n",
+        path.display()
+    );
+    for name in names {
+        text.push_str(&format!("{}={}\n", name, quote(&original[name])));
+    }
+
+    let temp = TempFile::create()?;
+    let edited = loop {
+        temp.write(&text)?;
+        let status = editor_command(temp.path())?.status()?;
+        if !status.success() {
+            return Err(format!(
+                "the editor exited with {}; {} was not changed",
+                status,
+                path.display()
+            )
+            .into());
+        }
+        let saved = fs::read_to_string(temp.path())?;
+        if saved == text && text.starts_with(ERROR_PREFIX) {
+            return Err(format!(
+                "the edits were not fixed; {} was not changed",
+                path.display()
+            )
+            .into());
+        }
+        text = saved;
+        match parse(&text) {
+            Ok(edited) => break edited,
+            Err((line, message)) => {
+                let kept: String = text
+                    .split_inclusive('\n')
+                    .filter(|l| !l.starts_with(ERROR_PREFIX))
+                    .collect();
+                text = format!("{} line {}: {}\n{}", ERROR_PREFIX, line, message, kept);
+            }
+        }
+    };
+    drop(temp);
+
+    let (mut created, mut updated, mut removed) = (0, 0, 0);
+    for (name, value) in &edited {
+        match original.get(name) {
+            Some(old) if old == value => {}
+            Some(_) => {
+                session.upsert(name, value, Overwrite::Yes)?;
+                updated += 1;
+            }
+            None => {
+                session.upsert(name, value, Overwrite::Yes)?;
+                created += 1;
+            }
+        }
+    }
+    let edited_names: HashSet<&String> = edited.iter().map(|(name, _)| name).collect();
+    for name in original.keys() {
+        if !edited_names.contains(name) {
+            session.remove(name)?;
+            removed += 1;
+        }
+    }
+    match created + updated + removed {
+        0 => eprintln!("No changes to {}.", path.display()),
+        _ => eprintln!(
+            "Updated {}: {} added, {} changed, {} removed.",
+            path.display(),
+            created,
+            updated,
+            removed
+        ),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Returns `value` as written to the temporary file: as it is if it reads back unchanged, and
+/// as a JSON string otherwise.
+fn quote(value: &str) -> String {
+    let plain = !value.starts_with('"')
+        && value.trim() == value
+        && !value.contains(['\n', '\r'])
+        && !value.starts_with('#');
+    match plain {
+        true => value.to_string(),
+        false => serde_json::to_string(value).expect("strings serialize to JSON"),
+    }
+}
+
+/// Parses the edited temporary file into its variables, in order, or returns the line number
+/// and description of the first error.
+fn parse(text: &str) -> Result<Vec<(String, String)>, (usize, String)> {
+    let mut vars = Vec::new();
+    let mut seen = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let (name, value) = trimmed
+            .split_once('=')
+            .ok_or_else(|| (number, "expected NAME=value".to_string()))?;
+        let name = name.trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+        {
+            return Err((number, format!("{:?} is not a valid variable name", name)));
+        }
+        let value = match value.starts_with('"') {
+            true => serde_json::from_str(value)
+                .map_err(|err| (number, format!("invalid quoted value: {}", err)))?,
+            false => value.to_string(),
+        };
+        if let Some(first) = seen.insert(name.to_string(), number) {
+            return Err((number, format!("{} is already set on line {}", name, first)));
+        }
+        vars.push((name.to_string(), value));
+    }
+    Ok(vars)
+}
+
+/// Returns the command that opens `path` in the editor named by `$VISUAL` or `$EDITOR`, which
+/// may include arguments, or in `vi`.
+fn editor_command(path: &Path) -> Result<Command, String> {
+    let editor = env::var("VISUAL")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("no editor is set")?;
+    let mut command = Command::new(program);
+    command.args(words).arg(path);
+    Ok(command)
+}
+
+/// A temporary file in `$TMPDIR` that only the owner can read, overwritten with zeros and
+/// removed when dropped.
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Creates a new, empty temporary file with a random name.
+    fn create() -> std::io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let name = format!("envenc-edit-{:016x}.env", rand::thread_rng().gen::<u64>());
+        let path = env::temp_dir().join(name);
+        options.open(&path)?;
+        Ok(TempFile { path })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replaces the contents of the file with `text`.
+    fn write(&self, text: &str) -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // Editors may have replaced the file, so overwrite whatever is there now.
+        if let Ok(len) = fs::metadata(&self.path).map(|metadata| metadata.len()) {
+            if let Ok(mut file) = OpenOptions::new().write(true).open(&self.path) {
+                let _ = file.write_all(&vec![0u8; len as usize]);
+                let _ = file.sync_all();
+            }
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
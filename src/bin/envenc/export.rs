@@ -0,0 +1,146 @@
+use crate::{
+    args::{FileArgs, KeyArgs},
+    CliResult,
+};
+use clap::{Args, ValueEnum};
+use envenc::{export_env_from, export_env_to_file, ExportFormat, ExportReport};
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// The formats `envenc export` writes.
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// A JSON object, indented and sorted by name.
+    Json,
+    /// A YAML mapping of double-quoted strings.
+    Yaml,
+    /// `export KEY='value'` lines, to `eval` or source in a POSIX shell.
+    Shell,
+    /// The manifest of a Kubernetes `Secret`, named with `--name`.
+    K8s,
+    /// A systemd `EnvironmentFile=`.
+    Systemd,
+    /// `KEY=value` lines, quoted where needed, as read by dotenv loaders and `envenc import`.
+    Dotenv,
+    /// Unquoted `KEY=value` lines, as read by docker-compose.
+    Compose,
+    /// Lines for the `$GITHUB_ENV` file of a GitHub Actions step.
+    Github,
+}
+
+/// The arguments of `envenc export`.
+#[derive(Args)]
+pub(crate) struct ExportArgs {
+    /// The format to write the decrypted variables in.
+    #[arg(long, value_enum)]
+    format: Format,
+    /// Write to this file, readable only by the owner, instead of stdout.
+    #[arg(long, short, value_name = "PATH")]
+    out: Option<PathBuf>,
+    /// The name of the Kubernetes `Secret`, with `--format k8s`.
+    #[arg(long, required_if_eq("format", "k8s"))]
+    name: Option<String>,
+    /// The namespace of the Kubernetes `Secret`, with `--format k8s`.
+    #[arg(long, requires = "name")]
+    namespace: Option<String>,
+    /// Write the values of the Kubernetes `Secret` in plain text under `stringData`, instead of
+    /// base64-encoded under `data`.
+    #[arg(long, requires = "name")]
+    string_data: bool,
+    /// Write to stdout even when it is redirected to a file that other users can read.
+    #[arg(long)]
+    insecure_permissions: bool,
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc export`.
+///
+/// Nothing is written unless every variable decrypts. A file given with `--out` is replaced
+/// atomically and only its owner can read it. Stdout redirected to a file that other users can
+/// read is refused unless `--insecure-permissions` is given, so that the plaintext values do
+/// not end up in a world-readable file by accident.
+pub(crate) fn run(args: ExportArgs) -> CliResult {
+    let path = &args.file.file;
+    if args.out.is_none() && !args.insecure_permissions && stdout_is_world_readable_file()? {
+        return Err(
+            "stdout is a file that other users can read; write it with --out instead, \
+                    or pass --insecure-permissions"
+                .into(),
+        );
+    }
+    let format = match args.format {
+        Format::Json => ExportFormat::Json {
+            pretty: true,
+            sort_keys: true,
+        },
+        Format::Yaml => ExportFormat::Yaml,
+        Format::Shell => ExportFormat::Shell { export: true },
+        Format::K8s => ExportFormat::KubernetesSecret {
+            name: args.name.clone().unwrap_or_default(),
+            namespace: args.namespace.clone(),
+            string_data: args.string_data,
+        },
+        Format::Systemd => ExportFormat::SystemdEnvFile,
+        Format::Dotenv => ExportFormat::Dotenv,
+        Format::Compose => ExportFormat::DockerComposeEnv,
+        Format::Github => ExportFormat::GithubEnv,
+    };
+    let key = args.key.load(path)?;
+
+    let report = match &args.out {
+        Some(out) => export_env_to_file(path, format, args.key.cipher, &key, out)?,
+        None => {
+            let mut stdout = io::stdout().lock();
+            let report = export_env_from(path, format, args.key.cipher, &key, &mut stdout)?;
+            // JSON is written without a final newline, which a terminal prompt would run into.
+            if matches!(args.format, Format::Json) {
+                writeln!(stdout)?;
+            }
+            stdout.flush()?;
+            report
+        }
+    };
+    warn(&report, args.out.as_deref());
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Warns on stderr that the output holds plaintext secrets, and lists the variables that were
+/// renamed or left out.
+fn warn(report: &ExportReport, out: Option<&Path>) {
+    let target = out.map_or("the output".to_string(), |out| out.display().to_string());
+    eprintln!(
+        "WARNING: {} holds the decrypted values in plain text. Do not commit, log, or share it.",
+        target
+    );
+    for (name, key) in &report.renamed {
+        eprintln!("Wrote {} as {}.", name, key);
+    }
+    if !report.skipped.is_empty() {
+        eprintln!(
+            "Left out {}, which the format cannot name.",
+            report.skipped.join(", ")
+        );
+    }
+}
+
+/// Returns `true` if stdout is redirected to a regular file that users other than its owner
+/// and group can read.
+#[cfg(unix)]
+fn stdout_is_world_readable_file() -> io::Result<bool> {
+    use std::os::{fd::AsFd, unix::fs::PermissionsExt};
+    let stdout = std::fs::File::from(io::stdout().as_fd().try_clone_to_owned()?);
+    let metadata = stdout.metadata()?;
+    Ok(metadata.is_file() && metadata.permissions().mode() & 0o004 != 0)
+}
+
+/// Returns `false`, since only Unix permissions are checked.
+#[cfg(not(unix))]
+fn stdout_is_world_readable_file() -> io::Result<bool> {
+    Ok(false)
+}
@@ -0,0 +1,37 @@
+use crate::{
+    args::{FileArgs, KeyArgs},
+    CliResult,
+};
+use clap::Args;
+use std::{io::Write, process::ExitCode};
+
+/// The arguments of `envenc get`.
+#[derive(Args)]
+pub(crate) struct GetArgs {
+    /// The name of the variable.
+    name: String,
+    /// Print the value without a trailing newline, for command substitution.
+    #[arg(long)]
+    raw: bool,
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc get`.
+pub(crate) fn run(args: GetArgs) -> CliResult {
+    let path = &args.file.file;
+    let value = args
+        .key
+        .session(path)?
+        .get(&args.name)?
+        .ok_or_else(|| format!("{} is not set in {}", args.name, path.display()))?;
+    let mut stdout = std::io::stdout().lock();
+    match args.raw {
+        true => write!(stdout, "{}", value)?,
+        false => writeln!(stdout, "{}", value)?,
+    }
+    stdout.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
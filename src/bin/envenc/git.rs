@@ -0,0 +1,207 @@
+use crate::{args::KeyArgs, CliResult};
+use clap::Args;
+use envenc::{clean_env, smudge_env};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, Stdio},
+};
+
+/// The name the filter is registered under in the git config and `.gitattributes`.
+const FILTER_NAME: &str = "envenc";
+
+/// The arguments of `envenc clean` and `envenc smudge`.
+#[derive(Args)]
+pub(crate) struct FilterArgs {
+    /// The path of the file in the repository, which git passes as `%f`.
+    #[arg(default_value = ".env")]
+    path: PathBuf,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc clean`, the git filter that encrypts the plaintext env file on stdin to stdout
+/// when it is staged.
+///
+/// Values that are unchanged from the staged version of the file keep its ciphertext, so that
+/// git does not see the file as modified after every checkout.
+pub(crate) fn clean(args: FilterArgs) -> CliResult {
+    let plaintext = read_stdin()?;
+    let key = args.key.load(&args.path)?;
+    let previous = staged_contents(&args.path);
+    let encrypted = clean_env(
+        &args.path,
+        &plaintext,
+        previous.as_deref(),
+        args.key.cipher,
+        &key,
+    )?;
+    write_stdout(&encrypted)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Runs `envenc smudge`, the git filter that decrypts the env file on stdin to stdout when it
+/// is checked out.
+///
+/// Without the key, the file is checked out encrypted, with a warning, so that clones without
+/// access to the key still work.
+pub(crate) fn smudge(args: FilterArgs) -> CliResult {
+    let contents = read_stdin()?;
+    let decrypted = args
+        .key
+        .load(&args.path)
+        .and_then(|key| smudge_env(&args.path, &contents, args.key.cipher, &key));
+    match decrypted {
+        Ok(decrypted) => write_stdout(&decrypted)?,
+        Err(err) => {
+            eprintln!(
+                "envenc: checking out {} encrypted: {}",
+                args.path.display(),
+                err
+            );
+            write_stdout(&contents)?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The arguments of `envenc git-install`.
+#[derive(Args)]
+pub(crate) struct GitInstallArgs {
+    /// The patterns of the env files to filter, as written to `.gitattributes`.
+    #[arg(default_value = ".env")]
+    patterns: Vec<String>,
+    /// Have the filters read the key from this file, instead of from `ENVENC_KEY_FILE` or
+    /// `ENVENC_KEY` in the environment git runs in.
+    #[arg(long, value_name = "PATH")]
+    key_file: Option<PathBuf>,
+}
+
+/// Runs `envenc git-install`, which registers the filters in the git config of the current
+/// repository and assigns them to `patterns` in its `.gitattributes`.
+///
+/// The filter is marked as required, so that git fails instead of staging plaintext if the
+/// clean filter cannot encrypt the file.
+pub(crate) fn git_install(args: GitInstallArgs) -> CliResult {
+    let key_flag = match &args.key_file {
+        Some(path) => {
+            let path = fs::canonicalize(path)?;
+            let path = path
+                .to_str()
+                .ok_or("the key file path is not valid UTF-8")?;
+            format!(" --key-file {}", shell_quote(path))
+        }
+        None => String::new(),
+    };
+    let config = [
+        ("clean", format!("envenc clean{} %f", key_flag)),
+        ("smudge", format!("envenc smudge{} %f", key_flag)),
+        ("required", "true".to_string()),
+    ];
+    for (name, value) in &config {
+        git(&["config", &format!("filter.{}.{}", FILTER_NAME, name), value])?;
+    }
+
+    let root = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim_end());
+    let added = add_attributes(&root.join(".gitattributes"), &args.patterns)?;
+    eprintln!(
+        "Registered the {} filter in the git config of {}.",
+        FILTER_NAME,
+        root.display()
+    );
+    if !added.is_empty() {
+        eprintln!("Added {} to .gitattributes.", added.join(", "));
+    }
+    eprintln!(
+        "Files that are already committed in plain text stay so in the history; run \
+         `git add --renormalize .` to encrypt them from now on."
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Appends a `<pattern> filter=envenc` line to the `.gitattributes` file at `path` for each of
+/// `patterns` that it does not assign to the filter yet, and returns the lines added.
+fn add_attributes(path: &Path, patterns: &[String]) -> io::Result<Vec<String>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    let attribute = format!("filter={}", FILTER_NAME);
+    let added: Vec<String> = patterns
+        .iter()
+        .filter(|pattern| {
+            !contents.lines().any(|line| {
+                let mut words = line.split_whitespace();
+                words.next() == Some(pattern.as_str()) && words.any(|word| word == attribute)
+            })
+        })
+        .map(|pattern| format!("{} {}", pattern, attribute))
+        .collect();
+    if added.is_empty() {
+        return Ok(added);
+    }
+
+    let mut appended = String::new();
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        appended.push('\n');
+    }
+    for line in &added {
+        appended.push_str(line);
+        appended.push('\n');
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(appended.as_bytes())?;
+    Ok(added)
+}
+
+/// Returns the contents of `path` in the git index, or `None` if it is not staged or git
+/// cannot be run.
+fn staged_contents(path: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("cat-file")
+        .arg("blob")
+        .arg(format!(":{}", path.to_str()?))
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    match output.status.success() {
+        true => String::from_utf8(output.stdout).ok(),
+        false => None,
+    }
+}
+
+/// Runs git with `args` and returns its stdout, failing with its stderr if it exits non-zero.
+fn git(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("git").args(args).output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Returns `value` in single quotes for the shell git runs filters with.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r#"'"'"'"#))
+}
+
+fn read_stdin() -> io::Result<String> {
+    let mut contents = String::new();
+    io::stdin().read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+fn write_stdout(contents: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(contents.as_bytes())?;
+    stdout.flush()
+}
@@ -0,0 +1,123 @@
+use crate::{
+    args::{FileArgs, KeyArgs},
+    backup::{self, Mode},
+    CliResult,
+};
+use clap::{Args, ValueEnum};
+use envenc::{import_env, EnvEncError, ImportFormat, OnConflict};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+/// The formats `envenc import` reads.
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// A flat JSON object of strings, numbers, and booleans.
+    Json,
+    /// `KEY=value` lines, which may start with `export `, where values may be quoted and `#`
+    /// starts a comment outside quotes.
+    Dotenv,
+    /// A flat TOML table.
+    #[cfg(feature = "toml")]
+    Toml,
+    /// A flat YAML mapping.
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+/// What to do with a variable that is already set in the env file.
+#[derive(Clone, Copy, ValueEnum)]
+enum Conflict {
+    /// Keep the stored value.
+    Skip,
+    /// Replace the stored value.
+    Overwrite,
+    /// Import nothing and fail.
+    Fail,
+}
+
+/// The arguments of `envenc import`.
+#[derive(Args)]
+pub(crate) struct ImportArgs {
+    /// The file of plaintext secrets to import, or `-` for stdin.
+    input: PathBuf,
+    /// The format of the input.
+    #[arg(long, value_enum)]
+    format: Format,
+    /// What to do with a variable that is already set in the env file.
+    #[arg(long, value_enum, default_value = "fail")]
+    on_conflict: Conflict,
+    /// Do not back up the env file to `<file>.<timestamp>.bak` first.
+    #[arg(long)]
+    no_backup: bool,
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc import`.
+///
+/// Each value is encrypted with a fresh nonce. The input is parsed in full before anything is
+/// stored, so an input that is not flat, or a conflict with `--on-conflict fail`, leaves
+/// the env file unchanged.
+pub(crate) fn run(args: ImportArgs) -> CliResult {
+    let path = &args.file.file;
+    let format = match args.format {
+        Format::Json => ImportFormat::Json,
+        Format::Dotenv => ImportFormat::Dotenv,
+        #[cfg(feature = "toml")]
+        Format::Toml => ImportFormat::Toml,
+        #[cfg(feature = "yaml")]
+        Format::Yaml => ImportFormat::Yaml,
+    };
+    let on_conflict = match args.on_conflict {
+        Conflict::Skip => OnConflict::Skip,
+        Conflict::Overwrite => OnConflict::Overwrite,
+        Conflict::Fail => OnConflict::Error,
+    };
+    let key = args.key.load(path)?;
+    let stdin = args.input.as_os_str() == "-";
+    let input: Box<dyn Read> = match stdin {
+        true => Box::new(io::stdin().lock()),
+        false => Box::new(File::open(&args.input)?),
+    };
+    let mode = Mode::from_flags(false, args.no_backup);
+    let report = backup::rewrite(path, mode, |path| {
+        import_env(input, format, args.key.cipher, &key, path, on_conflict)
+    });
+    let report = match report {
+        Err(EnvEncError::VarExists { var }) => {
+            return Err(format!(
+                "{} is already set in {}; nothing was imported. Pass --on-conflict skip or \
+                 --on-conflict overwrite",
+                var,
+                path.display()
+            )
+            .into())
+        }
+        result => result?,
+    };
+
+    match report.imported.is_empty() {
+        true => eprintln!("Nothing was imported into {}.", path.display()),
+        false => eprintln!(
+            "Imported {} into {}.",
+            report.imported.join(", "),
+            path.display()
+        ),
+    }
+    if !report.skipped.is_empty() {
+        eprintln!("Kept the stored values of {}.", report.skipped.join(", "));
+    }
+    if !stdin {
+        eprintln!(
+            "{} still holds the values in plain text; delete it once it is no longer needed.",
+            args.input.display()
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
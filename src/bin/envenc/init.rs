@@ -0,0 +1,199 @@
+use crate::{
+    args::{read_passphrase, FileArgs, PASSPHRASE_VAR},
+    CliResult,
+};
+use clap::Args;
+use envenc::{
+    derive_key_from_passphrase, init_env_file, key_fingerprint, keys_generation,
+    write_kdf_metadata, CipherType, KdfKind, DEFAULT_KEY_VAR,
+};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// The arguments of `envenc init`.
+#[derive(Args)]
+pub(crate) struct InitArgs {
+    #[command(flatten)]
+    file: FileArgs,
+    /// The cipher values are encrypted with.
+    #[arg(long, default_value = "aes256gcm")]
+    cipher: CipherType,
+    /// Write the key to this file, as hex, readable only by the owner.
+    #[arg(long, value_name = "PATH")]
+    key_file: Option<PathBuf>,
+    /// Store the key in the OS keyring, under this service name.
+    #[cfg(feature = "keyring")]
+    #[arg(long, value_name = "SERVICE", conflicts_with = "key_file")]
+    keyring: Option<String>,
+    /// Derive the key from a passphrase instead of generating one, recording the salt in the
+    /// env file.
+    #[arg(long, conflicts_with = "key_file")]
+    passphrase: bool,
+    /// Replace an env file or key file that is already initialized.
+    #[arg(long)]
+    force: bool,
+    /// Do not print the key.
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+/// Runs `envenc init`.
+///
+/// The key is printed at most once, to stdout, and never passed on a command line, so it does
+/// not end up in shell history files.
+pub(crate) fn run(args: InitArgs) -> CliResult {
+    let path = &args.file.file;
+    if args.passphrase {
+        #[cfg(feature = "keyring")]
+        if args.keyring.is_some() {
+            return Err("--passphrase derives the key, so it cannot be stored in a keyring".into());
+        }
+        let passphrase = read_passphrase(PASSPHRASE_VAR, "New passphrase: ", true)?;
+        let (key, metadata) =
+            derive_key_from_passphrase(&passphrase, args.cipher, KdfKind::default())?;
+        init_env_file(path, &key, args.force)?;
+        write_kdf_metadata(path, &metadata)?;
+        eprintln!(
+            "Initialized {} for key {}, derived from the passphrase.",
+            path.display(),
+            key_fingerprint(&key)
+        );
+        update_gitignore(path, None)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    #[cfg(feature = "keyring")]
+    if let Some(service) = &args.keyring {
+        let key = crate::args::keyring_source(service).load_or_generate(args.cipher)?;
+        init_env_file(path, &key, args.force)?;
+        eprintln!(
+            "Initialized {} for key {}, stored in the OS keyring under {}.",
+            path.display(),
+            key_fingerprint(&key),
+            service
+        );
+        print_key(&key, args.quiet, false);
+        update_gitignore(path, None)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let (key, _) = keys_generation(args.cipher)?;
+    let Some(key_file) = &args.key_file else {
+        init_env_file(path, &key, args.force)?;
+        eprintln!(
+            "Initialized {} for key {}. The key is not saved anywhere: store it in a secret \
+             manager and export it as {} to use the file.",
+            path.display(),
+            key_fingerprint(&key),
+            DEFAULT_KEY_VAR
+        );
+        print_key(&key, args.quiet, true);
+        update_gitignore(path, None)?;
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    write_key_file(key_file, &key, args.force)?;
+    if let Err(err) = init_env_file(path, &key, args.force) {
+        // Do not leave a key behind for an env file that was not initialized with it.
+        let _ = fs::remove_file(key_file);
+        return Err(err.into());
+    }
+    eprintln!(
+        "Initialized {} for key {}, written to {}.",
+        path.display(),
+        key_fingerprint(&key),
+        key_file.display()
+    );
+    print_key(&key, args.quiet, false);
+    update_gitignore(path, Some(key_file))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints `key` as hex to stdout, as an `export ENVENC_KEY=...` line if `export` is set, unless
+/// `quiet` is set. This is the only place the key is printed.
+fn print_key(key: &[u8], quiet: bool, export: bool) {
+    match (quiet, export) {
+        (true, _) => {}
+        (false, true) => println!("export {}={}", DEFAULT_KEY_VAR, hex::encode(key)),
+        (false, false) => println!("{}", hex::encode(key)),
+    }
+}
+
+/// Writes `key` as hex to a new file at `path` that only its owner can read, replacing an
+/// existing file only if `force` is set.
+pub(crate) fn write_key_file(path: &Path, key: &[u8], force: bool) -> std::io::Result<()> {
+    write_private_file(path, format!("{}\n", hex::encode(key)).as_bytes(), force)
+}
+
+/// Writes `contents` to a new file at `path` that only its owner can read, replacing an
+/// existing file only if `force` is set.
+pub(crate) fn write_private_file(path: &Path, contents: &[u8], force: bool) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    match force {
+        true => options.create(true).truncate(true),
+        false => options.create_new(true),
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Appends the lock, temporary, and backup files of the env file at `env_file`, and `key_file`,
+/// to the `.gitignore` next to the env file if one exists, skipping entries already listed.
+fn update_gitignore(env_file: &Path, key_file: Option<&Path>) -> std::io::Result<()> {
+    let dir = env_file.parent().unwrap_or(Path::new(""));
+    let gitignore = dir.join(".gitignore");
+    let Ok(contents) = fs::read_to_string(&gitignore) else {
+        return Ok(());
+    };
+    let name = env_file
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let mut entries = vec![
+        format!(".{}.lock", name),
+        format!(".{}.tmp", name),
+        format!("{}.*.bak", name),
+    ];
+    // Entries are relative to the directory of the `.gitignore`, so a key file elsewhere is
+    // left out.
+    if let Some(key_file) = key_file.and_then(|key_file| match dir.as_os_str().is_empty() {
+        true => Some(key_file).filter(|key_file| key_file.is_relative()),
+        false => key_file.strip_prefix(dir).ok(),
+    }) {
+        entries.push(format!("/{}", key_file.display()));
+    }
+    let missing: Vec<String> = entries
+        .into_iter()
+        .filter(|entry| !contents.lines().any(|line| line.trim() == entry))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let mut appended = String::new();
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        appended.push('\n');
+    }
+    appended.push_str("# envenc\n");
+    for entry in &missing {
+        appended.push_str(entry);
+        appended.push('\n');
+    }
+    OpenOptions::new()
+        .append(true)
+        .open(&gitignore)?
+        .write_all(appended.as_bytes())?;
+    eprintln!("Added {} to {}.", missing.join(", "), gitignore.display());
+    Ok(())
+}
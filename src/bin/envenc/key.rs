@@ -0,0 +1,225 @@
+use crate::{
+    args::{FileArgs, KeyArgs},
+    init::{write_key_file, write_private_file},
+    CliResult,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::{Args, Subcommand, ValueEnum};
+use envenc::{
+    combine_shares, key_fingerprint, keys_generation, recorded_fingerprint, split_key, CipherType,
+    KeyShare, KeySource,
+};
+use std::{
+    fs,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// The encodings a key is written in.
+#[derive(Clone, Copy, ValueEnum)]
+enum Encoding {
+    /// Hexadecimal, on one line.
+    Hex,
+    /// Standard base64, on one line.
+    Base64,
+    /// The bytes of the key as they are.
+    Raw,
+}
+
+/// The arguments of `envenc generate-key`.
+#[derive(Args)]
+pub(crate) struct GenerateKeyArgs {
+    /// The cipher the key is generated for.
+    #[arg(long, default_value = "aes256gcm")]
+    cipher: CipherType,
+    /// Write the key to this new file, readable only by the owner, instead of stdout. An
+    /// existing file is never replaced.
+    #[arg(long, short, value_name = "PATH")]
+    out: Option<PathBuf>,
+    /// The encoding the key is written in.
+    #[arg(long, value_enum, default_value = "hex")]
+    format: Encoding,
+}
+
+/// Runs `envenc generate-key`.
+pub(crate) fn generate(args: GenerateKeyArgs) -> CliResult {
+    let (key, _) = keys_generation(args.cipher)?;
+    let encoded = match args.format {
+        Encoding::Hex => format!("{}\n", hex::encode(&key)).into_bytes(),
+        Encoding::Base64 => format!("{}\n", STANDARD.encode(&key)).into_bytes(),
+        Encoding::Raw => key.clone(),
+    };
+    let Some(out) = &args.out else {
+        let mut stdout = io::stdout().lock();
+        if matches!(args.format, Encoding::Raw) && stdout.is_terminal() {
+            return Err("a raw key is not printable; write it with --out or --format hex".into());
+        }
+        stdout.write_all(&encoded)?;
+        stdout.flush()?;
+        eprintln!("Generated key {}.", key_fingerprint(&key));
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    match write_private_file(out, &encoded, false) {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(format!(
+                "{} already exists; remove it first to replace the key it holds",
+                out.display()
+            )
+            .into())
+        }
+        result => result?,
+    }
+    eprintln!("Wrote key {} to {}.", key_fingerprint(&key), out.display());
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The arguments of `envenc key`.
+#[derive(Args)]
+pub(crate) struct KeyCommandArgs {
+    #[command(subcommand)]
+    command: KeyCommand,
+}
+
+#[derive(Subcommand)]
+enum KeyCommand {
+    /// Print the fingerprint of a key, or the one an env file records for its key, without
+    /// decrypting anything.
+    Fingerprint(FingerprintArgs),
+    /// Split a key into shares, any `--threshold` of which recombine it.
+    Shares(SharesArgs),
+    /// Recombine a key from shares read from stdin, one per line.
+    Combine(CombineArgs),
+}
+
+/// Runs `envenc key`.
+pub(crate) fn run(args: KeyCommandArgs) -> CliResult {
+    match args.command {
+        KeyCommand::Fingerprint(args) => fingerprint(args),
+        KeyCommand::Shares(args) => shares(args),
+        KeyCommand::Combine(args) => combine(args),
+    }
+}
+
+/// The arguments of `envenc key fingerprint`.
+#[derive(Args)]
+struct FingerprintArgs {
+    /// A key file, or an env file whose header records the fingerprint of its key. Without it,
+    /// the key is read from the file named by `ENVENC_KEY_FILE`, or else from `ENVENC_KEY`.
+    source: Option<PathBuf>,
+    /// The cipher the key is checked against.
+    #[arg(long, default_value = "aes256gcm")]
+    cipher: CipherType,
+}
+
+/// Runs `envenc key fingerprint`, which prints the same fingerprint as the `# envenc key-id:`
+/// header of the env files written with the key.
+fn fingerprint(args: FingerprintArgs) -> CliResult {
+    let Some(source) = &args.source else {
+        println!(
+            "{}",
+            key_fingerprint(&KeySource::from_env().load(args.cipher)?)
+        );
+        return Ok(ExitCode::SUCCESS);
+    };
+    // An env file is told apart by its header, which a key file never has.
+    if let Some(recorded) = recorded_header(source)? {
+        println!("{}", recorded);
+        return Ok(ExitCode::SUCCESS);
+    }
+    match KeySource::file(source).load(args.cipher) {
+        Ok(key) => println!("{}", key_fingerprint(&key)),
+        Err(err) => {
+            return Err(format!(
+                "{} is neither a key file nor an env file that records the fingerprint of its \
+                 key: {}",
+                source.display(),
+                err
+            )
+            .into())
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Returns the fingerprint recorded in the header of `path`, or `None` if it is not an env file
+/// with a header, such as a key file of raw bytes.
+fn recorded_header(path: &Path) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let contents =
+        fs::read(path).map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+    if std::str::from_utf8(&contents).is_err() {
+        return Ok(None);
+    }
+    Ok(recorded_fingerprint(path)?)
+}
+
+/// The arguments of `envenc key shares`.
+#[derive(Args)]
+struct SharesArgs {
+    /// The number of shares to split the key into.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(2..))]
+    split: u8,
+    /// The number of shares needed to recombine the key.
+    #[arg(long, value_name = "K", value_parser = clap::value_parser!(u8).range(1..))]
+    threshold: u8,
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc key shares`, which prints one share per line. Fewer shares than the threshold
+/// reveal nothing about the key.
+fn shares(args: SharesArgs) -> CliResult {
+    let key = args.key.load(&args.file.file)?;
+    let shares = split_key(&key, args.split, args.threshold)?;
+    let mut stdout = io::stdout().lock();
+    for share in &shares {
+        writeln!(stdout, "{}", share)?;
+    }
+    stdout.flush()?;
+    eprintln!(
+        "Split key {} into {} shares, any {} of which recombine it with `envenc key combine`. \
+         Hand each share to a different holder.",
+        key_fingerprint(&key),
+        args.split,
+        args.threshold
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The arguments of `envenc key combine`.
+#[derive(Args)]
+struct CombineArgs {
+    /// Write the key as hex to this new file, readable only by the owner, instead of stdout.
+    #[arg(long, short, value_name = "PATH")]
+    out: Option<PathBuf>,
+}
+
+/// Runs `envenc key combine`, reading shares from stdin until its end. Blank lines are
+/// skipped.
+fn combine(args: CombineArgs) -> CliResult {
+    let mut shares = Vec::new();
+    for (index, line) in io::stdin().lock().lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let share = KeyShare::parse(&line)
+            .map_err(|err| format!("line {}: invalid key share: {}", index + 1, err))?;
+        shares.push(share);
+    }
+    let key = combine_shares(&shares)?;
+    match &args.out {
+        Some(out) => {
+            write_key_file(out, &key, false)?;
+            eprintln!("Wrote key {} to {}.", key_fingerprint(&key), out.display());
+        }
+        None => {
+            println!("{}", hex::encode(&key));
+            eprintln!("Recombined key {}.", key_fingerprint(&key));
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
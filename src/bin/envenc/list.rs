@@ -0,0 +1,105 @@
+use crate::{args::FileArgs, CliResult};
+use clap::Args;
+use envenc::{list_enc_env, recorded_fingerprint, EnvEntryInfo};
+use serde_json::json;
+use std::process::ExitCode;
+
+/// The arguments of `envenc list`.
+#[derive(Args)]
+pub(crate) struct ListArgs {
+    /// Also print the cipher, key ID, and length of each value.
+    #[arg(long, short)]
+    long: bool,
+    /// Print the entries as JSON.
+    #[arg(long)]
+    json: bool,
+    #[command(flatten)]
+    file: FileArgs,
+}
+
+/// Runs `envenc list`, which prints the names of the variables in file order, never their
+/// values.
+///
+/// No key is needed: the cipher, key ID, and length are read from the stored ciphertext.
+pub(crate) fn run(args: ListArgs) -> CliResult {
+    let path = &args.file.file;
+    if !path.exists() {
+        return Err(format!("{} does not exist", path.display()).into());
+    }
+    let entries = list_enc_env(path)?;
+
+    if args.json {
+        let output = json!({
+            "file": path.display().to_string(),
+            "fingerprint": recorded_fingerprint(path)?,
+            "variables": entries
+                .iter()
+                .map(|entry| json!({
+                    "name": entry.name,
+                    "line": entry.line,
+                    "encrypted": entry.looks_encrypted(),
+                    "cipher": entry.cipher.map(|cipher| cipher.to_string()),
+                    "key_id": entry.key_id,
+                    "bytes": entry.plaintext_len(),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else if args.long {
+        print_long(&entries);
+    } else {
+        for entry in &entries {
+            println!("{}", entry.name);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints `entries` as a table with the cipher, key ID, and value length of each.
+fn print_long(entries: &[EnvEntryInfo]) {
+    let rows: Vec<[String; 4]> = entries
+        .iter()
+        .map(|entry| {
+            let cipher = match (entry.looks_encrypted(), entry.cipher) {
+                (false, _) => "plaintext".to_string(),
+                (true, Some(cipher)) => cipher.to_string(),
+                (true, None) => "-".to_string(),
+            };
+            let bytes = entry
+                .plaintext_len()
+                .map_or("-".to_string(), |len| len.to_string());
+            [
+                entry.name.clone(),
+                cipher,
+                entry.key_id.clone().unwrap_or_else(|| "-".to_string()),
+                bytes,
+            ]
+        })
+        .collect();
+    let header = ["VARIABLE", "CIPHER", "KEY ID", "BYTES"];
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].len())
+                .chain([header[column].len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let print_row = |row: [&str; 4]| {
+        println!(
+            "{:w0$}  {:w1$}  {:w2$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        );
+    };
+    print_row(header);
+    for row in &rows {
+        print_row([&row[0], &row[1], &row[2], &row[3]]);
+    }
+}
@@ -0,0 +1,38 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// The variable that sets the level of the messages the library logs, such as `debug` to see
+/// which files are read and written. Defaults to `warn`.
+const LOG_VAR: &str = "ENVENC_LOG";
+
+/// Prints the messages the library logs to stderr, after the name of the tool.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error => eprintln!("envenc: error: {}", record.args()),
+            Level::Warn => eprintln!("envenc: warning: {}", record.args()),
+            _ => eprintln!("envenc: {}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger at the level `ENVENC_LOG` names, or `warn` if it is unset or invalid.
+pub(crate) fn init() {
+    let level = std::env::var(LOG_VAR)
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::Warn);
+    if log::set_logger(&StderrLogger).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -0,0 +1,119 @@
+//! The `envenc` command-line tool, built with the `cli` feature.
+
+mod args;
+mod backup;
+mod diff;
+mod edit;
+mod export;
+mod get;
+mod git;
+mod import;
+mod init;
+mod key;
+mod list;
+mod logger;
+mod rekey;
+mod rotate;
+mod run;
+mod scan;
+mod set;
+mod unset;
+mod validate;
+mod verify;
+
+use clap::{Parser, Subcommand};
+use std::process::ExitCode;
+
+/// The result of a subcommand: the exit code to report, or an error to print.
+type CliResult = Result<ExitCode, Box<dyn std::error::Error>>;
+
+/// Manage env files whose values are encrypted with envenc.
+#[derive(Parser)]
+#[command(name = "envenc", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a key and create an empty env file for it.
+    Init(init::InitArgs),
+    /// Generate a key without creating an env file.
+    GenerateKey(key::GenerateKeyArgs),
+    /// Inspect, split, and recombine keys.
+    Key(key::KeyCommandArgs),
+    /// Encrypt a value and store it in the env file.
+    Set(set::SetArgs),
+    /// Decrypt a value from the env file and print it.
+    Get(get::GetArgs),
+    /// List the variables of the env file, without their values or the key.
+    List(list::ListArgs),
+    /// Remove variables from the env file.
+    Unset(unset::UnsetArgs),
+    /// Run a command with the decrypted variables in its environment.
+    Run(run::RunArgs),
+    /// Edit the decrypted variables in $EDITOR and encrypt the changed values again.
+    Edit(edit::EditArgs),
+    /// Compare the decrypted variables of two env files, exiting with a failure if they differ.
+    Diff(diff::DiffArgs),
+    /// Encrypt every value again under a new key.
+    Rotate(rotate::RotateArgs),
+    /// Wrap the data key with a new passphrase or key, without changing the values.
+    Rekey(rekey::RekeyArgs),
+    /// Decrypt the variables and write them in plain text for other tools.
+    Export(export::ExportArgs),
+    /// Encrypt the secrets of a JSON or dotenv file into the env file.
+    Import(import::ImportArgs),
+    /// Replace the env file with its newest backup, made by rotate, rekey, or import.
+    Restore(backup::RestoreArgs),
+    /// Git filter: encrypt the plaintext env file on stdin to stdout when it is staged.
+    Clean(git::FilterArgs),
+    /// Git filter: decrypt the env file on stdin to stdout when it is checked out.
+    Smudge(git::FilterArgs),
+    /// Register `envenc clean` and `envenc smudge` as git filters for env files.
+    GitInstall(git::GitInstallArgs),
+    /// Find values that look like plaintext secrets in env files, exiting with a failure if
+    /// any is found, for pre-commit hooks.
+    Scan(scan::ScanArgs),
+    /// Report which variables decrypt and whether the integrity checks pass, without printing
+    /// any value.
+    Status(verify::VerifyArgs),
+    /// Like status, but exit with a failure unless every check passes, for CI and deploys.
+    Verify(verify::VerifyArgs),
+    /// Check the decrypted variables against a schema file, exiting with a failure if any
+    /// rule is broken, or write a `.env.example` for the schema.
+    Validate(validate::ValidateArgs),
+}
+
+fn main() -> ExitCode {
+    logger::init();
+    let result = match Cli::parse().command {
+        Command::Init(args) => init::run(args),
+        Command::GenerateKey(args) => key::generate(args),
+        Command::Key(args) => key::run(args),
+        Command::Set(args) => set::run(args),
+        Command::Get(args) => get::run(args),
+        Command::List(args) => list::run(args),
+        Command::Unset(args) => unset::run(args),
+        Command::Run(args) => run::run(args),
+        Command::Edit(args) => edit::run(args),
+        Command::Diff(args) => diff::run(args),
+        Command::Rotate(args) => rotate::run(args),
+        Command::Rekey(args) => rekey::run(args),
+        Command::Export(args) => export::run(args),
+        Command::Import(args) => import::run(args),
+        Command::Restore(args) => backup::restore(args),
+        Command::Clean(args) => git::clean(args),
+        Command::Smudge(args) => git::smudge(args),
+        Command::GitInstall(args) => git::git_install(args),
+        Command::Scan(args) => scan::run(args),
+        Command::Status(args) => verify::run(args, false),
+        Command::Verify(args) => verify::run(args, true),
+        Command::Validate(args) => validate::run(args),
+    };
+    result.unwrap_or_else(|err| {
+        eprintln!("envenc: {}", err);
+        ExitCode::FAILURE
+    })
+}
@@ -0,0 +1,79 @@
+use crate::{
+    args::{read_passphrase, FileArgs, KeyArgs, NEW_PASSPHRASE_VAR},
+    backup::{self, Mode},
+    rotate::print_table,
+    CliResult,
+};
+use clap::Args;
+use envenc::{change_passphrase, list_enc_env, rewrap_dek, KeySource};
+use std::{path::PathBuf, process::ExitCode};
+
+/// The arguments of `envenc rekey`.
+#[derive(Args)]
+pub(crate) struct RekeyArgs {
+    /// Wrap the data key with a new passphrase, prompted for or read from
+    /// `ENVENC_NEW_PASSPHRASE`. The current one is given with `--passphrase`.
+    #[arg(
+        long,
+        required_unless_present = "new_key_file",
+        requires = "passphrase"
+    )]
+    new_passphrase: bool,
+    /// Wrap the data key with the key in this file.
+    #[arg(long, value_name = "PATH", conflicts_with = "new_passphrase")]
+    new_key_file: Option<PathBuf>,
+    /// Show what would change without changing the env file.
+    #[arg(long)]
+    dry_run: bool,
+    /// Do not back up the env file to `<file>.<timestamp>.bak` first.
+    #[arg(long)]
+    no_backup: bool,
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc rekey`.
+///
+/// The data key of an env file in data key mode is unwrapped and wrapped again with the new
+/// passphrase or key. The values stay encrypted with the data key, so their lines are not
+/// changed.
+pub(crate) fn run(args: RekeyArgs) -> CliResult {
+    let path = &args.file.file;
+    let mode = Mode::from_flags(args.dry_run, args.no_backup);
+    match &args.new_key_file {
+        Some(new_key_file) => {
+            let old_kek = args.key.load(path)?;
+            let new_kek = KeySource::file(new_key_file).load(args.key.cipher)?;
+            backup::rewrite(path, mode, |path| {
+                rewrap_dek(path, args.key.cipher, &old_kek, &new_kek)
+            })?;
+        }
+        None => {
+            let old_passphrase = args.key.passphrase()?;
+            let new_passphrase = read_passphrase(NEW_PASSPHRASE_VAR, "New passphrase: ", true)?;
+            backup::rewrite(path, mode, |path| {
+                change_passphrase(&old_passphrase, &new_passphrase, path)
+            })?;
+        }
+    }
+
+    let mut rows = vec![("(data key)".to_string(), "re-wrapped".to_string())];
+    rows.extend(
+        list_enc_env(path)?
+            .into_iter()
+            .filter(|entry| entry.looks_encrypted())
+            .map(|entry| (entry.name, "unchanged".to_string())),
+    );
+    print_table(&rows);
+    eprintln!(
+        "{} the data key of {}.",
+        match mode {
+            Mode::DryRun => "Would re-wrap",
+            _ => "Re-wrapped",
+        },
+        path.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
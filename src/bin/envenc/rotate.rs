@@ -0,0 +1,124 @@
+use crate::{
+    args::{FileArgs, KeyArgs},
+    backup::{self, Mode},
+    init::write_key_file,
+    CliResult,
+};
+use clap::Args;
+use envenc::{
+    key_fingerprint, keys_generation, rotate_keys, EnvEncError, KeySource, RotateOptions,
+    SkipReason,
+};
+use std::{fs, path::PathBuf, process::ExitCode};
+
+/// The arguments of `envenc rotate`.
+#[derive(Args)]
+pub(crate) struct RotateArgs {
+    /// The file with the new key. If it does not exist, a new key is generated and written to
+    /// it, readable only by the owner.
+    #[arg(long, value_name = "PATH")]
+    new_key_file: PathBuf,
+    /// Drop the values that cannot be decrypted with the current key instead of aborting.
+    #[arg(long)]
+    force: bool,
+    /// Show what would change without changing the env file.
+    #[arg(long)]
+    dry_run: bool,
+    /// Do not back up the env file to `<file>.<timestamp>.bak` first.
+    #[arg(long)]
+    no_backup: bool,
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc rotate`.
+///
+/// Every value is decrypted with the current key and encrypted again with the new one. The env
+/// file is rewritten atomically, so a failed rotation leaves it untouched.
+pub(crate) fn run(args: RotateArgs) -> CliResult {
+    let path = &args.file.file;
+    let cipher = args.key.cipher;
+    let old_key = args.key.load(path)?;
+    let mode = Mode::from_flags(args.dry_run, args.no_backup);
+    let generate = !args.new_key_file.exists();
+    let new_key = match generate {
+        true => keys_generation(cipher)?.0,
+        false => KeySource::file(&args.new_key_file).load(cipher)?,
+    };
+    // The new key is saved before the values are encrypted with it, so that it cannot be lost.
+    let save_key = generate && !matches!(mode, Mode::DryRun);
+    if save_key {
+        write_key_file(&args.new_key_file, &new_key, false)?;
+    }
+
+    let options = RotateOptions {
+        force: args.force,
+        ..RotateOptions::default()
+    };
+    let report = backup::rewrite(path, mode, |path| {
+        rotate_keys(&old_key, &new_key, cipher, path, options)
+    });
+    let report = match report {
+        Ok(report) => report,
+        Err(err) => {
+            if save_key {
+                let _ = fs::remove_file(&args.new_key_file);
+            }
+            return Err(err.into());
+        }
+    };
+    if save_key {
+        eprintln!("Wrote the new key to {}.", args.new_key_file.display());
+    }
+
+    let mut rows: Vec<(String, String)> = Vec::new();
+    rows.extend(
+        report
+            .rotated
+            .iter()
+            .map(|name| (name.clone(), "re-encrypted".to_string())),
+    );
+    rows.extend(report.skipped.iter().map(|(name, reason)| match reason {
+        SkipReason::InvalidHex | SkipReason::TooShort => {
+            (name.clone(), "kept (plain text)".to_string())
+        }
+        reason => (name.clone(), format!("kept ({})", reason)),
+    }));
+    rows.extend(report.dropped.iter().map(|err| match err {
+        EnvEncError::DecryptionFailed { var } | EnvEncError::InvalidUtf8 { var } => {
+            (var.clone(), "dropped (cannot be decrypted)".to_string())
+        }
+        err => ("-".to_string(), format!("dropped ({})", err)),
+    }));
+    print_table(&rows);
+    eprintln!(
+        "{} {} from key {} to {}.",
+        match mode {
+            Mode::DryRun => "Would rotate",
+            _ => "Rotated",
+        },
+        path.display(),
+        key_fingerprint(&old_key),
+        match generate {
+            true if !save_key => "a new key".to_string(),
+            _ => format!("key {}", key_fingerprint(&new_key)),
+        }
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints `rows` of variable names and what happened to them as a two-column table.
+pub(crate) fn print_table(rows: &[(String, String)]) {
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.len())
+        .chain(["VARIABLE".len()])
+        .max()
+        .unwrap_or_default();
+    println!("{:width$}  CHANGE", "VARIABLE");
+    for (name, change) in rows {
+        println!("{:width$}  {}", name, change);
+    }
+}
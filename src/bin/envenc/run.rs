@@ -0,0 +1,76 @@
+use crate::{
+    args::{FileArgs, KeyArgs},
+    CliResult,
+};
+use clap::Args;
+use std::{ffi::OsString, process::Command};
+
+/// The arguments of `envenc run`.
+#[derive(Args)]
+pub(crate) struct RunArgs {
+    /// Pass only these variables, separated by commas.
+    #[arg(long, value_name = "VARS", value_delimiter = ',')]
+    only: Vec<String>,
+    /// Pass only the variables whose name starts with this prefix.
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Start the command with only the decrypted variables, instead of adding them to the
+    /// inherited environment.
+    #[arg(long)]
+    isolate: bool,
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+    /// The command to run and its arguments, after `--`.
+    #[arg(last = true, required = true, value_name = "COMMAND")]
+    command: Vec<OsString>,
+}
+
+/// Runs `envenc run`.
+///
+/// The decrypted variables are passed to the child process only, and never set in the
+/// environment of this process. On Unix the command replaces this process, so it receives
+/// signals directly and its exit status, including death by a signal, is the status of
+/// `envenc run`. Elsewhere the command is spawned and its exit code is passed on.
+pub(crate) fn run(args: RunArgs) -> CliResult {
+    let path = &args.file.file;
+    let mut vars = args.key.session(path)?.decrypt_to_map()?;
+    if let Some(missing) = args.only.iter().find(|name| !vars.contains_key(*name)) {
+        return Err(format!("{} is not set in {}", missing, path.display()).into());
+    }
+    vars.retain(|name, _| {
+        (args.only.is_empty() || args.only.contains(name))
+            && args
+                .prefix
+                .as_ref()
+                .is_none_or(|prefix| name.starts_with(prefix))
+    });
+
+    let (program, program_args) = args.command.split_first().expect("clap requires a command");
+    let mut command = Command::new(program);
+    command.args(program_args);
+    if args.isolate {
+        command.env_clear();
+    }
+    command.envs(&vars);
+    exec(command, program)
+}
+
+/// Replaces this process with `command`, returning only if it cannot be started.
+#[cfg(unix)]
+fn exec(mut command: Command, program: &OsString) -> CliResult {
+    use std::os::unix::process::CommandExt;
+
+    let err = command.exec();
+    Err(format!("cannot run {}: {}", program.to_string_lossy(), err).into())
+}
+
+/// Runs `command` and exits with its exit code.
+#[cfg(not(unix))]
+fn exec(mut command: Command, program: &OsString) -> CliResult {
+    let status = command
+        .status()
+        .map_err(|err| format!("cannot run {}: {}", program.to_string_lossy(), err))?;
+    std::process::exit(status.code().unwrap_or(1))
+}
@@ -0,0 +1,82 @@
+use crate::CliResult;
+use clap::Args;
+use envenc::{scan_for_plaintext_secrets_with, ScanRules, SecretPattern};
+use serde_json::json;
+use std::{path::PathBuf, process::ExitCode};
+
+/// The arguments of `envenc scan`.
+#[derive(Args)]
+pub(crate) struct ScanArgs {
+    /// The env files to scan, such as the staged files a pre-commit hook is given.
+    #[arg(default_value = ".env")]
+    files: Vec<PathBuf>,
+    /// Also flag values that start with this prefix, such as the token prefix of an internal
+    /// service. May be given more than once.
+    #[arg(long, value_name = "PREFIX")]
+    prefix: Vec<String>,
+    /// Do not flag values for their entropy, only for known prefixes and URL credentials.
+    #[arg(long)]
+    no_entropy: bool,
+    /// Print the findings as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+/// Runs `envenc scan`, which exits with a failure if any file holds a value that looks like a
+/// plaintext secret, so that a pre-commit hook stops the commit.
+///
+/// Findings show a redacted preview of the value, never the value itself.
+pub(crate) fn run(args: ScanArgs) -> CliResult {
+    let mut rules = ScanRules::default();
+    for prefix in &args.prefix {
+        let name = format!("secret with prefix {}", prefix);
+        rules.patterns.push(SecretPattern::prefix(name, prefix));
+    }
+    if args.no_entropy {
+        rules.min_entropy_len = 0;
+    }
+
+    let mut found = false;
+    let mut files = Vec::new();
+    for path in &args.files {
+        let findings = scan_for_plaintext_secrets_with(path, &rules)
+            .map_err(|err| format!("cannot scan {}: {}", path.display(), err))?;
+        found |= !findings.is_empty();
+        match args.json {
+            true => files.push(json!({
+                "file": path.display().to_string(),
+                "findings": findings
+                    .iter()
+                    .map(|finding| json!({
+                        "line": finding.line,
+                        "name": finding.var,
+                        "detector": finding.detector,
+                        "preview": finding.preview,
+                    }))
+                    .collect::<Vec<_>>(),
+            })),
+            false => {
+                for finding in &findings {
+                    println!(
+                        "{}:{}: {} looks like a plaintext {}: {}",
+                        path.display(),
+                        finding.line,
+                        finding.var,
+                        finding.detector,
+                        finding.preview
+                    );
+                }
+            }
+        }
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&files)?);
+    } else if found {
+        eprintln!("Encrypt these values with `envenc set` before committing.");
+    }
+    match found {
+        true => Ok(ExitCode::FAILURE),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{
+    args::{FileArgs, KeyArgs},
+    CliResult,
+};
+use clap::Args;
+use envenc::{set_plain_env_in, Overwrite, SetOutcome};
+use std::{io::Read, process::ExitCode};
+
+/// The arguments of `envenc set`.
+#[derive(Args)]
+pub(crate) struct SetArgs {
+    /// The name of the variable.
+    name: String,
+    /// The value to encrypt. Prefer `--stdin` or `--prompt` for secrets, which keep the value out
+    /// of shell history files.
+    #[arg(required_unless_present_any = ["stdin", "prompt"])]
+    value: Option<String>,
+    /// Read the value from stdin, without a single trailing newline.
+    #[arg(long, conflicts_with_all = ["value", "prompt"])]
+    stdin: bool,
+    /// Prompt for the value on the terminal without echoing it.
+    #[arg(long, conflicts_with = "value")]
+    prompt: bool,
+    /// Replace the value if the variable is already set.
+    #[arg(long)]
+    overwrite: bool,
+    /// Store the value as plain text rather than encrypted, for a setting that is not secret,
+    /// such as `RUST_LOG`. No key is needed.
+    #[arg(long)]
+    plain: bool,
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc set`.
+pub(crate) fn run(args: SetArgs) -> CliResult {
+    let value = match (args.value, args.stdin) {
+        (Some(value), _) => value,
+        (None, true) => {
+            let mut value = String::new();
+            std::io::stdin().read_to_string(&mut value)?;
+            let trimmed = value.strip_suffix('\n').unwrap_or(&value);
+            trimmed.strip_suffix('\r').unwrap_or(trimmed).to_string()
+        }
+        (None, false) => rpassword::prompt_password(format!("Value of {}: ", args.name))?,
+    };
+    let path = &args.file.file;
+    let overwrite = match args.overwrite {
+        true => Overwrite::IfChanged,
+        false => Overwrite::No,
+    };
+    let outcome = match args.plain {
+        true => set_plain_env_in(path, &args.name, &value, overwrite)?,
+        false => args
+            .key
+            .session(path)?
+            .upsert(&args.name, &value, overwrite)?,
+    };
+    match outcome {
+        SetOutcome::AlreadyExists => Err(format!(
+            "{} is already set in {}; pass --overwrite to replace it",
+            args.name,
+            path.display()
+        )
+        .into()),
+        SetOutcome::Created => {
+            eprintln!("Set {} in {}.", args.name, path.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        SetOutcome::Updated => {
+            eprintln!("Updated {} in {}.", args.name, path.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        SetOutcome::Unchanged => {
+            eprintln!(
+                "{} in {} already has this value.",
+                args.name,
+                path.display()
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{args::FileArgs, CliResult};
+use clap::Args;
+use envenc::{list_enc_env, remove_enc_env_in, RemoveOptions};
+use std::process::ExitCode;
+
+/// The arguments of `envenc unset`.
+#[derive(Args)]
+pub(crate) struct UnsetArgs {
+    /// The names of the variables to remove.
+    #[arg(required_unless_present = "all", conflicts_with = "all")]
+    names: Vec<String>,
+    /// Remove every variable, keeping comments and the header of the file.
+    #[arg(long, requires = "yes")]
+    all: bool,
+    /// Confirm `--all`.
+    #[arg(long)]
+    yes: bool,
+    #[command(flatten)]
+    file: FileArgs,
+}
+
+/// Runs `envenc unset`.
+///
+/// No key is needed. Every name is checked before anything is removed, so a misspelled name
+/// leaves the env file unchanged.
+pub(crate) fn run(args: UnsetArgs) -> CliResult {
+    let path = &args.file.file;
+    if !path.exists() {
+        return Err(format!("{} does not exist", path.display()).into());
+    }
+    let stored: Vec<String> = list_enc_env(path)?
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+    let names = match args.all {
+        true => stored,
+        false => {
+            let missing: Vec<&String> = args
+                .names
+                .iter()
+                .filter(|name| !stored.contains(name))
+                .collect();
+            if let Some(name) = missing.first() {
+                return Err(format!("{} is not set in {}", name, path.display()).into());
+            }
+            args.names
+        }
+    };
+
+    let mut removed = Vec::new();
+    for name in &names {
+        if remove_enc_env_in(path, name, RemoveOptions::default())? {
+            removed.push(name.as_str());
+        }
+    }
+    match removed.is_empty() {
+        true => eprintln!("No variables to remove from {}.", path.display()),
+        false => eprintln!("Removed {} from {}.", removed.join(", "), path.display()),
+    }
+    Ok(ExitCode::SUCCESS)
+}
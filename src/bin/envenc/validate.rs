@@ -0,0 +1,70 @@
+use crate::{
+    args::{FileArgs, KeyArgs},
+    CliResult,
+};
+use clap::Args;
+use envenc::{validate_against_schema, DecryptedEnv, Schema, DEFAULT_SCHEMA_FILE};
+use serde_json::json;
+use std::{fs, path::PathBuf, process::ExitCode};
+
+/// The arguments of `envenc validate`.
+#[derive(Args)]
+pub(crate) struct ValidateArgs {
+    /// The schema file declaring the expected variables.
+    #[arg(long, default_value = DEFAULT_SCHEMA_FILE)]
+    schema: PathBuf,
+    /// Write a `.env.example` for the schema to this path, instead of validating the env file.
+    #[arg(long, value_name = "PATH")]
+    write_example: Option<PathBuf>,
+    /// Print the violations as JSON.
+    #[arg(long)]
+    json: bool,
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc validate`, which exits with a failure if the decrypted variables of the env
+/// file break a rule of the schema, for CI and deploys.
+///
+/// Violations name the variable and the rule, never a value.
+pub(crate) fn run(args: ValidateArgs) -> CliResult {
+    let schema = Schema::from_file(&args.schema)
+        .map_err(|err| format!("cannot read {}: {}", args.schema.display(), err))?;
+    if let Some(example) = &args.write_example {
+        fs::write(example, schema.env_example())?;
+        eprintln!("wrote {}", example.display());
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let path = &args.file.file;
+    let env = DecryptedEnv::from(args.key.session(path)?.decrypt_to_map()?);
+    let violations = validate_against_schema(&env, &schema);
+    if args.json {
+        let output = json!({
+            "file": path.display().to_string(),
+            "schema": args.schema.display().to_string(),
+            "ok": violations.is_empty(),
+            "violations": violations
+                .iter()
+                .map(|violation| json!({
+                    "name": violation.var,
+                    "rule": violation.rule.to_string(),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        for violation in &violations {
+            println!("{}", violation);
+        }
+        if violations.is_empty() {
+            println!("{} satisfies {}", path.display(), args.schema.display());
+        }
+    }
+    match violations.is_empty() {
+        true => Ok(ExitCode::SUCCESS),
+        false => Ok(ExitCode::FAILURE),
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{
+    args::{FileArgs, KeyArgs},
+    CliResult,
+};
+use clap::Args;
+use envenc::{verify_env, verify_env_file, CheckStatus, EnvEncError, VerifyReport, VerifyingKey};
+use serde_json::json;
+use std::process::ExitCode;
+
+/// The arguments of `envenc status` and `envenc verify`.
+#[derive(Args)]
+pub(crate) struct VerifyArgs {
+    /// Print the report as JSON.
+    #[arg(long)]
+    json: bool,
+    /// Check the signature of the env file with this Ed25519 verifying key, as base64.
+    #[arg(long, value_name = "KEY")]
+    verifying_key: Option<VerifyingKey>,
+    #[command(flatten)]
+    file: FileArgs,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Runs `envenc status`, or `envenc verify` if `gate` is set, which exits with a failure unless
+/// every check passes.
+///
+/// The report lists variable names and check results only, never a decrypted value.
+pub(crate) fn run(args: VerifyArgs, gate: bool) -> CliResult {
+    let path = &args.file.file;
+    let key = args.key.load(path)?;
+    let mut report = verify_env(path, args.key.cipher, &key)?;
+    if let Some(verifying_key) = &args.verifying_key {
+        report.signature = match verify_env_file(path, verifying_key) {
+            Ok(()) => CheckStatus::Valid,
+            Err(EnvEncError::MissingSignature { .. } | EnvEncError::InvalidSignature { .. }) => {
+                CheckStatus::Invalid
+            }
+            Err(err) => return Err(err.into()),
+        };
+    }
+
+    match args.json {
+        true => println!("{}", to_json(&report, &path.display().to_string())),
+        false => print_text(&report, &path.display().to_string()),
+    }
+    match (gate, report.is_ok()) {
+        (true, false) => Ok(ExitCode::FAILURE),
+        _ => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// Returns the name of the variable `err` is about, for the errors a `VerifyReport` lists.
+fn failed_var(err: &EnvEncError) -> Option<&str> {
+    match err {
+        EnvEncError::DecryptionFailed { var }
+        | EnvEncError::InvalidUtf8 { var }
+        | EnvEncError::InvalidEnvelope { var, .. } => Some(var),
+        _ => None,
+    }
+}
+
+fn print_text(report: &VerifyReport, file: &str) {
+    println!(
+        "File:         {}{}",
+        file,
+        if report.sealed { " (sealed)" } else { "" }
+    );
+    println!(
+        "Variables:    {} ({} decrypted, {} plain, {} skipped, {} failed)",
+        report.variables,
+        report.decrypted.len(),
+        report.plain.len(),
+        report.skipped.len(),
+        report.failed.len()
+    );
+    println!("Fingerprint:  {}", report.fingerprint);
+    println!("Data key:     {}", report.data_key);
+    println!("MAC:          {}", report.mac);
+    println!("Signature:    {}", report.signature);
+    if !report.failed.is_empty() {
+        println!("Failed:");
+        for err in &report.failed {
+            println!("  {}", err);
+        }
+    }
+    if !report.plain.is_empty() {
+        println!("Plain:        {}", report.plain.join(", "));
+    }
+    if !report.skipped.is_empty() {
+        println!("Skipped:");
+        for (name, reason) in &report.skipped {
+            println!("  {} ({})", name, reason);
+        }
+    }
+    println!(
+        "Result:       {}",
+        if report.is_ok() { "ok" } else { "FAILED" }
+    );
+}
+
+fn to_json(report: &VerifyReport, file: &str) -> serde_json::Value {
+    json!({
+        "file": file,
+        "ok": report.is_ok(),
+        "sealed": report.sealed,
+        "variables": report.variables,
+        "fingerprint": report.fingerprint.name(),
+        "data_key": report.data_key.name(),
+        "mac": report.mac.name(),
+        "signature": report.signature.name(),
+        "decrypted": report.decrypted,
+        "plain": report.plain,
+        "skipped": report
+            .skipped
+            .iter()
+            .map(|(name, reason)| json!({ "name": name, "reason": reason.to_string() }))
+            .collect::<Vec<_>>(),
+        "failed": report
+            .failed
+            .iter()
+            .map(|err| json!({ "name": failed_var(err), "error": err.to_string() }))
+            .collect::<Vec<_>>(),
+    })
+}
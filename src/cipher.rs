@@ -0,0 +1,484 @@
+use crate::{validate_key, CipherType, EnvEncError, Key};
+use aead::{generic_array::typenum::Unsigned, Aead, AeadInPlace, KeyInit, Payload};
+use aes_gcm::{Aes128Gcm, Aes256Gcm, Key as AesKey, Nonce as AesNonce};
+use aes_gcm_siv::Aes256GcmSiv;
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key as ChaChaKey, Nonce as ChaChaNonce, XChaCha20Poly1305, XNonce,
+};
+use std::sync::{Mutex, PoisonError};
+
+/// An AEAD cipher that values can be encrypted with.
+///
+/// The built-in ciphers are available through `CipherType`, which implements this trait.
+/// Implement it for your own type to use another algorithm with an `EnvEnc` session, through
+/// `EnvEncBuilder::custom_cipher`.
+///
+/// Key and nonce lengths are checked against `key_size` and `nonce_size` before `seal` and
+/// `open` are called, so implementations can assume they are correct. The associated data
+/// passed to both must be authenticated along with the ciphertext; envenc uses it to bind each
+/// value to the name of its variable.
+///
+/// # Example
+///
+/// ```
+/// use aes_gcm_siv::aead::{Aead, KeyInit, Payload};
+/// use aes_gcm_siv::{Aes128GcmSiv, Nonce};
+/// use envenc::{read_env_enc_from, EnvCipher, EnvEnc, EnvEncError};
+///
+/// struct Aes128GcmSivCipher;
+///
+/// impl EnvCipher for Aes128GcmSivCipher {
+///     fn name(&self) -> &str {
+///         "AES128GCMSIV"
+///     }
+///
+///     fn key_size(&self) -> usize {
+///         16
+///     }
+///
+///     fn nonce_size(&self) -> usize {
+///         12
+///     }
+///
+///     fn seal(&self, key: &[u8], nonce: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, EnvEncError> {
+///         let cipher = Aes128GcmSiv::new_from_slice(key).map_err(|_| EnvEncError::EncryptionFailed)?;
+///         cipher
+///             .encrypt(Nonce::from_slice(nonce), Payload { msg: plaintext, aad })
+///             .map_err(|_| EnvEncError::EncryptionFailed)
+///     }
+///
+///     fn open(&self, key: &[u8], nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, EnvEncError> {
+///         let cipher = Aes128GcmSiv::new_from_slice(key).map_err(|_| EnvEncError::AuthenticationFailed)?;
+///         cipher
+///             .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+///             .map_err(|_| EnvEncError::AuthenticationFailed)
+///     }
+/// }
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let mut session = EnvEnc::builder()
+///     .custom_cipher(Aes128GcmSivCipher)
+///     .key([7u8; 16])
+///     .env_file(&path)
+///     .build()?;
+///
+/// session.set("API_KEY", "super_secret_api_key")?;
+/// assert!(read_env_enc_from(&path)?["API_KEY"].starts_with("ENC[v2:AES128GCMSIV:"));
+/// assert_eq!(session.get("API_KEY")?.as_deref(), Some("super_secret_api_key"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait EnvCipher: Send + Sync {
+    /// The name recorded in the envelope of every value encrypted with this cipher. It must
+    /// not contain `:` or `]`, and should not clash with the names of the built-in ciphers.
+    fn name(&self) -> &str;
+
+    /// The key size in bytes.
+    fn key_size(&self) -> usize;
+
+    /// The nonce size in bytes.
+    fn nonce_size(&self) -> usize;
+
+    /// Encrypts `plaintext` and authenticates it together with `aad`, returning the ciphertext
+    /// with its authentication tag.
+    ///
+    /// # Errors
+    ///
+    /// Should return `EnvEncError::EncryptionFailed` if the plaintext cannot be encrypted.
+    fn seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, EnvEncError>;
+
+    /// Decrypts `ciphertext` and authenticates it together with `aad`.
+    ///
+    /// # Errors
+    ///
+    /// Should return `EnvEncError::AuthenticationFailed` if the ciphertext cannot be
+    /// authenticated.
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, EnvEncError>;
+
+    /// Returns the built-in cipher this is, if it is one, so that operations over a whole env
+    /// file prepare each key once with `CipherContext` rather than for every value. Custom
+    /// ciphers keep the default, `None`.
+    fn builtin(&self) -> Option<CipherType> {
+        None
+    }
+}
+
+impl EnvCipher for CipherType {
+    fn name(&self) -> &str {
+        self.as_str()
+    }
+
+    fn key_size(&self) -> usize {
+        CipherType::key_size(self)
+    }
+
+    fn nonce_size(&self) -> usize {
+        CipherType::nonce_size(self)
+    }
+
+    fn seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, EnvEncError> {
+        CipherContext::new(*self, key)?.seal(nonce, plaintext, aad)
+    }
+
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, EnvEncError> {
+        CipherContext::new(*self, key)?.open(nonce, ciphertext, aad)
+    }
+
+    fn builtin(&self) -> Option<CipherType> {
+        Some(*self)
+    }
+}
+
+/// A built-in cipher with its key prepared, including the AES key schedule, so that many
+/// values can be encrypted and decrypted under the key without preparing it for each.
+///
+/// `encrypt`, `decrypt`, and the other functions that handle a single value prepare the key
+/// on every call. Sessions and the functions that handle a whole env file, such as
+/// `decrypt_env_to_map`, `rotate_keys`, and `encrypt_existing_env`, prepare each key once
+/// and reuse it for every value. Use a context directly when encrypting or decrypting many
+/// values by hand.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, CipherContext, CipherType, EnvEncError};
+///
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, nonce) = keys_generation(cipher_type)?;
+/// let context = CipherContext::new(cipher_type, &key)?;
+///
+/// let ciphertext = context.seal(&nonce, b"sk_live_123", b"API_KEY")?;
+/// assert_eq!(context.open(&nonce, &ciphertext, b"API_KEY")?, b"sk_live_123");
+/// // The associated data is authenticated along with the ciphertext.
+/// assert!(matches!(
+///     context.open(&nonce, &ciphertext, b"OTHER_KEY"),
+///     Err(EnvEncError::AuthenticationFailed)
+/// ));
+/// # Ok::<(), EnvEncError>(())
+/// ```
+#[derive(Clone)]
+pub struct CipherContext {
+    cipher_type: CipherType,
+    aead: PreparedAead,
+}
+
+/// The cipher of a `CipherContext`, initialized with its key.
+#[derive(Clone)]
+enum PreparedAead {
+    XChaCha20Poly1305(XChaCha20Poly1305),
+    ChaCha20Poly1305(ChaCha20Poly1305),
+    Aes256Gcm(Aes256Gcm),
+    Aes128Gcm(Aes128Gcm),
+    Aes256GcmSiv(Aes256GcmSiv),
+}
+
+impl CipherContext {
+    /// Prepares `key` for `cipher_type`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidKeyLength` if `key` does not match `cipher_type`.
+    pub fn new(cipher_type: CipherType, key: impl AsRef<[u8]>) -> Result<Self, EnvEncError> {
+        let key = key.as_ref();
+        validate_key(&cipher_type, key)?;
+        let aead =
+            match cipher_type {
+                CipherType::XChaCha20Poly1305 => PreparedAead::XChaCha20Poly1305(
+                    XChaCha20Poly1305::new(ChaChaKey::from_slice(key)),
+                ),
+                CipherType::ChaCha20Poly1305 => PreparedAead::ChaCha20Poly1305(
+                    ChaCha20Poly1305::new(ChaChaKey::from_slice(key)),
+                ),
+                CipherType::AES256GCM => {
+                    PreparedAead::Aes256Gcm(Aes256Gcm::new(AesKey::<Aes256Gcm>::from_slice(key)))
+                }
+                CipherType::AES128GCM => {
+                    PreparedAead::Aes128Gcm(Aes128Gcm::new(AesKey::<Aes128Gcm>::from_slice(key)))
+                }
+                CipherType::AES256GCMSIV => PreparedAead::Aes256GcmSiv(Aes256GcmSiv::new(
+                    AesKey::<Aes256GcmSiv>::from_slice(key),
+                )),
+            };
+        Ok(CipherContext { cipher_type, aead })
+    }
+
+    /// Returns the cipher of the context.
+    pub fn cipher_type(&self) -> CipherType {
+        self.cipher_type
+    }
+
+    /// Encrypts `plaintext` and authenticates it together with `aad`, returning the ciphertext
+    /// with its authentication tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidNonceLength` if `nonce` does not match the cipher, and
+    /// `EnvEncError::EncryptionFailed` if the plaintext cannot be encrypted.
+    pub fn seal(&self, nonce: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, EnvEncError> {
+        self.check_nonce(nonce)?;
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        let result = match &self.aead {
+            PreparedAead::XChaCha20Poly1305(cipher) => {
+                cipher.encrypt(XNonce::from_slice(nonce), payload)
+            }
+            PreparedAead::ChaCha20Poly1305(cipher) => {
+                cipher.encrypt(ChaChaNonce::from_slice(nonce), payload)
+            }
+            PreparedAead::Aes256Gcm(cipher) => cipher.encrypt(AesNonce::from_slice(nonce), payload),
+            PreparedAead::Aes128Gcm(cipher) => cipher.encrypt(AesNonce::from_slice(nonce), payload),
+            PreparedAead::Aes256GcmSiv(cipher) => {
+                cipher.encrypt(AesNonce::from_slice(nonce), payload)
+            }
+        };
+        result.map_err(|_| EnvEncError::EncryptionFailed)
+    }
+
+    /// Decrypts `ciphertext` and authenticates it together with `aad`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidNonceLength` if `nonce` does not match the cipher, and
+    /// `EnvEncError::AuthenticationFailed` if the ciphertext cannot be authenticated.
+    pub fn open(
+        &self,
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, EnvEncError> {
+        self.check_nonce(nonce)?;
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        let result = match &self.aead {
+            PreparedAead::XChaCha20Poly1305(cipher) => {
+                cipher.decrypt(XNonce::from_slice(nonce), payload)
+            }
+            PreparedAead::ChaCha20Poly1305(cipher) => {
+                cipher.decrypt(ChaChaNonce::from_slice(nonce), payload)
+            }
+            PreparedAead::Aes256Gcm(cipher) => cipher.decrypt(AesNonce::from_slice(nonce), payload),
+            PreparedAead::Aes128Gcm(cipher) => cipher.decrypt(AesNonce::from_slice(nonce), payload),
+            PreparedAead::Aes256GcmSiv(cipher) => {
+                cipher.decrypt(AesNonce::from_slice(nonce), payload)
+            }
+        };
+        result.map_err(|_| EnvEncError::AuthenticationFailed)
+    }
+
+    /// Checks that `nonce` has the length the cipher requires.
+    fn check_nonce(&self, nonce: &[u8]) -> Result<(), EnvEncError> {
+        match nonce.len() == self.cipher_type.nonce_size() {
+            true => Ok(()),
+            false => Err(EnvEncError::InvalidNonceLength {
+                cipher: self.cipher_type.to_string(),
+                expected: self.cipher_type.nonce_size(),
+                actual: nonce.len(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Debug for CipherContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CipherContext")
+            .field("cipher_type", &self.cipher_type)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The cipher that operations over a whole env file encrypt and decrypt its values with. It
+/// delegates to `cipher`, but prepares the key of a built-in cipher once with
+/// `CipherContext` and reuses it for as long as the next value uses the same key. Keys
+/// derived for each variable change from value to value, so only the last key is kept.
+pub(crate) struct BatchCipher<'a> {
+    cipher: &'a dyn EnvCipher,
+    context: Mutex<Option<(Key, CipherContext)>>,
+}
+
+impl<'a> BatchCipher<'a> {
+    /// Returns a cipher over `cipher` that has prepared no key yet.
+    pub(crate) fn new(cipher: &'a dyn EnvCipher) -> Self {
+        BatchCipher {
+            cipher,
+            context: Mutex::new(None),
+        }
+    }
+
+    /// Calls `f` with the context of `cipher_type` for `key`, preparing it unless it is the
+    /// one the last value used.
+    fn with_context<T>(
+        &self,
+        cipher_type: CipherType,
+        key: &[u8],
+        f: impl FnOnce(&CipherContext) -> Result<T, EnvEncError>,
+    ) -> Result<T, EnvEncError> {
+        let mut slot = self.context.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((prepared, context)) = &*slot {
+            if context.cipher_type() == cipher_type && prepared.as_bytes() == key {
+                return f(context);
+            }
+        }
+        let context = CipherContext::new(cipher_type, key)?;
+        let result = f(&context);
+        *slot = Some((Key::from_bytes(key), context));
+        result
+    }
+}
+
+impl EnvCipher for BatchCipher<'_> {
+    fn name(&self) -> &str {
+        self.cipher.name()
+    }
+
+    fn key_size(&self) -> usize {
+        self.cipher.key_size()
+    }
+
+    fn nonce_size(&self) -> usize {
+        self.cipher.nonce_size()
+    }
+
+    fn seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, EnvEncError> {
+        match self.cipher.builtin() {
+            Some(cipher_type) => self.with_context(cipher_type, key, |context| {
+                context.seal(nonce, plaintext, aad)
+            }),
+            None => self.cipher.seal(key, nonce, plaintext, aad),
+        }
+    }
+
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, EnvEncError> {
+        match self.cipher.builtin() {
+            Some(cipher_type) => self.with_context(cipher_type, key, |context| {
+                context.open(nonce, ciphertext, aad)
+            }),
+            None => self.cipher.open(key, nonce, ciphertext, aad),
+        }
+    }
+
+    fn builtin(&self) -> Option<CipherType> {
+        self.cipher.builtin()
+    }
+}
+
+impl CipherType {
+    /// Encrypts `plaintext`, returning the ciphertext and the authentication tag separately.
+    pub(crate) fn seal_detached(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        plaintext: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), EnvEncError> {
+        match self {
+            CipherType::XChaCha20Poly1305 => {
+                seal_detached_with::<XChaCha20Poly1305>(key, nonce, plaintext)
+            }
+            CipherType::ChaCha20Poly1305 => {
+                seal_detached_with::<ChaCha20Poly1305>(key, nonce, plaintext)
+            }
+            CipherType::AES256GCM => seal_detached_with::<Aes256Gcm>(key, nonce, plaintext),
+            CipherType::AES128GCM => seal_detached_with::<Aes128Gcm>(key, nonce, plaintext),
+            CipherType::AES256GCMSIV => seal_detached_with::<Aes256GcmSiv>(key, nonce, plaintext),
+        }
+    }
+
+    /// Decrypts `ciphertext` and authenticates it against the detached `tag`.
+    pub(crate) fn open_detached(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        ciphertext: &[u8],
+        tag: &[u8],
+    ) -> Result<Vec<u8>, EnvEncError> {
+        match self {
+            CipherType::XChaCha20Poly1305 => {
+                open_detached_with::<XChaCha20Poly1305>(key, nonce, ciphertext, tag)
+            }
+            CipherType::ChaCha20Poly1305 => {
+                open_detached_with::<ChaCha20Poly1305>(key, nonce, ciphertext, tag)
+            }
+            CipherType::AES256GCM => open_detached_with::<Aes256Gcm>(key, nonce, ciphertext, tag),
+            CipherType::AES128GCM => open_detached_with::<Aes128Gcm>(key, nonce, ciphertext, tag),
+            CipherType::AES256GCMSIV => {
+                open_detached_with::<Aes256GcmSiv>(key, nonce, ciphertext, tag)
+            }
+        }
+    }
+}
+
+/// Encrypts `plaintext` in place with the cipher `A`, detaching the authentication tag.
+fn seal_detached_with<A: AeadInPlace + KeyInit>(
+    key: &[u8],
+    nonce: &[u8],
+    plaintext: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), EnvEncError> {
+    let cipher = A::new_from_slice(key).map_err(|_| EnvEncError::EncryptionFailed)?;
+    let mut buffer = plaintext.to_vec();
+    let tag = cipher
+        .encrypt_in_place_detached(aead::Nonce::<A>::from_slice(nonce), &[], &mut buffer)
+        .map_err(|_| EnvEncError::EncryptionFailed)?;
+    Ok((buffer, tag.to_vec()))
+}
+
+/// Decrypts `ciphertext` in place with the cipher `A`, checking it against the detached `tag`.
+fn open_detached_with<A: AeadInPlace + KeyInit>(
+    key: &[u8],
+    nonce: &[u8],
+    ciphertext: &[u8],
+    tag: &[u8],
+) -> Result<Vec<u8>, EnvEncError> {
+    if tag.len() != A::TagSize::USIZE {
+        return Err(EnvEncError::AuthenticationFailed);
+    }
+    let cipher = A::new_from_slice(key).map_err(|_| EnvEncError::AuthenticationFailed)?;
+    let mut buffer = ciphertext.to_vec();
+    cipher
+        .decrypt_in_place_detached(
+            aead::Nonce::<A>::from_slice(nonce),
+            &[],
+            &mut buffer,
+            aead::Tag::<A>::from_slice(tag),
+        )
+        .map_err(|_| EnvEncError::AuthenticationFailed)?;
+    Ok(buffer)
+}
@@ -0,0 +1,121 @@
+use crate::{decrypt_file, CipherType, EnvEncError, DEFAULT_ENV_FILE};
+use std::{ffi::OsStr, path::Path, process::Command};
+
+/// Passes decrypted variables to a child process, without setting them in the environment of
+/// the current process.
+///
+/// The variables are added to the environment of the `Command` with `Command::envs`, so only
+/// the child sees them. Call `Command::env_clear` before to start the child with only the
+/// decrypted variables, and `Command::env` after to add or override variables.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, set_enc_env_in, CipherType, CommandEnvExt};
+/// use std::process::Command;
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "CHILD_API_KEY", "super_secret_api_key", cipher_type, &key)?;
+/// set_enc_env_in(&path, "CHILD_REGION", "eu-west-1", cipher_type, &key)?;
+///
+/// #[cfg(unix)]
+/// {
+///     let mut command = Command::new("printenv");
+///     command.env_clear().decrypted_envs_from(&path, cipher_type, &key)?.env("CHILD_REGION", "us-east-1");
+///     let output = String::from_utf8(command.output()?.stdout)?;
+///     let mut lines: Vec<&str> = output.lines().collect();
+///     lines.sort();
+///     assert_eq!(lines, ["CHILD_API_KEY=super_secret_api_key", "CHILD_REGION=us-east-1"]);
+/// }
+/// assert!(std::env::var("CHILD_API_KEY").is_err());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait CommandEnvExt {
+    /// Adds the decrypted variables of `.env` to the environment of the child process.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `decrypted_envs_from`.
+    fn decrypted_envs(
+        &mut self,
+        cipher_type: CipherType,
+        key: impl AsRef<[u8]>,
+    ) -> Result<&mut Self, EnvEncError>;
+
+    /// Adds the decrypted variables of the env file at `path` to the environment of the child
+    /// process. Entries that are not encrypted are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::KeyMismatch` if the file was written with another key,
+    /// `EnvEncError::InvalidDataKey` if its data key cannot be unwrapped with `key`,
+    /// `EnvEncError::DecryptionFailed` or `EnvEncError::InvalidUtf8` for the first variable
+    /// that could not be decrypted, and `EnvEncError::Io` if the file exists but cannot be
+    /// read. No variable is added in that case.
+    fn decrypted_envs_from(
+        &mut self,
+        path: impl AsRef<Path>,
+        cipher_type: CipherType,
+        key: impl AsRef<[u8]>,
+    ) -> Result<&mut Self, EnvEncError>;
+}
+
+impl CommandEnvExt for Command {
+    fn decrypted_envs(
+        &mut self,
+        cipher_type: CipherType,
+        key: impl AsRef<[u8]>,
+    ) -> Result<&mut Self, EnvEncError> {
+        self.decrypted_envs_from(DEFAULT_ENV_FILE, cipher_type, key)
+    }
+
+    fn decrypted_envs_from(
+        &mut self,
+        path: impl AsRef<Path>,
+        cipher_type: CipherType,
+        key: impl AsRef<[u8]>,
+    ) -> Result<&mut Self, EnvEncError> {
+        let (decrypted, report) = decrypt_file(path.as_ref(), cipher_type, key.as_ref())?;
+        report.into_result()?;
+        Ok(self.envs(
+            decrypted
+                .iter()
+                .map(|(name, value)| (name, value.expose_secret())),
+        ))
+    }
+}
+
+/// Builds a `Command` for `program` with `args` whose child process sees the decrypted
+/// variables of `.env`, like `dotenv run`, while the current process never has them in its
+/// environment.
+///
+/// The child also inherits the environment of the current process. To control which
+/// variables it sees, build the `Command` yourself and use `CommandEnvExt`.
+///
+/// # Errors
+///
+/// Returns the same errors as `CommandEnvExt::decrypted_envs_from`.
+///
+/// # Example
+///
+/// ```no_run
+/// use envenc::{command_with_env, keys_generation, CipherType};
+///
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// let status = command_with_env("./server", ["--port", "8080"], cipher_type, &key)?.status()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn command_with_env(
+    program: impl AsRef<OsStr>,
+    args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+) -> Result<Command, EnvEncError> {
+    let mut command = Command::new(program);
+    command.args(args).decrypted_envs(cipher_type, key)?;
+    Ok(command)
+}
@@ -0,0 +1,90 @@
+use crate::{CipherType, EnvEnc, EnvEncError, KeySource, DEFAULT_ENV_FILE};
+use std::path::PathBuf;
+
+/// Settings for an `EnvEnc` session that can be stored in an application's config file.
+///
+/// With the `serde` feature enabled, the config can be loaded with any serde format. Every
+/// field is optional: the cipher defaults to `AES256GCM`, the env file to `.env`, and the key
+/// is read from the `ENVENC_KEY` environment variable.
+///
+/// # Example
+///
+#[cfg_attr(feature = "serde", doc = "```")]
+#[cfg_attr(not(feature = "serde"), doc = "```ignore")]
+/// use envenc::{CipherType, EnvEncConfig, KeySource};
+///
+/// let config: EnvEncConfig = toml::from_str(r#"
+///     cipher = "xchacha20-poly1305"
+///     env_file = "config/secrets.env"
+///     key_source = { env_var = "APP_ENVENC_KEY" }
+/// "#)?;
+/// assert_eq!(config.cipher, CipherType::XChaCha20Poly1305);
+/// assert_eq!(config.key_source, KeySource::EnvVar("APP_ENVENC_KEY".to_string()));
+/// let round_trip: EnvEncConfig = toml::from_str(&toml::to_string(&config)?)?;
+/// assert_eq!(round_trip, config);
+///
+/// let defaults: EnvEncConfig = toml::from_str("")?;
+/// assert_eq!(defaults.cipher, CipherType::AES256GCM);
+/// assert_eq!(defaults.env_file, std::path::Path::new(".env"));
+/// assert_eq!(defaults.key_source, KeySource::EnvVar("ENVENC_KEY".to_string()));
+///
+/// let err = toml::from_str::<EnvEncConfig>(r#"
+///     cipher = "rot13"
+///     key_source = { env_var = "APP_ENVENC_KEY" }
+/// "#)
+/// .unwrap_err();
+/// assert!(err.to_string().contains("unknown cipher 'rot13', expected one of"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnvEncConfig {
+    /// The cipher used to encrypt new values.
+    #[cfg_attr(feature = "serde", serde(default = "default_cipher"))]
+    pub cipher: CipherType,
+    /// The path of the env file.
+    #[cfg_attr(feature = "serde", serde(default = "default_env_file"))]
+    pub env_file: PathBuf,
+    /// Where to load the encryption key from, by default the `ENVENC_KEY` environment
+    /// variable.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub key_source: KeySource,
+}
+
+impl EnvEncConfig {
+    /// Creates a config for the default cipher and env file, loading the key from `key_source`.
+    pub fn new(key_source: KeySource) -> Self {
+        EnvEncConfig {
+            cipher: default_cipher(),
+            env_file: default_env_file(),
+            key_source,
+        }
+    }
+
+    /// Loads the key and builds a session from this config.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `KeySource::load` and `EnvEncBuilder::build`.
+    pub fn build(&self) -> Result<EnvEnc, EnvEncError> {
+        EnvEnc::builder()
+            .cipher(self.cipher)
+            .key(self.key_source.load(self.cipher)?)
+            .env_file(&self.env_file)
+            .build()
+    }
+}
+
+impl Default for EnvEncConfig {
+    fn default() -> Self {
+        EnvEncConfig::new(KeySource::default())
+    }
+}
+
+fn default_cipher() -> CipherType {
+    CipherType::AES256GCM
+}
+
+fn default_env_file() -> PathBuf {
+    PathBuf::from(DEFAULT_ENV_FILE)
+}
@@ -0,0 +1,435 @@
+use crate::{
+    derive_key_from_passphrase, encode_stored_value,
+    env_file::{lock_env_file, parse_entries, read_contents, write_atomic, DEFAULT_LOCK_TIMEOUT},
+    random_bytes_from, validate_key, CipherType, EnvCipher, EnvEncError, Envelope, KdfKind,
+    KdfMetadata, KeyProvider, KeyStrategy,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::{thread_rng, RngCore};
+use std::{path::Path, sync::Arc};
+
+/// The prefix of the comment line that records the wrapped data key in an env file.
+const HEADER_PREFIX: &str = "# envenc dek:";
+
+/// The name the data key is authenticated under when it is wrapped, in place of a variable
+/// name.
+const WRAP_NAME: &str = "envenc dek";
+
+/// The prefix of a data key wrapped with a passphrase, before the key derivation metadata.
+const PASSPHRASE_PREFIX: &str = "passphrase:";
+
+/// The cipher a data key is wrapped with under a key derived from a passphrase.
+const PASSPHRASE_CIPHER: CipherType = CipherType::XChaCha20Poly1305;
+
+/// What the data key of an env file is wrapped with.
+#[derive(Clone, Copy)]
+pub(crate) enum KeyProtection<'a> {
+    /// A local key. The wrapped data key is recorded as an `Envelope`.
+    Key(&'a [u8]),
+    /// A key provider. The wrapped data key is recorded as the ID of the provider followed by
+    /// the wrapped data key in base64.
+    Provider(&'a dyn KeyProvider),
+    /// A key derived from a passphrase, with `kdf` when a data key is first wrapped. The
+    /// wrapped data key is recorded as `passphrase:` followed by the key derivation metadata
+    /// and an `Envelope`.
+    Passphrase(&'a str, KdfKind),
+}
+
+/// A `KeyProtection` other than a local key, as configured on a session.
+#[derive(Clone)]
+pub(crate) enum OwnedKeyProtection {
+    Provider(Arc<dyn KeyProvider>),
+    Passphrase(String, KdfKind),
+}
+
+impl OwnedKeyProtection {
+    pub(crate) fn as_protection(&self) -> KeyProtection<'_> {
+        match self {
+            OwnedKeyProtection::Provider(provider) => KeyProtection::Provider(&**provider),
+            OwnedKeyProtection::Passphrase(passphrase, kdf) => {
+                KeyProtection::Passphrase(passphrase, *kdf)
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for OwnedKeyProtection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OwnedKeyProtection::Provider(provider) => {
+                f.debug_tuple("Provider").field(&provider.id()).finish()
+            }
+            OwnedKeyProtection::Passphrase(_, kdf) => f
+                .debug_tuple("Passphrase")
+                .field(&"[REDACTED]")
+                .field(kdf)
+                .finish(),
+        }
+    }
+}
+
+/// Returns the wrapped data key recorded in the header line of `contents`.
+fn wrapped_data_key(contents: &str) -> Option<&str> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix(HEADER_PREFIX))
+        .map(str::trim)
+}
+
+/// Wraps `data_key` with `protection`, as recorded in the header line, drawing the nonce from
+/// `rng`. A local key wraps it with `cipher`.
+pub(crate) fn wrap_data_key(
+    cipher: &dyn EnvCipher,
+    protection: KeyProtection,
+    data_key: &[u8],
+    rng: &mut dyn RngCore,
+) -> Result<String, EnvEncError> {
+    let mut seal = |cipher: &dyn EnvCipher, key: &[u8]| {
+        let nonce = random_bytes_from(rng, cipher.nonce_size());
+        encode_stored_value(
+            cipher,
+            key,
+            None,
+            KeyStrategy::Direct,
+            WRAP_NAME,
+            &nonce,
+            data_key,
+        )
+    };
+    match protection {
+        KeyProtection::Key(key) => seal(cipher, key),
+        KeyProtection::Provider(provider) => {
+            let wrapped =
+                provider
+                    .wrap(data_key)
+                    .map_err(|reason| EnvEncError::KeyProviderFailed {
+                        provider: provider.id().to_string(),
+                        reason,
+                    })?;
+            Ok(format!("{} {}", provider.id(), STANDARD.encode(wrapped)))
+        }
+        KeyProtection::Passphrase(passphrase, kdf) => {
+            let (key, metadata) = derive_key_from_passphrase(passphrase, PASSPHRASE_CIPHER, kdf)?;
+            Ok(format!(
+                "{}{} {}",
+                PASSPHRASE_PREFIX,
+                metadata,
+                seal(&PASSPHRASE_CIPHER, &key)?
+            ))
+        }
+    }
+}
+
+/// Returns the data key recorded in the header line of `contents`, the env file at `path`,
+/// unwrapped with the first of `protections` that can unwrap it, or `None` if the file has no
+/// data key.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::KeyProviderRequired` if the data key is wrapped by a key provider that
+/// is not among `protections`, `EnvEncError::PassphraseRequired` if it is wrapped with a
+/// passphrase and none is among them, `EnvEncError::KeyProviderFailed` if the provider fails,
+/// and `EnvEncError::InvalidDataKey` if none of `protections` unwraps it.
+pub(crate) fn unwrap_data_key(
+    path: &Path,
+    contents: &str,
+    cipher: &dyn EnvCipher,
+    protections: &[KeyProtection],
+) -> Result<Option<Vec<u8>>, EnvEncError> {
+    let Some(wrapped) = wrapped_data_key(contents) else {
+        return Ok(None);
+    };
+    let invalid = || EnvEncError::InvalidDataKey {
+        path: path.to_path_buf(),
+    };
+
+    if Envelope::is_envelope(wrapped) {
+        let envelope = Envelope::parse_with(wrapped, Some(cipher)).map_err(|_| invalid())?;
+        return protections
+            .iter()
+            .find_map(|protection| match protection {
+                KeyProtection::Key(key) => envelope.open(cipher, key, WRAP_NAME).ok(),
+                _ => None,
+            })
+            .map(Some)
+            .ok_or_else(invalid);
+    }
+
+    if let Some(wrapped) = wrapped.strip_prefix(PASSPHRASE_PREFIX) {
+        let passphrase = protections
+            .iter()
+            .find_map(|protection| match protection {
+                KeyProtection::Passphrase(passphrase, _) => Some(*passphrase),
+                _ => None,
+            })
+            .ok_or_else(|| EnvEncError::PassphraseRequired {
+                path: path.to_path_buf(),
+            })?;
+        let (metadata, envelope) = wrapped.rsplit_once(' ').ok_or_else(invalid)?;
+        let metadata = KdfMetadata::parse(metadata).ok_or_else(invalid)?;
+        let envelope =
+            Envelope::parse_with(envelope, Some(&PASSPHRASE_CIPHER)).map_err(|_| invalid())?;
+        let key = metadata.derive_key(passphrase, PASSPHRASE_CIPHER)?;
+        return envelope
+            .open(&PASSPHRASE_CIPHER, &key, WRAP_NAME)
+            .map(Some)
+            .map_err(|_| invalid());
+    }
+
+    let (id, encoded) = wrapped.split_once(' ').ok_or_else(invalid)?;
+    let provider = protections
+        .iter()
+        .find_map(|protection| match protection {
+            KeyProtection::Provider(provider) if provider.id() == id => Some(*provider),
+            _ => None,
+        })
+        .ok_or_else(|| EnvEncError::KeyProviderRequired {
+            path: path.to_path_buf(),
+            provider: id.to_string(),
+        })?;
+    let blob = STANDARD.decode(encoded.trim()).map_err(|_| invalid())?;
+    let data_key = provider
+        .unwrap(&blob)
+        .map_err(|reason| EnvEncError::KeyProviderFailed {
+            provider: id.to_string(),
+            reason,
+        })?;
+    if data_key.len() != cipher.key_size() {
+        return Err(invalid());
+    }
+    Ok(Some(data_key))
+}
+
+/// Returns the key derivation function of the passphrase the data key recorded in the header
+/// line of `contents` is wrapped with, if it is wrapped with one.
+fn passphrase_kdf(contents: &str) -> Option<KdfKind> {
+    let wrapped = wrapped_data_key(contents)?.strip_prefix(PASSPHRASE_PREFIX)?;
+    let (metadata, _) = wrapped.rsplit_once(' ')?;
+    KdfMetadata::parse(metadata).map(|metadata| metadata.kind)
+}
+
+/// Returns `contents` with a header line recording `wrapped` as its first line, replacing any
+/// existing one. Every other line is kept as it is.
+pub(crate) fn with_data_key(contents: &str, wrapped: &str) -> String {
+    let mut updated = format!("{} {}\n", HEADER_PREFIX, wrapped);
+    for line in contents.split_inclusive('\n') {
+        if !line.starts_with(HEADER_PREFIX) {
+            updated.push_str(line);
+        }
+    }
+    updated
+}
+
+/// Returns the data key of the env file at `path`, whose contents are `contents`, generating
+/// one wrapped with `protection` if the file has none yet. The contents with the new header
+/// line are returned with it, to be written while the file is still locked, so concurrent
+/// writers agree on one data key.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::DataKeyConflict` if the file already holds values but no data key.
+pub(crate) fn ensure_data_key(
+    path: &Path,
+    contents: &str,
+    cipher: &dyn EnvCipher,
+    protection: KeyProtection,
+    rng: &mut dyn RngCore,
+) -> Result<(Vec<u8>, Option<String>), EnvEncError> {
+    if let Some(data_key) = unwrap_data_key(path, contents, cipher, &[protection])? {
+        return Ok((data_key, None));
+    }
+    if !parse_entries(contents).is_empty() {
+        return Err(EnvEncError::DataKeyConflict {
+            path: path.to_path_buf(),
+        });
+    }
+
+    let data_key = random_bytes_from(rng, cipher.key_size());
+    let wrapped = wrap_data_key(cipher, protection, &data_key, rng)?;
+    Ok((data_key, Some(with_data_key(contents, &wrapped))))
+}
+
+/// Re-wraps the data key of the env file at `path` from `old_kek` to `new_kek`.
+///
+/// An env file in data key mode, enabled with `EnvEncBuilder::data_key`, encrypts its values
+/// with a random data key that is itself encrypted by the master key, the key encryption key,
+/// and stored in a `# envenc dek: <wrapped key>` header line. Rotating the master key then only
+/// re-encrypts that header: the value lines are left untouched. The data key is wrapped with
+/// `cipher_type`.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::MissingDataKey` if the file has no data key,
+/// `EnvEncError::InvalidDataKey` if `old_kek` cannot unwrap it,
+/// `EnvEncError::KeyProviderRequired` if it is wrapped by a key provider,
+/// `EnvEncError::InvalidKeyLength` if `new_kek` does not match `cipher_type`, and
+/// `EnvEncError::Io` if the file cannot be read or written.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{
+///     decrypt_env_from, keys_generation, rewrap_dek, rotate_keys, set_enc_env_in, CipherType,
+///     EnvEnc, EnvEncError, RotateOptions,
+/// };
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (old_kek, _) = keys_generation(cipher_type)?;
+/// let (new_kek, _) = keys_generation(cipher_type)?;
+///
+/// let builder = EnvEnc::builder().cipher(cipher_type).env_file(&path);
+/// let mut session = builder.clone().key(old_kek.clone()).data_key(true).build()?;
+/// session.set("API_KEY", "secret")?;
+/// session.set("DATABASE_URL", "postgres://localhost/db")?;
+///
+/// let contents = std::fs::read_to_string(&path)?;
+/// assert!(contents.lines().any(|line| line.starts_with("# envenc dek: ENC[v2:AES256GCM:")));
+///
+/// rewrap_dek(&path, cipher_type, &old_kek, &new_kek)?;
+///
+/// // Only the data key header changed.
+/// let value_lines = |contents: &str| -> Vec<String> {
+///     contents.lines().filter(|line| !line.starts_with("# envenc dek:")).map(str::to_string).collect()
+/// };
+/// assert_eq!(value_lines(&std::fs::read_to_string(&path)?), value_lines(&contents));
+///
+/// let session = builder.clone().key(new_kek.clone()).build()?;
+/// assert_eq!(session.get("API_KEY")?.as_deref(), Some("secret"));
+/// assert!(matches!(builder.clone().key(old_kek).build(), Err(EnvEncError::InvalidDataKey { .. })));
+///
+/// // A full rotation re-encrypts the values under a fresh data key.
+/// let (rotated_kek, _) = keys_generation(cipher_type)?;
+/// rotate_keys(&new_kek, &rotated_kek, cipher_type, &path, RotateOptions::default())?;
+/// assert_eq!(decrypt_env_from(&path, cipher_type, &rotated_kek)?.decrypted.len(), 2);
+///
+/// // Data key mode cannot be enabled for a file whose values are encrypted without one.
+/// let plain = dir.path().join("plain.env");
+/// set_enc_env_in(&plain, "API_KEY", "secret", cipher_type, &rotated_kek)?;
+/// assert!(matches!(
+///     builder.key(rotated_kek).env_file(&plain).data_key(true).build(),
+///     Err(EnvEncError::DataKeyConflict { .. })
+/// ));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn rewrap_dek(
+    path: impl AsRef<Path>,
+    cipher_type: CipherType,
+    old_kek: &[u8],
+    new_kek: &[u8],
+) -> Result<(), EnvEncError> {
+    let path = path.as_ref();
+    validate_key(&cipher_type, new_kek)?;
+    let _lock = lock_env_file(path, DEFAULT_LOCK_TIMEOUT)?;
+    let contents = read_contents(path)?.unwrap_or_default();
+    let data_key = unwrap_data_key(
+        path,
+        &contents,
+        &cipher_type,
+        &[KeyProtection::Key(old_kek)],
+    )?
+    .ok_or_else(|| EnvEncError::MissingDataKey {
+        path: path.to_path_buf(),
+    })?;
+    let wrapped = wrap_data_key(
+        &cipher_type,
+        KeyProtection::Key(new_kek),
+        &data_key,
+        &mut thread_rng(),
+    )?;
+    write_atomic(path, &with_data_key(&contents, &wrapped))
+}
+
+/// Changes the passphrase the data key of the env file at `path` is wrapped with from
+/// `old_passphrase` to `new_passphrase`.
+///
+/// An env file whose session was built with `EnvEncBuilder::passphrase` encrypts its values
+/// with a random data key, wrapped under a key derived from the passphrase. The header line
+/// records the wrapped data key with the salt and parameters of the key derivation function,
+/// as in `# envenc dek: passphrase:argon2id m=19456 t=2 p=1 salt=<base64> ENC[...]`, so the
+/// passphrase is all that is needed to read the file, with `decrypt_env_with_passphrase`.
+/// Changing the passphrase re-wraps the data key under a fresh salt with the same parameters,
+/// and leaves the value lines untouched.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::MissingDataKey` if the file has no data key,
+/// `EnvEncError::PassphraseRequired` if its data key is not wrapped with a passphrase,
+/// `EnvEncError::InvalidDataKey` if `old_passphrase` is wrong, and `EnvEncError::Io` if the
+/// file cannot be read or written.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{change_passphrase, decrypt_env_with_passphrase, Argon2Params, CipherType, EnvEnc, EnvEncError};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// // Cheap parameters keep the example fast; use `Argon2Params::default()` in practice.
+/// let params = Argon2Params { memory_kib: 1024, iterations: 1, parallelism: 1 };
+///
+/// let builder = EnvEnc::builder().cipher(cipher_type).env_file(&path);
+/// let mut session = builder.clone().passphrase("correct horse", params).build()?;
+/// session.set("PASSPHRASE_DEK_TOKEN", "secret")?;
+///
+/// let contents = std::fs::read_to_string(&path)?;
+/// assert!(contents.lines().any(|line| line.starts_with("# envenc dek: passphrase:argon2id m=1024 t=1 p=1 salt=")));
+///
+/// let report = decrypt_env_with_passphrase(&path, cipher_type, "correct horse")?;
+/// assert_eq!(report.decrypted, ["PASSPHRASE_DEK_TOKEN"]);
+/// assert!(matches!(
+///     decrypt_env_with_passphrase(&path, cipher_type, "wrong horse"),
+///     Err(EnvEncError::InvalidDataKey { .. })
+/// ));
+///
+/// assert!(matches!(
+///     change_passphrase("wrong horse", "battery staple", &path),
+///     Err(EnvEncError::InvalidDataKey { .. })
+/// ));
+/// change_passphrase("correct horse", "battery staple", &path)?;
+///
+/// // Only the data key header changed.
+/// let value_lines = |contents: &str| -> Vec<String> {
+///     contents.lines().filter(|line| !line.starts_with("# envenc dek:")).map(str::to_string).collect()
+/// };
+/// assert_eq!(value_lines(&std::fs::read_to_string(&path)?), value_lines(&contents));
+///
+/// assert!(matches!(
+///     decrypt_env_with_passphrase(&path, cipher_type, "correct horse"),
+///     Err(EnvEncError::InvalidDataKey { .. })
+/// ));
+/// let mut session = builder.passphrase("battery staple", params).build()?;
+/// assert_eq!(session.get("PASSPHRASE_DEK_TOKEN")?.as_deref(), Some("secret"));
+/// session.set("OTHER_TOKEN", "other")?;
+/// let report = decrypt_env_with_passphrase(&path, cipher_type, "battery staple")?;
+/// assert_eq!(report.decrypted, ["OTHER_TOKEN", "PASSPHRASE_DEK_TOKEN"]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn change_passphrase(
+    old_passphrase: &str,
+    new_passphrase: &str,
+    path: impl AsRef<Path>,
+) -> Result<(), EnvEncError> {
+    let path = path.as_ref();
+    let _lock = lock_env_file(path, DEFAULT_LOCK_TIMEOUT)?;
+    let contents = read_contents(path)?.unwrap_or_default();
+    let kdf = passphrase_kdf(&contents).unwrap_or_default();
+    let data_key = unwrap_data_key(
+        path,
+        &contents,
+        &PASSPHRASE_CIPHER,
+        &[KeyProtection::Passphrase(old_passphrase, kdf)],
+    )?
+    .ok_or_else(|| EnvEncError::MissingDataKey {
+        path: path.to_path_buf(),
+    })?;
+    let wrapped = wrap_data_key(
+        &PASSPHRASE_CIPHER,
+        KeyProtection::Passphrase(new_passphrase, kdf),
+        &data_key,
+        &mut thread_rng(),
+    )?;
+    write_atomic(path, &with_data_key(&contents, &wrapped))
+}
@@ -0,0 +1,169 @@
+use crate::{
+    typed::{parse_bool, parse_value},
+    EnvEncError, SecretValue,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    str::FromStr,
+};
+
+/// Decrypted environment variables, held in memory instead of the process environment.
+///
+/// The variables are kept sorted by name. `Debug` shows the names but not the values; log a
+/// `redacted_env_snapshot` to see roughly what they contain.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{decrypt_env_to_map, keys_generation, read_env_enc_from, set_enc_env_in, CipherType, DecryptedEnv};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "API_KEY", "super_secret_api_key", cipher_type, &key)?;
+/// set_enc_env_in(&path, "DATABASE_URL", "postgres://localhost/db", cipher_type, &key)?;
+///
+/// let env = DecryptedEnv::from(decrypt_env_to_map(read_env_enc_from(&path)?, cipher_type, &key)?);
+/// assert_eq!(env.get("API_KEY"), Some("super_secret_api_key"));
+/// assert_eq!(env.names().collect::<Vec<_>>(), ["API_KEY", "DATABASE_URL"]);
+/// assert_eq!(
+///     format!("{:?}", env),
+///     r#"DecryptedEnv({"API_KEY": "[REDACTED]", "DATABASE_URL": "[REDACTED]"})"#
+/// );
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct DecryptedEnv {
+    values: BTreeMap<String, SecretValue>,
+}
+
+impl DecryptedEnv {
+    /// Returns the decrypted value of the variable `name`, if it is present.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(SecretValue::expose_secret)
+    }
+
+    /// Returns the decrypted value of the variable `name` parsed as a `T`, as
+    /// `read_env_parse` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::MissingVar` if the variable is not present, and
+    /// `EnvEncError::InvalidValue` if it cannot be parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use envenc::DecryptedEnv;
+    /// use std::collections::HashMap;
+    ///
+    /// let env = DecryptedEnv::from(HashMap::from([
+    ///     ("PORT".to_string(), "8080".to_string()),
+    ///     ("DEBUG".to_string(), "off".to_string()),
+    ///     ("WORKERS".to_string(), "many".to_string()),
+    /// ]));
+    /// assert_eq!(env.parse::<u16>("PORT")?, 8080);
+    /// assert!(!env.parse_bool("DEBUG")?);
+    /// assert!(env.parse::<u32>("WORKERS").is_err());
+    /// assert!(env.parse::<u32>("MISSING").is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn parse<T>(&self, name: &str) -> Result<T, EnvEncError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        parse_value(name, self.require_value(name)?)
+    }
+
+    /// Returns the decrypted value of the variable `name` as a flag, as `read_env_bool` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::MissingVar` if the variable is not present, and
+    /// `EnvEncError::InvalidValue` if it is not a flag.
+    pub fn parse_bool(&self, name: &str) -> Result<bool, EnvEncError> {
+        parse_bool(name, self.require_value(name)?)
+    }
+
+    /// Returns the decrypted value of the variable `name` deserialized from JSON, as
+    /// `read_env_json` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::MissingVar` if the variable is not present, and
+    /// `EnvEncError::InvalidValue` if it is not JSON of the shape of `T`.
+    #[cfg(feature = "serde")]
+    pub fn parse_json<T: serde::de::DeserializeOwned>(&self, name: &str) -> Result<T, EnvEncError> {
+        crate::typed::parse_json(name, self.require_value(name)?)
+    }
+
+    /// Returns the names of the variables, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
+    /// Returns the names and decrypted values of the variables, in order of their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.expose_secret()))
+    }
+
+    /// Returns the number of variables.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if there are no variables.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the decrypted value of the variable `name`, failing if it is not present.
+    fn require_value(&self, name: &str) -> Result<&str, EnvEncError> {
+        self.get(name).ok_or_else(|| EnvEncError::MissingVar {
+            var: name.to_string(),
+        })
+    }
+}
+
+impl From<HashMap<String, String>> for DecryptedEnv {
+    fn from(values: HashMap<String, String>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl From<HashMap<String, SecretValue>> for DecryptedEnv {
+    fn from(values: HashMap<String, SecretValue>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl FromIterator<(String, String)> for DecryptedEnv {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        iter.into_iter()
+            .map(|(name, value)| (name, SecretValue::from(value)))
+            .collect()
+    }
+}
+
+impl FromIterator<(String, SecretValue)> for DecryptedEnv {
+    fn from_iter<I: IntoIterator<Item = (String, SecretValue)>>(iter: I) -> Self {
+        DecryptedEnv {
+            values: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl std::fmt::Debug for DecryptedEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("DecryptedEnv(")?;
+        f.debug_map()
+            .entries(self.values.keys().map(|name| (name, "[REDACTED]")))
+            .finish()?;
+        f.write_str(")")
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{
+    env_file::{find_duplicates, parse_entries, remove_entry, set_entry},
+    env_syntax::strip_bom,
+    DuplicatePolicy, EnvEncError,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{BufRead, Write},
+};
+
+/// An env file held in memory, such as one read from an S3 object, a database blob, or stdin,
+/// rather than from a path.
+///
+/// The document keeps the text of the file as it is: comments, blank lines, header lines, the
+/// order of the entries, and the quoting of each value. Reading a document with
+/// `read_env_enc_from_reader` and writing it back with `write_env_to_writer` produces the same
+/// bytes, except for a byte order mark, which is dropped. Values are the stored values, which
+/// are encrypted or not as the file holds them; see `decrypt_env_to_map` to decrypt them.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{
+///     decrypt_env_to_map, keys_generation, read_env_enc_from_reader, set_enc_env_in,
+///     write_env_to_writer, CipherType,
+/// };
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "API_KEY", "sk_live_123", cipher_type, &key)?;
+/// // Bytes fetched from a storage backend rather than read from a path.
+/// let blob = format!("# Billing\n{}", std::fs::read_to_string(&path)?);
+///
+/// let mut document = read_env_enc_from_reader(blob.as_bytes())?;
+/// assert!(document.get("API_KEY").unwrap().starts_with("ENC[v2:AES256GCM:"));
+/// let decrypted = decrypt_env_to_map(document.to_map(), cipher_type, &key)?;
+/// assert_eq!(decrypted["API_KEY"], "sk_live_123");
+///
+/// // Writing the document back leaves it byte for byte the same.
+/// let mut written = Vec::new();
+/// write_env_to_writer(&mut written, &document)?;
+/// assert_eq!(String::from_utf8(written)?, blob);
+///
+/// // Edits keep the comments and the order of the other entries.
+/// assert_eq!(document.set("LOG_LEVEL", "debug info"), None);
+/// assert!(document.remove("API_KEY"));
+/// assert!(document.as_str().starts_with("# Billing\n"));
+/// assert!(document.as_str().ends_with("\nLOG_LEVEL='debug info'\n"));
+/// assert_eq!(document.entries().collect::<Vec<_>>(), [("LOG_LEVEL".to_string(), "debug info".to_string())]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvDocument {
+    contents: String,
+}
+
+impl EnvDocument {
+    /// Returns the document whose text is `contents`, without its byte order mark, if it has
+    /// one. Lines are read with the syntax of `read_env_enc_from`.
+    pub fn parse(contents: impl Into<String>) -> Self {
+        let contents = contents.into();
+        EnvDocument {
+            contents: match strip_bom(&contents) {
+                stripped if stripped.len() < contents.len() => stripped.to_string(),
+                _ => contents,
+            },
+        }
+    }
+
+    /// Returns the text of the document.
+    pub fn as_str(&self) -> &str {
+        &self.contents
+    }
+
+    /// Returns the name and stored value of every `KEY=value` line, in order. A variable set
+    /// on several lines is returned once for each.
+    pub fn entries(&self) -> impl Iterator<Item = (String, String)> {
+        parse_entries(&self.contents)
+            .into_iter()
+            .map(|(_, name, value)| (name, value))
+    }
+
+    /// Returns the stored value of `var_name`, which is the value on its last line if it is
+    /// set on several, as `read_env_enc_from` reads it.
+    pub fn get(&self, var_name: &str) -> Option<String> {
+        self.entries()
+            .filter(|(name, _)| name == var_name)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the stored value of every variable, as `read_env_enc_from` does. A variable
+    /// that is set on several lines takes the value of the last, and a warning is logged.
+    pub fn to_map(&self) -> HashMap<String, String> {
+        let entries = parse_entries(&self.contents);
+        // The last value wins, so this only logs the duplicates.
+        let _ = find_duplicates(&entries, DuplicatePolicy::LastWins);
+        entries
+            .into_iter()
+            .map(|(_, name, value)| (name, value))
+            .collect()
+    }
+
+    /// Stores `value` as it is under `var_name`, quoted if it needs to be, and returns the
+    /// value it replaces. The first line for `var_name` is updated in place and any later
+    /// ones are removed; a new variable is appended at the end.
+    ///
+    /// The value is stored as given: encrypt it first, or write through an `EnvEnc` session,
+    /// for a secret.
+    pub fn set(&mut self, var_name: &str, value: &str) -> Option<String> {
+        let previous = self.get(var_name);
+        self.contents = set_entry(&self.contents, var_name, value);
+        previous
+    }
+
+    /// Removes every line for `var_name`, and returns whether there was any.
+    pub fn remove(&mut self, var_name: &str) -> bool {
+        match remove_entry(&self.contents, var_name) {
+            Some(kept) => {
+                self.contents = kept;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for EnvDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.contents)
+    }
+}
+
+/// Reads an env file from `reader` into a document, without touching the filesystem or the
+/// process environment.
+///
+/// `read_env_enc_from` reads a path with it. See `EnvDocument` for an example.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::Io` if `reader` fails or does not hold UTF-8 text.
+pub fn read_env_enc_from_reader(mut reader: impl BufRead) -> Result<EnvDocument, EnvEncError> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    Ok(EnvDocument::parse(contents))
+}
+
+/// Writes `document` to `writer` as it is, and flushes it.
+///
+/// See `EnvDocument` for an example.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::Io` if `writer` fails.
+pub fn write_env_to_writer(
+    mut writer: impl Write,
+    document: &EnvDocument,
+) -> Result<(), EnvEncError> {
+    writer.write_all(document.as_str().as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
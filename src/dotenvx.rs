@@ -0,0 +1,78 @@
+use crate::{fingerprint, Envelope, SkipReason, FORMAT_VERSION};
+
+/// The prefix dotenvx puts in front of the values it encrypts.
+const ENCRYPTED_PREFIX: &str = "encrypted:";
+
+/// The start of the comment line that marks an env file whose `encrypted:` values were
+/// written by this crate.
+const HEADER_PREFIX: &str = "#/ envenc";
+
+/// The prefixes of the variables dotenvx keeps its own keys in.
+const DOTENVX_KEY_PREFIXES: [&str; 2] = ["DOTENV_PUBLIC_KEY", "DOTENV_PRIVATE_KEY"];
+
+/// Returns the value stored in the env file wrapped in the `encrypted:` prefix of dotenvx, so
+/// that dotenvx and similar tools recognize it as encrypted rather than as plain text.
+pub(crate) fn with_prefix(value: &str) -> String {
+    format!("{}{}", ENCRYPTED_PREFIX, value)
+}
+
+/// Returns the stored value without the `encrypted:` prefix and the double quotes dotenvx
+/// writes around it, along with whether it had the prefix.
+pub(crate) fn strip_prefix(value: &str) -> (&str, bool) {
+    let unquoted = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+    match unquoted.strip_prefix(ENCRYPTED_PREFIX) {
+        Some(inner) => (inner, true),
+        None => (value, false),
+    }
+}
+
+/// Returns the envelope or legacy value encrypted by this crate that is stored under
+/// `var_name`, without the `encrypted:` prefix if it has one.
+///
+/// Values under the prefix that are not envelopes were encrypted by dotenvx itself, and the
+/// public keys it writes alongside them are not secrets of this crate either, so both are
+/// reported as `SkipReason::ForeignFormat` instead of being taken for corrupt values.
+pub(crate) fn envenc_value<'a>(var_name: &str, value: &'a str) -> Result<&'a str, SkipReason> {
+    let (inner, prefixed) = strip_prefix(value);
+    let is_dotenvx_key = DOTENVX_KEY_PREFIXES
+        .iter()
+        .any(|prefix| var_name.starts_with(prefix));
+    if (prefixed || is_dotenvx_key) && !Envelope::is_envelope(inner) {
+        return Err(SkipReason::ForeignFormat);
+    }
+    Ok(inner)
+}
+
+/// Returns `true` if `contents` has the header line written by `with_header`.
+pub(crate) fn has_header(contents: &str) -> bool {
+    contents.lines().any(|line| line.starts_with(HEADER_PREFIX))
+}
+
+/// Returns `contents` with a comment line identifying the format of its `encrypted:` values,
+/// after the key fingerprint header if there is one, unless it has the line already.
+pub(crate) fn with_header(contents: &str) -> String {
+    if has_header(contents) {
+        return contents.to_string();
+    }
+    let header = format!(
+        "{} v{}: encrypted: values are envenc ENC[...] envelopes, not dotenvx ECIES\n",
+        HEADER_PREFIX, FORMAT_VERSION
+    );
+    let split = match contents.lines().next() {
+        Some(line) if fingerprint::is_header(line) => {
+            contents.find('\n').map_or(contents.len(), |end| end + 1)
+        }
+        _ => 0,
+    };
+    let (before, after) = contents.split_at(split);
+    let mut updated = before.to_string();
+    if !updated.is_empty() && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated.push_str(&header);
+    updated.push_str(after);
+    updated
+}
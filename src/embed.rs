@@ -0,0 +1,197 @@
+use crate::{
+    decrypt_env_to_map,
+    env_file::{find_duplicates, parse_entries, DuplicatePolicy},
+    fingerprint::check_fingerprint,
+    CipherType, EnvEncError,
+};
+use std::collections::HashMap;
+
+/// Embeds an encrypted env file in the binary as an `EncryptedEnv`, for single-binary
+/// deployments that ship their secrets encrypted and receive the key from the environment.
+///
+/// The path is read with `include_str!`, relative to the file the macro is invoked in. The
+/// build fails if the file does not exist, and if it is malformed: if a line that is not blank
+/// or a comment has no `=`, a quote is not closed, or text other than a comment follows a
+/// closing quote. Decrypt it at runtime with `decrypt_embedded`.
+///
+/// Only embed the file, never the key. Anything compiled into a binary can be read back from
+/// it with `strings`, so a key embedded next to the file or in the source protects nothing.
+/// Load the key at runtime instead, with `KeySource` or from a secret manager. Values that are
+/// not encrypted are embedded in plain text.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{decrypt_embedded, embed_env, CipherType, EncryptedEnv, KeySource};
+///
+/// static EMBEDDED: EncryptedEnv = embed_env!("../tests/fixtures/embedded.env");
+///
+/// # std::env::set_var("ENVENC_KEY", "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+/// let cipher_type = CipherType::AES256GCM;
+/// let key = KeySource::from_env().load(cipher_type)?;
+/// let env = decrypt_embedded(&EMBEDDED, cipher_type, &key)?;
+/// assert_eq!(env["DATABASE_URL"], "postgres://app:hunter2@db/app");
+/// assert_eq!(env["RUST_LOG"], "info");
+/// assert_eq!(EMBEDDED.names(), ["DATABASE_URL", "API_KEY", "RUST_LOG"]);
+/// # Ok::<(), envenc::EnvEncError>(())
+/// ```
+///
+/// A malformed file fails the build:
+///
+/// ```compile_fail,E0080
+/// use envenc::{embed_env, EncryptedEnv};
+///
+/// // Line 3 has no `=`.
+/// static EMBEDDED: EncryptedEnv = embed_env!("../tests/fixtures/malformed.env");
+/// ```
+#[macro_export]
+macro_rules! embed_env {
+    ($path:expr $(,)?) => {{
+        const EMBEDDED: $crate::EncryptedEnv =
+            $crate::EncryptedEnv::new(::core::include_str!($path));
+        EMBEDDED
+    }};
+}
+
+/// An encrypted env file compiled into the binary, usually with `embed_env!`.
+///
+/// It holds the file as written, with its values still encrypted, and formatting it with
+/// `Debug` prints only the names of its variables.
+#[derive(Clone, Copy)]
+pub struct EncryptedEnv {
+    contents: &'static str,
+}
+
+impl EncryptedEnv {
+    /// Returns the env file `contents`, checking that they are well formed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a line that is not blank or a comment has no `=`, a quote is not closed, or
+    /// text other than a comment follows a closing quote. In a constant, as `embed_env!` uses
+    /// it, this fails the build.
+    pub const fn new(contents: &'static str) -> Self {
+        if let Err(reason) = check_contents(contents.as_bytes()) {
+            panic!("{}", reason);
+        }
+        EncryptedEnv { contents }
+    }
+
+    /// Returns the env file as it was embedded.
+    pub fn contents(&self) -> &'static str {
+        self.contents
+    }
+
+    /// Returns the names of the variables of the env file, in file order.
+    pub fn names(&self) -> Vec<String> {
+        parse_entries(self.contents)
+            .into_iter()
+            .map(|(_, name, _)| name)
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for EncryptedEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("EncryptedEnv")
+            .field("names", &self.names())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Decrypts the variables of an embedded env file into a map, without touching the process
+/// environment. Values that are not encrypted are returned as they are, and a variable set on
+/// several lines takes the last value.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::KeyMismatch` if the file records the fingerprint of another key, and
+/// the errors of `decrypt_env_to_map`.
+pub fn decrypt_embedded(
+    env: &EncryptedEnv,
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+) -> Result<HashMap<String, String>, EnvEncError> {
+    let key = key.as_ref();
+    check_fingerprint(env.contents, &[key])?;
+    let entries = parse_entries(env.contents);
+    find_duplicates(&entries, DuplicatePolicy::LastWins)?;
+    let env_vars = entries
+        .into_iter()
+        .map(|(_, name, value)| (name, value))
+        .collect();
+    decrypt_env_to_map(env_vars, cipher_type, key)
+}
+
+/// Checks the syntax of `env_syntax` in `contents` at compile time, returning why the first
+/// malformed line is malformed.
+const fn check_contents(contents: &[u8]) -> Result<(), &'static str> {
+    let mut index = match contents {
+        [0xef, 0xbb, 0xbf, ..] => 3,
+        _ => 0,
+    };
+    while index < contents.len() {
+        index = skip_blanks(contents, index);
+        if index == contents.len() || contents[index] == b'\n' || contents[index] == b'\r' {
+            index = line_end(contents, index);
+            continue;
+        }
+        if contents[index] == b'#' {
+            index = line_end(contents, index);
+            continue;
+        }
+        while index < contents.len() && contents[index] != b'=' {
+            if contents[index] == b'\n' {
+                return Err("embed_env!: a line of the env file has no '='");
+            }
+            index += 1;
+        }
+        if index == contents.len() {
+            return Err("embed_env!: a line of the env file has no '='");
+        }
+        index = skip_blanks(contents, index + 1);
+        if index == contents.len() {
+            break;
+        }
+        let quote = contents[index];
+        if quote != b'\'' && quote != b'"' {
+            index = line_end(contents, index);
+            continue;
+        }
+        index += 1;
+        while index < contents.len() && contents[index] != quote {
+            if quote == b'"' && contents[index] == b'\\' {
+                index += 1;
+            }
+            index += 1;
+        }
+        if index >= contents.len() {
+            return Err("embed_env!: a quote in the env file is not closed");
+        }
+        index = skip_blanks(contents, index + 1);
+        if index < contents.len() && contents[index] == b'\r' {
+            index += 1;
+        }
+        if index < contents.len() && contents[index] != b'\n' && contents[index] != b'#' {
+            return Err("embed_env!: text follows a closing quote in the env file");
+        }
+        index = line_end(contents, index);
+    }
+    Ok(())
+}
+
+/// Returns the index of the first byte of `contents` from `index` that is not a space or tab.
+const fn skip_blanks(contents: &[u8], mut index: usize) -> usize {
+    while index < contents.len() && (contents[index] == b' ' || contents[index] == b'\t') {
+        index += 1;
+    }
+    index
+}
+
+/// Returns the index of the start of the line after the one `index` is on.
+const fn line_end(contents: &[u8], mut index: usize) -> usize {
+    while index < contents.len() && contents[index] != b'\n' {
+        index += 1;
+    }
+    index + 1
+}
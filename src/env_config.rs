@@ -0,0 +1,214 @@
+use crate::{
+    typed::{parse_bool, parse_value},
+    DecryptedEnv, EnvEnc, EnvEncError, SecretValue,
+};
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+};
+
+/// A typed config that is loaded from decrypted environment variables, usually implemented
+/// with `#[derive(EnvEncConfig)]` from the `derive` feature.
+///
+/// Each field is read from the variable named after it in upper case, such as `DATABASE_URL`
+/// for `database_url`, and parsed with `FromEnvValue`. The derive accepts these attributes on
+/// fields:
+///
+/// - `#[envenc(rename = "API_KEY")]` reads the field from another variable.
+/// - `#[envenc(default = "8080")]` parses this value when the variable is not set.
+/// - `#[envenc(from_str)]` parses the field with `FromStr`, for types that do not implement
+///   `FromEnvValue`.
+///
+/// A field of type `Option<T>` is `None` when its variable is not set. Every field is read
+/// before failing, so that `EnvEncError::InvalidConfig` reports all the missing and invalid
+/// variables at once, without their values.
+///
+/// # Example
+///
+#[cfg_attr(feature = "derive", doc = "```")]
+#[cfg_attr(not(feature = "derive"), doc = "```ignore")]
+/// use envenc::{keys_generation, CipherType, EnvConfig, EnvEnc, EnvEncConfig, EnvEncError, MemoryStore, SecretValue};
+/// use std::net::IpAddr;
+///
+/// #[derive(Debug, EnvEncConfig)]
+/// struct Config {
+///     database_url: String,
+///     #[envenc(rename = "STRIPE_KEY")]
+///     api_key: SecretValue,
+///     #[envenc(default = "8080")]
+///     port: u16,
+///     #[envenc(default = "127.0.0.1")]
+///     bind: IpAddr,
+///     debug: bool,
+///     sentry_dsn: Option<String>,
+/// }
+///
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// let store = MemoryStore::new();
+/// let mut session = EnvEnc::builder().cipher(cipher_type).key(key).store(Box::new(store)).build()?;
+/// session.set("DATABASE_URL", "postgres://localhost/app")?;
+/// session.set("STRIPE_KEY", "sk_live_123")?;
+/// session.set("DEBUG", "yes")?;
+///
+/// let config = Config::load(&session)?;
+/// assert_eq!(config.database_url, "postgres://localhost/app");
+/// assert_eq!(config.api_key.expose_secret(), "sk_live_123");
+/// assert_eq!((config.port, config.debug, config.sentry_dsn), (8080, true, None));
+///
+/// session.remove("DATABASE_URL")?;
+/// session.set("PORT", "http")?;
+/// let err = Config::load(&session).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "the config cannot be loaded: environment variable 'DATABASE_URL' is not set; \
+///      the value of environment variable 'PORT' is invalid: invalid digit found in string"
+/// );
+/// assert!(matches!(err, EnvEncError::InvalidConfig { errors } if errors.len() == 2));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait EnvConfig: Sized {
+    /// Reads the config from the decrypted variables `env`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidConfig` with the errors of every field that is missing or
+    /// cannot be parsed.
+    fn from_env(env: &DecryptedEnv) -> Result<Self, EnvEncError>;
+
+    /// Decrypts the variables of `session` and reads the config from them.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `EnvEnc::decrypt_to_map` and of `from_env`.
+    fn load(session: &EnvEnc) -> Result<Self, EnvEncError> {
+        Self::from_env(&DecryptedEnv::from(session.decrypt_to_map()?))
+    }
+}
+
+/// A type a field of an `EnvConfig` can be parsed into from the value of a variable.
+///
+/// Implemented for strings, secrets, flags, numbers, paths, and addresses. Flags accept the
+/// values `read_env_bool` does. Other types implement it, or are read with
+/// `#[envenc(from_str)]`.
+pub trait FromEnvValue: Sized {
+    /// Parses `value`, the value of `var_name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidValue` if `value` cannot be parsed, without including it.
+    fn from_env_value(var_name: &str, value: &str) -> Result<Self, EnvEncError>;
+}
+
+impl FromEnvValue for String {
+    fn from_env_value(_: &str, value: &str) -> Result<Self, EnvEncError> {
+        Ok(value.to_string())
+    }
+}
+
+impl FromEnvValue for SecretValue {
+    fn from_env_value(_: &str, value: &str) -> Result<Self, EnvEncError> {
+        Ok(SecretValue::from(value.to_string()))
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl FromEnvValue for secrecy::SecretString {
+    fn from_env_value(_: &str, value: &str) -> Result<Self, EnvEncError> {
+        Ok(secrecy::SecretString::from(value))
+    }
+}
+
+impl FromEnvValue for bool {
+    fn from_env_value(var_name: &str, value: &str) -> Result<Self, EnvEncError> {
+        parse_bool(var_name, value)
+    }
+}
+
+macro_rules! from_env_value_with_from_str {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl FromEnvValue for $ty {
+                fn from_env_value(var_name: &str, value: &str) -> Result<Self, EnvEncError> {
+                    parse_value(var_name, value)
+                }
+            }
+        )*
+    };
+}
+
+from_env_value_with_from_str!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, char, PathBuf,
+    IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr,
+);
+
+/// Reads the fields of an `EnvConfig` for `#[derive(EnvEncConfig)]`, collecting the error of
+/// every field that fails.
+#[doc(hidden)]
+pub struct ConfigLoader<'a> {
+    env: &'a DecryptedEnv,
+    errors: Vec<EnvEncError>,
+}
+
+#[doc(hidden)]
+impl<'a> ConfigLoader<'a> {
+    pub fn new(env: &'a DecryptedEnv) -> Self {
+        ConfigLoader {
+            env,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Returns the value of `var_name`, or `default` if it is not set, parsed with `parse`, or
+    /// `None` if it is not set and has no default, or cannot be parsed.
+    pub fn optional<T>(
+        &mut self,
+        var_name: &str,
+        default: Option<&str>,
+        parse: fn(&str, &str) -> Result<T, EnvEncError>,
+    ) -> Option<T> {
+        let value = self.env.get(var_name).or(default)?;
+        parse(var_name, value)
+            .map_err(|err| self.errors.push(err))
+            .ok()
+    }
+
+    /// Returns what `optional` does, recording an error if the variable is not set and has
+    /// no default.
+    pub fn required<T>(
+        &mut self,
+        var_name: &str,
+        default: Option<&str>,
+        parse: fn(&str, &str) -> Result<T, EnvEncError>,
+    ) -> Option<T> {
+        if self.env.get(var_name).or(default).is_none() {
+            self.errors.push(EnvEncError::MissingVar {
+                var: var_name.to_string(),
+            });
+            return None;
+        }
+        self.optional(var_name, default, parse)
+    }
+
+    /// Returns `EnvEncError::InvalidConfig` if any field failed, after which every required
+    /// field has a value.
+    pub fn finish(self) -> Result<(), EnvEncError> {
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(EnvEncError::InvalidConfig {
+                errors: self.errors,
+            }),
+        }
+    }
+
+    /// Parses `value`, the value of `var_name`, with `FromStr`, for `#[envenc(from_str)]`.
+    pub fn from_str<T>(var_name: &str, value: &str) -> Result<T, EnvEncError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        parse_value(var_name, value)
+    }
+}
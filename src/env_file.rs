@@ -0,0 +1,761 @@
+use crate::{
+    dotenvx, env_syntax,
+    error::join_lines,
+    file_mac::update_mac,
+    fingerprint::{check_fingerprint, with_fingerprint},
+    EnvEncError,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The line ending that env files are written with.
+///
+/// Env files are read the same way with either line ending, or a mix of both. Every write
+/// rewrites the whole file with one line ending, whatever the file used before. A byte order
+/// mark at the start of a file, as Notepad writes, is ignored when reading and dropped when
+/// writing. Set the line ending of a session with `EnvEncBuilder::line_ending`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{encrypt_existing_env, keys_generation, read_env_enc_from, CipherType, EncryptOptions, EnvEnc};
+///
+/// // A plaintext env file saved by Notepad, with a byte order mark and `\r\n` line endings.
+/// let fixture = include_str!("../tests/fixtures/windows.env");
+/// assert!(fixture.starts_with('\u{feff}') && fixture.contains("\r\n"));
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join(".env");
+/// std::fs::write(&path, fixture)?;
+///
+/// let pem = include_str!("../tests/fixtures/ec-private-key.pem");
+/// let mut names: Vec<_> = read_env_enc_from(&path)?.into_iter().collect();
+/// names.sort();
+/// assert_eq!(
+///     names,
+///     [
+///         ("API_KEY".to_string(), "sk_live_123".to_string()),
+///         ("EMPTY".to_string(), String::new()),
+///         ("GREETING".to_string(), "hello world".to_string()),
+///         ("TLS_KEY".to_string(), pem.to_string()),
+///     ]
+/// );
+///
+/// // Encrypting it keeps the values as they were meant, and the file reads back the same
+/// // after its line endings are changed again.
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// encrypt_existing_env(&path, cipher_type, &key, EncryptOptions::default())?;
+/// let encrypted = std::fs::read_to_string(&path)?;
+/// assert!(!encrypted.starts_with('\u{feff}'));
+/// std::fs::write(&path, format!("\u{feff}{}", encrypted.replace("\r\n", "\n").replace('\n', "\r\n")))?;
+///
+/// let session = EnvEnc::builder().cipher(cipher_type).key(key).env_file(&path).build()?;
+/// assert_eq!(session.get("API_KEY")?.as_deref(), Some("sk_live_123"));
+/// assert_eq!(session.get("GREETING")?.as_deref(), Some("hello world"));
+/// assert_eq!(session.get("TLS_KEY")?.as_deref(), Some(pem));
+/// assert_eq!(session.get("EMPTY")?.as_deref(), Some(""));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n`, as on Unix.
+    Lf,
+    /// `\r\n`, as on Windows.
+    CrLf,
+}
+
+impl Default for LineEnding {
+    /// Returns the line ending of the platform: `CrLf` on Windows and `Lf` elsewhere.
+    fn default() -> Self {
+        match cfg!(windows) {
+            true => LineEnding::CrLf,
+            false => LineEnding::Lf,
+        }
+    }
+}
+
+impl LineEnding {
+    /// Returns `contents` with every line ending replaced with this one.
+    pub(crate) fn apply(self, contents: &str) -> String {
+        let contents = contents.replace("\r\n", "\n");
+        match self {
+            LineEnding::Lf => contents,
+            LineEnding::CrLf => contents.replace('\n', "\r\n"),
+        }
+    }
+}
+
+/// What reading an env file does with a variable that is set on more than one line.
+///
+/// Writes never add a second line for a variable: setting a variable that is set on several
+/// lines keeps its first line and removes the others.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Use the value on the last line, as dotenv loaders do, log a warning, and list the
+    /// variable in `DecryptReport::duplicates`.
+    #[default]
+    LastWins,
+    /// Fail with `EnvEncError::DuplicateKey`.
+    Strict,
+}
+
+/// Returns the variables of `entries`, as returned by `parse_entries`, that are set on more
+/// than one line, with the numbers of those lines, sorted by name.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::DuplicateKey` for the first of them if `policy` is
+/// `DuplicatePolicy::Strict`. Otherwise each is logged as a warning.
+pub(crate) fn find_duplicates(
+    entries: &[(usize, String, String)],
+    policy: DuplicatePolicy,
+) -> Result<Vec<(String, Vec<usize>)>, EnvEncError> {
+    let mut lines: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (line, name, _) in entries {
+        lines.entry(name).or_default().push(*line);
+    }
+    let duplicates: Vec<(String, Vec<usize>)> = lines
+        .into_iter()
+        .filter(|(_, lines)| lines.len() > 1)
+        .map(|(name, lines)| (name.to_string(), lines))
+        .collect();
+    if let (DuplicatePolicy::Strict, Some((name, lines))) = (policy, duplicates.first()) {
+        return Err(EnvEncError::DuplicateKey {
+            name: name.clone(),
+            lines: lines.clone(),
+        });
+    }
+    for (name, lines) in &duplicates {
+        log::warn!(
+            "'{}' is set on lines {}; the last one is used",
+            name,
+            join_lines(lines)
+        );
+    }
+    Ok(duplicates)
+}
+
+/// How long writers wait for the lock on an env file by default.
+pub(crate) const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to sleep between attempts to take the lock on an env file.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Parses the `KEY=value` lines of the env file at `path`, treating a missing file as empty.
+/// A variable that is set on several lines takes the last value, with a warning.
+pub(crate) fn parse_env_file(path: &Path) -> Result<HashMap<String, String>, EnvEncError> {
+    let entries = parse_env_lines(path)?;
+    find_duplicates(&entries, DuplicatePolicy::LastWins)?;
+    Ok(entries
+        .into_iter()
+        .map(|(_, key, value)| (key, value))
+        .collect())
+}
+
+/// Parses the `KEY=value` lines of the env file at `path` in file order, returning the 1-based
+/// line number, name, and value of each. Comments and blank lines are skipped, and a missing
+/// file is treated as empty.
+pub(crate) fn parse_env_lines(path: &Path) -> Result<Vec<(usize, String, String)>, EnvEncError> {
+    Ok(parse_entries(&read_contents(path)?.unwrap_or_default()))
+}
+
+/// Parses the `KEY=value` lines of `contents` like `parse_env_lines`, with the syntax of
+/// `env_syntax`. A value that cannot be parsed, such as one with an unclosed quote, is returned
+/// trimmed as written.
+pub(crate) fn parse_entries(contents: &str) -> Vec<(usize, String, String)> {
+    env_syntax::logical_lines(contents)
+        .into_iter()
+        .filter_map(|(number, line)| {
+            let line = env_syntax::split_line(line.trim_end_matches(['\r', '\n']))?;
+            Some((number, line.name.to_string(), entry_value(line.rhs)))
+        })
+        .collect()
+}
+
+/// Returns the value of a line whose text after the `=` is `rhs`, or `rhs` trimmed if it cannot
+/// be parsed.
+fn entry_value(rhs: &str) -> String {
+    match env_syntax::parse_value(rhs) {
+        Ok(parsed) => parsed.value,
+        Err(_) => rhs.trim().to_string(),
+    }
+}
+
+/// Returns the serialization of the entries of `contents` that signatures and MACs cover:
+/// `context`, followed by every `KEY=value` line, trimmed, in file order. Comments, such as the
+/// lines recording the signature or MAC themselves, and blank lines are left out.
+///
+/// Lines are covered as written rather than as parsed, so that requoting a value or changing
+/// its inline comment is detected too.
+pub(crate) fn canonical_entries(context: &str, contents: &str) -> Vec<u8> {
+    let mut canonical = context.as_bytes().to_vec();
+    let lines = env_syntax::logical_lines(contents)
+        .into_iter()
+        .map(|(_, line)| line.trim_end_matches(['\r', '\n']))
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='));
+    for (key, value) in lines {
+        canonical.extend_from_slice(key.trim().as_bytes());
+        canonical.push(b'=');
+        canonical.extend_from_slice(value.trim().as_bytes());
+        canonical.push(b'\n');
+    }
+    canonical
+}
+
+/// How `edit_lines` should change a single `KEY=value` line.
+pub(crate) enum LineEdit {
+    /// Leave the line as it is.
+    Keep,
+    /// Replace the value, keeping the key as written.
+    Replace(String),
+    /// Replace both the key and the value.
+    Rename(String, String),
+    /// Drop the line.
+    Remove,
+}
+
+/// Reads the env file at `path` without its byte order mark, if it has one, returning `None`
+/// if it does not exist.
+pub(crate) fn read_contents(path: &Path) -> Result<Option<String>, EnvEncError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            log::debug!("read {}", path.display());
+            Ok(Some(match env_syntax::strip_bom(&contents) {
+                stripped if stripped.len() < contents.len() => stripped.to_string(),
+                _ => contents,
+            }))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The SHA-256 hash of the contents of an env file, which a session records when it reads the
+/// file, so that a later write can tell whether the file was changed in between.
+pub(crate) type ContentHash = [u8; 32];
+
+/// Returns the hash of `contents` as `read_contents` returns them. A file that does not exist
+/// hashes as an empty one.
+pub(crate) fn content_hash(contents: &str) -> ContentHash {
+    Sha256::digest(contents.as_bytes()).into()
+}
+
+/// Checks that `contents`, read from the env file at `path` under its lock, still hash to
+/// `expected`, if the writer expects them to.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::ConcurrentModification` if they do not.
+pub(crate) fn check_unchanged(
+    path: &Path,
+    contents: &str,
+    expected: Option<ContentHash>,
+) -> Result<(), EnvEncError> {
+    match expected {
+        Some(expected) if content_hash(contents) != expected => {
+            Err(EnvEncError::ConcurrentModification {
+                path: path.to_path_buf(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// How `write_env_var` and `remove_env_var` write an env file.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WriteOptions {
+    /// How long to wait for the lock on the file.
+    pub(crate) lock_timeout: Duration,
+    /// The line ending every line is written with.
+    pub(crate) line_ending: LineEnding,
+    /// The hash of the contents the writer read last, if the write must fail when the file
+    /// was changed since.
+    pub(crate) expected: Option<ContentHash>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            line_ending: LineEnding::default(),
+            expected: None,
+        }
+    }
+}
+
+/// Applies `edit` to every `KEY=value` line of `contents`, passing it the name and the value as
+/// parsed by `parse_entries`. A replaced value keeps the `export ` prefix and the inline comment
+/// of its line, and a replaced multi-line value is replaced as a whole. Comments, blank lines, and anything else are copied through unchanged.
+pub(crate) fn edit_lines(contents: &str, mut edit: impl FnMut(&str, &str) -> LineEdit) -> String {
+    let mut edited = String::with_capacity(contents.len());
+    for (_, line) in env_syntax::logical_lines(contents) {
+        let body = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[body.len()..];
+        let Some(raw) = env_syntax::split_line(body) else {
+            edited.push_str(line);
+            continue;
+        };
+        let (key, value) = match edit(raw.name, &entry_value(raw.rhs)) {
+            LineEdit::Keep => {
+                edited.push_str(line);
+                continue;
+            }
+            LineEdit::Remove => continue,
+            LineEdit::Replace(value) => (raw.lhs.to_string(), value),
+            LineEdit::Rename(key, value) => (key, value),
+        };
+        edited.push_str(&key);
+        edited.push('=');
+        edited.push_str(&value);
+        if let Some(comment) = env_syntax::parse_value(raw.rhs)
+            .ok()
+            .and_then(|parsed| parsed.comment)
+        {
+            edited.push(' ');
+            edited.push_str(comment);
+        }
+        edited.push_str(ending);
+    }
+    edited
+}
+
+/// Returns `contents` with `value`, quoted if it needs to be, stored under `var_name`: the
+/// first line for `var_name` is updated in place and any later ones are removed, or a line is
+/// appended at the end if there is none. All other lines are kept verbatim.
+pub(crate) fn set_entry(contents: &str, var_name: &str, value: &str) -> String {
+    let mut replaced = false;
+    let mut updated = edit_lines(contents, |key, _| match key == var_name {
+        true if replaced => LineEdit::Remove,
+        true => {
+            replaced = true;
+            LineEdit::Replace(env_syntax::quote_value(value))
+        }
+        false => LineEdit::Keep,
+    });
+    if !replaced {
+        if !updated.is_empty() && !updated.ends_with('\n') {
+            updated.push('\n');
+        }
+        updated.push_str(var_name);
+        updated.push('=');
+        updated.push_str(&env_syntax::quote_value(value));
+        updated.push('\n');
+    }
+    updated
+}
+
+/// Returns `contents` without the lines for `var_name`, or `None` if it has none.
+pub(crate) fn remove_entry(contents: &str, var_name: &str) -> Option<String> {
+    let mut removed = false;
+    let kept = edit_lines(contents, |key, _| match key == var_name {
+        true => {
+            removed = true;
+            LineEdit::Remove
+        }
+        false => LineEdit::Keep,
+    });
+    removed.then_some(kept)
+}
+
+/// Replaces the env file at `path` with `contents` by writing a sibling temporary file and
+/// renaming it over the original, so a failed write never leaves a partial file behind.
+///
+/// The temporary file is synced to disk before the rename and takes over the permissions of
+/// the file it replaces. A new file is only readable by its owner on Unix. Lines end with the
+/// line ending of the platform.
+pub(crate) fn write_atomic(path: &Path, contents: &str) -> Result<(), EnvEncError> {
+    write_atomic_with(path, contents, LineEnding::default())
+}
+
+/// Replaces the env file at `path` with `contents` like `write_atomic`, with every line ending
+/// in `line_ending`.
+pub(crate) fn write_atomic_with(
+    path: &Path,
+    contents: &str,
+    line_ending: LineEnding,
+) -> Result<(), EnvEncError> {
+    replace_file(path, line_ending.apply(contents).as_bytes(), true)
+}
+
+/// Replaces the env file at `path` with `contents` like `write_atomic_with`, and returns the
+/// hash of what was written.
+pub(crate) fn write_atomic_hashed(
+    path: &Path,
+    contents: &str,
+    line_ending: LineEnding,
+) -> Result<ContentHash, EnvEncError> {
+    let contents = line_ending.apply(contents);
+    replace_file(path, contents.as_bytes(), true)?;
+    Ok(content_hash(&contents))
+}
+
+/// Replaces the file at `path` with the binary `contents` like `write_atomic`.
+pub(crate) fn write_atomic_bytes(path: &Path, contents: &[u8]) -> Result<(), EnvEncError> {
+    replace_file(path, contents, true)
+}
+
+/// Replaces the file at `path` with `contents` like `write_atomic`, but leaves the new file
+/// only readable by its owner on Unix even if the file it replaces was not.
+pub(crate) fn write_atomic_private(path: &Path, contents: &[u8]) -> Result<(), EnvEncError> {
+    replace_file(path, contents, false)
+}
+
+/// Writes `contents` to a sibling temporary file and renames it over `path`, taking over the
+/// permissions of the replaced file if `keep_permissions` is set.
+fn replace_file(path: &Path, contents: &[u8], keep_permissions: bool) -> Result<(), EnvEncError> {
+    replace_file_with(&FsWriter, path, contents, keep_permissions)
+}
+
+/// The file system operations of an atomic write, which tests replace to make one of them
+/// fail.
+pub(crate) trait AtomicWriter {
+    /// Writes `contents` to the new file at `path` and syncs it to disk.
+    fn write_synced(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Renames the file at `from` over the one at `to`.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+}
+
+/// The `AtomicWriter` that writes to the file system.
+pub(crate) struct FsWriter;
+
+impl AtomicWriter for FsWriter {
+    fn write_synced(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        write_synced(path, contents)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+}
+
+/// Replaces the file at `path` with `contents` like `replace_file`, through `writer`.
+pub(crate) fn replace_file_with(
+    writer: &dyn AtomicWriter,
+    path: &Path,
+    contents: &[u8],
+    keep_permissions: bool,
+) -> Result<(), EnvEncError> {
+    let tmp_path = sibling_path(path, "tmp");
+    // A temporary file left behind by a killed writer would keep its old permissions.
+    let _ = fs::remove_file(&tmp_path);
+
+    let result = writer
+        .write_synced(&tmp_path, contents)
+        .and_then(|()| match fs::metadata(path) {
+            Ok(metadata) if keep_permissions => {
+                fs::set_permissions(&tmp_path, metadata.permissions())
+            }
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        })
+        .and_then(|()| writer.rename(&tmp_path, path));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err.into());
+    }
+
+    sync_parent_dir(path);
+    log::debug!("wrote {} bytes to {}", contents.len(), path.display());
+    Ok(())
+}
+
+/// Returns the path of the hidden file `.{name}.{extension}` next to the env file at `path`.
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}", file_name, extension))
+}
+
+/// An advisory lock on an env file, released when dropped.
+pub(crate) struct EnvFileLock {
+    _file: File,
+}
+
+/// Takes an exclusive advisory lock on the env file at `path`, waiting up to `timeout` for
+/// other writers to release it.
+///
+/// The lock is held on the sibling file `.{name}.lock` rather than on the env file itself,
+/// because atomic writes replace the env file. The lock file is left in place afterwards.
+pub(crate) fn lock_env_file(path: &Path, timeout: Duration) -> Result<EnvFileLock, EnvEncError> {
+    let file = private_options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling_path(path, "lock"))?;
+
+    let start = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => {
+                log::debug!("locked {}", path.display());
+                return Ok(EnvFileLock { _file: file });
+            }
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                thread::sleep(LOCK_RETRY_INTERVAL)
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(EnvEncError::LockTimeout {
+                    path: path.to_path_buf(),
+                    timeout,
+                })
+            }
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+    }
+}
+
+/// Returns `OpenOptions` that create files readable and writable only by their owner on
+/// Unix. Elsewhere new files inherit the permissions of their directory.
+fn private_options() -> OpenOptions {
+    #[allow(unused_mut)]
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+/// Returns an error if the env file at `path` can be read or written by users other than its
+/// owner. A missing file passes, and the check is a no-op outside Unix.
+pub(crate) fn check_permissions(path: &Path) -> Result<(), EnvEncError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.permissions().mode() & 0o077 != 0 => {
+                return Err(EnvEncError::InsecurePermissions {
+                    path: path.to_path_buf(),
+                    mode: metadata.permissions().mode() & 0o777,
+                })
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Writes `contents` to a new file at `path` and flushes it to disk.
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = private_options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Flushes the directory entry of a renamed file to disk, on platforms where that is possible.
+/// Failures are ignored, since the rename itself has already succeeded.
+fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// Stores `value` under `var_name` in the env file at `path`, creating the file if needed.
+///
+/// The first existing line for `var_name` is updated in place, any later ones are removed, and
+/// all other lines are kept verbatim. The value is quoted if it holds characters that need quoting. If the variable is
+/// not stored yet, it is appended at the end of the file. A file
+/// that holds no variables yet gets a header line with the fingerprint of the first of `keys`,
+/// and a file whose header records none of `keys` is left alone, unless `keys` is empty
+/// because the value is not encrypted with a symmetric key. A value under the
+/// `encrypted:` prefix of dotenvx adds a header line identifying its format. The MAC footer is
+/// updated with the first of `keys` if the file has one, or added if `add_mac` is set. The file
+/// is locked for the whole read-modify-write cycle, and written as `options` say. Returns the
+/// hash of the new contents.
+pub(crate) fn write_env_var(
+    path: &Path,
+    var_name: &str,
+    value: &str,
+    keys: &[&[u8]],
+    add_mac: bool,
+    options: WriteOptions,
+) -> Result<ContentHash, EnvEncError> {
+    rewrite_env_file(path, options, |contents| {
+        update_env_var(path, contents, var_name, value, keys, add_mac).map(Some)
+    })
+}
+
+/// Returns the contents of the env file at `path`, `contents`, with `value` stored under
+/// `var_name`, as `write_env_var` stores it.
+pub(crate) fn update_env_var(
+    path: &Path,
+    contents: &str,
+    var_name: &str,
+    value: &str,
+    keys: &[&[u8]],
+    add_mac: bool,
+) -> Result<String, EnvEncError> {
+    if !keys.is_empty() {
+        check_fingerprint(contents, keys)?;
+    }
+    let has_entries = !parse_entries(contents).is_empty();
+    let mut updated = set_entry(contents, var_name, value);
+    if let Some(key) = keys.first().filter(|_| !has_entries) {
+        updated = with_fingerprint(&updated, key);
+    }
+    if dotenvx::strip_prefix(value).1 {
+        updated = dotenvx::with_header(&updated);
+    }
+    update_mac(path, contents, updated, keys.first().copied(), add_mac)
+}
+
+/// Removes every `KEY=value` line for `var_name` from the env file at `path`, leaving all other
+/// lines untouched, and updates the MAC footer with `key` if the file has one. The file is
+/// written as `options` say. Returns whether any line was removed, and the hash of the contents
+/// of the file afterwards.
+pub(crate) fn remove_env_var(
+    path: &Path,
+    var_name: &str,
+    key: Option<&[u8]>,
+    options: WriteOptions,
+) -> Result<(bool, ContentHash), EnvEncError> {
+    let mut removed = false;
+    let hash = rewrite_env_file(path, options, |contents| {
+        let kept = remove_env_var_from(path, contents, var_name, key)?;
+        removed = kept.is_some();
+        Ok(kept)
+    })?;
+    Ok((removed, hash))
+}
+
+/// Returns the contents of the env file at `path`, `contents`, without the lines for
+/// `var_name`, as `remove_env_var` removes them, or `None` if it has none.
+pub(crate) fn remove_env_var_from(
+    path: &Path,
+    contents: &str,
+    var_name: &str,
+    key: Option<&[u8]>,
+) -> Result<Option<String>, EnvEncError> {
+    match remove_entry(contents, var_name) {
+        Some(kept) => update_mac(path, contents, kept, key, false).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Replaces the contents of the env file at `path` with what `update` returns for them, if
+/// anything, while holding the lock on the file. A file that does not exist is read as empty.
+/// Returns the hash of the contents of the file afterwards.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::ConcurrentModification` if the contents no longer hash to
+/// `options.expected`, and the error of `update`, in which case the file is left alone.
+pub(crate) fn rewrite_env_file(
+    path: &Path,
+    options: WriteOptions,
+    update: impl FnOnce(&str) -> Result<Option<String>, EnvEncError>,
+) -> Result<ContentHash, EnvEncError> {
+    let _lock = lock_env_file(path, options.lock_timeout)?;
+    let contents = read_contents(path)?.unwrap_or_default();
+    check_unchanged(path, &contents, options.expected)?;
+    match update(&contents)? {
+        Some(updated) => write_atomic_hashed(path, &updated, options.line_ending),
+        None => Ok(content_hash(&contents)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Writes the temporary file in full, then fails to rename it over the env file.
+    #[derive(Default)]
+    struct FailingRename {
+        written: RefCell<Option<Vec<u8>>>,
+    }
+
+    impl AtomicWriter for FailingRename {
+        fn write_synced(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+            FsWriter.write_synced(path, contents)
+        }
+
+        fn rename(&self, from: &Path, _to: &Path) -> io::Result<()> {
+            *self.written.borrow_mut() = Some(fs::read(from)?);
+            Err(io::Error::other("injected rename failure"))
+        }
+    }
+
+    /// Writes half of the temporary file, then fails as a full disk would.
+    struct FailingWrite;
+
+    impl AtomicWriter for FailingWrite {
+        fn write_synced(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+            FsWriter.write_synced(path, &contents[..contents.len() / 2])?;
+            Err(io::Error::other("injected write failure"))
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            FsWriter.rename(from, to)
+        }
+    }
+
+    const ORIGINAL: &str = "API_KEY=ENC[v2:AES256GCM:original]\nRUST_LOG=info\n";
+    const REPLACEMENT: &[u8] = b"API_KEY=ENC[v2:AES256GCM:replacement]\n";
+
+    fn env_file(dir: &tempfile::TempDir) -> PathBuf {
+        let path = dir.path().join(".env");
+        fs::write(&path, ORIGINAL).unwrap();
+        path
+    }
+
+    #[test]
+    fn failure_after_the_write_leaves_the_original_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = env_file(&dir);
+        let writer = FailingRename::default();
+
+        let result = replace_file_with(&writer, &path, REPLACEMENT, true);
+        assert!(matches!(result, Err(EnvEncError::Io(_))));
+        assert_eq!(writer.written.borrow().as_deref(), Some(REPLACEMENT));
+        assert_eq!(fs::read_to_string(&path).unwrap(), ORIGINAL);
+        assert!(!sibling_path(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn failure_during_the_write_leaves_the_original_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = env_file(&dir);
+
+        let result = replace_file_with(&FailingWrite, &path, REPLACEMENT, true);
+        assert!(matches!(result, Err(EnvEncError::Io(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), ORIGINAL);
+        assert!(!sibling_path(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn a_successful_write_replaces_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = env_file(&dir);
+
+        replace_file_with(&FsWriter, &path, REPLACEMENT, true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), REPLACEMENT);
+        assert!(!sibling_path(&path, "tmp").exists());
+    }
+}
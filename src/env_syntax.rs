@@ -0,0 +1,206 @@
+//! The syntax of `KEY=value` lines, shared by the readers and writers of env files.
+//!
+//! A line may start with `export `, as in a shell script. A value is unquoted, in single quotes
+//! taken literally, or in double quotes where a backslash escapes `\`, `"`, `'`, `$`, or a
+//! space, and `\n`, `\r`, and `\t` are control characters. A `#` outside quotes starts a
+//! comment when it begins the value or follows whitespace, and whitespace around an unquoted
+//! value is trimmed. A quoted value may span several lines, as a PEM certificate pasted into
+//! an env file does. Lines may end with `\n` or `\r\n`, which are read the same, and a byte
+//! order mark at the start of a file is ignored.
+
+/// The byte order mark that Windows editors such as Notepad write at the start of a file.
+const BOM: char = '\u{feff}';
+
+/// Returns `contents` without its byte order mark, if it has one.
+pub(crate) fn strip_bom(contents: &str) -> &str {
+    contents.strip_prefix(BOM).unwrap_or(contents)
+}
+
+/// A `KEY=value` line of an env file, split into its parts as written.
+pub(crate) struct RawLine<'a> {
+    /// Everything before the `=`, including an `export ` prefix and whitespace.
+    pub(crate) lhs: &'a str,
+    /// The name, without the `export ` prefix.
+    pub(crate) name: &'a str,
+    /// Everything after the `=`, including quotes and a trailing comment.
+    pub(crate) rhs: &'a str,
+}
+
+/// A value parsed by `parse_value`.
+pub(crate) struct ParsedValue<'a> {
+    /// The value, unquoted and unescaped.
+    pub(crate) value: String,
+    /// The comment after the value, starting with its `#`.
+    pub(crate) comment: Option<&'a str>,
+}
+
+/// Splits `contents` into its lines, with their line endings and the 1-based number of their
+/// first line. A line whose quoted value is not closed on it runs on to the line that closes
+/// the quote, so that a multi-line value is a single line; if no line closes it, the line
+/// stands alone. A byte order mark at the start of `contents` is left out.
+pub(crate) fn logical_lines(contents: &str) -> Vec<(usize, &str)> {
+    let contents = strip_bom(contents);
+    let physical: Vec<&str> = contents.split_inclusive('\n').collect();
+    let mut logical = Vec::with_capacity(physical.len());
+    let (mut index, mut start) = (0, 0);
+    while index < physical.len() {
+        let mut count = 1;
+        if opens_multiline(physical[index]) {
+            let mut len = physical[index].len();
+            for (offset, line) in physical[index + 1..].iter().enumerate() {
+                len += line.len();
+                if !opens_multiline(&contents[start..start + len]) {
+                    count = offset + 2;
+                    break;
+                }
+            }
+        }
+        let len: usize = physical[index..index + count]
+            .iter()
+            .map(|line| line.len())
+            .sum();
+        logical.push((index + 1, &contents[start..start + len]));
+        index += count;
+        start += len;
+    }
+    logical
+}
+
+/// Returns `true` if `text` is a `KEY=value` line whose value starts with a quote that is not
+/// closed.
+fn opens_multiline(text: &str) -> bool {
+    let Some(line) = split_line(text.trim_end_matches(['\r', '\n'])) else {
+        return false;
+    };
+    let rhs = line.rhs.trim_start();
+    match rhs.chars().next() {
+        Some('\'') => !rhs[1..].contains('\''),
+        Some('"') => parse_double_quoted(&rhs[1..]).is_err(),
+        _ => false,
+    }
+}
+
+/// Splits `line`, without its line ending, into its parts, or returns `None` if it is blank, a
+/// comment, or has no `=`.
+pub(crate) fn split_line(line: &str) -> Option<RawLine<'_>> {
+    if line.trim_start().starts_with('#') {
+        return None;
+    }
+    let (lhs, rhs) = line.split_once('=')?;
+    let name = lhs.trim();
+    let name = match name.strip_prefix("export") {
+        Some(rest) if rest.starts_with([' ', '\t']) => rest.trim_start(),
+        _ => name,
+    };
+    Some(RawLine { lhs, name, rhs })
+}
+
+/// Parses the text after the `=` of a line.
+///
+/// # Errors
+///
+/// Returns the reason if a quote is not closed or text other than a comment follows the
+/// closing quote.
+pub(crate) fn parse_value(rhs: &str) -> Result<ParsedValue<'_>, &'static str> {
+    let rhs = rhs.trim_start();
+    let (value, rest) = match rhs.chars().next() {
+        Some('\'') => {
+            let (value, rest) = rhs[1..].split_once('\'').ok_or("a quote is not closed")?;
+            (value.replace("\r\n", "\n"), rest)
+        }
+        Some('"') => parse_double_quoted(&rhs[1..])?,
+        _ => {
+            let end = comment_start(rhs).unwrap_or(rhs.len());
+            let (value, rest) = rhs.split_at(end);
+            return Ok(ParsedValue {
+                value: value.trim_end().to_string(),
+                comment: Some(rest).filter(|rest| !rest.is_empty()),
+            });
+        }
+    };
+    let rest = rest.trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err("text follows the closing quote");
+    }
+    Ok(ParsedValue {
+        value,
+        comment: Some(rest).filter(|rest| !rest.is_empty()),
+    })
+}
+
+/// Parses the rest of a double-quoted value after its opening quote, returning the value and
+/// the text after the closing quote. The line endings of a value that spans several lines are
+/// read as `\n`.
+fn parse_double_quoted(text: &str) -> Result<(String, &str), &'static str> {
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &text[index + 1..])),
+            '\r' if chars.peek().is_some_and(|&(_, next)| next == '\n') => {}
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 'r')) => value.push('\r'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, c @ ('\\' | '"' | '\'' | '$' | ' '))) => value.push(c),
+                // Other escapes are kept as written, as most dotenv loaders do.
+                Some((_, c)) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    Err("a quote is not closed")
+}
+
+/// Returns the byte offset of the `#` that starts a comment in the unquoted value `value`.
+fn comment_start(value: &str) -> Option<usize> {
+    let mut previous = None;
+    for (index, c) in value.char_indices() {
+        if c == '#' && previous.is_none_or(char::is_whitespace) {
+            return Some(index);
+        }
+        previous = Some(c);
+    }
+    None
+}
+
+/// Returns `true` if `c` can be written in an unquoted value.
+fn is_plain_char(c: char) -> bool {
+    c.is_ascii_alphanumeric()
+        || matches!(
+            c,
+            '_' | '-' | '.' | '/' | ':' | '@' | '+' | ',' | '=' | '%' | '[' | ']'
+        )
+}
+
+/// Returns `value` as written after the `=` of a line, so that `parse_value` reads it back
+/// unchanged: unquoted if it only holds characters that need no quoting, in single quotes if it
+/// holds no single quote or control character, and in double quotes with escapes otherwise.
+pub(crate) fn quote_value(value: &str) -> String {
+    if value.chars().all(is_plain_char) {
+        return value.to_string();
+    }
+    if !value.contains(|c: char| c == '\'' || c.is_control()) {
+        return format!("'{}'", value);
+    }
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\\' | '"' | '$' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,363 @@
+use crate::{
+    key_ring::is_valid_key_id, validate_key_nonce, CipherType, EnvCipher, EnvEncError, KeyStrategy,
+    CIPHER_TYPES,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// The prefix that marks a stored value as an envelope.
+pub(crate) const PREFIX: &str = "ENC[";
+
+/// The suffix that closes an envelope.
+pub(crate) const SUFFIX: &str = "]";
+
+/// The prefix of the envelope field that records the key ID.
+const KEY_ID_PREFIX: &str = "kid=";
+
+/// The envelope format version written by this crate.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// The first envelope format version that authenticates the variable name as associated data.
+const NAME_BOUND_VERSION: u32 = 2;
+
+/// An encrypted value in the self-describing format written to env files.
+///
+/// An envelope looks like `ENC[v2:AES256GCM:<base64>]`: the format version, the name of the
+/// cipher the value was encrypted with, and the base64 encoding of the nonce followed by the
+/// ciphertext. Because the cipher is recorded with every value, a single env file can hold
+/// values encrypted with different ciphers.
+///
+/// Since version 2, the name of the variable is authenticated as associated data, so a value
+/// moved to another variable fails to decrypt. Version 1 envelopes, and the bare hex of the
+/// nonce followed by the ciphertext written by earlier versions of this crate, are still
+/// accepted when reading. They are decrypted without associated data, the legacy hex ones
+/// using the cipher the caller passes in.
+///
+/// A value encrypted with a `KeyStrategy` other than `Direct` records it in a field between
+/// the cipher and the payload, as in `ENC[v2:AES256GCM:hkdf-sha256:<base64>]`. A value
+/// encrypted with a key of a `Keyring` records its key ID in a `kid=` field after that, as in
+/// `ENC[v2:AES256GCM:kid=2024:<base64>]`.
+///
+/// # Examples
+///
+/// ```
+/// use envenc::{CipherType, Envelope};
+///
+/// let envelope = Envelope::new(CipherType::AES256GCM, vec![0; 12], vec![1; 20]);
+/// let stored = envelope.to_string();
+/// assert!(stored.starts_with("ENC[v2:AES256GCM:"));
+/// assert_eq!(Envelope::parse(&stored)?, envelope);
+///
+/// let legacy = Envelope::parse(&stored.replacen("v2", "v1", 1))?;
+/// assert_eq!(legacy.version(), 1);
+/// # Ok::<(), envenc::EnvelopeError>(())
+/// ```
+///
+/// Malformed envelopes, unknown ciphers, and unknown versions are rejected:
+///
+/// ```
+/// use envenc::{Envelope, EnvelopeError};
+///
+/// let payload = "AAAAAAAAAAAAAAAAAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+///
+/// assert_eq!(Envelope::parse("ENC[v1:AES256GCM"), Err(EnvelopeError::Malformed));
+/// assert_eq!(Envelope::parse("ENC[v1:AES256GCM]"), Err(EnvelopeError::Malformed));
+/// assert_eq!(Envelope::parse("ENC[1:AES256GCM:AAAA]"), Err(EnvelopeError::Malformed));
+/// assert_eq!(Envelope::parse("not an envelope"), Err(EnvelopeError::Malformed));
+/// assert_eq!(
+///     Envelope::parse(&format!("ENC[v1:ROT13:{}]", payload)),
+///     Err(EnvelopeError::UnknownCipher("ROT13".to_string()))
+/// );
+/// assert_eq!(
+///     Envelope::parse(&format!("ENC[v9:AES256GCM:{}]", payload)),
+///     Err(EnvelopeError::UnsupportedVersion(9))
+/// );
+/// assert_eq!(
+///     Envelope::parse("ENC[v1:AES256GCM:not base64!]"),
+///     Err(EnvelopeError::InvalidBase64)
+/// );
+/// assert_eq!(Envelope::parse("ENC[v1:AES256GCM:AAAA]"), Err(EnvelopeError::TooShort));
+/// assert_eq!(
+///     Envelope::parse(&format!("ENC[v2:AES256GCM:scrypt:{}]", payload)),
+///     Err(EnvelopeError::UnknownKeyStrategy("scrypt".to_string()))
+/// );
+/// assert_eq!(
+///     Envelope::parse(&format!("ENC[v1:AES256GCM:hkdf-sha256:{}]", payload)),
+///     Err(EnvelopeError::Malformed)
+/// );
+/// assert_eq!(
+///     Envelope::parse(&format!("ENC[v2:AES256GCM:hkdf-sha256:kid=2024:{}]", payload))?.key_id(),
+///     Some("2024")
+/// );
+/// assert_eq!(
+///     Envelope::parse(&format!("ENC[v2:AES256GCM:kid=a:kid=b:{}]", payload)),
+///     Err(EnvelopeError::Malformed)
+/// );
+/// # Ok::<(), EnvelopeError>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Envelope {
+    version: u32,
+    cipher_name: String,
+    key_strategy: KeyStrategy,
+    key_id: Option<String>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    /// Creates an envelope in the current format for `ciphertext` encrypted with `cipher_type`
+    /// under `nonce`.
+    pub fn new(cipher_type: CipherType, nonce: Vec<u8>, ciphertext: Vec<u8>) -> Self {
+        Self::from_parts(
+            FORMAT_VERSION,
+            cipher_type.as_str(),
+            KeyStrategy::Direct,
+            nonce,
+            ciphertext,
+        )
+    }
+
+    /// Creates an envelope of format `version` for `ciphertext` encrypted with the cipher named
+    /// `cipher_name`, under a key obtained with `key_strategy`.
+    pub(crate) fn from_parts(
+        version: u32,
+        cipher_name: &str,
+        key_strategy: KeyStrategy,
+        nonce: Vec<u8>,
+        ciphertext: Vec<u8>,
+    ) -> Self {
+        Envelope {
+            version,
+            cipher_name: cipher_name.to_string(),
+            key_strategy,
+            key_id: None,
+            nonce,
+            ciphertext,
+        }
+    }
+
+    /// Records `key_id` as the ID of the key the value was encrypted with.
+    pub(crate) fn with_key_id(mut self, key_id: Option<&str>) -> Self {
+        self.key_id = key_id.map(str::to_string);
+        self
+    }
+
+    /// Returns `true` if `value` is marked as an envelope, whether or not it is well-formed.
+    pub fn is_envelope(value: &str) -> bool {
+        value.starts_with(PREFIX)
+    }
+
+    /// Parses a stored value in the envelope format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `EnvelopeError` describing why `value` is not a valid envelope.
+    pub fn parse(value: &str) -> Result<Self, EnvelopeError> {
+        Self::parse_with(value, None)
+    }
+
+    /// Parses a stored value in the envelope format, also accepting envelopes that name
+    /// `custom` in addition to the built-in ciphers.
+    pub(crate) fn parse_with(
+        value: &str,
+        custom: Option<&dyn EnvCipher>,
+    ) -> Result<Self, EnvelopeError> {
+        let inner = value
+            .strip_prefix(PREFIX)
+            .and_then(|rest| rest.strip_suffix(SUFFIX))
+            .ok_or(EnvelopeError::Malformed)?;
+
+        let fields: Vec<&str> = inner.split(':').collect();
+        let (version, cipher_name, tags, payload) = match fields[..] {
+            [version, cipher_name, ref tags @ .., payload] if tags.len() <= 2 => {
+                (version, cipher_name, tags, payload)
+            }
+            _ => return Err(EnvelopeError::Malformed),
+        };
+        // The key strategy comes first and the key ID last, each optional.
+        let (strategy_tag, key_id) = match *tags {
+            [] => (None, None),
+            [tag] => match key_id_field(tag) {
+                Some(key_id) => (None, Some(key_id)),
+                None => (Some(tag), None),
+            },
+            [tag, key_id] => match (key_id_field(tag), key_id_field(key_id)) {
+                (None, Some(key_id)) => (Some(tag), Some(key_id)),
+                _ => return Err(EnvelopeError::Malformed),
+            },
+            _ => return Err(EnvelopeError::Malformed),
+        };
+        if key_id.is_some_and(|key_id| !is_valid_key_id(key_id)) {
+            return Err(EnvelopeError::Malformed);
+        }
+
+        let version: u32 = version
+            .strip_prefix('v')
+            .and_then(|version| version.parse().ok())
+            .ok_or(EnvelopeError::Malformed)?;
+        if !(1..=FORMAT_VERSION).contains(&version) {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+        if !tags.is_empty() && version < NAME_BOUND_VERSION {
+            return Err(EnvelopeError::Malformed);
+        }
+        let key_strategy = match strategy_tag {
+            None => KeyStrategy::Direct,
+            Some(tag) => KeyStrategy::from_tag(tag)
+                .ok_or_else(|| EnvelopeError::UnknownKeyStrategy(tag.to_string()))?,
+        };
+
+        let nonce_size = custom
+            .filter(|cipher| cipher.name() == cipher_name)
+            .map(|cipher| cipher.nonce_size())
+            .or_else(|| builtin_cipher(cipher_name).map(|cipher_type| cipher_type.nonce_size()))
+            .ok_or_else(|| EnvelopeError::UnknownCipher(cipher_name.to_string()))?;
+
+        let mut combined = STANDARD
+            .decode(payload)
+            .map_err(|_| EnvelopeError::InvalidBase64)?;
+        if combined.len() < nonce_size {
+            return Err(EnvelopeError::TooShort);
+        }
+        let ciphertext = combined.split_off(nonce_size);
+
+        Ok(Envelope {
+            version,
+            cipher_name: cipher_name.to_string(),
+            key_strategy,
+            key_id: key_id.map(str::to_string),
+            nonce: combined,
+            ciphertext,
+        })
+    }
+
+    /// Returns the format version of the envelope.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the built-in cipher the value was encrypted with, or `None` if it was
+    /// encrypted with a custom `EnvCipher`.
+    pub fn cipher_type(&self) -> Option<CipherType> {
+        builtin_cipher(&self.cipher_name)
+    }
+
+    /// Returns the name of the cipher the value was encrypted with.
+    pub fn cipher_name(&self) -> &str {
+        &self.cipher_name
+    }
+
+    /// Returns how the key that encrypted the value was obtained.
+    pub fn key_strategy(&self) -> KeyStrategy {
+        self.key_strategy
+    }
+
+    /// Returns the ID of the `Keyring` key the value was encrypted with, if it records one.
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    /// Returns the nonce the value was encrypted with.
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    /// Returns the ciphertext, including its authentication tag.
+    pub fn ciphertext(&self) -> &[u8] {
+        &self.ciphertext
+    }
+
+    /// Returns the associated data a value stored under `var_name` is authenticated with in
+    /// this version of the format.
+    pub(crate) fn aad<'a>(&self, var_name: &'a str) -> &'a [u8] {
+        if self.version >= NAME_BOUND_VERSION {
+            var_name.as_bytes()
+        } else {
+            &[]
+        }
+    }
+
+    /// Decrypts the envelope of the value stored under `var_name` with `key`, using `custom` if
+    /// the envelope names it and the built-in cipher of that name otherwise.
+    pub(crate) fn open(
+        &self,
+        custom: &dyn EnvCipher,
+        key: &[u8],
+        var_name: &str,
+    ) -> Result<Vec<u8>, EnvEncError> {
+        let builtin;
+        let cipher = if custom.name() == self.cipher_name {
+            custom
+        } else {
+            builtin = self
+                .cipher_type()
+                .ok_or(EnvEncError::AuthenticationFailed)?;
+            &builtin
+        };
+        validate_key_nonce(cipher, key, &self.nonce)?;
+        let key = self.key_strategy.derive(key, var_name, cipher.key_size());
+        cipher.open(&key, &self.nonce, &self.ciphertext, self.aad(var_name))
+    }
+}
+
+/// Returns the key ID recorded by the envelope field `tag`, or `None` if it records none.
+fn key_id_field(tag: &str) -> Option<&str> {
+    tag.strip_prefix(KEY_ID_PREFIX)
+}
+
+/// Returns the built-in cipher named `name`, as written by `Display`.
+pub(crate) fn builtin_cipher(name: &str) -> Option<CipherType> {
+    CIPHER_TYPES
+        .into_iter()
+        .find(|cipher_type| cipher_type.as_str() == name)
+}
+
+impl std::fmt::Display for Envelope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut combined = Vec::with_capacity(self.nonce.len() + self.ciphertext.len());
+        combined.extend_from_slice(&self.nonce);
+        combined.extend_from_slice(&self.ciphertext);
+        write!(f, "{}v{}:{}:", PREFIX, self.version, self.cipher_name)?;
+        if let Some(tag) = self.key_strategy.tag() {
+            write!(f, "{}:", tag)?;
+        }
+        if let Some(key_id) = &self.key_id {
+            write!(f, "{}{}:", KEY_ID_PREFIX, key_id)?;
+        }
+        write!(f, "{}{}", STANDARD.encode(combined), SUFFIX)
+    }
+}
+
+/// Why a stored value could not be parsed as an `Envelope`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EnvelopeError {
+    /// The value is not of the form `ENC[v<version>:<cipher>:<payload>]`.
+    Malformed,
+    /// The envelope was written by a newer, unsupported version of the format.
+    UnsupportedVersion(u32),
+    /// The envelope names a cipher this crate does not support.
+    UnknownCipher(String),
+    /// The envelope names a key strategy this crate does not support.
+    UnknownKeyStrategy(String),
+    /// The payload is not valid base64.
+    InvalidBase64,
+    /// The payload is too short to contain a nonce.
+    TooShort,
+}
+
+impl std::fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EnvelopeError::Malformed => write!(f, "malformed envelope"),
+            EnvelopeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported envelope version v{}", version)
+            }
+            EnvelopeError::UnknownCipher(name) => write!(f, "unknown cipher '{}'", name),
+            EnvelopeError::UnknownKeyStrategy(tag) => write!(f, "unknown key strategy '{}'", tag),
+            EnvelopeError::InvalidBase64 => write!(f, "invalid base64 payload"),
+            EnvelopeError::TooShort => write!(f, "payload too short to contain a nonce"),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
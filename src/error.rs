@@ -0,0 +1,583 @@
+use crate::{EnvelopeError, KeyFileError, KeyProviderError, KeyShareError};
+use std::{path::PathBuf, time::Duration};
+
+/// Errors returned by EnvEnc operations.
+///
+/// # Examples
+///
+/// A truncated key is rejected before any cryptography happens:
+///
+/// ```
+/// use envenc::{CipherType, EnvEncError, Key};
+///
+/// let short_key = [0u8; 16];
+/// let result = Key::new(CipherType::AES256GCM, &short_key[..]);
+/// assert!(matches!(
+///     result,
+///     Err(EnvEncError::InvalidKeyLength { expected: 32, actual: 16, .. })
+/// ));
+/// ```
+///
+/// Garbage hex in a stored key is reported instead of panicking:
+///
+/// ```
+/// use envenc::{keys_generation_cached, CacheLocation, CipherType, EnvEncError};
+///
+/// std::env::set_var("AES256GCM_KEY", "not hex at all");
+/// let result = keys_generation_cached(CipherType::AES256GCM, CacheLocation::ProcessEnv);
+/// assert!(matches!(result, Err(EnvEncError::InvalidHex(_))));
+/// ```
+///
+/// A stored value that does not authenticate names the variable that failed:
+///
+/// ```
+/// use envenc::{decrypt_env, keys_generation, read_env_enc, CipherType, EnvEncError, Envelope};
+///
+/// # let dir = std::env::temp_dir().join(format!("envenc-doc-{}", std::process::id()));
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # std::env::set_current_dir(&dir).unwrap();
+/// let cipher_type = CipherType::AES256GCM;
+/// let forged = Envelope::new(cipher_type, vec![0xab; 12], vec![0xab; 28]);
+/// std::fs::write(".env", format!("API_KEY={}\n", forged)).unwrap();
+///
+/// let (key, nonce) = keys_generation(cipher_type).unwrap();
+/// let report = decrypt_env(read_env_enc().unwrap(), cipher_type, &key, &nonce).unwrap();
+/// let result = report.into_result();
+/// assert!(matches!(result, Err(EnvEncError::DecryptionFailed { var }) if var == "API_KEY"));
+/// ```
+///
+/// A `.env` that cannot be written surfaces as an IO error:
+///
+/// ```
+/// use envenc::{keys_generation, set_enc_env, CipherType, EnvEncError, Key};
+///
+/// # let dir = std::env::temp_dir().join(format!("envenc-doc-{}", std::process::id()));
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # std::env::set_current_dir(&dir).unwrap();
+/// // A directory in place of the `.env` file cannot be opened for writing.
+/// std::fs::create_dir_all(".env").unwrap();
+///
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type).unwrap();
+/// let key = Key::new(cipher_type, key).unwrap();
+/// let result = set_enc_env("API_KEY", "secret", cipher_type, &key);
+/// assert!(matches!(result, Err(EnvEncError::Io(_))));
+/// ```
+#[derive(Debug)]
+pub enum EnvEncError {
+    /// An IO error occurred while reading or writing the `.env` file.
+    Io(std::io::Error),
+    /// A value that should be hex-encoded could not be decoded.
+    InvalidHex(hex::FromHexError),
+    /// The key does not have the length required by the cipher.
+    InvalidKeyLength {
+        cipher: String,
+        expected: usize,
+        actual: usize,
+    },
+    /// The nonce does not have the length required by the cipher.
+    InvalidNonceLength {
+        cipher: String,
+        expected: usize,
+        actual: usize,
+    },
+    /// The key or nonce was created for another cipher than the one it was used with.
+    CipherMismatch { expected: String, actual: String },
+    /// The underlying cipher failed to encrypt the plaintext.
+    EncryptionFailed,
+    /// The ciphertext could not be authenticated, either because the wrong key was used
+    /// or because the data was corrupted or tampered with.
+    AuthenticationFailed,
+    /// The stored value of an environment variable could not be decrypted.
+    DecryptionFailed { var: String },
+    /// The stored value of an environment variable is marked as an envelope but cannot be parsed.
+    InvalidEnvelope { var: String, reason: EnvelopeError },
+    /// The decrypted value of an environment variable is not valid UTF-8.
+    InvalidUtf8 { var: String },
+    /// No key was provided when building a session.
+    MissingKey,
+    /// The environment variable that should hold the key is not set.
+    MissingKeyVar { var: String },
+    /// The key is neither valid hex nor valid base64.
+    InvalidKeyEncoding,
+    /// The environment variable is not stored in the env file.
+    VarNotFound { var: String },
+    /// The listed variables could not be decrypted, so the requested rewrite of the env file
+    /// was not performed.
+    UndecryptableEntries { vars: Vec<String> },
+    /// Another writer held the lock on the env file for longer than the timeout.
+    LockTimeout { path: PathBuf, timeout: Duration },
+    /// The env file can be read or written by users other than its owner, and the session was
+    /// built with strict permission checks. `mode` holds the Unix permission bits.
+    InsecurePermissions { path: PathBuf, mode: u32 },
+    /// The key could not be derived from the passphrase, because the key derivation
+    /// parameters are out of range.
+    KeyDerivationFailed,
+    /// The env file has no key derivation header, so no key can be derived from a passphrase.
+    MissingKdfHeader { path: PathBuf },
+    /// The key derivation header of the env file cannot be parsed.
+    InvalidKdfHeader { path: PathBuf },
+    /// The env file records a different key derivation function than the one requested.
+    KdfMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The key file cannot be loaded.
+    InvalidKeyFile { path: PathBuf, reason: KeyFileError },
+    /// The OS keyring is locked or cannot be reached. `reason` holds the message of the
+    /// keyring backend.
+    KeyringUnavailable { reason: String },
+    /// The OS keyring holds no key for the service and user.
+    MissingKeyringEntry { service: String, user: String },
+    /// The env file was written with a different key. `expected` is the fingerprint recorded
+    /// in the file and `got` the fingerprint of the key supplied, as returned by
+    /// `key_fingerprint`.
+    KeyMismatch { expected: String, got: String },
+    /// A key ID is not 1 to 32 ASCII letters, digits, `-`, `_`, or `.`.
+    InvalidKeyId { id: String },
+    /// The value of the variable was encrypted under a key ID that is not in the keyring.
+    UnknownKeyId { var: String, id: String },
+    /// The data key of the env file cannot be unwrapped with the key supplied, or its header
+    /// line is malformed.
+    InvalidDataKey { path: PathBuf },
+    /// The env file has no data key to re-wrap.
+    MissingDataKey { path: PathBuf },
+    /// Data key mode was requested for an env file that already holds values encrypted
+    /// without a data key.
+    DataKeyConflict { path: PathBuf },
+    /// The key provider named by `provider` could not wrap or unwrap the data key.
+    KeyProviderFailed {
+        provider: String,
+        reason: KeyProviderError,
+    },
+    /// The data key of the env file is wrapped by the key provider named by `provider`, which
+    /// was not supplied.
+    KeyProviderRequired { path: PathBuf, provider: String },
+    /// The data key of the env file is wrapped with a passphrase, which was not supplied.
+    PassphraseRequired { path: PathBuf },
+    /// Key shares cannot be created, parsed, or combined.
+    InvalidKeyShare { reason: KeyShareError },
+    /// The key file holding one half of a split key does not exist.
+    MissingKeyFile { path: PathBuf },
+    /// The two halves of a split key differ in length.
+    SplitKeyLengthMismatch { env_len: usize, file_len: usize },
+    /// The file is not a sealed env file, or it cannot be decrypted, either because the wrong
+    /// key was used or because it was corrupted or tampered with.
+    InvalidSealedFile { path: PathBuf },
+    /// The env file is sealed, so it can only be changed with its key.
+    SealedFile { path: PathBuf },
+    /// The obfuscated name of `var` is already taken by the variable `existing`.
+    NameTagCollision { var: String, existing: String },
+    /// Obfuscated names cannot keep `len` bytes of the HMAC.
+    InvalidNameTagLength { len: usize },
+    /// The environment variable is already stored in the env file.
+    VarExists { var: String },
+    /// The document of secrets to import cannot be parsed, or is not a flat map of names to
+    /// values.
+    InvalidImport { reason: String },
+    /// The value of the environment variable spans several lines, which files of `format`
+    /// cannot hold.
+    MultilineValue { var: String, format: &'static str },
+    /// The value of the environment variable cannot be written to files of `format` without
+    /// being read back differently, for `reason`.
+    UnsupportedValue {
+        var: String,
+        format: &'static str,
+        reason: &'static str,
+    },
+    /// The plaintext value of the environment variable in a plaintext env file cannot be
+    /// parsed, for `reason`.
+    InvalidPlaintextValue { var: String, reason: &'static str },
+    /// The environment variable is set on more than one line of the env file, at the 1-based
+    /// line numbers `lines`, and duplicates are not allowed by `DuplicatePolicy::Strict`.
+    DuplicateKey { name: String, lines: Vec<usize> },
+    /// The age recipient is neither an age nor an SSH public key.
+    InvalidAgeRecipient { recipient: String },
+    /// Encrypting or decrypting a key file with age failed.
+    AgeFailed { reason: String },
+    /// The string is not the base64 of an X25519 public key, or the key is a low-order point
+    /// that values cannot be encrypted to.
+    InvalidPublicKey { key: String },
+    /// A value cannot be encrypted to an empty list of recipients.
+    NoRecipients,
+    /// The value of the environment variable was not encrypted to the public key of the
+    /// identity it was decrypted with.
+    NotARecipient { var: String },
+    /// The string is not the base64 of a valid Ed25519 verifying key.
+    InvalidVerifyingKey { key: String },
+    /// The env file has no signature line, but a signature is required.
+    MissingSignature { path: PathBuf },
+    /// The signature of the env file does not match its entries or the verifying key, so the
+    /// file was changed after it was signed or signed with another key.
+    InvalidSignature { path: PathBuf },
+    /// The MAC footer of the env file does not match its entries, or is missing where one is
+    /// required, so the file was modified outside envenc, or was written with another key.
+    IntegrityError { path: PathBuf },
+    /// The env file has a MAC footer, which cannot be updated without its symmetric key.
+    MacKeyRequired { path: PathBuf },
+    /// The env file is already initialized for envenc.
+    AlreadyInitialized { path: PathBuf },
+    /// A required env file of a profile, or an env file to merge, does not exist.
+    MissingEnvFile { path: PathBuf },
+    /// The env file was changed by another writer since the session read it. Reload the
+    /// session with `EnvEnc::reload` and try again.
+    ConcurrentModification { path: PathBuf },
+    /// The env file has no backup to restore.
+    NoBackup { path: PathBuf },
+    /// Two env files that are merged set the environment variable to different values.
+    MergeConflict {
+        var: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    /// The environment variable that is read is not set.
+    MissingVar { var: String },
+    /// The required environment variables are not set, sorted by name.
+    MissingVars { vars: Vec<String> },
+    /// The value of the environment variable cannot be read as the type that was asked for, for
+    /// `reason`, which never includes the value.
+    InvalidValue { var: String, reason: String },
+    /// The schema file cannot be parsed, for `reason`.
+    InvalidSchema { reason: String },
+    /// The fields of a config cannot be read from the environment variables, for each of the
+    /// `errors`, which are `MissingVar` and `InvalidValue` errors.
+    InvalidConfig { errors: Vec<EnvEncError> },
+}
+
+impl std::fmt::Display for EnvEncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EnvEncError::Io(err) => write!(f, "io error: {}", err),
+            EnvEncError::InvalidHex(err) => write!(f, "invalid hex encoding: {}", err),
+            EnvEncError::InvalidKeyLength {
+                cipher,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "invalid key length for {}: expected {} bytes, got {}",
+                cipher, expected, actual
+            ),
+            EnvEncError::InvalidNonceLength {
+                cipher,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "invalid nonce length for {}: expected {} bytes, got {}",
+                cipher, expected, actual
+            ),
+            EnvEncError::CipherMismatch { expected, actual } => write!(
+                f,
+                "cipher mismatch: the key or nonce is for {}, not {}",
+                actual, expected
+            ),
+            EnvEncError::EncryptionFailed => write!(f, "encryption failed"),
+            EnvEncError::AuthenticationFailed => {
+                write!(
+                    f,
+                    "decryption failed: ciphertext could not be authenticated"
+                )
+            }
+            EnvEncError::DecryptionFailed { var } => {
+                write!(f, "failed to decrypt environment variable '{}'", var)
+            }
+            EnvEncError::InvalidEnvelope { var, reason } => write!(
+                f,
+                "stored value of environment variable '{}' is not a valid envelope: {}",
+                var, reason
+            ),
+            EnvEncError::InvalidUtf8 { var } => write!(
+                f,
+                "decrypted value of environment variable '{}' is not valid UTF-8",
+                var
+            ),
+            EnvEncError::MissingKey => write!(f, "no encryption key was provided"),
+            EnvEncError::MissingKeyVar { var } => {
+                write!(
+                    f,
+                    "environment variable '{}' holding the key is not set",
+                    var
+                )
+            }
+            EnvEncError::InvalidKeyEncoding => {
+                write!(f, "the key is neither valid hex nor valid base64")
+            }
+            EnvEncError::VarNotFound { var } => {
+                write!(
+                    f,
+                    "environment variable '{}' is not stored in the env file",
+                    var
+                )
+            }
+            EnvEncError::UndecryptableEntries { vars } => write!(
+                f,
+                "env file was not modified because these variables could not be decrypted: {}",
+                vars.join(", ")
+            ),
+            EnvEncError::LockTimeout { path, timeout } => write!(
+                f,
+                "timed out after {:?} waiting for the lock on '{}'",
+                timeout,
+                path.display()
+            ),
+            EnvEncError::InsecurePermissions { path, mode } => write!(
+                f,
+                "'{}' is accessible by other users (mode {:o})",
+                path.display(),
+                mode
+            ),
+            EnvEncError::KeyDerivationFailed => {
+                write!(f, "failed to derive a key with the given parameters")
+            }
+            EnvEncError::MissingKdfHeader { path } => write!(
+                f,
+                "'{}' has no key derivation header to derive a key from",
+                path.display()
+            ),
+            EnvEncError::InvalidKdfHeader { path } => write!(
+                f,
+                "the key derivation header of '{}' is malformed",
+                path.display()
+            ),
+            EnvEncError::KdfMismatch { expected, found } => write!(
+                f,
+                "env file keys are derived with {}, not {}",
+                found, expected
+            ),
+            EnvEncError::InvalidKeyFile { path, reason } => {
+                write!(f, "invalid key file '{}': {}", path.display(), reason)
+            }
+            EnvEncError::KeyringUnavailable { reason } => {
+                write!(f, "the OS keyring is unavailable: {}", reason)
+            }
+            EnvEncError::MissingKeyringEntry { service, user } => write!(
+                f,
+                "the OS keyring has no key for service '{}' and user '{}'",
+                service, user
+            ),
+            EnvEncError::KeyMismatch { expected, got } => write!(
+                f,
+                "wrong key: the env file was written with key {}, but key {} was supplied",
+                expected, got
+            ),
+            EnvEncError::InvalidKeyId { id } => write!(f, "invalid key ID '{}'", id),
+            EnvEncError::UnknownKeyId { var, id } => write!(
+                f,
+                "'{}' is encrypted under key ID '{}', which is not in the keyring",
+                var, id
+            ),
+            EnvEncError::InvalidDataKey { path } => write!(
+                f,
+                "the data key of '{}' cannot be unwrapped: wrong key or corrupt header",
+                path.display()
+            ),
+            EnvEncError::MissingDataKey { path } => {
+                write!(f, "'{}' has no data key", path.display())
+            }
+            EnvEncError::DataKeyConflict { path } => write!(
+                f,
+                "'{}' already holds values encrypted without a data key",
+                path.display()
+            ),
+            EnvEncError::KeyProviderFailed { provider, reason } => {
+                write!(f, "key provider '{}' failed: {}", provider, reason)
+            }
+            EnvEncError::KeyProviderRequired { path, provider } => write!(
+                f,
+                "the data key of '{}' is wrapped by key provider '{}'",
+                path.display(),
+                provider
+            ),
+            EnvEncError::PassphraseRequired { path } => write!(
+                f,
+                "the data key of '{}' is wrapped with a passphrase",
+                path.display()
+            ),
+            EnvEncError::InvalidKeyShare { reason } => write!(f, "invalid key shares: {}", reason),
+            EnvEncError::MissingKeyFile { path } => {
+                write!(f, "key file '{}' does not exist", path.display())
+            }
+            EnvEncError::SplitKeyLengthMismatch { env_len, file_len } => write!(
+                f,
+                "the halves of the split key differ in length: {} bytes in the environment, {} in the file",
+                env_len, file_len
+            ),
+            EnvEncError::InvalidSealedFile { path } => write!(
+                f,
+                "'{}' cannot be decrypted as a sealed env file: wrong key or corrupt file",
+                path.display()
+            ),
+            EnvEncError::SealedFile { path } => write!(
+                f,
+                "'{}' is a sealed env file and can only be changed with its key",
+                path.display()
+            ),
+            EnvEncError::NameTagCollision { var, existing } => write!(
+                f,
+                "the obfuscated name of '{}' collides with that of '{}'; use longer name tags",
+                var, existing
+            ),
+            EnvEncError::InvalidNameTagLength { len } => write!(
+                f,
+                "obfuscated names keep 1 to 32 bytes of the HMAC, not {}",
+                len
+            ),
+            EnvEncError::VarExists { var } => write!(
+                f,
+                "environment variable '{}' is already stored in the env file",
+                var
+            ),
+            EnvEncError::InvalidImport { reason } => {
+                write!(f, "cannot import secrets: {}", reason)
+            }
+            EnvEncError::MultilineValue { var, format } => write!(
+                f,
+                "the value of environment variable '{}' spans several lines, which {} files cannot hold",
+                var, format
+            ),
+            EnvEncError::UnsupportedValue {
+                var,
+                format,
+                reason,
+            } => write!(
+                f,
+                "the value of environment variable '{}' cannot be written to {} files: {}",
+                var, format, reason
+            ),
+            EnvEncError::InvalidPlaintextValue { var, reason } => write!(
+                f,
+                "the plaintext value of environment variable '{}' cannot be parsed: {}",
+                var, reason
+            ),
+            EnvEncError::InvalidAgeRecipient { recipient } => {
+                write!(f, "'{}' is not an age or SSH public key", recipient)
+            }
+            EnvEncError::AgeFailed { reason } => write!(f, "age failed: {}", reason),
+            EnvEncError::InvalidPublicKey { key } => {
+                write!(f, "invalid X25519 public key: {:?}", key)
+            }
+            EnvEncError::NoRecipients => write!(f, "no recipients to encrypt to"),
+            EnvEncError::NotARecipient { var } => write!(
+                f,
+                "environment variable '{}' was not encrypted to this identity",
+                var
+            ),
+            EnvEncError::InvalidVerifyingKey { key } => {
+                write!(f, "invalid Ed25519 verifying key: {:?}", key)
+            }
+            EnvEncError::MissingSignature { path } => {
+                write!(f, "env file '{}' is not signed", path.display())
+            }
+            EnvEncError::InvalidSignature { path } => write!(
+                f,
+                "the signature of env file '{}' does not match its entries",
+                path.display()
+            ),
+            EnvEncError::IntegrityError { path } => write!(
+                f,
+                "env file '{}' was modified outside envenc: its MAC is missing or does not match its entries",
+                path.display()
+            ),
+            EnvEncError::DuplicateKey { name, lines } => write!(
+                f,
+                "'{}' is set more than once in the env file, on lines {}",
+                name,
+                join_lines(lines)
+            ),
+            EnvEncError::MacKeyRequired { path } => write!(
+                f,
+                "env file '{}' has a MAC, which can only be updated with its key",
+                path.display()
+            ),
+            EnvEncError::AlreadyInitialized { path } => write!(
+                f,
+                "env file '{}' is already initialized; overwrite it to start over",
+                path.display()
+            ),
+            EnvEncError::MissingEnvFile { path } => {
+                write!(f, "env file '{}' does not exist", path.display())
+            }
+            EnvEncError::ConcurrentModification { path } => write!(
+                f,
+                "env file '{}' was changed since it was read; reload it and try again",
+                path.display()
+            ),
+            EnvEncError::NoBackup { path } => {
+                write!(f, "env file '{}' has no backup to restore", path.display())
+            }
+            EnvEncError::MergeConflict { var, first, second } => write!(
+                f,
+                "environment variable '{}' is set to different values in '{}' and '{}'",
+                var,
+                first.display(),
+                second.display()
+            ),
+            EnvEncError::MissingVar { var } => {
+                write!(f, "environment variable '{}' is not set", var)
+            }
+            EnvEncError::MissingVars { vars } => {
+                let mut vars = vars.clone();
+                vars.sort();
+                write!(
+                    f,
+                    "required environment variables are not set: {}",
+                    vars.join(", ")
+                )
+            }
+            EnvEncError::InvalidValue { var, reason } => write!(
+                f,
+                "the value of environment variable '{}' is invalid: {}",
+                var, reason
+            ),
+            EnvEncError::InvalidSchema { reason } => write!(f, "invalid schema: {}", reason),
+            EnvEncError::InvalidConfig { errors } => {
+                write!(f, "the config cannot be loaded: ")?;
+                for (index, err) in errors.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", err)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Returns the line numbers `lines` separated by commas.
+pub(crate) fn join_lines(lines: &[usize]) -> String {
+    lines
+        .iter()
+        .map(usize::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl std::error::Error for EnvEncError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EnvEncError::Io(err) => Some(err),
+            EnvEncError::InvalidHex(err) => Some(err),
+            EnvEncError::InvalidEnvelope { reason, .. } => Some(reason),
+            EnvEncError::InvalidKeyFile { reason, .. } => Some(reason),
+            EnvEncError::KeyProviderFailed { reason, .. } => Some(reason),
+            EnvEncError::InvalidKeyShare { reason } => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for EnvEncError {
+    fn from(err: std::io::Error) -> Self {
+        EnvEncError::Io(err)
+    }
+}
+
+impl From<hex::FromHexError> for EnvEncError {
+    fn from(err: hex::FromHexError) -> Self {
+        EnvEncError::InvalidHex(err)
+    }
+}
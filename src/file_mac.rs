@@ -0,0 +1,92 @@
+use crate::{env_file::canonical_entries, EnvEncError};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::path::Path;
+
+/// The prefix of the comment line that records the MAC of an env file.
+const MAC_PREFIX: &str = "# envenc mac:";
+
+/// The context string the serialization of the entries covered by the MAC starts with.
+const MAC_CONTEXT: &str = "envenc mac v1\n";
+
+/// The HKDF info string of the key the MAC is computed with, so that it never equals the key
+/// values are encrypted with.
+const MAC_KEY_INFO: &[u8] = b"envenc file mac key v1";
+
+/// Returns the MAC of the entries of `contents` under `key`, ready to be finalized or verified.
+fn file_mac(key: &[u8], contents: &str) -> Hmac<Sha256> {
+    let mut mac_key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(MAC_KEY_INFO, &mut mac_key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(&mac_key).expect("HMAC accepts keys of any length");
+    mac.update(&canonical_entries(MAC_CONTEXT, contents));
+    mac
+}
+
+/// Returns `true` if `contents` has the footer line written by `with_mac`.
+pub(crate) fn has_mac(contents: &str) -> bool {
+    contents.lines().any(|line| line.starts_with(MAC_PREFIX))
+}
+
+/// Returns `EnvEncError::IntegrityError` if `contents`, read from the env file at `path`, has a
+/// MAC footer that none of `keys` produces over its entries. Contents without the footer pass.
+pub(crate) fn check_mac(path: &Path, contents: &str, keys: &[&[u8]]) -> Result<(), EnvEncError> {
+    let Some(recorded) = contents
+        .lines()
+        .find_map(|line| line.strip_prefix(MAC_PREFIX))
+        .map(str::trim)
+    else {
+        return Ok(());
+    };
+    let matches = hex::decode(recorded).is_ok_and(|recorded| {
+        keys.iter()
+            .any(|key| file_mac(key, contents).verify_slice(&recorded).is_ok())
+    });
+    match matches {
+        true => Ok(()),
+        false => Err(EnvEncError::IntegrityError {
+            path: path.to_path_buf(),
+        }),
+    }
+}
+
+/// Returns `contents` with a `# envenc mac: <hex>` footer line recording the MAC of its
+/// entries under `key`, replacing any existing one. Every other line is kept as it is.
+pub(crate) fn with_mac(contents: &str, key: &[u8]) -> String {
+    let mut updated: String = contents
+        .split_inclusive('\n')
+        .filter(|line| !line.starts_with(MAC_PREFIX))
+        .collect();
+    if !updated.is_empty() && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    let mac = file_mac(key, contents).finalize().into_bytes();
+    updated.push_str(&format!("{} {}\n", MAC_PREFIX, hex::encode(mac)));
+    updated
+}
+
+/// Returns the `updated` contents of the env file at `path`, with their MAC footer brought up
+/// to date under `key` if `original` had one, or if `add` asks for one.
+///
+/// A writer without the symmetric key cannot update the MAC, so changing a file that has one
+/// without `key` fails with `EnvEncError::MacKeyRequired` instead of invalidating it.
+pub(crate) fn update_mac(
+    path: &Path,
+    original: &str,
+    updated: String,
+    key: Option<&[u8]>,
+    add: bool,
+) -> Result<String, EnvEncError> {
+    if !add && !has_mac(original) {
+        return Ok(updated);
+    }
+    match key {
+        Some(key) => Ok(with_mac(&updated, key)),
+        None => Err(EnvEncError::MacKeyRequired {
+            path: path.to_path_buf(),
+        }),
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{env_file::read_contents, EnvEncError};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// The prefix of the comment line that records the fingerprint of the key in an env file.
+const HEADER_PREFIX: &str = "# envenc key-id:";
+
+/// The number of bytes of the SHA-256 hash of the key kept in a fingerprint.
+const FINGERPRINT_SIZE: usize = 8;
+
+/// Returns the fingerprint of `key`: the first 8 bytes of its SHA-256 hash, as hex.
+///
+/// Env files written by this crate record the fingerprint of their key in a
+/// `# envenc key-id: <fingerprint>` header line, so that decrypting them with a different key
+/// fails with `EnvEncError::KeyMismatch` before any value is tried. The fingerprint reveals
+/// nothing useful about the key itself. Files without the header are read as before.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{decrypt_env_from, key_fingerprint, keys_generation, set_enc_env_in, CipherType, EnvEncError};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "FINGERPRINT_TOKEN", "value", cipher_type, &key)?;
+///
+/// let contents = std::fs::read_to_string(&path)?;
+/// assert_eq!(contents.lines().next(), Some(&*format!("# envenc key-id: {}", key_fingerprint(&key))));
+///
+/// // A teammate with the wrong key gets one clear error instead of a failure per value.
+/// let (wrong_key, _) = keys_generation(cipher_type)?;
+/// match decrypt_env_from(&path, cipher_type, &wrong_key) {
+///     Err(EnvEncError::KeyMismatch { expected, got }) => {
+///         assert_eq!(expected, key_fingerprint(&key));
+///         assert_eq!(got, key_fingerprint(&wrong_key));
+///     }
+///     other => panic!("expected a key mismatch, got {:?}", other),
+/// }
+/// assert!(matches!(
+///     set_enc_env_in(&path, "OTHER_TOKEN", "value", cipher_type, &wrong_key),
+///     Err(EnvEncError::KeyMismatch { .. })
+/// ));
+///
+/// // Files without the header still work.
+/// std::fs::write(&path, contents.lines().skip(1).collect::<Vec<_>>().join("\n"))?;
+/// assert_eq!(decrypt_env_from(&path, cipher_type, &key)?.decrypted, ["FINGERPRINT_TOKEN"]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn key_fingerprint(key: &[u8]) -> String {
+    hex::encode(&Sha256::digest(key)[..FINGERPRINT_SIZE])
+}
+
+/// Returns the key fingerprint recorded in the header line of the env file at `path`, without
+/// decrypting anything, or `None` if the file does not exist or has no header line.
+///
+/// Compare it with `key_fingerprint` to tell which key a file expects. In data key mode, the
+/// header records the fingerprint of the data key, not of the key that wraps it.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::Io` if the file exists but cannot be read.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{key_fingerprint, keys_generation, recorded_fingerprint, set_enc_env_in, CipherType};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// assert_eq!(recorded_fingerprint(&path)?, None);
+///
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "RECORDED_TOKEN", "value", cipher_type, &key)?;
+/// assert_eq!(recorded_fingerprint(&path)?, Some(key_fingerprint(&key)));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn recorded_fingerprint(path: impl AsRef<Path>) -> Result<Option<String>, EnvEncError> {
+    Ok(read_contents(path.as_ref())?.and_then(|contents| {
+        contents
+            .lines()
+            .find_map(|line| line.strip_prefix(HEADER_PREFIX))
+            .map(|fingerprint| fingerprint.trim().to_string())
+    }))
+}
+
+/// Returns `EnvEncError::KeyMismatch` if the header line of `contents` records the fingerprint
+/// of a key other than any of `keys`, reporting the fingerprint of the first one.
+pub(crate) fn check_fingerprint(contents: &str, keys: &[&[u8]]) -> Result<(), EnvEncError> {
+    let recorded = contents
+        .lines()
+        .find_map(|line| line.strip_prefix(HEADER_PREFIX))
+        .map(str::trim);
+    match recorded {
+        Some(expected) if !keys.iter().any(|key| key_fingerprint(key) == expected) => {
+            Err(EnvEncError::KeyMismatch {
+                expected: expected.to_string(),
+                got: keys
+                    .first()
+                    .map(|key| key_fingerprint(key))
+                    .unwrap_or_default(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns `true` if `line` is the header line recording the fingerprint of the key.
+pub(crate) fn is_header(line: &str) -> bool {
+    line.starts_with(HEADER_PREFIX)
+}
+
+/// Returns `contents` with a header line recording the fingerprint of `key` as its first line,
+/// replacing any existing one. Every other line is kept as it is.
+pub(crate) fn with_fingerprint(contents: &str, key: &[u8]) -> String {
+    let mut updated = format!("{} {}\n", HEADER_PREFIX, key_fingerprint(key));
+    for line in contents.split_inclusive('\n') {
+        if !line.starts_with(HEADER_PREFIX) {
+            updated.push_str(line);
+        }
+    }
+    updated
+}
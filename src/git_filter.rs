@@ -0,0 +1,247 @@
+use crate::{
+    cipher::BatchCipher,
+    data_key::{unwrap_data_key, KeyProtection},
+    decrypt_stored_value, dotenvx, encode_stored_value,
+    env_file::{edit_lines, parse_entries, LineEdit},
+    env_syntax::{logical_lines, parse_value, quote_value, split_line, strip_bom},
+    file_mac::update_mac,
+    fingerprint::{check_fingerprint, with_fingerprint},
+    is_key_material, random_bytes, validate_key, CipherType, EnvEncError, Envelope, KeyStrategy,
+    SecretValue, StoredValueError,
+};
+use std::{collections::HashMap, path::Path};
+
+/// Encrypts the plaintext env file `plaintext`, as the `clean` filter of git does before a file
+/// is staged, so that the repository only holds ciphertext.
+///
+/// `previous` is the encrypted version of the file that is already staged or committed, if
+/// any. A value that is unchanged from it keeps its stored ciphertext, so cleaning the same
+/// plaintext again produces the same output and git does not report the file as modified
+/// after every checkout. Changed and new values are encrypted with fresh nonces.
+///
+/// Values are read the way dotenv loaders read them: unquoted, in single quotes taken
+/// literally, or in double quotes where a backslash escapes `\`, `'`, `"`, `$`, or a space, and
+/// `\n`, `\r`, and `\t` are control characters. `$` references are stored as they are, not substituted. Values that
+/// are already encrypted, comments, including inline ones, and the order of the entries are
+/// kept.
+///
+/// `path` is the name of the file in the repository, used in errors.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::InvalidPlaintextValue` if a value cannot be parsed, such as one with
+/// an unterminated quote, `EnvEncError::KeyMismatch` if the header of `plaintext` records
+/// another key, `EnvEncError::InvalidDataKey` if its data key cannot be unwrapped with `key`,
+/// and `EnvEncError::InvalidKeyLength` if `key` does not match `cipher_type`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{clean_env, keys_generation, smudge_env, CipherType};
+///
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// let plaintext = "# Local settings\n\
+///                  API_KEY=sk_live_123\n\
+///                  GREETING=\"it's a \\\"quoted\\\" line\\nand another\"\n\
+///                  LITERAL='$HOME stays as written'\n";
+///
+/// // `git add` runs the clean filter: the repository only sees ciphertext.
+/// let staged = clean_env(".env", plaintext, None, cipher_type, &key)?;
+/// assert!(!staged.contains("sk_live_123"));
+/// assert!(staged.contains("# Local settings\nAPI_KEY=ENC[v2:AES256GCM:"));
+///
+/// // Cleaning the same plaintext again reuses the staged ciphertext, so nothing changes.
+/// assert_eq!(clean_env(".env", plaintext, Some(&staged), cipher_type, &key)?, staged);
+///
+/// // `git checkout` runs the smudge filter, and reading the result back changes nothing.
+/// let checked_out = smudge_env(".env", &staged, cipher_type, &key)?;
+/// assert!(checked_out.contains("API_KEY=sk_live_123\n"));
+/// assert!(checked_out.contains("GREETING=\"it's a \\\"quoted\\\" line\\nand another\"\n"));
+/// assert!(checked_out.contains("LITERAL='$HOME stays as written'\n"));
+/// assert_eq!(clean_env(".env", &checked_out, Some(&staged), cipher_type, &key)?, staged);
+///
+/// // Only the changed value gets new ciphertext.
+/// let edited = checked_out.replace("sk_live_123", "sk_live_456");
+/// let restaged = clean_env(".env", &edited, Some(&staged), cipher_type, &key)?;
+/// let changed: Vec<_> = staged.lines().zip(restaged.lines()).filter(|(a, b)| a != b).collect();
+/// assert_eq!(changed.len(), 1);
+/// assert!(changed[0].1.starts_with("API_KEY="));
+/// assert!(smudge_env(".env", &restaged, cipher_type, &key)?.contains("API_KEY=sk_live_456\n"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn clean_env(
+    path: impl AsRef<Path>,
+    plaintext: &str,
+    previous: Option<&str>,
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+) -> Result<String, EnvEncError> {
+    let path = path.as_ref();
+    let key = key.as_ref();
+    let plaintext = strip_bom(plaintext);
+    validate_key(&cipher_type, key)?;
+    // The smudge filter keeps the header lines, including a wrapped data key.
+    let data_key = unwrap_data_key(path, plaintext, &cipher_type, &[KeyProtection::Key(key)])?;
+    let key = data_key.as_deref().unwrap_or(key);
+    check_fingerprint(plaintext, &[key])?;
+
+    let previous: HashMap<String, String> = previous
+        .map(parse_entries)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(_, name, value)| (name, value))
+        .collect();
+    let lines = logical_lines(plaintext).into_iter();
+    for line in lines.filter_map(|(_, line)| split_line(line.trim_end_matches(['\r', '\n']))) {
+        if let Err(reason) = parse_value(line.rhs) {
+            return Err(EnvEncError::InvalidPlaintextValue {
+                var: line.name.to_string(),
+                reason,
+            });
+        }
+    }
+    let mut error = None;
+    let cipher = BatchCipher::new(&cipher_type);
+    let encrypted = edit_lines(plaintext, |var_name, value| {
+        if error.is_some()
+            || is_key_material(var_name)
+            || Envelope::is_envelope(dotenvx::strip_prefix(value).0)
+        {
+            return LineEdit::Keep;
+        }
+        let value = SecretValue::from(value.to_string());
+        if let Some(stored) = previous.get(var_name) {
+            let unchanged = decrypt_stored_value(&cipher, key, var_name, stored)
+                .is_ok_and(|old| SecretValue::from(old) == value);
+            if unchanged {
+                return LineEdit::Replace(stored.clone());
+            }
+        }
+        let nonce = random_bytes(cipher_type.nonce_size());
+        match encode_stored_value(
+            &cipher,
+            key,
+            None,
+            KeyStrategy::Direct,
+            var_name,
+            &nonce,
+            value.expose_secret().as_bytes(),
+        ) {
+            Ok(stored) => LineEdit::Replace(stored),
+            Err(err) => {
+                error = Some(err);
+                LineEdit::Keep
+            }
+        }
+    });
+    if let Some(err) = error {
+        return Err(err);
+    }
+    let encrypted = with_fingerprint(&encrypted, key);
+    update_mac(path, plaintext, encrypted, Some(key), false)
+}
+
+/// Decrypts the env file `contents`, as the `smudge` filter of git does when a file is
+/// checked out, into a plaintext env file that dotenv loaders read and `clean_env` encrypts
+/// back to `contents`.
+///
+/// Values are written unquoted when they only hold characters that need no quoting, in single
+/// quotes when they hold no single quote or control character, and in double quotes with escapes
+/// otherwise. Entries that are not encrypted, comments, and header lines are kept as they are.
+///
+/// `path` is the name of the file in the repository, used in errors.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::DecryptionFailed` or `EnvEncError::InvalidUtf8` for the first value
+/// that cannot be decrypted, `EnvEncError::UnsupportedValue` for a value with a carriage
+/// return, which dotenv loaders cannot read back, and the same errors as `clean_env` for the
+/// key.
+///
+/// # Example
+///
+/// See `clean_env` for the round trip through git. Each value below is written as shown and
+/// read back unchanged:
+///
+/// ```
+/// use envenc::{keys_generation, read_env_enc_from, set_enc_env_in, smudge_env, CipherType};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+///
+/// let cases = [
+///     ("sk_live_123", "sk_live_123"),
+///     ("https://user@example.com/db?x=1", "'https://user@example.com/db?x=1'"),
+///     ("", ""),
+///     ("two words", "'two words'"),
+///     (" # not a comment", "' # not a comment'"),
+///     ("$HOME \\ stays", "'$HOME \\ stays'"),
+///     ("it's", "\"it's\""),
+///     ("line one\nline two\tend", "\"line one\\nline two\\tend\""),
+///     ("say \"hi\" to $USER's \\", "\"say \\\"hi\\\" to \\$USER's \\\\\""),
+/// ];
+/// for (value, written) in cases {
+///     std::fs::remove_file(&path).ok();
+///     set_enc_env_in(&path, "VALUE", value, cipher_type, &key)?;
+///     let checked_out = smudge_env(".env", &std::fs::read_to_string(&path)?, cipher_type, &key)?;
+///     assert!(checked_out.contains(&format!("\nVALUE={}\n", written)), "{}", checked_out);
+///
+///     std::fs::write(&path, checked_out)?;
+///     assert_eq!(read_env_enc_from(&path)?["VALUE"], value);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn smudge_env(
+    path: impl AsRef<Path>,
+    contents: &str,
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+) -> Result<String, EnvEncError> {
+    let path = path.as_ref();
+    let key = key.as_ref();
+    let contents = strip_bom(contents);
+    validate_key(&cipher_type, key)?;
+    let data_key = unwrap_data_key(path, contents, &cipher_type, &[KeyProtection::Key(key)])?;
+    let key = data_key.as_deref().unwrap_or(key);
+    check_fingerprint(contents, &[key])?;
+
+    let mut error = None;
+    let decrypted = edit_lines(contents, |var_name, value| {
+        if error.is_some() {
+            return LineEdit::Keep;
+        }
+        let result = decrypt_stored_value(&cipher_type, key, var_name, value)
+            .map(SecretValue::from)
+            .and_then(|value| {
+                quote_plain_value(var_name, value.expose_secret()).map_err(StoredValueError::Failed)
+            });
+        match result {
+            Ok(quoted) => LineEdit::Replace(quoted),
+            Err(StoredValueError::Skipped(_)) => LineEdit::Keep,
+            Err(StoredValueError::Failed(err)) => {
+                error = Some(err);
+                LineEdit::Keep
+            }
+        }
+    });
+    match error {
+        Some(err) => Err(err),
+        None => Ok(decrypted),
+    }
+}
+
+/// Returns `value` as written in a plaintext env file, so that `clean_env` and dotenv loaders
+/// read it back unchanged.
+fn quote_plain_value(var_name: &str, value: &str) -> Result<String, EnvEncError> {
+    if value.contains('\r') {
+        return Err(EnvEncError::UnsupportedValue {
+            var: var_name.to_string(),
+            format: "plaintext env",
+            reason: "dotenv loaders cannot read a carriage return back",
+        });
+    }
+    Ok(quote_value(value))
+}
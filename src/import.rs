@@ -0,0 +1,415 @@
+use crate::{
+    env_syntax::{logical_lines, parse_value, split_line, strip_bom},
+    CipherType, EnvEnc, EnvEncError, Overwrite, SecretValue,
+};
+use serde_json::Value;
+use std::{collections::BTreeMap, io::Read, mem, path::Path};
+
+/// The format of the document `import_env` reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// A JSON object, as in `{"API_KEY": "value"}`.
+    Json,
+    /// A TOML table of `KEY = "value"` pairs. Requires the `toml` feature.
+    #[cfg(feature = "toml")]
+    Toml,
+    /// A YAML mapping of `KEY: value` pairs. Requires the `yaml` feature.
+    #[cfg(feature = "yaml")]
+    Yaml,
+    /// A systemd `EnvironmentFile=`, read the way systemd reads it: `KEY=value` lines, where
+    /// values may be single- or double-quoted, a backslash escapes the next character, and
+    /// lines starting with `#` or `;` are comments. A variable assigned twice takes its last
+    /// value.
+    SystemdEnvFile,
+    /// A dotenv file, read the way this crate reads env files: `KEY=value` lines that may start
+    /// with `export `, where values may be single- or double-quoted, a `#` outside quotes
+    /// starts a comment, and double-quoted values take escapes such as `\n`. A variable
+    /// assigned twice takes its last value.
+    ///
+    /// ```
+    /// use envenc::{get_enc_env_from, import_env, keys_generation, CipherType, ImportFormat, OnConflict};
+    ///
+    /// let dir = tempfile::tempdir()?;
+    /// let path = dir.path().join("secrets.env");
+    /// let cipher_type = CipherType::AES256GCM;
+    /// let (key, _) = keys_generation(cipher_type)?;
+    ///
+    /// let dotenv = concat!(
+    ///     "# Copied from the old server\n",
+    ///     "export API_KEY=sk_live_123 # the production key\n",
+    ///     "GREETING=\"hello # not a comment\\n\"\n",
+    ///     "PADDED='  kept  '\n",
+    /// );
+    /// import_env(dotenv.as_bytes(), ImportFormat::Dotenv, cipher_type, &key, &path, OnConflict::Skip)?;
+    /// let get = |name| get_enc_env_from(&path, name, cipher_type, &key);
+    /// assert_eq!(get("API_KEY")?.as_deref(), Some("sk_live_123"));
+    /// assert_eq!(get("GREETING")?.as_deref(), Some("hello # not a comment\n"));
+    /// assert_eq!(get("PADDED")?.as_deref(), Some("  kept  "));
+    ///
+    /// let broken = "API_KEY=\"unclosed\n";
+    /// let result = import_env(broken.as_bytes(), ImportFormat::Dotenv, cipher_type, &key, &path, OnConflict::Skip);
+    /// assert_eq!(result.unwrap_err().to_string(), "cannot import secrets: line 1 of the dotenv file is invalid: a quote is not closed");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    Dotenv,
+}
+
+/// What `import_env` does with a variable that is already stored in the env file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Keep the stored value.
+    #[default]
+    Skip,
+    /// Replace the stored value with the imported one.
+    Overwrite,
+    /// Fail with `EnvEncError::VarExists` before anything is imported.
+    Error,
+}
+
+/// The variables imported by `import_env`, sorted by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Variables that were encrypted and stored, including the ones that replaced a stored
+    /// value.
+    pub imported: Vec<String>,
+    /// Variables that were already stored and were kept, with `OnConflict::Skip`.
+    pub skipped: Vec<String>,
+}
+
+/// Reads a flat document of secrets from `reader` in `format`, encrypts each of them with a
+/// fresh nonce, and stores them in the env file at `path`.
+///
+/// Apart from systemd env files and dotenv files, the document must be a single object, table, or mapping whose
+/// values are strings, numbers, or booleans; numbers and booleans are stored as written. Nested objects and arrays are
+/// rejected rather than flattened, since there is no name for their entries that every tool
+/// would agree on, and nothing is imported in that case.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::InvalidImport` if the document cannot be parsed or is not flat,
+/// `EnvEncError::VarExists` if `on_conflict` is `OnConflict::Error` and a variable is already
+/// stored, and the same errors as `set_enc_env_in` otherwise.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{get_enc_env_from, import_env, keys_generation, set_enc_env_in, CipherType, EnvEncError, ImportFormat, OnConflict};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "API_KEY", "kept", cipher_type, &key)?;
+///
+/// let secrets = r#"{"API_KEY": "imported", "DB_PASSWORD": "hunter2", "PORT": 5432, "DEBUG": false}"#;
+/// let report = import_env(secrets.as_bytes(), ImportFormat::Json, cipher_type, &key, &path, OnConflict::Skip)?;
+/// assert_eq!(report.imported, ["DB_PASSWORD", "DEBUG", "PORT"]);
+/// assert_eq!(report.skipped, ["API_KEY"]);
+/// assert_eq!(get_enc_env_from(&path, "API_KEY", cipher_type, &key)?.as_deref(), Some("kept"));
+/// assert_eq!(get_enc_env_from(&path, "PORT", cipher_type, &key)?.as_deref(), Some("5432"));
+///
+/// let result = import_env(secrets.as_bytes(), ImportFormat::Json, cipher_type, &key, &path, OnConflict::Error);
+/// assert!(matches!(result, Err(EnvEncError::VarExists { var }) if var == "API_KEY"));
+///
+/// import_env(secrets.as_bytes(), ImportFormat::Json, cipher_type, &key, &path, OnConflict::Overwrite)?;
+/// assert_eq!(get_enc_env_from(&path, "API_KEY", cipher_type, &key)?.as_deref(), Some("imported"));
+///
+/// // Nested objects are rejected, and nothing is imported.
+/// let nested = r#"{"NEW_KEY": "value", "database": {"password": "hunter2"}}"#;
+/// let result = import_env(nested.as_bytes(), ImportFormat::Json, cipher_type, &key, &path, OnConflict::Skip);
+/// assert_eq!(
+///     result.unwrap_err().to_string(),
+///     "cannot import secrets: the value of 'database' is an object, not a string, number, or boolean"
+/// );
+/// assert_eq!(get_enc_env_from(&path, "NEW_KEY", cipher_type, &key)?, None);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// A systemd env file can be imported too, such as one written by `export_env_to_file`:
+///
+/// ```
+/// use envenc::{export_env_to_file, get_enc_env_from, import_env, keys_generation, CipherType, ExportFormat, ImportFormat, OnConflict};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+///
+/// let unit_env = concat!(
+///     "# Written by hand\n",
+///     "; also a comment\n",
+///     "  API_KEY = sk_live_123   \n",
+///     "QUOTED=\"say \\\"hi\\\" to $USER\\\\n\" 'and' more\n",
+///     "LITERAL='no \\escapes $here'\n",
+///     "CONTINUED=first \\\n",
+///     "second\n",
+///     "API_KEY=overridden\n",
+/// );
+/// import_env(unit_env.as_bytes(), ImportFormat::SystemdEnvFile, cipher_type, &key, &path, OnConflict::Skip)?;
+/// let get = |name| get_enc_env_from(&path, name, cipher_type, &key);
+/// assert_eq!(get("API_KEY")?.as_deref(), Some("overridden"));
+/// assert_eq!(get("QUOTED")?.as_deref(), Some(r#"say "hi" to $USER\nandmore"#));
+/// assert_eq!(get("LITERAL")?.as_deref(), Some(r"no \escapes $here"));
+/// assert_eq!(get("CONTINUED")?.as_deref(), Some("first second"));
+///
+/// // What `export_env_to_file` writes is imported back unchanged.
+/// let exported = dir.path().join("app.env");
+/// export_env_to_file(&path, ExportFormat::SystemdEnvFile, cipher_type, &key, &exported)?;
+/// let copy = dir.path().join("copy.env");
+/// import_env(std::fs::File::open(&exported)?, ImportFormat::SystemdEnvFile, cipher_type, &key, &copy, OnConflict::Skip)?;
+/// for name in ["API_KEY", "QUOTED", "LITERAL", "CONTINUED"] {
+///     assert_eq!(get_enc_env_from(&copy, name, cipher_type, &key)?, get(name)?);
+/// }
+///
+/// let broken = "API_KEY\n";
+/// let result = import_env(broken.as_bytes(), ImportFormat::SystemdEnvFile, cipher_type, &key, &copy, OnConflict::Skip);
+/// assert_eq!(result.unwrap_err().to_string(), "cannot import secrets: line 1 of the systemd env file has no '='");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(feature = "toml", doc = "")]
+#[cfg_attr(
+    feature = "toml",
+    doc = "TOML and YAML documents are read the same way:"
+)]
+#[cfg_attr(feature = "toml", doc = "")]
+#[cfg_attr(all(feature = "toml", feature = "yaml"), doc = "```")]
+#[cfg_attr(all(feature = "toml", not(feature = "yaml")), doc = "```ignore")]
+#[cfg_attr(
+    feature = "toml",
+    doc = r#"use envenc::{get_enc_env_from, import_env, keys_generation, CipherType, ImportFormat, OnConflict};
+
+let dir = tempfile::tempdir()?;
+let path = dir.path().join("secrets.env");
+let cipher_type = CipherType::AES256GCM;
+let (key, _) = keys_generation(cipher_type)?;
+
+let toml = "API_KEY = \"from toml\"\nRETRIES = 3\n";
+import_env(toml.as_bytes(), ImportFormat::Toml, cipher_type, &key, &path, OnConflict::Skip)?;
+let yaml = "DB_PASSWORD: \"it's: quoted\"\nVERBOSE: true\n";
+import_env(yaml.as_bytes(), ImportFormat::Yaml, cipher_type, &key, &path, OnConflict::Skip)?;
+
+assert_eq!(get_enc_env_from(&path, "API_KEY", cipher_type, &key)?.as_deref(), Some("from toml"));
+assert_eq!(get_enc_env_from(&path, "RETRIES", cipher_type, &key)?.as_deref(), Some("3"));
+assert_eq!(get_enc_env_from(&path, "DB_PASSWORD", cipher_type, &key)?.as_deref(), Some("it's: quoted"));
+assert_eq!(get_enc_env_from(&path, "VERBOSE", cipher_type, &key)?.as_deref(), Some("true"));
+
+let nested = "[database]\npassword = \"hunter2\"\n";
+assert!(import_env(nested.as_bytes(), ImportFormat::Toml, cipher_type, &key, &path, OnConflict::Skip).is_err());
+# Ok::<(), Box<dyn std::error::Error>>(())"#
+)]
+#[cfg_attr(feature = "toml", doc = "```")]
+pub fn import_env(
+    mut reader: impl Read,
+    format: ImportFormat,
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+    path: impl AsRef<Path>,
+    on_conflict: OnConflict,
+) -> Result<ImportReport, EnvEncError> {
+    let mut document = String::new();
+    reader.read_to_string(&mut document)?;
+    let document = SecretValue::from(document);
+    let vars = parse_document(document.expose_secret(), format)?;
+
+    let mut session = EnvEnc::builder()
+        .cipher(cipher_type)
+        .key(key.as_ref())
+        .env_file(path)
+        .build()?;
+    if on_conflict == OnConflict::Error {
+        if let Some((var_name, _)) = vars.iter().find(|(name, _)| session.contains(name)) {
+            return Err(EnvEncError::VarExists {
+                var: var_name.clone(),
+            });
+        }
+    }
+
+    let mut report = ImportReport::default();
+    for (var_name, value) in &vars {
+        if on_conflict == OnConflict::Skip && session.contains(var_name) {
+            report.skipped.push(var_name.clone());
+            continue;
+        }
+        session.upsert(var_name, value.expose_secret(), Overwrite::Yes)?;
+        report.imported.push(var_name.clone());
+    }
+    Ok(report)
+}
+
+/// Parses `document` in `format` into its variables, sorted by name, ignoring a byte order mark
+/// at its start.
+fn parse_document(
+    document: &str,
+    format: ImportFormat,
+) -> Result<Vec<(String, SecretValue)>, EnvEncError> {
+    let document = strip_bom(document);
+    let parsed: Result<Value, String> = match format {
+        ImportFormat::SystemdEnvFile => return parse_systemd(document),
+        ImportFormat::Dotenv => return parse_dotenv(document),
+        ImportFormat::Json => serde_json::from_str(document).map_err(|err| err.to_string()),
+        #[cfg(feature = "toml")]
+        ImportFormat::Toml => toml::from_str(document).map_err(|err| err.to_string()),
+        #[cfg(feature = "yaml")]
+        ImportFormat::Yaml => serde_yaml::from_str(document).map_err(|err| err.to_string()),
+    };
+    let invalid = |reason: String| EnvEncError::InvalidImport { reason };
+    let Value::Object(entries) = parsed.map_err(invalid)? else {
+        return Err(invalid(
+            "the document is not a map of names to values".to_string(),
+        ));
+    };
+
+    let mut vars = Vec::with_capacity(entries.len());
+    for (var_name, value) in entries {
+        let value = match value {
+            Value::String(value) => value,
+            Value::Number(value) => value.to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Object(_) => return Err(invalid(not_scalar(&var_name, "an object"))),
+            Value::Array(_) => return Err(invalid(not_scalar(&var_name, "an array"))),
+            Value::Null => return Err(invalid(not_scalar(&var_name, "null"))),
+        };
+        vars.push((var_name, SecretValue::from(value)));
+    }
+    vars.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(vars)
+}
+
+/// Describes a value of `var_name` of `kind` that cannot be imported.
+fn not_scalar(var_name: &str, kind: &str) -> String {
+    format!(
+        "the value of '{}' is {}, not a string, number, or boolean",
+        var_name, kind
+    )
+}
+
+/// Parses a dotenv file into its variables, sorted by name, with the syntax of `env_syntax`.
+fn parse_dotenv(document: &str) -> Result<Vec<(String, SecretValue)>, EnvEncError> {
+    let invalid = |line: usize, reason: &str| EnvEncError::InvalidImport {
+        reason: format!("line {} of the dotenv file {}", line, reason),
+    };
+    let mut vars = BTreeMap::new();
+    for (number, line) in logical_lines(document) {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let raw = split_line(line).ok_or_else(|| invalid(number, "has no '='"))?;
+        let parsed = parse_value(raw.rhs)
+            .map_err(|reason| invalid(number, &format!("is invalid: {}", reason)))?;
+        vars.insert(raw.name.to_string(), SecretValue::from(parsed.value));
+    }
+    Ok(vars.into_iter().collect())
+}
+
+/// Parses a systemd env file into its variables, sorted by name, following the parser of
+/// systemd itself.
+fn parse_systemd(document: &str) -> Result<Vec<(String, SecretValue)>, EnvEncError> {
+    enum State {
+        PreKey,
+        Key,
+        Comment,
+        CommentEscape,
+        PreValue,
+        Value,
+        ValueEscape,
+        SingleQuoted,
+        DoubleQuoted,
+        DoubleQuotedEscape,
+    }
+
+    let no_equals = |line: usize| EnvEncError::InvalidImport {
+        reason: format!("line {} of the systemd env file has no '='", line),
+    };
+    let mut vars = BTreeMap::new();
+    let mut state = State::PreKey;
+    let mut key = String::new();
+    let mut value = String::new();
+    // The length of the unescaped whitespace at the end of an unquoted value, which is
+    // trimmed.
+    let mut trailing_whitespace = 0;
+    let mut line = 1;
+    let mut finish = |key: &mut String, value: &mut String, trailing_whitespace: &mut usize| {
+        value.truncate(value.len() - mem::take(trailing_whitespace));
+        vars.insert(
+            mem::take(key).trim_end().to_string(),
+            SecretValue::from(mem::take(value)),
+        );
+    };
+
+    for c in document.chars() {
+        let is_newline = c == '\n' || c == '\r';
+        state = match state {
+            State::PreKey if c == '#' || c == ';' => State::Comment,
+            State::PreKey if c.is_whitespace() => State::PreKey,
+            State::PreKey | State::Key if is_newline => return Err(no_equals(line)),
+            State::PreKey | State::Key if c == '=' => State::PreValue,
+            State::PreKey | State::Key => {
+                key.push(c);
+                State::Key
+            }
+            State::Comment if c == '\\' => State::CommentEscape,
+            State::Comment if is_newline => State::PreKey,
+            State::Comment | State::CommentEscape => State::Comment,
+            State::PreValue | State::Value if is_newline => {
+                finish(&mut key, &mut value, &mut trailing_whitespace);
+                State::PreKey
+            }
+            State::PreValue if c == '\'' => State::SingleQuoted,
+            State::PreValue if c == '"' => State::DoubleQuoted,
+            State::PreValue if c.is_whitespace() => State::PreValue,
+            State::PreValue | State::Value if c == '\\' => State::ValueEscape,
+            State::PreValue | State::Value => {
+                value.push(c);
+                if c.is_whitespace() {
+                    trailing_whitespace += c.len_utf8();
+                } else {
+                    trailing_whitespace = 0;
+                }
+                State::Value
+            }
+            State::ValueEscape => {
+                // An escaped line break continues the value on the next line.
+                if !is_newline {
+                    value.push(c);
+                }
+                trailing_whitespace = 0;
+                State::Value
+            }
+            State::SingleQuoted if c == '\'' => State::PreValue,
+            State::DoubleQuoted if c == '"' => State::PreValue,
+            State::DoubleQuoted if c == '\\' => State::DoubleQuotedEscape,
+            State::SingleQuoted | State::DoubleQuoted => {
+                value.push(c);
+                state
+            }
+            State::DoubleQuotedEscape => {
+                if matches!(c, '"' | '\\' | '`' | '$') {
+                    value.push(c);
+                } else if !is_newline {
+                    value.push('\\');
+                    value.push(c);
+                }
+                State::DoubleQuoted
+            }
+        };
+        if c == '\n' {
+            line += 1;
+        }
+    }
+
+    match state {
+        State::PreKey | State::Comment | State::CommentEscape => {}
+        State::Key => return Err(no_equals(line)),
+        State::PreValue | State::Value | State::ValueEscape => {
+            finish(&mut key, &mut value, &mut trailing_whitespace)
+        }
+        State::SingleQuoted | State::DoubleQuoted | State::DoubleQuotedEscape => {
+            return Err(EnvEncError::InvalidImport {
+                reason: "the systemd env file ends inside a quoted value".to_string(),
+            })
+        }
+    }
+    Ok(vars.into_iter().collect())
+}
@@ -0,0 +1,213 @@
+use crate::{
+    read_kdf_metadata, CipherType, EnvEnc, EnvEncBuilder, EnvEncError, KdfKind, KeySource,
+    DEFAULT_ENV_FILE, DEFAULT_KEY_FILE_VAR, DEFAULT_KEY_VAR,
+};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+/// The environment variable `init` reads the keyring service of the key from, when neither
+/// `ENVENC_KEY_FILE` nor `ENVENC_KEY` is set.
+#[cfg(feature = "keyring")]
+pub const DEFAULT_KEYRING_SERVICE_VAR: &str = "ENVENC_KEYRING_SERVICE";
+
+/// The outcome of the first successful `init`.
+static INITIALIZED: OnceLock<InitReport> = OnceLock::new();
+
+/// Held while `init` loads the env file, so that concurrent calls load it once.
+static INIT_LOCK: Mutex<()> = Mutex::new(());
+
+/// The variables `init` loaded into the process environment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InitReport {
+    /// The env file the variables were read from.
+    pub path: PathBuf,
+    /// The names of the variables that were set, sorted.
+    pub loaded: Vec<String>,
+    /// The names of the variables that were already set in the environment and kept their
+    /// value, sorted.
+    pub kept: Vec<String>,
+}
+
+/// Decrypts `.env` in the current directory into the process environment, once per process,
+/// as a one-line start of `main`.
+///
+/// The key is read from the file named by `ENVENC_KEY_FILE` if it is set, and from
+/// `ENVENC_KEY` otherwise; with the `keyring` feature, it is read from the OS keyring under the
+/// service named by `ENVENC_KEYRING_SERVICE` if neither is set. Values are encrypted with
+/// `AES256GCM`, and values that are not encrypted are loaded as they are. Variables already
+/// set in the environment keep their value, as with dotenv, so that an orchestrator can
+/// override the file.
+///
+/// Every value is decrypted before any is set, so a failure loads nothing. Only success is
+/// remembered: later calls, from any thread, return the report of the first successful call
+/// without reading the file again, while a call after a failure tries again. Concurrent
+/// calls wait for each other, so variables are set by one thread only.
+///
+/// The process environment is shared by all threads and changing it while another thread
+/// reads it is not thread-safe on every platform, so call `init` before spawning threads,
+/// such as at the top of `main`. Use `EnvEnc` or `LazyEnv` to keep decrypted values out of the
+/// environment altogether.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::MissingEnvFile` if `.env` does not exist (see `try_init`), the errors
+/// of `KeySource::load` and `EnvEncBuilder::build`, and `EnvEncError::DecryptionFailed` if a
+/// value does not decrypt.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{init, keys_generation, set_enc_env, CipherType, Key};
+///
+/// # let dir = tempfile::tempdir()?;
+/// # std::env::set_current_dir(dir.path())?;
+/// let cipher_type = CipherType::AES256GCM;
+/// let (raw_key, _) = keys_generation(cipher_type)?;
+/// let key = Key::new(cipher_type, raw_key.clone())?;
+/// set_enc_env("DATABASE_URL", "postgres://localhost/app", cipher_type, &key)?;
+/// set_enc_env("RUST_LOG", "debug", cipher_type, &key)?;
+/// std::env::set_var("ENVENC_KEY", hex::encode(&raw_key));
+/// std::env::set_var("RUST_LOG", "info");
+///
+/// let report = init()?;
+/// assert_eq!(report.loaded, ["DATABASE_URL"]);
+/// assert_eq!(report.kept, ["RUST_LOG"]);
+/// assert_eq!(std::env::var("DATABASE_URL")?, "postgres://localhost/app");
+/// assert_eq!(std::env::var("RUST_LOG")?, "info");
+///
+/// // Later calls return the same report without reading the file again.
+/// std::fs::remove_file(".env")?;
+/// let again = std::thread::spawn(|| init().map(|report| report as *const _ as usize)).join().unwrap()?;
+/// assert_eq!(again, report as *const _ as usize);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn init() -> Result<&'static InitReport, EnvEncError> {
+    init_with(true, default_key).map(|report| report.expect("the env file is required"))
+}
+
+/// Does what `init` does, but returns `None` instead of failing if `.env` does not exist, for
+/// applications that also run with their environment set by other means.
+///
+/// # Errors
+///
+/// Returns the errors of `init`, except `EnvEncError::MissingEnvFile`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::try_init;
+///
+/// # let dir = tempfile::tempdir()?;
+/// # std::env::set_current_dir(dir.path())?;
+/// assert!(try_init()?.is_none());
+/// # Ok::<(), envenc::EnvEncError>(())
+/// ```
+pub fn try_init() -> Result<Option<&'static InitReport>, EnvEncError> {
+    init_with(false, default_key)
+}
+
+/// Does what `init` does, with the key derived from `passphrase` rather than read from the
+/// environment: with the salt recorded in the header of `.env` by `envenc init --passphrase`,
+/// or else to unwrap the data key of the file.
+///
+/// # Errors
+///
+/// Returns the errors of `init`, and `EnvEncError::AuthenticationFailed` if the passphrase
+/// does not unwrap the data key.
+pub fn init_from_passphrase(passphrase: &str) -> Result<&'static InitReport, EnvEncError> {
+    init_with(true, |builder, path| {
+        Ok(match read_kdf_metadata(path)? {
+            Some(metadata) => builder.key(metadata.derive_key(passphrase, CipherType::AES256GCM)?),
+            None => builder.passphrase(passphrase, KdfKind::default()),
+        })
+    })
+    .map(|report| report.expect("the env file is required"))
+}
+
+/// Loads `.env` into the process environment unless it was loaded already, with the key that
+/// `key` sets on a builder for the file at a path. Returns `None` if the file does not exist
+/// and `require_file` is not set.
+fn init_with(
+    require_file: bool,
+    key: impl FnOnce(EnvEncBuilder, &Path) -> Result<EnvEncBuilder, EnvEncError>,
+) -> Result<Option<&'static InitReport>, EnvEncError> {
+    if let Some(report) = INITIALIZED.get() {
+        return Ok(Some(report));
+    }
+    let _lock = INIT_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(report) = INITIALIZED.get() {
+        return Ok(Some(report));
+    }
+    let path = Path::new(DEFAULT_ENV_FILE);
+    match (path.try_exists()?, require_file) {
+        (true, _) => {}
+        (false, true) => {
+            return Err(EnvEncError::MissingEnvFile {
+                path: path.to_path_buf(),
+            })
+        }
+        (false, false) => return Ok(None),
+    }
+    let builder = EnvEnc::builder()
+        .cipher(CipherType::AES256GCM)
+        .env_file(path);
+    let session = key(builder, path)?.build()?;
+    let (decrypted, report) = session.decrypt_entries();
+    report.into_result()?;
+
+    let mut names: Vec<&String> = decrypted.keys().collect();
+    names.sort();
+    let mut loaded = Vec::new();
+    let mut kept = Vec::new();
+    for name in names {
+        match env::var_os(name) {
+            Some(_) => kept.push(name.clone()),
+            None => {
+                env::set_var(name, decrypted[name].expose_secret());
+                loaded.push(name.clone());
+            }
+        }
+    }
+    log::info!(
+        "loaded {} variables from {} into the environment",
+        loaded.len(),
+        path.display()
+    );
+    let report = InitReport {
+        path: path.to_path_buf(),
+        loaded,
+        kept,
+    };
+    Ok(Some(INITIALIZED.get_or_init(|| report)))
+}
+
+/// Sets the key of `init` on `builder`.
+fn default_key(builder: EnvEncBuilder, _: &Path) -> Result<EnvEncBuilder, EnvEncError> {
+    let source = match (
+        env::var_os(DEFAULT_KEY_FILE_VAR),
+        env::var_os(DEFAULT_KEY_VAR),
+    ) {
+        (None, None) => keyring_source().unwrap_or_default(),
+        _ => KeySource::from_env(),
+    };
+    Ok(builder.key(source.load(CipherType::AES256GCM)?))
+}
+
+/// Returns the keyring source named by `ENVENC_KEYRING_SERVICE`, if it is set.
+#[cfg(feature = "keyring")]
+fn keyring_source() -> Option<KeySource> {
+    let service = env::var(DEFAULT_KEYRING_SERVICE_VAR).ok()?;
+    Some(KeySource::OsKeyring {
+        service,
+        user: "envenc".to_string(),
+    })
+}
+
+/// Returns `None`, as there is no keyring without the `keyring` feature.
+#[cfg(not(feature = "keyring"))]
+fn keyring_source() -> Option<KeySource> {
+    None
+}
@@ -0,0 +1,418 @@
+use crate::{
+    data_key::{unwrap_data_key, KeyProtection},
+    decrypt_env, decrypt_env_from,
+    env_file::{lock_env_file, read_contents, write_atomic, DEFAULT_LOCK_TIMEOUT},
+    fingerprint::check_fingerprint,
+    random_bytes, read_env_enc_from, CipherType, DecryptReport, EnvEncError,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::Sha256;
+use std::{collections::HashMap, path::Path};
+
+/// The prefix of the comment line that records the key derivation metadata in an env file.
+const HEADER_PREFIX: &str = "# envenc-kdf:";
+
+/// The length of the random salt generated for every derived key, in bytes.
+const SALT_SIZE: usize = 16;
+
+/// The cost parameters of Argon2id.
+///
+/// The defaults follow the OWASP recommendation of 19 MiB of memory, 2 iterations, and a
+/// single lane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Argon2Params {
+    /// The memory cost in KiB.
+    pub memory_kib: u32,
+    /// The number of passes over the memory.
+    pub iterations: u32,
+    /// The degree of parallelism.
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Argon2Params {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// The key derivation function used to derive a key from a passphrase, with its parameters.
+///
+/// Argon2id is the default. PBKDF2-HMAC-SHA256 needs almost no memory, which suits machines
+/// that cannot spare the memory Argon2id is configured with, at the cost of being cheaper to
+/// attack with dedicated hardware. OWASP recommends at least 600,000 iterations for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KdfKind {
+    /// Argon2id with the given cost parameters.
+    Argon2id(Argon2Params),
+    /// PBKDF2 with HMAC-SHA256 and the given number of iterations.
+    Pbkdf2Sha256 { iterations: u32 },
+}
+
+impl KdfKind {
+    /// Returns the name of the key derivation function, as recorded in the header line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            KdfKind::Argon2id(_) => "argon2id",
+            KdfKind::Pbkdf2Sha256 { .. } => "pbkdf2-sha256",
+        }
+    }
+}
+
+impl Default for KdfKind {
+    fn default() -> Self {
+        KdfKind::Argon2id(Argon2Params::default())
+    }
+}
+
+impl From<Argon2Params> for KdfKind {
+    fn from(params: Argon2Params) -> Self {
+        KdfKind::Argon2id(params)
+    }
+}
+
+/// What is needed, besides the passphrase, to derive the same key again: the salt and the
+/// key derivation function with its parameters.
+///
+/// The metadata is not secret. Store it in the env file with `write_kdf_metadata`, where it is
+/// kept as a comment line, and read it back with `read_kdf_metadata`.
+///
+/// # Example
+///
+/// Derivation is deterministic for a fixed salt, passphrase, and set of parameters:
+///
+/// ```
+/// use envenc::{CipherType, KdfKind, KdfMetadata};
+///
+/// let metadata = KdfMetadata {
+///     salt: b"salt".to_vec(),
+///     kind: KdfKind::Pbkdf2Sha256 { iterations: 1 },
+/// };
+/// let key = metadata.derive_key("password", CipherType::AES256GCM)?;
+/// assert_eq!(
+///     hex::encode(&key),
+///     "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+/// );
+/// assert_eq!(metadata.derive_key("password", CipherType::AES256GCM)?, key);
+/// assert_eq!(metadata.to_string(), "pbkdf2-sha256 i=1 salt=c2FsdA==");
+/// # Ok::<(), envenc::EnvEncError>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KdfMetadata {
+    /// The random salt the key was derived with.
+    pub salt: Vec<u8>,
+    /// The key derivation function the key was derived with.
+    pub kind: KdfKind,
+}
+
+impl KdfMetadata {
+    /// Derives the key for `cipher_type` from `passphrase` again, using the stored salt and
+    /// key derivation function.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::KeyDerivationFailed` if the stored parameters are out of range.
+    pub fn derive_key(
+        &self,
+        passphrase: &str,
+        cipher_type: CipherType,
+    ) -> Result<Vec<u8>, EnvEncError> {
+        let mut key = vec![0u8; cipher_type.key_size()];
+        match self.kind {
+            KdfKind::Argon2id(params) => {
+                let params = Params::new(
+                    params.memory_kib,
+                    params.iterations,
+                    params.parallelism,
+                    Some(key.len()),
+                )
+                .map_err(|_| EnvEncError::KeyDerivationFailed)?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), &self.salt, &mut key)
+                    .map_err(|_| EnvEncError::KeyDerivationFailed)?;
+            }
+            KdfKind::Pbkdf2Sha256 { iterations } => {
+                if iterations == 0 {
+                    return Err(EnvEncError::KeyDerivationFailed);
+                }
+                pbkdf2::pbkdf2_hmac::<Sha256>(
+                    passphrase.as_bytes(),
+                    &self.salt,
+                    iterations,
+                    &mut key,
+                );
+            }
+        }
+        Ok(key)
+    }
+
+    /// Parses the body of a header line, as written by `Display`.
+    pub(crate) fn parse(header: &str) -> Option<Self> {
+        let mut fields = header.split_whitespace();
+        let name = fields.next()?;
+
+        let mut values = HashMap::new();
+        for field in fields {
+            let (key, value) = field.split_once('=')?;
+            values.insert(key, value);
+        }
+        let mut number = |key| values.remove(key)?.parse().ok();
+
+        let kind = match name {
+            "argon2id" => KdfKind::Argon2id(Argon2Params {
+                memory_kib: number("m")?,
+                iterations: number("t")?,
+                parallelism: number("p")?,
+            }),
+            "pbkdf2-sha256" => KdfKind::Pbkdf2Sha256 {
+                iterations: number("i")?,
+            },
+            _ => return None,
+        };
+        let salt = STANDARD.decode(values.remove("salt")?).ok()?;
+        if !values.is_empty() {
+            return None;
+        }
+        Some(KdfMetadata { salt, kind })
+    }
+}
+
+/// Formats the metadata as the body of the header line, for example
+/// `argon2id m=19456 t=2 p=1 salt=<base64>`.
+impl std::fmt::Display for KdfMetadata {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} ", self.kind.name())?;
+        match self.kind {
+            KdfKind::Argon2id(params) => write!(
+                f,
+                "m={} t={} p={}",
+                params.memory_kib, params.iterations, params.parallelism
+            )?,
+            KdfKind::Pbkdf2Sha256 { iterations } => write!(f, "i={}", iterations)?,
+        }
+        write!(f, " salt={}", STANDARD.encode(&self.salt))
+    }
+}
+
+/// Derives a key for `cipher_type` from `passphrase` with a fresh random salt.
+///
+/// The returned `KdfMetadata` holds the salt and parameters needed to derive the same key
+/// again. Store it next to the encrypted values with `write_kdf_metadata`.
+///
+/// # Arguments
+///
+/// * `passphrase` - The passphrase to derive the key from.
+/// * `cipher_type` - The cipher the key is for, which determines its length.
+/// * `kdf` - The key derivation function to use. `Argon2Params` select Argon2id.
+///
+/// # Returns
+///
+/// A tuple of the derived key and its metadata.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::KeyDerivationFailed` if the parameters of `kdf` are out of range.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{
+///     decrypt_env_with_passphrase, derive_key_from_passphrase, get_enc_env_from,
+///     read_kdf_metadata, set_enc_env_in, write_kdf_metadata, Argon2Params, CipherType,
+///     EnvEncError,
+/// };
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::XChaCha20Poly1305;
+/// // Cheap parameters keep the example fast; use `Argon2Params::default()` in practice.
+/// let params = Argon2Params { memory_kib: 1024, iterations: 1, parallelism: 1 };
+///
+/// {
+///     let (key, metadata) = derive_key_from_passphrase("correct horse", cipher_type, params)?;
+///     write_kdf_metadata(&path, &metadata)?;
+///     set_enc_env_in(&path, "PASSPHRASE_DB_URL", "postgres://localhost/db", cipher_type, &key)?;
+/// }
+/// let contents = std::fs::read_to_string(&path)?;
+/// assert!(contents.lines().any(|line| line.starts_with("# envenc-kdf: argon2id m=1024 t=1 p=1 salt=")));
+///
+/// // Later, only the passphrase is needed to derive the same key from the stored salt.
+/// let metadata = read_kdf_metadata(&path)?.expect("metadata was written");
+/// let key = metadata.derive_key("correct horse", cipher_type)?;
+/// let value = get_enc_env_from(&path, "PASSPHRASE_DB_URL", cipher_type, &key)?;
+/// assert_eq!(value.as_deref(), Some("postgres://localhost/db"));
+///
+/// let report = decrypt_env_with_passphrase(&path, cipher_type, "correct horse")?;
+/// assert_eq!(report.decrypted, ["PASSPHRASE_DB_URL"]);
+/// assert_eq!(std::env::var("PASSPHRASE_DB_URL")?, "postgres://localhost/db");
+///
+/// // A wrong passphrase derives a different key, which the key fingerprint header catches.
+/// assert!(matches!(
+///     decrypt_env_with_passphrase(&path, cipher_type, "wrong horse"),
+///     Err(EnvEncError::KeyMismatch { .. })
+/// ));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn derive_key_from_passphrase(
+    passphrase: &str,
+    cipher_type: CipherType,
+    kdf: impl Into<KdfKind>,
+) -> Result<(Vec<u8>, KdfMetadata), EnvEncError> {
+    let metadata = KdfMetadata {
+        salt: random_bytes(SALT_SIZE),
+        kind: kdf.into(),
+    };
+    let key = metadata.derive_key(passphrase, cipher_type)?;
+    Ok((key, metadata))
+}
+
+/// Reads the key derivation metadata stored in the header line of the env file at `path`.
+///
+/// # Returns
+///
+/// `Ok(None)` if the file does not exist or has no header line.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::InvalidKdfHeader` if the header line cannot be parsed, and
+/// `EnvEncError::Io` if the file cannot be read.
+pub fn read_kdf_metadata(path: impl AsRef<Path>) -> Result<Option<KdfMetadata>, EnvEncError> {
+    let path = path.as_ref();
+    let Some(contents) = read_contents(path)? else {
+        return Ok(None);
+    };
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix(HEADER_PREFIX))
+        .map(|header| {
+            KdfMetadata::parse(header).ok_or_else(|| EnvEncError::InvalidKdfHeader {
+                path: path.to_path_buf(),
+            })
+        })
+        .transpose()
+}
+
+/// Stores `metadata` as the header line of the env file at `path`, creating the file if
+/// needed.
+///
+/// The header is a comment, so dotenv loaders ignore it. An existing header is replaced and
+/// every other line is kept as it is.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::Io` if the file cannot be read or written.
+pub fn write_kdf_metadata(
+    path: impl AsRef<Path>,
+    metadata: &KdfMetadata,
+) -> Result<(), EnvEncError> {
+    let path = path.as_ref();
+    let _lock = lock_env_file(path, DEFAULT_LOCK_TIMEOUT)?;
+    let contents = read_contents(path)?.unwrap_or_default();
+
+    let mut updated = format!("{} {}\n", HEADER_PREFIX, metadata);
+    for line in contents.split_inclusive('\n') {
+        if !line.starts_with(HEADER_PREFIX) {
+            updated.push_str(line);
+        }
+    }
+    write_atomic(path, &updated)
+}
+
+/// Decrypts the env file at `path` with a key derived from `passphrase`, and sets the
+/// variables in the current process environment.
+///
+/// The salt and parameters are read from the header line written by `write_kdf_metadata`.
+/// See `derive_key_from_passphrase` for an example. If the file has a data key wrapped with the
+/// passphrase, as written by a session built with `EnvEncBuilder::passphrase`, the values are
+/// decrypted with that data key instead. See `change_passphrase` for an example.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::MissingKdfHeader` if the file has neither a header line nor a data
+/// key, and the errors of `read_kdf_metadata` and `KdfMetadata::derive_key`. A wrong
+/// passphrase yields `EnvEncError::KeyMismatch`, like a wrong key, or
+/// `EnvEncError::InvalidDataKey` for a data key.
+pub fn decrypt_env_with_passphrase(
+    path: impl AsRef<Path>,
+    cipher_type: CipherType,
+    passphrase: &str,
+) -> Result<DecryptReport, EnvEncError> {
+    let path = path.as_ref();
+    let contents = read_contents(path)?.unwrap_or_default();
+    let protection = KeyProtection::Passphrase(passphrase, KdfKind::default());
+    if let Some(data_key) = unwrap_data_key(path, &contents, &cipher_type, &[protection])? {
+        check_fingerprint(&contents, &[&data_key])?;
+        return decrypt_env(read_env_enc_from(path)?, cipher_type, &data_key, &[]);
+    }
+    let metadata = read_kdf_metadata(path)?.ok_or_else(|| EnvEncError::MissingKdfHeader {
+        path: path.to_path_buf(),
+    })?;
+    let key = metadata.derive_key(passphrase, cipher_type)?;
+    decrypt_env_from(path, cipher_type, &key)
+}
+
+/// Derives the key for the env file at `path` from `passphrase`, using the key derivation
+/// metadata in its header line.
+///
+/// If the file has no header yet, a fresh salt is generated, the key is derived with `kdf`, and
+/// the metadata is written to the file. Otherwise the stored salt and parameters are used, so
+/// the same passphrase always yields the same key for the file. The stored parameters take
+/// precedence over those in `kdf`, but the key derivation function itself must match.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::KdfMismatch` if the header names a different key derivation function
+/// than `kdf`, and the errors of `read_kdf_metadata`, `write_kdf_metadata`, and
+/// `KdfMetadata::derive_key`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{derive_key_for_env_file, Argon2Params, CipherType, EnvEncError, KdfKind};
+///
+/// let dir = tempfile::tempdir()?;
+/// let cipher_type = CipherType::AES256GCM;
+/// let pbkdf2 = KdfKind::Pbkdf2Sha256 { iterations: 1_000 };
+/// let argon2 = KdfKind::Argon2id(Argon2Params { memory_kib: 1024, iterations: 1, parallelism: 1 });
+///
+/// let pbkdf2_file = dir.path().join("pbkdf2.env");
+/// let key = derive_key_for_env_file(&pbkdf2_file, "passphrase", cipher_type, pbkdf2)?;
+/// assert_eq!(derive_key_for_env_file(&pbkdf2_file, "passphrase", cipher_type, pbkdf2)?, key);
+/// assert!(matches!(
+///     derive_key_for_env_file(&pbkdf2_file, "passphrase", cipher_type, argon2),
+///     Err(EnvEncError::KdfMismatch { expected: "argon2id", found: "pbkdf2-sha256" })
+/// ));
+///
+/// let argon2_file = dir.path().join("argon2.env");
+/// derive_key_for_env_file(&argon2_file, "passphrase", cipher_type, argon2)?;
+/// assert!(matches!(
+///     derive_key_for_env_file(&argon2_file, "passphrase", cipher_type, pbkdf2),
+///     Err(EnvEncError::KdfMismatch { expected: "pbkdf2-sha256", found: "argon2id" })
+/// ));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn derive_key_for_env_file(
+    path: impl AsRef<Path>,
+    passphrase: &str,
+    cipher_type: CipherType,
+    kdf: impl Into<KdfKind>,
+) -> Result<Vec<u8>, EnvEncError> {
+    let path = path.as_ref();
+    let kdf = kdf.into();
+    match read_kdf_metadata(path)? {
+        Some(metadata) if metadata.kind.name() != kdf.name() => Err(EnvEncError::KdfMismatch {
+            expected: kdf.name(),
+            found: metadata.kind.name(),
+        }),
+        Some(metadata) => metadata.derive_key(passphrase, cipher_type),
+        None => {
+            let (key, metadata) = derive_key_from_passphrase(passphrase, cipher_type, kdf)?;
+            write_kdf_metadata(path, &metadata)?;
+            Ok(key)
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{env_file::write_atomic_private, random_bytes, CipherType, EnvEncError, Key};
+use std::{fs, path::Path};
+
+/// The bytes every key file starts with.
+const MAGIC: &[u8] = b"ENVENCK";
+
+/// The key file format version written by this crate.
+const KEY_FILE_VERSION: u8 = 1;
+
+/// The bytes every age-encrypted file starts with.
+const AGE_MAGIC: &[u8] = b"age-encryption.org/";
+
+/// The first line of an age-encrypted file in the ASCII armored format.
+const AGE_ARMOR_MAGIC: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// A key together with the cipher it is for, as stored in a key file.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{load_keys, save_keys, CipherType, EnvEncError, KeyFileError, KeyMaterial};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("envenc.key");
+///
+/// let keys = KeyMaterial::generate(CipherType::XChaCha20Poly1305);
+/// save_keys(&path, &keys)?;
+/// assert_eq!(load_keys(&path)?, keys);
+///
+/// #[cfg(unix)]
+/// {
+///     use std::os::unix::fs::PermissionsExt;
+///     assert_eq!(std::fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+/// }
+///
+/// // Truncated and corrupt files are reported precisely.
+/// let bytes = std::fs::read(&path)?;
+/// std::fs::write(&path, &bytes[..bytes.len() - 1])?;
+/// assert!(matches!(
+///     load_keys(&path),
+///     Err(EnvEncError::InvalidKeyFile { reason: KeyFileError::Truncated, .. })
+/// ));
+/// std::fs::write(&path, b"not a key file")?;
+/// assert!(matches!(
+///     load_keys(&path),
+///     Err(EnvEncError::InvalidKeyFile { reason: KeyFileError::BadMagic, .. })
+/// ));
+///
+/// // The key length is checked against the cipher the file declares.
+/// save_keys(&path, &KeyMaterial::generate(CipherType::AES128GCM))?;
+/// let bytes = std::fs::read(&path)?;
+/// let relabeled = [&bytes[..9], b"AES256GCM", &bytes[18..]].concat();
+/// std::fs::write(&path, relabeled)?;
+/// assert!(matches!(
+///     load_keys(&path),
+///     Err(EnvEncError::InvalidKeyLength { expected: 32, actual: 16, .. })
+/// ));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct KeyMaterial {
+    cipher_type: CipherType,
+    key: Key,
+}
+
+impl KeyMaterial {
+    /// Creates key material from an existing key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidKeyLength` if `key` does not match `cipher_type`.
+    pub fn new(cipher_type: CipherType, key: impl Into<Vec<u8>>) -> Result<Self, EnvEncError> {
+        let key = Key::new(cipher_type, key)?;
+        Ok(KeyMaterial { cipher_type, key })
+    }
+
+    /// Generates a new random key for `cipher_type`.
+    pub fn generate(cipher_type: CipherType) -> Self {
+        KeyMaterial {
+            cipher_type,
+            key: Key::new(cipher_type, random_bytes(cipher_type.key_size()))
+                .expect("random_bytes returns the requested length"),
+        }
+    }
+
+    /// Returns the cipher the key is for.
+    pub fn cipher_type(&self) -> CipherType {
+        self.cipher_type
+    }
+
+    /// Returns the key, tied to the cipher it is for.
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    /// Encodes the key material in the key file format.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let name = self.cipher_type.to_string();
+        let key = self.key.as_bytes();
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 3 + name.len() + key.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(KEY_FILE_VERSION);
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(key.len() as u8);
+        bytes.extend_from_slice(key);
+        bytes
+    }
+
+    /// Decodes the key file format, without checking the key length against the cipher.
+    fn decode(bytes: &[u8]) -> Result<(CipherType, Vec<u8>), KeyFileError> {
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            return Err(if is_legacy_format(bytes) {
+                KeyFileError::LegacyFormat
+            } else if bytes.starts_with(AGE_MAGIC) || bytes.starts_with(AGE_ARMOR_MAGIC) {
+                KeyFileError::AgeEncrypted
+            } else {
+                KeyFileError::BadMagic
+            });
+        };
+
+        let (&version, rest) = rest.split_first().ok_or(KeyFileError::Truncated)?;
+        if version != KEY_FILE_VERSION {
+            return Err(KeyFileError::UnsupportedVersion(version));
+        }
+        let (name, rest) = split_field(rest)?;
+        let (key, rest) = split_field(rest)?;
+        if !rest.is_empty() {
+            return Err(KeyFileError::TrailingBytes);
+        }
+
+        let name = String::from_utf8_lossy(name);
+        let cipher_type = name
+            .parse()
+            .map_err(|_| KeyFileError::UnknownCipher(name.to_string()))?;
+        Ok((cipher_type, key.to_vec()))
+    }
+
+    /// Decodes the contents `bytes` of the key file at `path`.
+    pub(crate) fn from_key_file(path: &Path, bytes: &[u8]) -> Result<Self, EnvEncError> {
+        let (cipher_type, key) =
+            Self::decode(bytes).map_err(|reason| EnvEncError::InvalidKeyFile {
+                path: path.to_path_buf(),
+                reason,
+            })?;
+        KeyMaterial::new(cipher_type, key)
+    }
+}
+
+impl std::fmt::Debug for KeyMaterial {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("KeyMaterial")
+            .field("cipher_type", &self.cipher_type)
+            .field("key", &"[REDACTED]")
+            .finish()
+    }
+}
+
+/// Splits a field prefixed by its one-byte length off the front of `bytes`.
+fn split_field(bytes: &[u8]) -> Result<(&[u8], &[u8]), KeyFileError> {
+    let (&len, rest) = bytes.split_first().ok_or(KeyFileError::Truncated)?;
+    if rest.len() < len as usize {
+        return Err(KeyFileError::Truncated);
+    }
+    Ok(rest.split_at(len as usize))
+}
+
+/// Returns `true` if `bytes` look like the two hex lines of a key and nonce that the examples
+/// of earlier versions of this crate wrote.
+fn is_legacy_format(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes).is_ok_and(|text| {
+        let lines: Vec<&str> = text.lines().collect();
+        (1..=2).contains(&lines.len()) && lines.iter().all(|line| hex::decode(line.trim()).is_ok())
+    })
+}
+
+/// Saves `keys` to a key file at `path`, replacing any existing file.
+///
+/// The file records the cipher name next to the key and is only readable by its owner on
+/// Unix. It holds no nonce, since every value is encrypted with its own nonce. See
+/// `KeyMaterial` for an example.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::Io` if the file cannot be written.
+pub fn save_keys(path: impl AsRef<Path>, keys: &KeyMaterial) -> Result<(), EnvEncError> {
+    write_atomic_private(path.as_ref(), Key::from_bytes(keys.encode()).as_bytes())
+}
+
+/// Loads key material from the key file at `path`, as written by `save_keys`.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::InvalidKeyFile` if the file is corrupt, truncated, in the legacy
+/// two-line hex format, or encrypted with age, `EnvEncError::InvalidKeyLength` if the key does not match the cipher
+/// the file declares, and `EnvEncError::Io` if the file cannot be read.
+pub fn load_keys(path: impl AsRef<Path>) -> Result<KeyMaterial, EnvEncError> {
+    let path = path.as_ref();
+    let bytes = Key::from_bytes(fs::read(path)?);
+    KeyMaterial::from_key_file(path, bytes.as_bytes())
+}
+
+/// Converts a key file in the legacy format, a hex key line optionally followed by a hex nonce
+/// line, to the format of `save_keys` in place, and returns its key material.
+///
+/// Legacy files do not record their cipher, so it has to be given. The nonce is dropped, since
+/// every value now carries its own. This function will be removed in the next release.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::InvalidHex` if the key line is not valid hex,
+/// `EnvEncError::InvalidKeyLength` if the key does not match `cipher_type`, and
+/// `EnvEncError::Io` if the file cannot be read or written.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{load_keys, migrate_legacy_key_file, CipherType, EnvEncError, KeyFileError};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("key_nonce.txt");
+/// std::fs::write(&path, format!("{}\n{}", "ab".repeat(32), "cd".repeat(12)))?;
+/// assert!(matches!(
+///     load_keys(&path),
+///     Err(EnvEncError::InvalidKeyFile { reason: KeyFileError::LegacyFormat, .. })
+/// ));
+///
+/// let keys = migrate_legacy_key_file(&path, CipherType::ChaCha20Poly1305)?;
+/// assert_eq!(keys.key().as_bytes(), [0xab; 32]);
+/// assert_eq!(load_keys(&path)?, keys);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn migrate_legacy_key_file(
+    path: impl AsRef<Path>,
+    cipher_type: CipherType,
+) -> Result<KeyMaterial, EnvEncError> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)?;
+    let key_line = contents.lines().next().unwrap_or_default();
+    let keys = KeyMaterial::new(cipher_type, hex::decode(key_line.trim())?)?;
+    save_keys(path, &keys)?;
+    Ok(keys)
+}
+
+/// Why a key file could not be loaded by `load_keys`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyFileError {
+    /// The file does not start with the key file magic bytes.
+    BadMagic,
+    /// The file is in the legacy two-line hex format; convert it with
+    /// `migrate_legacy_key_file`.
+    LegacyFormat,
+    /// The file was written by a newer, unsupported version of the format.
+    UnsupportedVersion(u8),
+    /// The file names a cipher this crate does not support.
+    UnknownCipher(String),
+    /// The file ends before the key does.
+    Truncated,
+    /// The file has unexpected bytes after the key.
+    TrailingBytes,
+    /// The file is encrypted with age; load it with `load_keys_with_identity`, which requires
+    /// the `age` feature.
+    AgeEncrypted,
+}
+
+impl std::fmt::Display for KeyFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeyFileError::BadMagic => write!(f, "not an envenc key file"),
+            KeyFileError::LegacyFormat => write!(
+                f,
+                "legacy hex key file, convert it with migrate_legacy_key_file"
+            ),
+            KeyFileError::UnsupportedVersion(version) => {
+                write!(f, "unsupported key file version {}", version)
+            }
+            KeyFileError::UnknownCipher(name) => write!(f, "unknown cipher '{}'", name),
+            KeyFileError::Truncated => write!(f, "key file is truncated"),
+            KeyFileError::TrailingBytes => write!(f, "unexpected bytes after the key"),
+            KeyFileError::AgeEncrypted => write!(
+                f,
+                "key file is encrypted with age, load it with load_keys_with_identity"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KeyFileError {}
@@ -0,0 +1,92 @@
+/// A service that wraps and unwraps data keys with a master key it holds, such as a cloud key
+/// management service.
+///
+/// A session configured with `EnvEncBuilder::key_provider` encrypts its env file in data key
+/// mode: the values are encrypted with a random data key, and only the provider can unwrap it.
+/// The master key never leaves the provider. The header line of the env file records the
+/// identifier of the provider next to the wrapped data key, as
+/// `# envenc dek: <provider id> <base64 of the wrapped data key>`, so that readers know which
+/// provider to ask.
+///
+/// The `testing` module has an in-memory provider for tests, and the `aws-kms` feature adds
+/// `AwsKmsProvider`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{decrypt_env_from_provider, testing::InMemoryKeyProvider, CipherType, EnvEnc, EnvEncError, KeyProviderError};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let provider = InMemoryKeyProvider::new("in-memory:test");
+///
+/// let mut session = EnvEnc::builder()
+///     .cipher(cipher_type)
+///     .key_provider(provider.clone())
+///     .env_file(&path)
+///     .build()?;
+/// session.set("API_KEY", "secret")?;
+/// let contents = std::fs::read_to_string(&path)?;
+/// assert!(contents.lines().any(|line| line.starts_with("# envenc dek: in-memory:test ")));
+///
+/// let report = decrypt_env_from_provider(&path, cipher_type, &provider)?;
+/// assert_eq!(report.decrypted, ["API_KEY"]);
+///
+/// // Failures of the provider are passed on as typed errors.
+/// provider.set_unavailable(true);
+/// assert!(matches!(
+///     decrypt_env_from_provider(&path, cipher_type, &provider),
+///     Err(EnvEncError::KeyProviderFailed { reason: KeyProviderError::Network(_), .. })
+/// ));
+///
+/// // Another provider, or a plain key, cannot read the file.
+/// let other = InMemoryKeyProvider::new("in-memory:other");
+/// assert!(matches!(
+///     decrypt_env_from_provider(&path, cipher_type, &other),
+///     Err(EnvEncError::KeyProviderRequired { provider, .. }) if provider == "in-memory:test"
+/// ));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait KeyProvider: Send + Sync {
+    /// Returns the identifier recorded in the env file, such as `aws-kms:<key ARN>`. It must not
+    /// contain whitespace.
+    fn id(&self) -> &str;
+
+    /// Wraps `data_key` with the master key of the provider.
+    fn wrap(&self, data_key: &[u8]) -> Result<Vec<u8>, KeyProviderError>;
+
+    /// Unwraps a data key wrapped by `wrap`.
+    fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>, KeyProviderError>;
+}
+
+/// Why a `KeyProvider` could not wrap or unwrap a data key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyProviderError {
+    /// The provider could not be reached or did not answer in time.
+    Network(String),
+    /// The provider refused access to the master key.
+    AccessDenied(String),
+    /// The master key does not exist or is disabled.
+    KeyUnavailable(String),
+    /// The wrapped data key is corrupt or was wrapped with another master key.
+    InvalidCiphertext,
+    /// Any other failure reported by the provider.
+    Other(String),
+}
+
+impl std::fmt::Display for KeyProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeyProviderError::Network(reason) => write!(f, "network error: {}", reason),
+            KeyProviderError::AccessDenied(reason) => write!(f, "access denied: {}", reason),
+            KeyProviderError::KeyUnavailable(reason) => {
+                write!(f, "master key unavailable: {}", reason)
+            }
+            KeyProviderError::InvalidCiphertext => write!(f, "invalid wrapped data key"),
+            KeyProviderError::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for KeyProviderError {}
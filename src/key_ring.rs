@@ -0,0 +1,216 @@
+use crate::{open_stored_value, parse_stored_value, EnvCipher, EnvEncError, Key, StoredValueError};
+use std::collections::BTreeMap;
+
+/// The longest key ID a `Keyring` accepts.
+const MAX_KEY_ID_LEN: usize = 32;
+
+/// A set of keys identified by short key IDs, for env files that hold values encrypted under
+/// more than one key.
+///
+/// One key is the primary key: sessions built with `EnvEncBuilder::keyring` encrypt new values
+/// with it and record its ID in each envelope, as in `ENC[v2:AES256GCM:kid=2024:<base64>]`.
+/// Values that record a key ID are decrypted with that key, and values without one, such as
+/// those written before the keyring was introduced, are tried with every key, starting with the
+/// primary one. This lets values under an old and a new key coexist during a rotation window,
+/// until `rotate_keyring` re-encrypts them all under the primary key.
+///
+/// Key IDs are 1 to 32 ASCII letters, digits, `-`, `_`, or `.`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{decrypt_env_with_keyring, keys_generation, read_env_enc_from, set_enc_env_in, CipherType, EnvEnc, EnvEncError, Keyring};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (old_key, _) = keys_generation(cipher_type)?;
+/// let (new_key, _) = keys_generation(cipher_type)?;
+///
+/// // A value written before keyrings were introduced records no key ID.
+/// set_enc_env_in(&path, "LEGACY_TOKEN", "legacy value", cipher_type, &old_key)?;
+///
+/// let old_ring = Keyring::new("2023", old_key.clone())?;
+/// let mut session = EnvEnc::builder().cipher(cipher_type).keyring(old_ring).env_file(&path).build()?;
+/// session.set("OLD_TOKEN", "old value")?;
+///
+/// let mut ring = Keyring::new("2024", new_key)?;
+/// ring.insert("2023", old_key)?;
+/// let mut session = EnvEnc::builder().cipher(cipher_type).keyring(ring.clone()).env_file(&path).build()?;
+/// session.set("NEW_TOKEN", "new value")?;
+///
+/// let stored = read_env_enc_from(&path)?;
+/// assert!(stored["OLD_TOKEN"].starts_with("ENC[v2:AES256GCM:kid=2023:"));
+/// assert!(stored["NEW_TOKEN"].starts_with("ENC[v2:AES256GCM:kid=2024:"));
+///
+/// let report = decrypt_env_with_keyring(stored.clone(), cipher_type, &ring)?;
+/// assert_eq!(report.decrypted, ["LEGACY_TOKEN", "NEW_TOKEN", "OLD_TOKEN"]);
+/// assert_eq!(std::env::var("LEGACY_TOKEN")?, "legacy value");
+/// assert_eq!(std::env::var("OLD_TOKEN")?, "old value");
+/// assert_eq!(std::env::var("NEW_TOKEN")?, "new value");
+///
+/// // A value whose key ID is missing from the keyring is reported by ID.
+/// let (other_key, _) = keys_generation(cipher_type)?;
+/// let report = decrypt_env_with_keyring(stored, cipher_type, &Keyring::new("2024", other_key)?)?;
+/// assert!(report.failed.iter().any(|err| matches!(
+///     err,
+///     EnvEncError::UnknownKeyId { var, id } if var == "OLD_TOKEN" && id == "2023"
+/// )));
+///
+/// assert!(matches!(Keyring::new("not:valid", vec![0; 32]), Err(EnvEncError::InvalidKeyId { .. })));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Keyring {
+    primary: String,
+    keys: BTreeMap<String, Key>,
+}
+
+impl Keyring {
+    /// Creates a keyring whose primary key is `key`, identified by `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidKeyId` if `id` is not a valid key ID.
+    pub fn new(id: impl Into<String>, key: impl Into<Vec<u8>>) -> Result<Self, EnvEncError> {
+        let id = validate_key_id(id.into())?;
+        Ok(Keyring {
+            keys: BTreeMap::from([(id.clone(), Key::from_bytes(key.into()))]),
+            primary: id,
+        })
+    }
+
+    /// Adds `key` under `id`, replacing the key already stored under that ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidKeyId` if `id` is not a valid key ID.
+    pub fn insert(
+        &mut self,
+        id: impl Into<String>,
+        key: impl Into<Vec<u8>>,
+    ) -> Result<(), EnvEncError> {
+        let id = validate_key_id(id.into())?;
+        self.keys.insert(id, Key::from_bytes(key.into()));
+        Ok(())
+    }
+
+    /// Returns the ID of the primary key.
+    pub fn primary_id(&self) -> &str {
+        &self.primary
+    }
+
+    /// Returns the primary key.
+    pub fn primary_key(&self) -> &[u8] {
+        self.keys[&self.primary].as_bytes()
+    }
+
+    /// Returns the key stored under `id`.
+    pub fn get(&self, id: &str) -> Option<&[u8]> {
+        self.keys.get(id).map(Key::as_bytes)
+    }
+
+    /// Returns the IDs of all keys, in sorted order.
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.keys.keys().map(String::as_str)
+    }
+
+    /// Returns every key, starting with the primary one.
+    fn candidates(&self) -> impl Iterator<Item = &[u8]> {
+        let others = self
+            .keys
+            .iter()
+            .filter(move |(id, _)| **id != self.primary)
+            .map(|(_, key)| key.as_bytes());
+        std::iter::once(self.primary_key()).chain(others)
+    }
+}
+
+impl std::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Keyring")
+            .field("primary", &self.primary)
+            .field("ids", &self.keys.keys().collect::<Vec<_>>())
+            .field("keys", &"[REDACTED]")
+            .finish()
+    }
+}
+
+/// Returns `id` if it is a valid key ID.
+fn validate_key_id(id: String) -> Result<String, EnvEncError> {
+    if is_valid_key_id(&id) {
+        Ok(id)
+    } else {
+        Err(EnvEncError::InvalidKeyId { id })
+    }
+}
+
+/// Returns `true` if `id` can be recorded in an envelope as a key ID.
+pub(crate) fn is_valid_key_id(id: &str) -> bool {
+    (1..=MAX_KEY_ID_LEN).contains(&id.len())
+        && id
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"-_.".contains(&byte))
+}
+
+/// The keys a stored value may be decrypted with.
+#[derive(Clone, Copy)]
+pub(crate) enum Keys<'a> {
+    /// A single key, used whatever key ID a value records.
+    Single(&'a [u8]),
+    /// A keyring, whose keys are selected by the key ID a value records.
+    Ring(&'a Keyring),
+}
+
+impl<'a> Keys<'a> {
+    /// Returns every key, starting with the one new values are encrypted with.
+    pub(crate) fn all(&self) -> Vec<&'a [u8]> {
+        match *self {
+            Keys::Single(key) => vec![key],
+            Keys::Ring(keyring) => keyring.candidates().collect(),
+        }
+    }
+
+    /// Returns the key new values are encrypted with and the key ID recorded with them.
+    pub(crate) fn encrypting(&self) -> (&'a [u8], Option<&'a str>) {
+        match *self {
+            Keys::Single(key) => (key, None),
+            Keys::Ring(keyring) => (keyring.primary_key(), Some(keyring.primary_id())),
+        }
+    }
+
+    /// Decrypts a single value as stored in the env file, with the key its envelope names if
+    /// it names one and these are a keyring.
+    pub(crate) fn decrypt(
+        &self,
+        cipher: &dyn EnvCipher,
+        var_name: &str,
+        enc_value: &str,
+    ) -> Result<String, StoredValueError> {
+        let envelope = parse_stored_value(cipher, var_name, enc_value)?;
+        match (*self, envelope.key_id()) {
+            (Keys::Single(key), _) => open_stored_value(&envelope, cipher, key, var_name),
+            (Keys::Ring(keyring), Some(id)) => {
+                let key = keyring.get(id).ok_or_else(|| {
+                    StoredValueError::Failed(EnvEncError::UnknownKeyId {
+                        var: var_name.to_string(),
+                        id: id.to_string(),
+                    })
+                })?;
+                open_stored_value(&envelope, cipher, key, var_name)
+            }
+            (Keys::Ring(keyring), None) => {
+                let mut result = Err(StoredValueError::Failed(EnvEncError::DecryptionFailed {
+                    var: var_name.to_string(),
+                }));
+                for key in keyring.candidates() {
+                    result = open_stored_value(&envelope, cipher, key, var_name);
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                result
+            }
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use crate::{random_bytes, EnvEncError, Key};
+use sha2::{Digest, Sha256};
+
+/// The number of random bytes that identify the shares of one split.
+const SPLIT_ID_SIZE: usize = 4;
+
+/// The number of bytes of the SHA-256 checksum at the end of a serialized share.
+const CHECKSUM_SIZE: usize = 4;
+
+/// The number of bytes of a serialized share besides its value: the index, the threshold,
+/// the split ID, and the checksum.
+const OVERHEAD: usize = 2 + SPLIT_ID_SIZE + CHECKSUM_SIZE;
+
+/// One share of a key split with `split_key`.
+///
+/// A share is serialized as hex by `Display` and parsed back by `KeyShare::parse`. The
+/// serialized form holds the index of the share, the number of shares needed to recombine the
+/// key, an ID shared by all shares of one split, and a checksum, so that a mistyped share or
+/// shares of different keys are rejected rather than combined into a wrong key. `Debug` does
+/// not show the share itself.
+#[derive(Clone, PartialEq, Eq)]
+pub struct KeyShare {
+    index: u8,
+    threshold: u8,
+    split_id: [u8; SPLIT_ID_SIZE],
+    value: Vec<u8>,
+}
+
+impl KeyShare {
+    /// Parses a share serialized by `Display`. Surrounding whitespace is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `KeyShareError::Malformed` if `share` is not a hex-encoded share, and
+    /// `KeyShareError::ChecksumMismatch` if it was altered.
+    pub fn parse(share: &str) -> Result<Self, KeyShareError> {
+        let bytes =
+            Key::from_bytes(hex::decode(share.trim()).map_err(|_| KeyShareError::Malformed)?);
+        let bytes = bytes.as_bytes();
+        if bytes.len() < OVERHEAD {
+            return Err(KeyShareError::Malformed);
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+        if checksum_of(body) != checksum {
+            return Err(KeyShareError::ChecksumMismatch);
+        }
+        let (header, value) = body.split_at(2 + SPLIT_ID_SIZE);
+        let share = KeyShare {
+            index: header[0],
+            threshold: header[1],
+            split_id: header[2..].try_into().expect("header holds the split ID"),
+            value: value.to_vec(),
+        };
+        if share.index == 0 || share.threshold == 0 {
+            return Err(KeyShareError::Malformed);
+        }
+        Ok(share)
+    }
+
+    /// Returns the index of this share, from 1 to the number of shares.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Returns the number of shares needed to recombine the key.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Returns the bytes the checksum is computed over.
+    fn body(&self) -> Vec<u8> {
+        let mut body = vec![self.index, self.threshold];
+        body.extend_from_slice(&self.split_id);
+        body.extend_from_slice(&self.value);
+        body
+    }
+}
+
+/// Formats the share as hex, for example to hand it to one of its holders.
+impl std::fmt::Display for KeyShare {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut bytes = self.body();
+        let checksum = checksum_of(&bytes);
+        bytes.extend_from_slice(&checksum);
+        write!(f, "{}", hex::encode(bytes))
+    }
+}
+
+impl std::str::FromStr for KeyShare {
+    type Err = KeyShareError;
+
+    fn from_str(share: &str) -> Result<Self, Self::Err> {
+        KeyShare::parse(share)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for KeyShare {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.value);
+    }
+}
+
+impl std::fmt::Debug for KeyShare {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("KeyShare")
+            .field("index", &self.index)
+            .field("threshold", &self.threshold)
+            .field("value", &"[REDACTED]")
+            .finish()
+    }
+}
+
+/// Why key shares could not be created, parsed, or combined.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyShareError {
+    /// The threshold is zero or larger than the number of shares.
+    InvalidThreshold { threshold: u8, shares: u8 },
+    /// The share is not a hex-encoded key share.
+    Malformed,
+    /// The checksum of the share does not match: it was altered or mistyped.
+    ChecksumMismatch,
+    /// Fewer shares than the threshold were given.
+    NotEnoughShares { needed: u8, got: usize },
+    /// The share with this index was given more than once.
+    DuplicateShare(u8),
+    /// The shares come from different splits.
+    MixedShares,
+}
+
+impl std::fmt::Display for KeyShareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeyShareError::InvalidThreshold { threshold, shares } => write!(
+                f,
+                "a threshold of {} is invalid for {} shares",
+                threshold, shares
+            ),
+            KeyShareError::Malformed => write!(f, "not a key share"),
+            KeyShareError::ChecksumMismatch => {
+                write!(f, "the checksum of the key share does not match")
+            }
+            KeyShareError::NotEnoughShares { needed, got } => {
+                write!(
+                    f,
+                    "{} key shares are needed, but {} were given",
+                    needed, got
+                )
+            }
+            KeyShareError::DuplicateShare(index) => {
+                write!(f, "key share {} was given more than once", index)
+            }
+            KeyShareError::MixedShares => write!(f, "the key shares come from different keys"),
+        }
+    }
+}
+
+impl std::error::Error for KeyShareError {}
+
+/// Splits `key` into `shares` shares with Shamir's Secret Sharing over GF(256), any
+/// `threshold` of which recombine it with `combine_shares`.
+///
+/// Fewer than `threshold` shares reveal nothing about the key. Hand each share to a different
+/// person, so that no single person can decrypt the env file, and load the key from the shares
+/// with `KeySource::Shares`.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::InvalidKeyShare` with `KeyShareError::InvalidThreshold` if
+/// `threshold` is zero or larger than `shares`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{combine_shares, keys_generation, split_key, CipherType, EnvEncError, KeyShare, KeyShareError, KeySource};
+///
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// let shares = split_key(&key, 5, 3)?;
+///
+/// // Any 3 of the 5 shares recombine the key.
+/// for i in 0..5 {
+///     for j in i + 1..5 {
+///         for k in j + 1..5 {
+///             let subset = [shares[i].clone(), shares[j].clone(), shares[k].clone()];
+///             assert_eq!(combine_shares(&subset)?, key);
+///         }
+///     }
+/// }
+///
+/// // 2 shares are not enough.
+/// assert!(matches!(
+///     combine_shares(&shares[..2]),
+///     Err(EnvEncError::InvalidKeyShare { reason: KeyShareError::NotEnoughShares { needed: 3, got: 2 } })
+/// ));
+///
+/// // Shares are handed out as hex, and an altered share is rejected by its checksum.
+/// let serialized: Vec<String> = shares.iter().map(ToString::to_string).collect();
+/// assert_eq!(KeyShare::parse(&serialized[0])?, shares[0]);
+/// let mut tampered = serialized[0].clone().into_bytes();
+/// tampered[10] = if tampered[10] == b'0' { b'1' } else { b'0' };
+/// let tampered = String::from_utf8(tampered)?;
+/// assert_eq!(KeyShare::parse(&tampered), Err(KeyShareError::ChecksumMismatch));
+///
+/// // Shares of different keys are not combined into a wrong key.
+/// let (other_key, _) = keys_generation(cipher_type)?;
+/// let other_shares = split_key(&other_key, 5, 3)?;
+/// assert!(matches!(
+///     combine_shares(&[shares[0].clone(), shares[1].clone(), other_shares[2].clone()]),
+///     Err(EnvEncError::InvalidKeyShare { reason: KeyShareError::MixedShares })
+/// ));
+///
+/// let source = KeySource::Shares(serialized[2..].to_vec());
+/// assert_eq!(source.load(cipher_type)?, key);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn split_key(key: &[u8], shares: u8, threshold: u8) -> Result<Vec<KeyShare>, EnvEncError> {
+    if threshold == 0 || threshold > shares {
+        return Err(EnvEncError::InvalidKeyShare {
+            reason: KeyShareError::InvalidThreshold { threshold, shares },
+        });
+    }
+    let split_id = random_bytes(SPLIT_ID_SIZE)
+        .try_into()
+        .expect("random_bytes returns the requested length");
+    // The coefficients of one polynomial per byte of the key, whose constant term is that
+    // byte. The other coefficients are random.
+    let coefficients: Vec<Vec<u8>> = key
+        .iter()
+        .map(|&byte| {
+            let mut coefficients = vec![byte];
+            coefficients.extend(random_bytes(usize::from(threshold) - 1));
+            coefficients
+        })
+        .collect();
+
+    Ok((1..=shares)
+        .map(|index| KeyShare {
+            index,
+            threshold,
+            split_id,
+            value: coefficients
+                .iter()
+                .map(|coefficients| evaluate(coefficients, index))
+                .collect(),
+        })
+        .collect())
+}
+
+/// Recombines a key split with `split_key` from at least as many of its shares as its
+/// threshold. See `split_key` for an example.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::InvalidKeyShare` with `KeyShareError::NotEnoughShares` if fewer
+/// shares than the threshold are given, `KeyShareError::DuplicateShare` if a share is given
+/// twice, and `KeyShareError::MixedShares` if the shares come from different splits.
+pub fn combine_shares(shares: &[KeyShare]) -> Result<Vec<u8>, EnvEncError> {
+    let invalid = |reason| EnvEncError::InvalidKeyShare { reason };
+    let Some(first) = shares.first() else {
+        return Err(invalid(KeyShareError::NotEnoughShares {
+            needed: 1,
+            got: 0,
+        }));
+    };
+    for (position, share) in shares.iter().enumerate() {
+        if share.split_id != first.split_id
+            || share.threshold != first.threshold
+            || share.value.len() != first.value.len()
+        {
+            return Err(invalid(KeyShareError::MixedShares));
+        }
+        if shares[..position]
+            .iter()
+            .any(|other| other.index == share.index)
+        {
+            return Err(invalid(KeyShareError::DuplicateShare(share.index)));
+        }
+    }
+    if shares.len() < usize::from(first.threshold) {
+        return Err(invalid(KeyShareError::NotEnoughShares {
+            needed: first.threshold,
+            got: shares.len(),
+        }));
+    }
+
+    // Lagrange interpolation at zero, over the first `threshold` shares.
+    let shares = &shares[..usize::from(first.threshold)];
+    let mut key = vec![0u8; first.value.len()];
+    for share in shares {
+        let mut basis = 1;
+        for other in shares.iter().filter(|other| other.index != share.index) {
+            basis = gf_mul(
+                basis,
+                gf_mul(other.index, gf_inv(other.index ^ share.index)),
+            );
+        }
+        for (byte, &value) in key.iter_mut().zip(&share.value) {
+            *byte ^= gf_mul(value, basis);
+        }
+    }
+    Ok(key)
+}
+
+/// Returns the first bytes of the SHA-256 hash of `bytes`.
+fn checksum_of(bytes: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    Sha256::digest(bytes)[..CHECKSUM_SIZE]
+        .try_into()
+        .expect("a SHA-256 hash is longer than the checksum")
+}
+
+/// Evaluates the polynomial with `coefficients`, lowest degree first, at `x` in GF(256).
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0, |result, &coefficient| gf_mul(result, x) ^ coefficient)
+}
+
+/// Multiplies `a` and `b` in GF(256) with the AES polynomial, in constant time.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// Returns the multiplicative inverse of the non-zero `a` in GF(256), as `a` to the 254th.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = gf_mul(result, power);
+        }
+        power = gf_mul(power, power);
+        exponent >>= 1;
+    }
+    result
+}
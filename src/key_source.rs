@@ -0,0 +1,528 @@
+use crate::{
+    combine_shares, env_file::check_permissions, random_bytes, validate_key, CipherType,
+    EnvEncError, Key, KeyShare, KeyShareError, SecretValue,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The environment variable the default `KeySource` reads the key from.
+pub const DEFAULT_KEY_VAR: &str = "ENVENC_KEY";
+
+/// The environment variable `KeySource::from_env` reads the path of a key file from.
+pub const DEFAULT_KEY_FILE_VAR: &str = "ENVENC_KEY_FILE";
+
+/// Where to load the encryption key from.
+///
+/// The default source is the `ENVENC_KEY` environment variable, which suits deployments where
+/// an orchestrator injects the key. Keys are never written back to the environment or logged,
+/// and `Debug` only shows where the key comes from.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{CipherType, EnvEncError, KeySource};
+///
+/// // Keys can be given as hex or as base64.
+/// std::env::set_var("ENVENC_KEY", "11".repeat(32));
+/// assert_eq!(KeySource::default().load(CipherType::AES256GCM)?, [0x11; 32]);
+///
+/// std::env::set_var("APP_ENVENC_KEY", "IiIiIiIiIiIiIiIiIiIiIiIiIiIiIiIiIiIiIiIiIiI=");
+/// let source = KeySource::EnvVar("APP_ENVENC_KEY".to_string());
+/// assert_eq!(source.load(CipherType::AES256GCM)?, [0x22; 32]);
+///
+/// // The key must match the cipher.
+/// assert!(matches!(
+///     source.load(CipherType::AES128GCM),
+///     Err(EnvEncError::InvalidKeyLength { expected: 16, actual: 32, .. })
+/// ));
+///
+/// std::env::set_var("APP_ENVENC_KEY", "not a key!");
+/// assert!(matches!(source.load(CipherType::AES256GCM), Err(EnvEncError::InvalidKeyEncoding)));
+///
+/// // A missing variable is an error rather than a reason to generate a new key.
+/// let missing = KeySource::EnvVar("APP_MISSING_ENVENC_KEY".to_string());
+/// assert!(matches!(
+///     missing.load(CipherType::AES256GCM),
+///     Err(EnvEncError::MissingKeyVar { var }) if var == "APP_MISSING_ENVENC_KEY"
+/// ));
+/// assert!(std::env::var("APP_MISSING_ENVENC_KEY").is_err());
+/// # Ok::<(), envenc::EnvEncError>(())
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum KeySource {
+    /// The hex or base64 encoded key is read from the named environment variable.
+    EnvVar(String),
+    /// The hex-encoded key is stored in the OS keyring under `service` and `user`: the macOS
+    /// Keychain, the Windows Credential Manager, or the Secret Service on Linux.
+    #[cfg(feature = "keyring")]
+    OsKeyring { service: String, user: String },
+    /// The key is read from the file at `path`, such as a Docker or Kubernetes secret mounted
+    /// under `/run/secrets/`. Trailing newlines are ignored.
+    ///
+    /// On Unix, a key file that other users can read is logged as a warning through `log`, or
+    /// rejected with `EnvEncError::InsecurePermissions` if `strict_permissions` is set.
+    File {
+        path: PathBuf,
+        #[cfg_attr(feature = "serde", serde(default))]
+        encoding: KeyEncoding,
+        #[cfg_attr(feature = "serde", serde(default))]
+        strict_permissions: bool,
+    },
+    /// The key is recombined from shares created by `split_key` and serialized as hex, at
+    /// least as many as the threshold they were split with. See `split_key` for an example.
+    Shares(Vec<String>),
+    /// The key is split into two halves, one read from the environment variable `env_var` as
+    /// hex or base64 and one from the key file at `file`, and is their XOR.
+    ///
+    /// Neither half alone reveals anything about the key, so access to the filesystem of the
+    /// machine or to the environment its orchestrator injects is not enough to decrypt. This
+    /// only holds if both halves are random and as long as the key: create them with
+    /// `generate_split_key`, which draws both from the operating system's random number
+    /// generator, rather than splitting an existing key by hand.
+    Split { env_var: String, file: PathBuf },
+}
+
+/// How a key is encoded in a key file read by `KeySource::File`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum KeyEncoding {
+    /// Detect the encoding: the first of hex, base64, and raw bytes that yields a key of the
+    /// length the cipher requires.
+    #[default]
+    Auto,
+    /// The file holds the key bytes themselves.
+    Raw,
+    /// The file holds the key as hex.
+    Hex,
+    /// The file holds the key as standard base64.
+    Base64,
+}
+
+impl KeySource {
+    /// Returns a source that reads the key from the file at `path`, detecting its encoding.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use base64::{engine::general_purpose::STANDARD, Engine};
+    /// use envenc::{CipherType, KeySource};
+    ///
+    /// let dir = tempfile::tempdir()?;
+    /// let path = dir.path().join("envenc_key");
+    /// let key = [0x5a; 32];
+    ///
+    /// let encodings = [key.to_vec(), hex::encode(key).into_bytes(), STANDARD.encode(key).into_bytes()];
+    /// for contents in encodings {
+    ///     for ending in [&b""[..], b"\n", b"\r\n"] {
+    ///         std::fs::write(&path, [&contents[..], ending].concat())?;
+    ///         assert_eq!(KeySource::file(&path).load(CipherType::AES256GCM)?, key);
+    ///     }
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// Key files that other users can read are rejected in strict mode:
+    ///
+    /// ```
+    /// use envenc::{CipherType, EnvEncError, KeyEncoding, KeySource};
+    ///
+    /// let dir = tempfile::tempdir()?;
+    /// let path = dir.path().join("envenc_key");
+    /// std::fs::write(&path, "ab".repeat(32))?;
+    /// let source = KeySource::File {
+    ///     path: path.clone(),
+    ///     encoding: KeyEncoding::Hex,
+    ///     strict_permissions: true,
+    /// };
+    ///
+    /// #[cfg(unix)]
+    /// {
+    ///     use std::os::unix::fs::PermissionsExt;
+    ///     std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644))?;
+    ///     assert!(matches!(
+    ///         source.load(CipherType::AES256GCM),
+    ///         Err(EnvEncError::InsecurePermissions { mode: 0o644, .. })
+    ///     ));
+    ///     std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    /// }
+    /// assert_eq!(source.load(CipherType::AES256GCM)?, [0xab; 32]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn file(path: impl AsRef<Path>) -> Self {
+        KeySource::File {
+            path: path.as_ref().to_path_buf(),
+            encoding: KeyEncoding::Auto,
+            strict_permissions: false,
+        }
+    }
+
+    /// Returns the source configured by the environment: the key file named by
+    /// `ENVENC_KEY_FILE` if it is set, and the `ENVENC_KEY` variable otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use envenc::KeySource;
+    ///
+    /// assert_eq!(KeySource::from_env(), KeySource::EnvVar("ENVENC_KEY".to_string()));
+    ///
+    /// std::env::set_var("ENVENC_KEY_FILE", "/run/secrets/envenc_key");
+    /// assert_eq!(KeySource::from_env(), KeySource::file("/run/secrets/envenc_key"));
+    /// ```
+    pub fn from_env() -> Self {
+        match env::var_os(DEFAULT_KEY_FILE_VAR) {
+            Some(path) => KeySource::file(path),
+            None => KeySource::default(),
+        }
+    }
+
+    /// Loads the key and checks that it has the length required by `cipher_type`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::MissingKeyVar` if the environment variable is not set,
+    /// `EnvEncError::MissingKeyringEntry` if the keyring holds no key,
+    /// `EnvEncError::KeyringUnavailable` if the keyring cannot be accessed,
+    /// `EnvEncError::InvalidKeyEncoding` if the stored key is neither hex nor base64, and
+    /// `EnvEncError::InvalidKeyLength` if the key does not match `cipher_type`. A key file
+    /// that cannot be read yields `EnvEncError::Io`, and one that other users can read yields
+    /// `EnvEncError::InsecurePermissions` in strict mode. Shares that cannot be parsed or
+    /// combined yield `EnvEncError::InvalidKeyShare`. A split key whose file half does not
+    /// exist yields `EnvEncError::MissingKeyFile`, and halves of different lengths yield
+    /// `EnvEncError::SplitKeyLengthMismatch`.
+    pub fn load(&self, cipher_type: CipherType) -> Result<Vec<u8>, EnvEncError> {
+        let encoded = SecretValue::from(match self {
+            KeySource::File {
+                path,
+                encoding,
+                strict_permissions,
+            } => return load_key_file(path, *encoding, *strict_permissions, cipher_type),
+            KeySource::Shares(shares) => return load_key_shares(shares, cipher_type),
+            KeySource::Split { env_var, file } => {
+                return load_split_key(env_var, file, cipher_type)
+            }
+            KeySource::EnvVar(var) => {
+                env::var(var).map_err(|_| EnvEncError::MissingKeyVar { var: var.clone() })?
+            }
+            #[cfg(feature = "keyring")]
+            KeySource::OsKeyring { service, user } => {
+                match keyring_entry(service, user)?.get_password() {
+                    Ok(encoded) => encoded,
+                    Err(keyring::Error::NoEntry) => {
+                        return Err(EnvEncError::MissingKeyringEntry {
+                            service: service.clone(),
+                            user: user.clone(),
+                        })
+                    }
+                    Err(err) => return Err(keyring_unavailable(err)),
+                }
+            }
+        });
+        let key = decode_key(encoded.expose_secret().trim())?;
+        validate_key(&cipher_type, key.as_bytes())?;
+        Ok(key.into_vec())
+    }
+
+    /// Loads the key like `load`, generating and storing a new one on first use where the
+    /// source can be written to.
+    ///
+    /// With `OsKeyring`, a missing key is generated and saved to the keyring, and later calls
+    /// return the same key. Other sources are never written to, so a missing key is an error
+    /// just as with `load`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `load`, except `EnvEncError::MissingKeyringEntry`.
+    ///
+    #[cfg_attr(feature = "keyring", doc = "```no_run")]
+    #[cfg_attr(not(feature = "keyring"), doc = "```ignore")]
+    /// use envenc::{CipherType, EnvEnc, KeySource};
+    ///
+    /// let cipher_type = CipherType::AES256GCM;
+    /// let source = KeySource::OsKeyring {
+    ///     service: "my-app".to_string(),
+    ///     user: "envenc".to_string(),
+    /// };
+    /// let key = source.load_or_generate(cipher_type)?;
+    /// assert_eq!(source.load(cipher_type)?, key);
+    ///
+    /// let session = EnvEnc::builder().cipher(cipher_type).key(key).build()?;
+    /// # Ok::<(), envenc::EnvEncError>(())
+    /// ```
+    pub fn load_or_generate(&self, cipher_type: CipherType) -> Result<Vec<u8>, EnvEncError> {
+        match self {
+            #[cfg(feature = "keyring")]
+            KeySource::OsKeyring { service, user } => match self.load(cipher_type) {
+                Err(EnvEncError::MissingKeyringEntry { .. }) => {
+                    let key = crate::random_bytes(cipher_type.key_size());
+                    keyring_entry(service, user)?
+                        .set_password(&hex::encode(&key))
+                        .map_err(keyring_unavailable)?;
+                    Ok(key)
+                }
+                result => result,
+            },
+            _ => self.load(cipher_type),
+        }
+    }
+}
+
+impl std::fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeySource::EnvVar(var) => f.debug_tuple("EnvVar").field(var).finish(),
+            #[cfg(feature = "keyring")]
+            KeySource::OsKeyring { service, user } => f
+                .debug_struct("OsKeyring")
+                .field("service", service)
+                .field("user", user)
+                .finish(),
+            KeySource::File {
+                path,
+                encoding,
+                strict_permissions,
+            } => f
+                .debug_struct("File")
+                .field("path", path)
+                .field("encoding", encoding)
+                .field("strict_permissions", strict_permissions)
+                .finish(),
+            KeySource::Shares(shares) => f
+                .debug_tuple("Shares")
+                .field(&format_args!("[{} shares]", shares.len()))
+                .finish(),
+            KeySource::Split { env_var, file } => f
+                .debug_struct("Split")
+                .field("env_var", env_var)
+                .field("file", file)
+                .finish(),
+        }
+    }
+}
+
+impl Default for KeySource {
+    fn default() -> Self {
+        KeySource::EnvVar(DEFAULT_KEY_VAR.to_string())
+    }
+}
+
+/// Decodes a key given as hex or, failing that, as standard base64.
+fn decode_key(encoded: &str) -> Result<Key, EnvEncError> {
+    hex::decode(encoded)
+        .or_else(|_| STANDARD.decode(encoded))
+        .map(Key::from_bytes)
+        .map_err(|_| EnvEncError::InvalidKeyEncoding)
+}
+
+/// A random key for `KeySource::Split` with its two random halves, as returned by
+/// `generate_split_key`. `Debug` does not show the key or its halves.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SplitKey {
+    key: Key,
+    env_half: Key,
+    file_half: Key,
+}
+
+impl SplitKey {
+    /// Returns the key, the XOR of the two halves.
+    pub fn key(&self) -> &[u8] {
+        self.key.as_bytes()
+    }
+
+    /// Returns the half to store in the environment variable, encoded as hex or base64.
+    pub fn env_half(&self) -> &[u8] {
+        self.env_half.as_bytes()
+    }
+
+    /// Returns the half to store in the key file.
+    pub fn file_half(&self) -> &[u8] {
+        self.file_half.as_bytes()
+    }
+}
+
+impl std::fmt::Debug for SplitKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SplitKey")
+            .field("key", &"[REDACTED]")
+            .field("env_half", &"[REDACTED]")
+            .field("file_half", &"[REDACTED]")
+            .finish()
+    }
+}
+
+/// Generates a random key for `cipher_type`, split into two random halves for
+/// `KeySource::Split`.
+///
+/// Both halves are drawn from the operating system's random number generator, and the key is
+/// their XOR, so that each half on its own is indistinguishable from random bytes. Store the
+/// halves in different places, such as a secret injected by the orchestrator and a file on the
+/// machine, and never next to each other.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{generate_split_key, CipherType, EnvEncError, KeySource};
+///
+/// let dir = tempfile::tempdir()?;
+/// let file = dir.path().join("key_half");
+/// let cipher_type = CipherType::AES256GCM;
+/// let split = generate_split_key(cipher_type);
+///
+/// std::env::set_var("SPLIT_KEY_HALF", hex::encode(split.env_half()));
+/// std::fs::write(&file, split.file_half())?;
+/// let source = KeySource::Split { env_var: "SPLIT_KEY_HALF".to_string(), file: file.clone() };
+/// assert_eq!(source.load(cipher_type)?, split.key());
+///
+/// // Each missing half is reported as such.
+/// let missing_file = KeySource::Split { env_var: "SPLIT_KEY_HALF".to_string(), file: dir.path().join("missing") };
+/// assert!(matches!(missing_file.load(cipher_type), Err(EnvEncError::MissingKeyFile { .. })));
+/// let missing_var = KeySource::Split { env_var: "SPLIT_KEY_MISSING_HALF".to_string(), file: file.clone() };
+/// assert!(matches!(missing_var.load(cipher_type), Err(EnvEncError::MissingKeyVar { .. })));
+///
+/// // So are halves of different lengths.
+/// std::fs::write(&file, &split.file_half()[..16])?;
+/// assert!(matches!(
+///     source.load(cipher_type),
+///     Err(EnvEncError::SplitKeyLengthMismatch { env_len: 32, file_len: 16 })
+/// ));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn generate_split_key(cipher_type: CipherType) -> SplitKey {
+    let env_half = Key::from_bytes(random_bytes(cipher_type.key_size()));
+    let file_half = Key::from_bytes(random_bytes(cipher_type.key_size()));
+    SplitKey {
+        key: Key::from_bytes(xor(env_half.as_bytes(), file_half.as_bytes())),
+        env_half,
+        file_half,
+    }
+}
+
+/// Returns the bytewise XOR of `a` and `b`, which have the same length.
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
+
+/// Reads both halves of a split key for `cipher_type` and combines them.
+fn load_split_key(
+    env_var: &str,
+    file: &Path,
+    cipher_type: CipherType,
+) -> Result<Vec<u8>, EnvEncError> {
+    let env_half =
+        SecretValue::from(env::var(env_var).map_err(|_| EnvEncError::MissingKeyVar {
+            var: env_var.to_string(),
+        })?);
+    let env_half = decode_key(env_half.expose_secret().trim())?;
+    if !file.try_exists()? {
+        return Err(EnvEncError::MissingKeyFile {
+            path: file.to_path_buf(),
+        });
+    }
+    let file_half = read_key_file(file, KeyEncoding::Auto, false, cipher_type)?;
+    let (env_half, file_half) = (env_half.as_bytes(), file_half.as_bytes());
+    if env_half.len() != file_half.len() {
+        return Err(EnvEncError::SplitKeyLengthMismatch {
+            env_len: env_half.len(),
+            file_len: file_half.len(),
+        });
+    }
+    let key = Key::from_bytes(xor(env_half, file_half));
+    validate_key(&cipher_type, key.as_bytes())?;
+    Ok(key.into_vec())
+}
+
+/// Recombines the key for `cipher_type` from serialized key shares.
+fn load_key_shares(shares: &[String], cipher_type: CipherType) -> Result<Vec<u8>, EnvEncError> {
+    let shares = shares
+        .iter()
+        .map(|share| KeyShare::parse(share))
+        .collect::<Result<Vec<_>, KeyShareError>>()
+        .map_err(|reason| EnvEncError::InvalidKeyShare { reason })?;
+    let key = Key::from_bytes(combine_shares(&shares)?);
+    validate_key(&cipher_type, key.as_bytes())?;
+    Ok(key.into_vec())
+}
+
+/// Reads the key for `cipher_type` from the key file at `path`.
+fn load_key_file(
+    path: &Path,
+    encoding: KeyEncoding,
+    strict_permissions: bool,
+    cipher_type: CipherType,
+) -> Result<Vec<u8>, EnvEncError> {
+    let key = read_key_file(path, encoding, strict_permissions, cipher_type)?;
+    validate_key(&cipher_type, key.as_bytes())?;
+    Ok(key.into_vec())
+}
+
+/// Reads and decodes the key file at `path`, preferring decodings that yield a key for
+/// `cipher_type`, without checking the length of the key.
+fn read_key_file(
+    path: &Path,
+    encoding: KeyEncoding,
+    strict_permissions: bool,
+    cipher_type: CipherType,
+) -> Result<Key, EnvEncError> {
+    match check_permissions(path) {
+        Err(EnvEncError::InsecurePermissions { mode, .. }) if !strict_permissions => log::warn!(
+            "key file '{}' is accessible by other users (mode {:o})",
+            path.display(),
+            mode
+        ),
+        result => result?,
+    }
+
+    let contents = Key::from_bytes(fs::read(path)?);
+    let bytes = contents.as_bytes();
+    let trimmed = bytes.trim_ascii_end();
+    Ok(match encoding {
+        KeyEncoding::Raw if bytes.len() == cipher_type.key_size() => contents,
+        KeyEncoding::Raw => Key::from_bytes(trimmed),
+        KeyEncoding::Hex => Key::from_bytes(hex::decode(trimmed)?),
+        KeyEncoding::Base64 => Key::from_bytes(
+            STANDARD
+                .decode(trimmed)
+                .map_err(|_| EnvEncError::InvalidKeyEncoding)?,
+        ),
+        KeyEncoding::Auto => {
+            let candidates = [
+                hex::decode(trimmed).ok(),
+                STANDARD.decode(trimmed).ok(),
+                Some(bytes.to_vec()),
+                Some(trimmed.to_vec()),
+            ];
+            candidates
+                .into_iter()
+                .flatten()
+                .map(Key::from_bytes)
+                .find(|key| key.as_bytes().len() == cipher_type.key_size())
+                .unwrap_or(contents)
+        }
+    })
+}
+
+/// Opens the keyring entry for `service` and `user`.
+#[cfg(feature = "keyring")]
+fn keyring_entry(service: &str, user: &str) -> Result<keyring::Entry, EnvEncError> {
+    keyring::Entry::new(service, user).map_err(keyring_unavailable)
+}
+
+/// Converts an error of the keyring backend into `EnvEncError::KeyringUnavailable`.
+#[cfg(feature = "keyring")]
+fn keyring_unavailable(err: keyring::Error) -> EnvEncError {
+    EnvEncError::KeyringUnavailable {
+        reason: err.to_string(),
+    }
+}
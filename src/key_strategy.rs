@@ -0,0 +1,95 @@
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::borrow::Cow;
+
+/// How the key that encrypts a value is obtained from the key passed in.
+///
+/// The strategy is recorded in the `Envelope` of every value, so values are always decrypted
+/// the way they were encrypted, whatever strategy the reader is configured with. Select the
+/// strategy for new values with `EnvEncBuilder::key_strategy`.
+///
+/// # Example
+///
+/// With `PerVariableHkdf`, a value is encrypted under a subkey derived from its variable name,
+/// so a renamed variable can no longer be read:
+///
+/// ```
+/// use envenc::{keys_generation, CipherType, EnvEnc, EnvEncError, EnvStore, KeyStrategy, MemoryStore};
+///
+/// let store = MemoryStore::new();
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// let builder = EnvEnc::builder().cipher(cipher_type).key(key.clone());
+///
+/// let mut session = builder
+///     .clone()
+///     .key_strategy(KeyStrategy::PerVariableHkdf)
+///     .store(Box::new(store.clone()))
+///     .build()?;
+/// session.set("PRIMARY_TOKEN", "same value")?;
+/// session.set("BACKUP_TOKEN", "same value")?;
+///
+/// let stored = store.load()?.to_map();
+/// assert!(stored["PRIMARY_TOKEN"].starts_with("ENC[v2:AES256GCM:hkdf-sha256:"));
+/// assert_ne!(stored["PRIMARY_TOKEN"], stored["BACKUP_TOKEN"]);
+///
+/// // A session using the default strategy still reads the values, since the strategy is
+/// // recorded with each one.
+/// let open = |contents: String| builder.clone().store(Box::new(MemoryStore::with_contents(contents))).build();
+/// assert_eq!(open(store.contents().unwrap())?.get("PRIMARY_TOKEN")?.as_deref(), Some("same value"));
+///
+/// // Neither value decrypts under the other's name.
+/// let reader = open(format!(
+///     "PRIMARY_TOKEN={}\nBACKUP_TOKEN={}\n",
+///     stored["BACKUP_TOKEN"], stored["PRIMARY_TOKEN"]
+/// ))?;
+/// assert!(matches!(reader.get("PRIMARY_TOKEN"), Err(EnvEncError::DecryptionFailed { .. })));
+/// assert!(matches!(reader.get("BACKUP_TOKEN"), Err(EnvEncError::DecryptionFailed { .. })));
+///
+/// // Renaming a variable makes its value unreadable.
+/// let reader = open(format!("RENAMED_TOKEN={}\n", stored["PRIMARY_TOKEN"]))?;
+/// assert!(matches!(reader.get("RENAMED_TOKEN"), Err(EnvEncError::DecryptionFailed { .. })));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum KeyStrategy {
+    /// Every value is encrypted with the key itself.
+    #[default]
+    Direct,
+    /// Every value is encrypted with its own subkey, derived from the key with HKDF-SHA256
+    /// using the variable name as the info string. A nonce reused across two variables then
+    /// never repeats under the same key. Renaming a variable makes its value unreadable.
+    PerVariableHkdf,
+}
+
+impl KeyStrategy {
+    /// Returns the tag recorded in envelopes, or `None` for `Direct`, which is not recorded.
+    pub(crate) fn tag(&self) -> Option<&'static str> {
+        match self {
+            KeyStrategy::Direct => None,
+            KeyStrategy::PerVariableHkdf => Some("hkdf-sha256"),
+        }
+    }
+
+    /// Returns the strategy recorded in envelopes as `tag`.
+    pub(crate) fn from_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "hkdf-sha256" => Some(KeyStrategy::PerVariableHkdf),
+            _ => None,
+        }
+    }
+
+    /// Returns the key of `len` bytes that encrypts the value of `var_name` under `key`.
+    pub(crate) fn derive<'a>(&self, key: &'a [u8], var_name: &str, len: usize) -> Cow<'a, [u8]> {
+        match self {
+            KeyStrategy::Direct => Cow::Borrowed(key),
+            KeyStrategy::PerVariableHkdf => {
+                let mut subkey = vec![0u8; len];
+                Hkdf::<Sha256>::new(None, key)
+                    .expand(var_name.as_bytes(), &mut subkey)
+                    .expect("cipher keys are far shorter than the HKDF-SHA256 output limit");
+                Cow::Owned(subkey)
+            }
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    decrypt_or_plain, decrypt_stored_value,
+    typed::{parse_bool, parse_value},
+    validate_key, CipherType, EnvEncError, Key, SecretValue,
+};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+/// Encrypted environment variables that are only decrypted when they are first read.
+///
+/// A service that reads few of its variables in a given run then never decrypts the others,
+/// and never holds their plaintext in memory or in the process environment. Each value is
+/// decrypted once and kept for later reads, unless caching is turned off with `cache`, in
+/// which case it is decrypted on every read and only the ciphertext is kept. Concurrent first
+/// reads of a variable wait for a single decryption, while other variables decrypt in parallel.
+///
+/// A `LazyEnv` is `Sync`, so it can live in a global such as a `OnceLock`. `decryptions`
+/// counts the values decrypted so far, for tests that check how often that happens.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, read_env_enc_from, set_enc_env_in, CipherType, LazyEnv};
+/// use std::sync::OnceLock;
+///
+/// static ENV: OnceLock<LazyEnv> = OnceLock::new();
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "API_KEY", "sk_live_123", cipher_type, &key)?;
+/// set_enc_env_in(&path, "DATABASE_URL", "postgres://localhost/db", cipher_type, &key)?;
+///
+/// let env = ENV.get_or_init(|| LazyEnv::new(read_env_enc_from(&path).unwrap(), cipher_type, &key).unwrap());
+/// assert_eq!(env.decryptions(), 0);
+///
+/// std::thread::scope(|scope| {
+///     for _ in 0..8 {
+///         scope.spawn(|| {
+///             let value = env.get("API_KEY").unwrap().unwrap();
+///             assert_eq!(value.expose_secret(), "sk_live_123");
+///         });
+///     }
+/// });
+/// // Eight threads read the value, which was decrypted once; `DATABASE_URL` never was.
+/// assert_eq!(env.decryptions(), 1);
+/// assert!(env.get("MISSING")?.is_none());
+///
+/// let uncached = LazyEnv::new(read_env_enc_from(&path)?, cipher_type, &key)?.cache(false);
+/// uncached.get("DATABASE_URL")?;
+/// uncached.get("DATABASE_URL")?;
+/// assert_eq!(uncached.decryptions(), 2);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct LazyEnv {
+    cipher_type: CipherType,
+    key: Key,
+    entries: HashMap<String, LazyEntry>,
+    cache: bool,
+    legacy_hex: bool,
+    decryptions: AtomicUsize,
+}
+
+/// A variable of a `LazyEnv`.
+struct LazyEntry {
+    /// The value as stored in the env file.
+    stored: String,
+    /// The decrypted value, once it has been read, if values are cached.
+    decrypted: Mutex<Option<SecretValue>>,
+}
+
+impl LazyEnv {
+    /// Returns the variables `env_vars`, as read by `read_env_enc_from`, to be decrypted with
+    /// `key` when they are read. Values that are not encrypted are passed through as they are;
+    /// see `legacy_hex` to decrypt legacy hex values with `cipher_type`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidKeyLength` if `key` does not match `cipher_type`.
+    pub fn new(
+        env_vars: HashMap<String, String>,
+        cipher_type: CipherType,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Self, EnvEncError> {
+        let key = key.as_ref();
+        validate_key(&cipher_type, key)?;
+        let entries = env_vars
+            .into_iter()
+            .map(|(name, stored)| {
+                let entry = LazyEntry {
+                    stored,
+                    decrypted: Mutex::new(None),
+                };
+                (name, entry)
+            })
+            .collect();
+        Ok(LazyEnv {
+            cipher_type,
+            key: Key::from_bytes(key),
+            entries,
+            cache: true,
+            legacy_hex: false,
+            decryptions: AtomicUsize::new(0),
+        })
+    }
+
+    /// Sets whether decrypted values are kept for later reads. Defaults to `true`; without
+    /// caching, every read decrypts the value again.
+    pub fn cache(mut self, enabled: bool) -> Self {
+        self.cache = enabled;
+        self
+    }
+
+    /// Sets whether values that are bare hex, as written by earlier versions of this crate,
+    /// are decrypted with `cipher_type` rather than passed through as plain text. Defaults to
+    /// `false`, so that a plain value that happens to be hex, such as a git SHA, is read as
+    /// it is.
+    pub fn legacy_hex(mut self, enabled: bool) -> Self {
+        self.legacy_hex = enabled;
+        self
+    }
+
+    /// Returns the decrypted value of `var_name`, decrypting it if it has not been read yet
+    /// or caching is off, or `None` if it is not set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::DecryptionFailed` or `EnvEncError::InvalidUtf8` if the value cannot
+    /// be decrypted. It is not cached, so a later read tries again.
+    pub fn get(&self, var_name: &str) -> Result<Option<SecretValue>, EnvEncError> {
+        let Some(entry) = self.entries.get(var_name) else {
+            return Ok(None);
+        };
+        if !self.cache {
+            return self.decrypt(var_name, &entry.stored).map(Some);
+        }
+        let mut decrypted = entry
+            .decrypted
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if decrypted.is_none() {
+            *decrypted = Some(self.decrypt(var_name, &entry.stored)?);
+        }
+        Ok(decrypted.clone())
+    }
+
+    /// Returns the decrypted value of `var_name` parsed as a `T`, as `read_env_parse` does,
+    /// decrypting it as `get` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::MissingVar` if the variable is not set, the errors of `get`, and
+    /// `EnvEncError::InvalidValue` if the value cannot be parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use envenc::{keys_generation, read_env_enc_from, set_enc_env_in, CipherType, LazyEnv};
+    ///
+    /// let dir = tempfile::tempdir()?;
+    /// let path = dir.path().join("secrets.env");
+    /// let cipher_type = CipherType::AES256GCM;
+    /// let (key, _) = keys_generation(cipher_type)?;
+    /// set_enc_env_in(&path, "PORT", "5432", cipher_type, &key)?;
+    /// set_enc_env_in(&path, "TLS", "on", cipher_type, &key)?;
+    ///
+    /// let env = LazyEnv::new(read_env_enc_from(&path)?, cipher_type, &key)?;
+    /// assert_eq!(env.parse::<u16>("PORT")?, 5432);
+    /// assert!(env.parse_bool("TLS")?);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn parse<T>(&self, var_name: &str) -> Result<T, EnvEncError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        parse_value(var_name, self.require(var_name)?.expose_secret())
+    }
+
+    /// Returns the decrypted value of `var_name` as a flag, as `read_env_bool` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::MissingVar` if the variable is not set, the errors of `get`, and
+    /// `EnvEncError::InvalidValue` if the value is not a flag.
+    pub fn parse_bool(&self, var_name: &str) -> Result<bool, EnvEncError> {
+        parse_bool(var_name, self.require(var_name)?.expose_secret())
+    }
+
+    /// Returns the decrypted value of `var_name` deserialized from JSON, as `read_env_json`
+    /// does.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::MissingVar` if the variable is not set, the errors of `get`, and
+    /// `EnvEncError::InvalidValue` if the value is not JSON of the shape of `T`.
+    #[cfg(feature = "serde")]
+    pub fn parse_json<T: serde::de::DeserializeOwned>(
+        &self,
+        var_name: &str,
+    ) -> Result<T, EnvEncError> {
+        crate::typed::parse_json(var_name, self.require(var_name)?.expose_secret())
+    }
+
+    /// Returns `true` if `var_name` is set, without decrypting it.
+    pub fn contains(&self, var_name: &str) -> bool {
+        self.entries.contains_key(var_name)
+    }
+
+    /// Returns the names of the variables, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Returns the number of values decrypted so far. A cached value counts once, however
+    /// often it is read.
+    pub fn decryptions(&self) -> usize {
+        self.decryptions.load(Ordering::Relaxed)
+    }
+
+    /// Returns the decrypted value of `var_name`, failing if it is not set.
+    fn require(&self, var_name: &str) -> Result<SecretValue, EnvEncError> {
+        self.get(var_name)?.ok_or_else(|| EnvEncError::MissingVar {
+            var: var_name.to_string(),
+        })
+    }
+
+    /// Decrypts the stored value `stored` of `var_name`, passing a value that is not encrypted
+    /// through as it is.
+    fn decrypt(&self, var_name: &str, stored: &str) -> Result<SecretValue, EnvEncError> {
+        self.decryptions.fetch_add(1, Ordering::Relaxed);
+        let result = decrypt_or_plain(var_name, stored, self.legacy_hex, || {
+            decrypt_stored_value(&self.cipher_type, self.key.as_bytes(), var_name, stored)
+        });
+        match result {
+            Ok(Some(value)) => Ok(SecretValue::from(value)),
+            Ok(None) => Ok(SecretValue::from(stored.to_string())),
+            Err(err) => Err(err.into_error(var_name)),
+        }
+    }
+}
+
+impl std::fmt::Debug for LazyEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut names: Vec<&str> = self.names().collect();
+        names.sort_unstable();
+        f.debug_struct("LazyEnv")
+            .field("cipher_type", &self.cipher_type)
+            .field("names", &names)
+            .field("cache", &self.cache)
+            .field("legacy_hex", &self.legacy_hex)
+            .field("decryptions", &self.decryptions())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{DecryptedEnv, EnvEnc, EnvEncError, SecretValue};
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    path::{Path, PathBuf},
+};
+
+/// How `merge_env_files` resolves a variable that more than one file sets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// The value of the last file that sets the variable is used.
+    #[default]
+    LastWins,
+    /// The value of the first file that sets the variable is used, so later files only add
+    /// variables.
+    FirstWins,
+    /// Two files that set the variable to different values are an error,
+    /// `EnvEncError::MergeConflict`. Files that set it to the same value are not.
+    Error,
+}
+
+/// The variables of several env files, merged by `merge_env_files`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergedEnv {
+    /// The decrypted values.
+    pub env: DecryptedEnv,
+    /// The file that supplied the value of each variable.
+    pub sources: BTreeMap<String, PathBuf>,
+}
+
+/// Decrypts the env files `files`, each with its own key, and merges their variables into one
+/// set of values, without touching the process environment.
+///
+/// Files are read in order, and `strategy` decides which value a variable gets when several
+/// files set it. Values that are not encrypted are passed through as they are. Each file is
+/// read by an `EnvEnc` session, so data keys, MAC footers, and sealed files are read as they
+/// are for a single file.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::MissingEnvFile` if a file does not exist,
+/// `EnvEncError::MergeConflict` for the first variable two files set to different values
+/// under `MergeStrategy::Error`, the first value that fails to decrypt, as
+/// `EnvEncError::DecryptionFailed`, and the errors of `EnvEncBuilder::build`, such as
+/// `EnvEncError::KeyMismatch` if a file is encrypted with another key.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, merge_env_files, set_enc_env_in, CipherType, EnvEncError, MergeStrategy};
+///
+/// let dir = tempfile::tempdir()?;
+/// let cipher_type = CipherType::AES256GCM;
+/// let (team_key, _) = keys_generation(cipher_type)?;
+/// let (service_key, _) = keys_generation(cipher_type)?;
+/// let team = dir.path().join("team.env");
+/// let service = dir.path().join("billing.env");
+/// set_enc_env_in(&team, "SENTRY_DSN", "https://key@sentry.example.com/1", cipher_type, &team_key)?;
+/// set_enc_env_in(&team, "DATABASE_URL", "postgres://shared/db", cipher_type, &team_key)?;
+/// set_enc_env_in(&service, "STRIPE_KEY", "sk_live_123", cipher_type, &service_key)?;
+/// set_enc_env_in(&service, "DATABASE_URL", "postgres://billing/db", cipher_type, &service_key)?;
+/// let files = [(&team, &team_key), (&service, &service_key)];
+///
+/// let merged = merge_env_files(&files, MergeStrategy::LastWins)?;
+/// assert_eq!(merged.env.get("DATABASE_URL"), Some("postgres://billing/db"));
+/// assert_eq!(merged.sources["DATABASE_URL"], service);
+/// assert_eq!(merged.sources["SENTRY_DSN"], team);
+/// assert_eq!(merged.env.names().collect::<Vec<_>>(), ["DATABASE_URL", "SENTRY_DSN", "STRIPE_KEY"]);
+///
+/// let merged = merge_env_files(&files, MergeStrategy::FirstWins)?;
+/// assert_eq!(merged.env.get("DATABASE_URL"), Some("postgres://shared/db"));
+///
+/// let result = merge_env_files(&files, MergeStrategy::Error);
+/// assert!(matches!(
+///     result,
+///     Err(EnvEncError::MergeConflict { var, first, second })
+///         if var == "DATABASE_URL" && first == team && second == service
+/// ));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn merge_env_files<P, K>(
+    files: &[(P, K)],
+    strategy: MergeStrategy,
+) -> Result<MergedEnv, EnvEncError>
+where
+    P: AsRef<Path>,
+    K: AsRef<[u8]>,
+{
+    let mut values: BTreeMap<String, (SecretValue, PathBuf)> = BTreeMap::new();
+    for (path, key) in files {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Err(EnvEncError::MissingEnvFile {
+                path: path.to_path_buf(),
+            });
+        }
+        let session = EnvEnc::builder().key(key.as_ref()).env_file(path).build()?;
+        let (decrypted, report) = session.decrypt_entries();
+        report.into_result()?;
+        for (name, value) in decrypted {
+            match values.entry(name) {
+                Entry::Vacant(entry) => {
+                    entry.insert((value, path.to_path_buf()));
+                }
+                Entry::Occupied(mut entry) => match strategy {
+                    MergeStrategy::LastWins => {
+                        entry.insert((value, path.to_path_buf()));
+                    }
+                    MergeStrategy::FirstWins => {}
+                    MergeStrategy::Error if entry.get().0 == value => {}
+                    MergeStrategy::Error => {
+                        return Err(EnvEncError::MergeConflict {
+                            var: entry.key().clone(),
+                            first: entry.get().1.clone(),
+                            second: path.to_path_buf(),
+                        })
+                    }
+                },
+            }
+        }
+    }
+
+    Ok(MergedEnv {
+        env: values
+            .iter()
+            .map(|(name, (value, _))| (name.clone(), value.clone()))
+            .collect(),
+        sources: values
+            .into_iter()
+            .map(|(name, (_, path))| (name, path))
+            .collect(),
+    })
+}
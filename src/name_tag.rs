@@ -0,0 +1,76 @@
+use crate::{key_ring::Keys, secret, EnvCipher, EnvEncError, StoredValueError};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// The prefix of the stored name of a variable whose name is obfuscated.
+const TAG_PREFIX: &str = "ENVENC_";
+
+/// The number of bytes of the HMAC kept in an obfuscated name by default.
+pub(crate) const DEFAULT_NAME_TAG_LEN: usize = 16;
+
+/// The largest number of bytes of the HMAC that an obfuscated name can keep.
+pub(crate) const MAX_NAME_TAG_LEN: usize = 32;
+
+/// Returns the obfuscated name stored in place of `var_name`: `ENVENC_` followed by the hex of
+/// the first `len` bytes of `HMAC-SHA256(key, var_name)`.
+pub(crate) fn name_tag(key: &[u8], var_name: &str, len: usize) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(var_name.as_bytes());
+    let digest = mac.finalize().into_bytes();
+    format!("{}{}", TAG_PREFIX, hex::encode(&digest[..len]))
+}
+
+/// Returns the number of HMAC bytes in `stored_name` if it is an obfuscated name, or `None` if
+/// it is a plain name.
+pub(crate) fn tag_len(stored_name: &str) -> Option<usize> {
+    let hex = stored_name.strip_prefix(TAG_PREFIX)?;
+    let is_tag = hex.len() % 2 == 0
+        && (1..=MAX_NAME_TAG_LEN).contains(&(hex.len() / 2))
+        && hex
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'));
+    is_tag.then_some(hex.len() / 2)
+}
+
+/// Returns `true` if the obfuscated `stored_name` is the name tag of `var_name` under one of
+/// `keys`.
+pub(crate) fn matches(keys: Keys, stored_name: &str, var_name: &str) -> bool {
+    tag_len(stored_name).is_some_and(|len| {
+        keys.all()
+            .into_iter()
+            .any(|key| name_tag(key, var_name, len) == stored_name)
+    })
+}
+
+/// Returns the plaintext encrypted under an obfuscated name, which carries the real name of
+/// the variable in front of its value.
+pub(crate) fn with_name(var_name: &str, value: &str) -> String {
+    format!("{}={}", var_name, value)
+}
+
+/// Decrypts the value stored under `stored_name`, returning the real name of the variable
+/// along with its plaintext value.
+///
+/// The stored name is authenticated as associated data like any other, so a value stored
+/// under an obfuscated name cannot be moved to another one, and the real name it carries
+/// cannot be altered.
+pub(crate) fn decrypt_named(
+    keys: Keys,
+    cipher: &dyn EnvCipher,
+    stored_name: &str,
+    enc_value: &str,
+) -> Result<(String, String), StoredValueError> {
+    let plaintext = keys.decrypt(cipher, stored_name, enc_value)?;
+    if tag_len(stored_name).is_none() {
+        return Ok((stored_name.to_string(), plaintext));
+    }
+    let named = plaintext
+        .split_once('=')
+        .map(|(var_name, value)| (var_name.to_string(), value.to_string()));
+    secret::wipe(plaintext.into_bytes());
+    named.ok_or_else(|| {
+        StoredValueError::Failed(EnvEncError::DecryptionFailed {
+            var: stored_name.to_string(),
+        })
+    })
+}
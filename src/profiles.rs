@@ -0,0 +1,235 @@
+use crate::{
+    env_file::parse_env_lines, CipherType, DecryptReport, EnvEnc, EnvEncError, Key, SecretValue,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    path::{Path, PathBuf},
+};
+
+/// The env files of a profile, such as `production`, read in order so that later files
+/// override the values of earlier ones, as dotenv-flow does.
+///
+/// `Profiles::new("production")` reads `.env`, `.env.production`, `.env.local`, and
+/// `.env.production.local`. Only `.env` is required; the other files are read if they exist.
+/// The `test` profile leaves out `.env.local`, so that tests do not depend on the settings of
+/// a developer's machine. Each file may be encrypted with its own key, set with `key_for`;
+/// the others are read with the key passed to `decrypt_env`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, set_enc_env_in, set_plain_env_in, CipherType, EnvEncError, Overwrite, Profiles};
+/// use std::path::Path;
+///
+/// let dir = tempfile::tempdir()?;
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// let (production_key, _) = keys_generation(cipher_type)?;
+/// let base = dir.path().join(".env");
+/// let production = dir.path().join(".env.production");
+/// let production_local = dir.path().join(".env.production.local");
+/// set_enc_env_in(&base, "PROFILE_API_KEY", "sk_test_123", cipher_type, &key)?;
+/// set_plain_env_in(&base, "PROFILE_API_URL", "http://localhost:8080", Overwrite::No)?;
+/// set_enc_env_in(&production, "PROFILE_API_KEY", "sk_live_456", cipher_type, &production_key)?;
+/// set_plain_env_in(&production, "PROFILE_API_URL", "https://api.example.com", Overwrite::No)?;
+/// set_plain_env_in(&production_local, "PROFILE_API_URL", "https://staging.example.com", Overwrite::No)?;
+///
+/// // `.env.local` does not exist, which is fine.
+/// let profiles = Profiles::in_dir(dir.path(), "production").key_for(&production, &production_key);
+/// let report = profiles.decrypt_env(cipher_type, &key)?;
+/// assert_eq!(std::env::var("PROFILE_API_KEY")?, "sk_live_456");
+/// assert_eq!(std::env::var("PROFILE_API_URL")?, "https://staging.example.com");
+/// assert_eq!(report.files, [base.clone(), production.clone(), production_local.clone()]);
+/// assert_eq!(report.sources["PROFILE_API_KEY"], production);
+/// assert_eq!(report.sources["PROFILE_API_URL"], production_local);
+/// assert_eq!(report.report.decrypted, ["PROFILE_API_KEY"]);
+/// assert_eq!(report.report.plain, ["PROFILE_API_URL"]);
+///
+/// // The `test` profile leaves out `.env.local`.
+/// let names: Vec<_> = Profiles::new("test").paths().map(Path::to_path_buf).collect();
+/// assert_eq!(names, [Path::new(".env"), Path::new(".env.test"), Path::new(".env.test.local")]);
+///
+/// // The base file is required.
+/// std::fs::remove_file(&base)?;
+/// let result = profiles.decrypt_env(cipher_type, &key);
+/// assert!(matches!(result, Err(EnvEncError::MissingEnvFile { path }) if path == base));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Profiles {
+    files: Vec<ProfileFile>,
+}
+
+/// An env file of a `Profiles` list.
+#[derive(Clone, Debug)]
+struct ProfileFile {
+    path: PathBuf,
+    required: bool,
+    /// The key of the file, if it is not the one passed to `Profiles::decrypt_env`.
+    key: Option<Key>,
+}
+
+/// The outcome of decrypting the env files of a profile with `Profiles::decrypt_env`.
+#[derive(Debug, Default)]
+pub struct ProfileReport {
+    /// The variables, merged across the files: `decrypted` and `plain` list the final value of
+    /// each variable, while `skipped`, `failed`, and `duplicates` list those of every file, so
+    /// that a value a later file overrides still reports a key that does not decrypt it.
+    pub report: DecryptReport,
+    /// The file each variable was read from, which is the last file that sets it.
+    pub sources: BTreeMap<String, PathBuf>,
+    /// The files that were read, in order. Optional files that do not exist are left out.
+    pub files: Vec<PathBuf>,
+}
+
+impl Profiles {
+    /// Returns the files of `profile` in the current directory: `.env`, `.env.<profile>`,
+    /// `.env.local` unless the profile is `test`, and `.env.<profile>.local`.
+    pub fn new(profile: &str) -> Self {
+        Profiles::in_dir("", profile)
+    }
+
+    /// Returns the files of `profile` in `dir`, as `Profiles::new` lists them.
+    pub fn in_dir(dir: impl AsRef<Path>, profile: &str) -> Self {
+        let dir = dir.as_ref();
+        let mut profiles = Profiles::default()
+            .file(dir.join(".env"), true)
+            .file(dir.join(format!(".env.{}", profile)), false);
+        if profile != "test" {
+            profiles = profiles.file(dir.join(".env.local"), false);
+        }
+        profiles.file(dir.join(format!(".env.{}.local", profile)), false)
+    }
+
+    /// Adds `path` after the files already listed, so that its values override theirs. A
+    /// `required` file that does not exist is an error; an optional one is left out.
+    pub fn file(mut self, path: impl Into<PathBuf>, required: bool) -> Self {
+        self.files.push(ProfileFile {
+            path: path.into(),
+            required,
+            key: None,
+        });
+        self
+    }
+
+    /// Reads the file at `path` with `key` instead of the key passed to `decrypt_env`. If
+    /// `path` is not listed, it is added as an optional file after the others.
+    pub fn key_for(mut self, path: impl AsRef<Path>, key: impl AsRef<[u8]>) -> Self {
+        let path = path.as_ref();
+        let key = Some(Key::from_bytes(key.as_ref()));
+        match self.files.iter_mut().find(|file| file.path == path) {
+            Some(file) => file.key = key,
+            None => self.files.push(ProfileFile {
+                path: path.to_path_buf(),
+                required: false,
+                key,
+            }),
+        }
+        self
+    }
+
+    /// Returns the paths of the files, in the order they are read.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|file| file.path.as_path())
+    }
+
+    /// Reads the variables stored in the files, without decrypting them, as `read_env_enc`
+    /// does for a single file. A later file overrides the values of earlier ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::MissingEnvFile` if a required file does not exist, and
+    /// `EnvEncError::Io` if a file cannot be read.
+    pub fn read_env_enc(&self) -> Result<HashMap<String, String>, EnvEncError> {
+        let mut env_vars = HashMap::new();
+        for file in self.existing_files()? {
+            let entries = parse_env_lines(&file.path)?;
+            env_vars.extend(entries.into_iter().map(|(_, name, value)| (name, value)));
+        }
+        Ok(env_vars)
+    }
+
+    /// Decrypts the variables of the files and sets their final values in the current process
+    /// environment, as `decrypt_env_from` does for a single file.
+    ///
+    /// Each file is read by an `EnvEnc` session with its key, so data keys, MAC footers, and
+    /// sealed files are read as they are for a single file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::MissingEnvFile` if a required file does not exist, and the errors
+    /// of `EnvEncBuilder::build` for a file, such as `EnvEncError::KeyMismatch` if it is
+    /// encrypted with another key. Values that fail to decrypt are listed in the report.
+    pub fn decrypt_env(
+        &self,
+        cipher_type: CipherType,
+        key: impl AsRef<[u8]>,
+    ) -> Result<ProfileReport, EnvEncError> {
+        let key = key.as_ref();
+        let mut values: HashMap<String, SecretValue> = HashMap::new();
+        let mut plain = HashMap::new();
+        let mut profile_report = ProfileReport::default();
+        for file in self.existing_files()? {
+            let file_key = file.key.as_ref().map_or(key, Key::as_bytes);
+            let session = EnvEnc::builder()
+                .cipher(cipher_type)
+                .key(file_key)
+                .env_file(&file.path)
+                .build()?;
+            let (decrypted, report) = session.decrypt_entries();
+            for (name, value) in decrypted {
+                plain.insert(name.clone(), report.plain.contains(&name));
+                profile_report
+                    .sources
+                    .insert(name.clone(), file.path.clone());
+                values.insert(name, value);
+            }
+            let merged = &mut profile_report.report;
+            merged.skipped.extend(report.skipped);
+            merged.failed.extend(report.failed);
+            merged.duplicates.extend(report.duplicates);
+            profile_report.files.push(file.path.clone());
+        }
+        for (name, value) in values {
+            env::set_var(&name, value.expose_secret());
+            match plain[&name] {
+                true => profile_report.report.plain.push(name),
+                false => profile_report.report.decrypted.push(name),
+            }
+        }
+        profile_report.report.decrypted.sort();
+        profile_report.report.plain.sort();
+        Ok(profile_report)
+    }
+
+    /// Returns the files that exist, in order, logging the optional files that do not.
+    fn existing_files(&self) -> Result<Vec<&ProfileFile>, EnvEncError> {
+        let mut files = Vec::with_capacity(self.files.len());
+        for file in &self.files {
+            match (file.path.exists(), file.required) {
+                (true, _) => files.push(file),
+                (false, true) => {
+                    return Err(EnvEncError::MissingEnvFile {
+                        path: file.path.clone(),
+                    })
+                }
+                (false, false) => log::debug!("{} does not exist; skipped", file.path.display()),
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Decrypts the env files of `profile` in the current directory with `key` and sets the
+/// final values in the current process environment.
+///
+/// See `Profiles::new` for the files that are read and `Profiles::decrypt_env` for the
+/// errors.
+pub fn load_profile(
+    profile: &str,
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+) -> Result<ProfileReport, EnvEncError> {
+    Profiles::new(profile).decrypt_env(cipher_type, key)
+}
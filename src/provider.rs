@@ -0,0 +1,220 @@
+use crate::{CipherType, EnvEnc, EnvEncError, Key};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// A configuration source over an encrypted env file, for layering its decrypted values with
+/// the other sources of figment, with the `figment` feature, or config-rs, with the
+/// `config-rs` feature.
+///
+/// The file is decrypted every time the configuration is built, and its values go straight
+/// into the configuration, never into the process environment. Names are lower-cased by
+/// default, as the `Env` providers of both crates do, and with `split` a separator such as
+/// `__` nests them, so that `DATABASE__URL` becomes `database.url`. Values that are not
+/// encrypted are passed through as they are.
+///
+/// # Example
+///
+#[cfg_attr(feature = "figment", doc = "```")]
+#[cfg_attr(not(feature = "figment"), doc = "```ignore")]
+/// use envenc::{keys_generation, set_enc_env_in, CipherType, EnvEncProvider};
+/// use figment::{providers::Serialized, Figment};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct Database {
+///     url: String,
+///     pool_size: u32,
+/// }
+///
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct Config {
+///     api_key: String,
+///     debug: bool,
+///     database: Database,
+/// }
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "API_KEY", "sk_live_123", cipher_type, &key)?;
+/// set_enc_env_in(&path, "DATABASE__URL", "postgres://app:hunter2@db/app", cipher_type, &key)?;
+///
+/// let defaults = Config {
+///     api_key: String::new(),
+///     debug: true,
+///     database: Database { url: "postgres://localhost/app".to_string(), pool_size: 4 },
+/// };
+/// let config: Config = Figment::from(Serialized::defaults(defaults))
+///     .merge(EnvEncProvider::new(&path, cipher_type, &key)?.split("__"))
+///     .extract()?;
+/// assert_eq!(config.api_key, "sk_live_123");
+/// assert_eq!(config.database.url, "postgres://app:hunter2@db/app");
+/// assert_eq!((config.debug, config.database.pool_size), (true, 4));
+/// assert!(std::env::var("API_KEY").is_err());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// With config-rs:
+///
+#[cfg_attr(feature = "config-rs", doc = "```")]
+#[cfg_attr(not(feature = "config-rs"), doc = "```ignore")]
+/// use envenc::{keys_generation, set_enc_env_in, CipherType, EnvEncProvider};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "SERVER__PORT", "8443", cipher_type, &key)?;
+///
+/// let config = config::Config::builder()
+///     .set_default("server.port", 8080)?
+///     .add_source(EnvEncProvider::new(&path, cipher_type, &key)?.split("__"))
+///     .build()?;
+/// assert_eq!(config.get::<u16>("server.port")?, 8443);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone)]
+pub struct EnvEncProvider {
+    path: PathBuf,
+    cipher_type: CipherType,
+    key: Key,
+    lowercase: bool,
+    separator: Option<String>,
+}
+
+impl EnvEncProvider {
+    /// Returns a provider of the variables of the env file at `path`, which are decrypted
+    /// with `key` when the configuration is built.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidKeyLength` if `key` does not match `cipher_type`.
+    pub fn new(
+        path: impl AsRef<Path>,
+        cipher_type: CipherType,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Self, EnvEncError> {
+        Ok(EnvEncProvider {
+            path: path.as_ref().to_path_buf(),
+            cipher_type,
+            key: Key::new(cipher_type, key.as_ref())?,
+            lowercase: true,
+            separator: None,
+        })
+    }
+
+    /// Sets whether names are lower-cased, so that `API_KEY` sets the field `api_key`.
+    /// Defaults to `true`.
+    pub fn lowercase(mut self, enabled: bool) -> Self {
+        self.lowercase = enabled;
+        self
+    }
+
+    /// Nests names on `separator`, so that with `__`, `DATABASE__URL` sets `url` in the
+    /// `database` table. Names are not nested by default.
+    pub fn split(mut self, separator: impl Into<String>) -> Self {
+        self.separator = Some(separator.into());
+        self
+    }
+
+    /// Returns the path of the env file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Decrypts the env file and returns the path of each variable, as the parts of its name
+    /// between separators, with its value, sorted by name.
+    fn entries(&self) -> Result<Vec<(Vec<String>, String)>, EnvEncError> {
+        let session = EnvEnc::builder()
+            .cipher(self.cipher_type)
+            .key(self.key.as_bytes())
+            .env_file(&self.path)
+            .build()?;
+        let values: BTreeMap<String, String> = session.decrypt_to_map()?.into_iter().collect();
+        Ok(values
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let name = match self.lowercase {
+                    true => name.to_lowercase(),
+                    false => name,
+                };
+                let path: Vec<String> = match &self.separator {
+                    Some(separator) if !separator.is_empty() => name
+                        .split(separator.as_str())
+                        .filter(|part| !part.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                    _ => vec![name],
+                };
+                (!path.is_empty()).then_some((path, value))
+            })
+            .collect())
+    }
+}
+
+#[cfg(feature = "figment")]
+impl figment::Provider for EnvEncProvider {
+    fn metadata(&self) -> figment::Metadata {
+        figment::Metadata::from("envenc file", self.path.as_path())
+    }
+
+    fn data(
+        &self,
+    ) -> Result<figment::value::Map<figment::Profile, figment::value::Dict>, figment::Error> {
+        use figment::value::{Dict, Value};
+
+        let mut dict = Dict::new();
+        for (path, value) in self.entries().map_err(|err| err.to_string())? {
+            let (last, parents) = path.split_last().expect("paths are not empty");
+            let mut table = &mut dict;
+            for parent in parents {
+                let entry = table
+                    .entry(parent.clone())
+                    .or_insert_with(|| Value::from(Dict::new()));
+                if !matches!(entry, Value::Dict(..)) {
+                    *entry = Value::from(Dict::new());
+                }
+                let Value::Dict(_, nested) = entry else {
+                    unreachable!("the entry was just made a table")
+                };
+                table = nested;
+            }
+            // Figment reads `8080` as a number and `true` as a flag, as its `Env` provider does.
+            let value = value.parse().unwrap_or_else(|never| match never {});
+            table.insert(last.clone(), value);
+        }
+        Ok(figment::Profile::Default.collect(dict))
+    }
+}
+
+#[cfg(feature = "config-rs")]
+impl config::Source for EnvEncProvider {
+    fn clone_into_box(&self) -> Box<dyn config::Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<config::Map<String, config::Value>, config::ConfigError> {
+        let origin = self.path.display().to_string();
+        let entries = self
+            .entries()
+            .map_err(|err| config::ConfigError::Foreign(Box::new(err)))?;
+        Ok(entries
+            .into_iter()
+            .map(|(path, value)| (path.join("."), config::Value::new(Some(&origin), value)))
+            .collect())
+    }
+}
+
+impl std::fmt::Debug for EnvEncProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("EnvEncProvider")
+            .field("path", &self.path)
+            .field("cipher_type", &self.cipher_type)
+            .field("lowercase", &self.lowercase)
+            .field("separator", &self.separator)
+            .finish_non_exhaustive()
+    }
+}
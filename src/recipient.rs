@@ -0,0 +1,491 @@
+use crate::{
+    dotenvx,
+    env_file::{self, WriteOptions},
+    envelope::{builtin_cipher, PREFIX, SUFFIX},
+    is_key_material, random_bytes, sealed, secret, CipherType, DecryptReport, EnvCipher,
+    EnvEncError, Envelope, EnvelopeError, SetOutcome, SkipReason, DEFAULT_ENV_FILE, FORMAT_VERSION,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use std::{collections::HashMap, env, path::Path, str::FromStr};
+use x25519_dalek::StaticSecret;
+
+/// The envelope field that marks a value as encrypted to public keys.
+const RECIPIENTS_TAG: &str = "x25519";
+
+/// The HKDF info string of the keys that wrap the data key for each recipient.
+const WRAP_INFO: &[u8] = b"envenc x25519 v1";
+
+/// The cipher values encrypted to public keys are written with.
+const CIPHER_TYPE: CipherType = CipherType::AES256GCM;
+
+/// The size in bytes of an X25519 key.
+const KEY_SIZE: usize = 32;
+
+/// The X25519 public key of a recipient that values can be encrypted to with
+/// `set_enc_env_for_recipients`.
+///
+/// A public key is not secret: it is shared as the base64 string written by `Display` and
+/// parsed by `FromStr`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{PublicKey, SecretKey};
+///
+/// let identity = SecretKey::generate();
+/// let shared = identity.public_key().to_string();
+/// assert_eq!(shared.len(), 44);
+/// assert_eq!(shared.parse::<PublicKey>()?, identity.public_key());
+/// assert!("not a key".parse::<PublicKey>().is_err());
+/// # Ok::<(), envenc::EnvEncError>(())
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PublicKey([u8; KEY_SIZE]);
+
+impl PublicKey {
+    /// Creates a public key from its 32 bytes.
+    pub fn from_bytes(bytes: [u8; KEY_SIZE]) -> Self {
+        PublicKey(bytes)
+    }
+
+    /// Returns the 32 bytes of the public key.
+    pub fn as_bytes(&self) -> &[u8; KEY_SIZE] {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", STANDARD.encode(self.0))
+    }
+}
+
+impl std::fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("PublicKey").field(&self.to_string()).finish()
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = EnvEncError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        STANDARD
+            .decode(s.trim())
+            .ok()
+            .and_then(|bytes| <[u8; KEY_SIZE]>::try_from(bytes).ok())
+            .map(PublicKey)
+            .ok_or_else(|| EnvEncError::InvalidPublicKey { key: s.to_string() })
+    }
+}
+
+/// The X25519 secret key of a recipient, which decrypts the values encrypted to its public key
+/// with `decrypt_env_with_identity`.
+///
+/// Each member of a team keeps their own secret key and hands out its public key, so that
+/// secrets can be added for them without sharing a symmetric key. The `Debug` output shows
+/// only the public key.
+#[derive(Clone)]
+pub struct SecretKey(StaticSecret);
+
+impl SecretKey {
+    /// Generates a random secret key.
+    pub fn generate() -> Self {
+        SecretKey(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// Creates a secret key from its 32 bytes.
+    pub fn from_bytes(bytes: [u8; KEY_SIZE]) -> Self {
+        SecretKey(StaticSecret::from(bytes))
+    }
+
+    /// Returns the 32 bytes of the secret key, for example to store it in a key file.
+    pub fn to_bytes(&self) -> [u8; KEY_SIZE] {
+        self.0.to_bytes()
+    }
+
+    /// Returns the public key that values are encrypted to for this secret key.
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(x25519_dalek::PublicKey::from(&self.0).to_bytes())
+    }
+}
+
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SecretKey")
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A value encrypted to public keys, in the form
+/// `ENC[v2:AES256GCM:x25519:<ephemeral>:<wrapped>,...:<payload>]`.
+///
+/// The value is encrypted with a random data key, which is wrapped once per recipient under a
+/// key derived with HKDF-SHA256 from the X25519 shared secret of the ephemeral key and the
+/// recipient. The ephemeral public key, the wrapped data keys, and the nonce followed by the
+/// ciphertext are base64-encoded. The variable name is authenticated as associated data, both
+/// by the value and by every wrapped data key.
+struct RecipientEnvelope {
+    cipher_type: CipherType,
+    ephemeral: PublicKey,
+    wrapped: Vec<Vec<u8>>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+impl RecipientEnvelope {
+    /// Encrypts `value`, stored under `var_name`, to every one of `recipients`.
+    fn seal(var_name: &str, value: &str, recipients: &[PublicKey]) -> Result<Self, EnvEncError> {
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey(x25519_dalek::PublicKey::from(&ephemeral).to_bytes());
+        let data_key = random_bytes(CIPHER_TYPE.key_size());
+        let nonce = random_bytes(CIPHER_TYPE.nonce_size());
+
+        let sealed = (|| -> Result<_, EnvEncError> {
+            let wrapped = recipients
+                .iter()
+                .map(|recipient| {
+                    let wrap_key = wrap_key(&ephemeral, recipient, &ephemeral_public, recipient)
+                        .ok_or_else(|| EnvEncError::InvalidPublicKey {
+                            key: recipient.to_string(),
+                        })?;
+                    let wrapped =
+                        CIPHER_TYPE.seal(&wrap_key, &wrap_nonce(), &data_key, var_name.as_bytes());
+                    secret::wipe(wrap_key);
+                    wrapped
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let ciphertext =
+                CIPHER_TYPE.seal(&data_key, &nonce, value.as_bytes(), var_name.as_bytes())?;
+            Ok((wrapped, ciphertext))
+        })();
+        secret::wipe(data_key);
+        let (wrapped, ciphertext) = sealed?;
+
+        Ok(RecipientEnvelope {
+            cipher_type: CIPHER_TYPE,
+            ephemeral: ephemeral_public,
+            wrapped,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    /// Parses a value written by `Display`.
+    fn parse(value: &str) -> Result<Self, EnvelopeError> {
+        let inner = value
+            .strip_prefix(PREFIX)
+            .and_then(|rest| rest.strip_suffix(SUFFIX))
+            .ok_or(EnvelopeError::Malformed)?;
+        let fields: Vec<&str> = inner.split(':').collect();
+        let [version, cipher_name, RECIPIENTS_TAG, ephemeral, wrapped, payload] = fields[..] else {
+            return Err(EnvelopeError::Malformed);
+        };
+        let version: u32 = version
+            .strip_prefix('v')
+            .and_then(|version| version.parse().ok())
+            .ok_or(EnvelopeError::Malformed)?;
+        if version != FORMAT_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+        let cipher_type = builtin_cipher(cipher_name)
+            .ok_or_else(|| EnvelopeError::UnknownCipher(cipher_name.to_string()))?;
+
+        let decode = |field: &str| {
+            STANDARD
+                .decode(field)
+                .map_err(|_| EnvelopeError::InvalidBase64)
+        };
+        let ephemeral = <[u8; KEY_SIZE]>::try_from(decode(ephemeral)?)
+            .map(PublicKey)
+            .map_err(|_| EnvelopeError::TooShort)?;
+        let wrapped = wrapped.split(',').map(decode).collect::<Result<_, _>>()?;
+        let mut nonce = decode(payload)?;
+        if nonce.len() < cipher_type.nonce_size() {
+            return Err(EnvelopeError::TooShort);
+        }
+        let ciphertext = nonce.split_off(cipher_type.nonce_size());
+
+        Ok(RecipientEnvelope {
+            cipher_type,
+            ephemeral,
+            wrapped,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    /// Decrypts the value stored under `var_name` with `identity`.
+    fn open(&self, identity: &SecretKey, var_name: &str) -> Result<String, EnvEncError> {
+        let not_a_recipient = || EnvEncError::NotARecipient {
+            var: var_name.to_string(),
+        };
+        let public_key = identity.public_key();
+        let wrap_key = wrap_key(&identity.0, &self.ephemeral, &self.ephemeral, &public_key)
+            .ok_or_else(not_a_recipient)?;
+        let data_key = self.wrapped.iter().find_map(|wrapped| {
+            self.cipher_type
+                .open(&wrap_key, &wrap_nonce(), wrapped, var_name.as_bytes())
+                .ok()
+        });
+        secret::wipe(wrap_key);
+        let data_key = data_key.ok_or_else(not_a_recipient)?;
+
+        let decrypted = self.cipher_type.open(
+            &data_key,
+            &self.nonce,
+            &self.ciphertext,
+            var_name.as_bytes(),
+        );
+        secret::wipe(data_key);
+        let decrypted = decrypted.map_err(|_| EnvEncError::DecryptionFailed {
+            var: var_name.to_string(),
+        })?;
+        String::from_utf8(decrypted).map_err(|err| {
+            secret::wipe(err.into_bytes());
+            EnvEncError::InvalidUtf8 {
+                var: var_name.to_string(),
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for RecipientEnvelope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let wrapped: Vec<String> = self
+            .wrapped
+            .iter()
+            .map(|key| STANDARD.encode(key))
+            .collect();
+        let mut combined = self.nonce.clone();
+        combined.extend_from_slice(&self.ciphertext);
+        write!(
+            f,
+            "{}v{}:{}:{}:{}:{}:{}{}",
+            PREFIX,
+            FORMAT_VERSION,
+            self.cipher_type,
+            RECIPIENTS_TAG,
+            self.ephemeral,
+            wrapped.join(","),
+            STANDARD.encode(combined),
+            SUFFIX
+        )
+    }
+}
+
+/// Returns the key that wraps the data key for `recipient` under the `ephemeral` key, derived
+/// from the shared secret of `secret` and `peer`: the ephemeral secret key and the recipient
+/// when encrypting, and the other way around when decrypting. Returns `None` if the shared
+/// secret is degenerate, because `peer` is a low-order point.
+fn wrap_key(
+    secret: &StaticSecret,
+    peer: &PublicKey,
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> Option<Vec<u8>> {
+    let shared = secret.diffie_hellman(&x25519_dalek::PublicKey::from(peer.0));
+    if !shared.was_contributory() {
+        return None;
+    }
+    let mut salt = ephemeral.0.to_vec();
+    salt.extend_from_slice(&recipient.0);
+    let mut key = vec![0u8; CIPHER_TYPE.key_size()];
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(WRAP_INFO, &mut key)
+        .expect("the key size is a valid HKDF-SHA256 output length");
+    Some(key)
+}
+
+/// Returns the nonce the data key is wrapped with. Every wrap key is derived from a fresh
+/// ephemeral key and wraps a single data key, so the nonce never repeats under the same key.
+fn wrap_nonce() -> Vec<u8> {
+    vec![0u8; CIPHER_TYPE.nonce_size()]
+}
+
+/// Returns `true` if `value` is an envelope encrypted to public keys, whether or not it is
+/// well-formed.
+pub(crate) fn is_recipient_envelope(value: &str) -> bool {
+    value
+        .strip_prefix(PREFIX)
+        .and_then(|rest| rest.split(':').nth(2))
+        == Some(RECIPIENTS_TAG)
+}
+
+/// Encrypts a variable to the public keys of `recipients` and stores it in `.env`.
+///
+/// This behaves like `set_enc_env_for_recipients_in` on `.env` in the current working
+/// directory.
+///
+/// # Errors
+///
+/// Returns the same errors as `set_enc_env_for_recipients_in`.
+pub fn set_enc_env_for_recipients(
+    var_name: &str,
+    var_text: &str,
+    recipients: &[PublicKey],
+) -> Result<SetOutcome, EnvEncError> {
+    set_enc_env_for_recipients_in(DEFAULT_ENV_FILE, var_name, var_text, recipients)
+}
+
+/// Encrypts a variable to the public keys of `recipients` and stores it in the env file at
+/// `path`, so that each of them can decrypt it with their own `SecretKey`.
+///
+/// No symmetric key is needed: the value gets an ephemeral X25519 key, and the random data key
+/// it is encrypted with is wrapped once for every recipient, under a key derived with HKDF from
+/// their shared secret. Like `set_enc_env_in`, a variable that is already stored is left
+/// unchanged.
+///
+/// Values encrypted to public keys can share an env file with values encrypted with a
+/// symmetric key. Readers that take a symmetric key skip them as `SkipReason::Recipients`,
+/// and `rotate_keys` leaves them alone.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::NoRecipients` if `recipients` is empty,
+/// `EnvEncError::InvalidPublicKey` if one of them is a low-order point,
+/// `EnvEncError::SealedFile` if the file is a sealed env file, `EnvEncError::MacKeyRequired` if
+/// it has a MAC footer, which cannot be updated without the symmetric key, and
+/// `EnvEncError::Io` if the file cannot be read or written.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{
+///     decrypt_env, decrypt_env_with_identity, keys_generation, read_env_enc_from,
+///     set_enc_env_for_recipients_in, set_enc_env_in, CipherType, EnvEncError, PublicKey,
+///     SecretKey, SetOutcome, SkipReason,
+/// };
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+///
+/// // Each teammate keeps their own secret key and shares its public key.
+/// let (alice, bob, carol) = (SecretKey::generate(), SecretKey::generate(), SecretKey::generate());
+/// let team: Vec<PublicKey> = vec![alice.public_key(), bob.public_key()];
+///
+/// let outcome = set_enc_env_for_recipients_in(&path, "TEAM_API_KEY", "super_secret_api_key", &team)?;
+/// assert_eq!(outcome, SetOutcome::Created);
+///
+/// let stored = read_env_enc_from(&path)?;
+/// assert!(stored["TEAM_API_KEY"].starts_with("ENC[v2:AES256GCM:x25519:"));
+///
+/// for identity in [&alice, &bob] {
+///     std::env::remove_var("TEAM_API_KEY");
+///     let report = decrypt_env_with_identity(stored.clone(), identity)?;
+///     assert_eq!(report.decrypted, ["TEAM_API_KEY"]);
+///     assert_eq!(std::env::var("TEAM_API_KEY")?, "super_secret_api_key");
+/// }
+///
+/// // Someone the value was not encrypted to cannot read it.
+/// let report = decrypt_env_with_identity(stored, &carol)?;
+/// assert!(matches!(&report.failed[..], [EnvEncError::NotARecipient { var }] if var == "TEAM_API_KEY"));
+///
+/// // Values encrypted with a symmetric key can live in the same file.
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, nonce) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "TEAM_REGION", "eu-west-1", cipher_type, &key)?;
+/// let report = decrypt_env(read_env_enc_from(&path)?, cipher_type, &key, &nonce)?;
+/// assert_eq!(report.decrypted, ["TEAM_REGION"]);
+/// assert_eq!(report.skipped, [("TEAM_API_KEY".to_string(), SkipReason::Recipients)]);
+/// let report = decrypt_env_with_identity(read_env_enc_from(&path)?, &alice)?;
+/// assert_eq!(report.skipped, [("TEAM_REGION".to_string(), SkipReason::SharedKey)]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn set_enc_env_for_recipients_in(
+    path: impl AsRef<Path>,
+    var_name: &str,
+    var_text: &str,
+    recipients: &[PublicKey],
+) -> Result<SetOutcome, EnvEncError> {
+    let path = path.as_ref();
+    if recipients.is_empty() {
+        return Err(EnvEncError::NoRecipients);
+    }
+    if sealed::read_sealed(path)?.is_some() {
+        return Err(EnvEncError::SealedFile {
+            path: path.to_path_buf(),
+        });
+    }
+    if env_file::parse_env_file(path)?.contains_key(var_name) {
+        log::info!(
+            "'{}' is already set in {}; left unchanged",
+            var_name,
+            path.display()
+        );
+        return Ok(SetOutcome::AlreadyExists);
+    }
+    let envelope = RecipientEnvelope::seal(var_name, var_text, recipients)?;
+    env_file::write_env_var(
+        path,
+        var_name,
+        &envelope.to_string(),
+        &[],
+        false,
+        WriteOptions::default(),
+    )?;
+    Ok(SetOutcome::Created)
+}
+
+/// Decrypts the provided environment variables that were encrypted to the public key of
+/// `identity`, and sets them in the current process environment.
+///
+/// This is the counterpart of `decrypt_env` for values written by
+/// `set_enc_env_for_recipients`. Values encrypted with a symmetric key are skipped as
+/// `SkipReason::SharedKey`, values encrypted only to other public keys are reported as
+/// `EnvEncError::NotARecipient`, and plain values are set as they are. See `set_enc_env_for_recipients_in` for an example.
+///
+/// # Returns
+///
+/// A `DecryptReport`, as returned by `decrypt_env`.
+pub fn decrypt_env_with_identity(
+    env_vars: HashMap<String, String>,
+    identity: &SecretKey,
+) -> Result<DecryptReport, EnvEncError> {
+    let mut report = DecryptReport::default();
+    let mut env_vars: Vec<(String, String)> = env_vars.into_iter().collect();
+    env_vars.sort();
+
+    for (var_name, enc_value) in env_vars {
+        if is_key_material(&var_name) {
+            report.skip(var_name, SkipReason::KeyMaterial);
+            continue;
+        }
+        let enc_value = match dotenvx::envenc_value(&var_name, &enc_value) {
+            Ok(enc_value) => enc_value,
+            Err(reason) => {
+                report.skip(var_name, reason);
+                continue;
+            }
+        };
+        if !is_recipient_envelope(enc_value) {
+            match Envelope::is_envelope(enc_value) || hex::decode(enc_value).is_ok() {
+                true => report.skip(var_name, SkipReason::SharedKey),
+                false => {
+                    env::set_var(&var_name, enc_value);
+                    report.pass_plain(var_name);
+                }
+            }
+            continue;
+        }
+        let decrypted = RecipientEnvelope::parse(enc_value)
+            .map_err(|reason| EnvEncError::InvalidEnvelope {
+                var: var_name.clone(),
+                reason,
+            })
+            .and_then(|envelope| envelope.open(identity, &var_name));
+        match decrypted {
+            Ok(value) => {
+                env::set_var(&var_name, &value);
+                secret::wipe(value.into_bytes());
+                report.decrypted.push(var_name);
+            }
+            Err(err) => report.fail(err),
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,184 @@
+use crate::{CipherType, EnvEncError};
+
+/// Why an entry was skipped by `decrypt_env` without attempting decryption.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The stored value is not valid hex.
+    InvalidHex,
+    /// The stored value is too short to contain a nonce.
+    TooShort,
+    /// The entry holds a key or nonce written by `keys_generation_cached`, not an encrypted value.
+    KeyMaterial,
+    /// The entry was encrypted by another tool, such as an `encrypted:` value or a
+    /// `DOTENV_PUBLIC_KEY` written by dotenvx.
+    ForeignFormat,
+    /// The entry was encrypted to public keys with `set_enc_env_for_recipients`, so it is
+    /// decrypted with a `SecretKey` rather than a symmetric key.
+    Recipients,
+    /// The entry was encrypted with a symmetric key, not to public keys.
+    SharedKey,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SkipReason::InvalidHex => write!(f, "invalid hex encoding"),
+            SkipReason::TooShort => write!(f, "combined data too short"),
+            SkipReason::KeyMaterial => write!(f, "key material, not an encrypted value"),
+            SkipReason::ForeignFormat => write!(f, "encrypted by another tool"),
+            SkipReason::Recipients => write!(f, "encrypted to public keys"),
+            SkipReason::SharedKey => write!(f, "encrypted with a symmetric key"),
+        }
+    }
+}
+
+/// What happened to the stored value when setting an environment variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOutcome {
+    /// The variable was not stored before and has been added.
+    Created,
+    /// The variable was already stored and was left unchanged.
+    AlreadyExists,
+    /// The variable was already stored and its value has been replaced.
+    Updated,
+    /// The variable was already stored with the same plaintext, so the file was not rewritten.
+    Unchanged,
+}
+
+/// The outcome of decrypting a set of environment variables with `decrypt_env`.
+///
+/// Decryption continues past failures, so a single stale entry does not prevent the other
+/// variables from being loaded. Use `into_result` to treat any failure as fatal.
+///
+/// Only values marked as encrypted, as `ENC[...]` envelopes, are decrypted. Every other value
+/// is plain text, such as `RUST_LOG=info`, and is passed through as it is and listed in
+/// `plain`. Bare hex values written by earlier versions of this crate are only decrypted by a
+/// session built with `EnvEncBuilder::legacy_hex`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{decrypt_env, encrypt, keys_generation, CipherType, EnvEncError, Envelope, Key, Nonce, SkipReason};
+/// use std::collections::HashMap;
+///
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, nonce) = keys_generation(cipher_type)?;
+///
+/// let mut env_vars = HashMap::new();
+/// env_vars.insert("RUST_LOG".to_string(), "info".to_string());
+/// // Plain text that happens to be hex is not mistaken for ciphertext.
+/// env_vars.insert("BUILD_ID".to_string(), "ab".repeat(40));
+/// // A value marked as encrypted that does not decrypt with the key.
+/// let stale = Envelope::new(cipher_type, vec![0xab; 12], vec![0xab; 28]).to_string();
+/// env_vars.insert("STALE".to_string(), stale);
+/// env_vars.insert("AES256GCM_KEY".to_string(), hex::encode(&key));
+///
+/// // A value that decrypts to bytes that are not UTF-8 is reported rather than panicking.
+/// let (typed_key, typed_nonce) = (Key::new(cipher_type, key.clone())?, Nonce::new(cipher_type, nonce.clone())?);
+/// let ciphertext = encrypt(cipher_type, &typed_key, &typed_nonce, &[0xff, 0xfe])?;
+/// let binary = Envelope::new(cipher_type, nonce.clone(), ciphertext).to_string();
+/// // Version 1 envelopes are decrypted without the variable name as associated data.
+/// env_vars.insert("BINARY".to_string(), binary.replacen("v2", "v1", 1));
+///
+/// let report = decrypt_env(env_vars, cipher_type, &key, &nonce)?;
+/// assert!(report.decrypted.is_empty());
+/// assert_eq!(report.skipped, vec![("AES256GCM_KEY".to_string(), SkipReason::KeyMaterial)]);
+/// assert_eq!(report.plain, ["BUILD_ID", "RUST_LOG"]);
+/// assert_eq!(std::env::var("RUST_LOG")?, "info");
+/// assert!(matches!(
+///     &report.failed[..],
+///     [EnvEncError::InvalidUtf8 { var: a }, EnvEncError::DecryptionFailed { var: b }]
+///         if a == "BINARY" && b == "STALE"
+/// ));
+/// assert!(report.into_result().is_err());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct DecryptReport {
+    /// Variables that were decrypted (and, for `decrypt_env`, set in the process environment).
+    pub decrypted: Vec<String>,
+    /// Variables whose stored value is not an encrypted payload, such as key material, or
+    /// that are encrypted in a way that needs another key.
+    pub skipped: Vec<(String, SkipReason)>,
+    /// Variables whose stored value is not encrypted, such as `RUST_LOG=info`, which were
+    /// passed through as they are.
+    pub plain: Vec<String>,
+    /// Variables that failed to decrypt, as `EnvEncError::DecryptionFailed` or
+    /// `EnvEncError::InvalidUtf8`.
+    pub failed: Vec<EnvEncError>,
+    /// Variables that are set on more than one line of the env file, with the 1-based numbers
+    /// of those lines, sorted by name. The value on the last of them was used.
+    pub duplicates: Vec<(String, Vec<usize>)>,
+}
+
+impl DecryptReport {
+    /// Returns `true` if no variable failed to decrypt.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Records that the value stored under `var_name` was skipped, logging it at warn level
+    /// unless it is key material, which env files are expected to hold.
+    pub(crate) fn skip(&mut self, var_name: String, reason: SkipReason) {
+        match reason {
+            SkipReason::KeyMaterial => log::debug!("skipped '{}': {}", var_name, reason),
+            _ => log::warn!("skipped '{}': {}", var_name, reason),
+        }
+        self.skipped.push((var_name, reason));
+    }
+
+    /// Records that the value stored under `var_name` is plain text, logging it at debug level.
+    pub(crate) fn pass_plain(&mut self, var_name: String) {
+        log::debug!("'{}' is not encrypted; passed through as it is", var_name);
+        self.plain.push(var_name);
+    }
+
+    /// Records that a value failed to decrypt, logging it at warn level.
+    pub(crate) fn fail(&mut self, err: EnvEncError) {
+        log::warn!("{}", err);
+        self.failed.push(err);
+    }
+
+    /// Converts the report into a `Result`, returning the first failure if there was one
+    /// and the names of the decrypted variables otherwise.
+    pub fn into_result(self) -> Result<Vec<String>, EnvEncError> {
+        match self.failed.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(self.decrypted),
+        }
+    }
+}
+
+/// A variable stored in an env file, as listed by `list_enc_env`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvEntryInfo {
+    /// The name of the variable.
+    pub name: String,
+    /// The 1-based line number of the entry in the env file.
+    pub line: usize,
+    /// The length of the stored value in characters.
+    pub value_len: usize,
+    /// The length in bytes of the ciphertext, including its authentication tag, if the stored
+    /// value looks like an encrypted payload.
+    pub ciphertext_len: Option<usize>,
+    /// The cipher named by the stored value, if it is an `Envelope`. Legacy hex values do not
+    /// record their cipher.
+    pub cipher: Option<CipherType>,
+    /// The ID of the key the value was encrypted with, if the `Envelope` records one, as values
+    /// encrypted with a `KeyRing` do.
+    pub key_id: Option<String>,
+}
+
+impl EnvEntryInfo {
+    /// Returns `true` if the stored value is an `Envelope` or legacy hex long enough to hold a
+    /// nonce and an authentication tag. Plaintext values are reported as `false`.
+    pub fn looks_encrypted(&self) -> bool {
+        self.ciphertext_len.is_some()
+    }
+
+    /// Returns the length in bytes of the decrypted value, which the length of the ciphertext
+    /// reveals without the key, if the stored value looks like an encrypted payload.
+    pub fn plaintext_len(&self) -> Option<usize> {
+        self.ciphertext_len.map(|len| len - crate::TAG_SIZE)
+    }
+}
@@ -0,0 +1,396 @@
+use crate::{typed::parse_bool, DecryptedEnv, EnvEncError};
+use regex_lite::Regex;
+use std::{fmt, fs, path::Path};
+
+/// The file name a schema is conventionally kept in, next to the env file.
+pub const DEFAULT_SCHEMA_FILE: &str = "envenc.schema.toml";
+
+/// The variables an env file is expected to hold, read from a TOML schema file, as a contract
+/// for the configuration of a service.
+///
+/// Each variable is a `[[var]]` table with a `name`, and optionally whether it is `required`
+/// (default `false`), its `type` (`"string"`, the default, `"int"`, `"bool"`, or `"url"`), a
+/// `pattern` its value must match, and a `description`. Patterns are regular expressions that
+/// match anywhere in the value unless anchored with `^` and `$`. Unknown keys are rejected, so
+/// that a misspelled rule is not silently ignored.
+///
+/// Check decrypted values against a schema with `validate_against_schema`, and write a
+/// `.env.example` for it with `env_example`.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{validate_against_schema, DecryptedEnv, Schema, SchemaRule};
+/// use std::collections::HashMap;
+///
+/// let schema = Schema::parse(r#"
+///     [[var]]
+///     name = "DATABASE_URL"
+///     required = true
+///     type = "url"
+///     pattern = "^postgres(ql)?://"
+///     description = "The primary database."
+///
+///     [[var]]
+///     name = "PORT"
+///     type = "int"
+///
+///     [[var]]
+///     name = "API_KEY"
+///     required = true
+/// "#)?;
+///
+/// let env = DecryptedEnv::from(HashMap::from([
+///     ("DATABASE_URL".to_string(), "mysql://app:hunter2@db/app".to_string()),
+///     ("PORT".to_string(), "eighty".to_string()),
+/// ]));
+/// let violations = validate_against_schema(&env, &schema);
+/// let found: Vec<String> = violations.iter().map(|violation| violation.to_string()).collect();
+/// assert_eq!(found, [
+///     "DATABASE_URL does not match the pattern ^postgres(ql)?://",
+///     "PORT is not a valid int",
+///     "API_KEY is required but not set",
+/// ]);
+/// assert_eq!(violations[2].rule, SchemaRule::Missing);
+/// // Violations never include the values.
+/// assert!(found.iter().all(|violation| !violation.contains("hunter2")));
+///
+/// assert_eq!(schema.env_example(), concat!(
+///     "# The primary database.\n",
+///     "# Required. Type: url.\n",
+///     "# Must match: ^postgres(ql)?://\n",
+///     "DATABASE_URL=\n",
+///     "\n",
+///     "# Type: int.\n",
+///     "PORT=\n",
+///     "\n",
+///     "# Required.\n",
+///     "API_KEY=\n",
+/// ));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Schema {
+    vars: Vec<SchemaVar>,
+}
+
+/// A variable declared by a `Schema`.
+#[derive(Clone, Debug)]
+pub struct SchemaVar {
+    name: String,
+    required: bool,
+    value_type: ValueType,
+    pattern: Option<Regex>,
+    description: Option<String>,
+}
+
+/// The type a `Schema` declares for the value of a variable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum ValueType {
+    /// Any value.
+    #[default]
+    String,
+    /// A signed 64-bit integer.
+    Int,
+    /// A flag, as `read_env_bool` reads it.
+    Bool,
+    /// A URL with a scheme, such as `postgres://db/app`.
+    Url,
+}
+
+/// A rule of a `Schema` that one of the variables of an env file breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// The name of the variable.
+    pub var: String,
+    /// The rule its value breaks.
+    pub rule: SchemaRule,
+}
+
+/// The rule of a `Schema` a `Violation` breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaRule {
+    /// The variable is required but not set.
+    Missing,
+    /// The variable is required but set to an empty value.
+    Empty,
+    /// The value is not of the declared type.
+    Type(ValueType),
+    /// The value does not match the declared pattern, which is held here.
+    Pattern(String),
+}
+
+impl Schema {
+    /// Parses the TOML schema `document`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidSchema` if `document` is not TOML, does not declare its
+    /// variables as `[[var]]` tables, declares a variable twice, or holds a key, type, or
+    /// pattern that is not valid.
+    pub fn parse(document: &str) -> Result<Self, EnvEncError> {
+        let table: toml::Table = document
+            .parse()
+            .map_err(|err: toml::de::Error| invalid(err.to_string().trim_end()))?;
+        let mut vars: Vec<SchemaVar> = Vec::new();
+        for (key, value) in table {
+            let (true, toml::Value::Array(entries)) = (key == "var", value) else {
+                return Err(invalid(format!(
+                    "unknown key '{}', expected [[var]] tables",
+                    key
+                )));
+            };
+            for entry in entries {
+                let var = SchemaVar::parse(entry)?;
+                if vars.iter().any(|declared| declared.name == var.name) {
+                    return Err(invalid(format!("'{}' is declared twice", var.name)));
+                }
+                vars.push(var);
+            }
+        }
+        Ok(Schema { vars })
+    }
+
+    /// Reads and parses the TOML schema file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::Io` if the file cannot be read, and the errors of `parse`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, EnvEncError> {
+        Schema::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Returns the declared variables, in the order of the schema.
+    pub fn vars(&self) -> &[SchemaVar] {
+        &self.vars
+    }
+
+    /// Returns the text of a `.env.example` file for the schema: each variable with an empty
+    /// value, after comments with its description and rules, so that a new checkout knows what
+    /// to set.
+    pub fn env_example(&self) -> String {
+        let mut example = String::new();
+        for (index, var) in self.vars.iter().enumerate() {
+            if index > 0 {
+                example.push('\n');
+            }
+            for line in var.description.iter().flat_map(|text| text.lines()) {
+                match line.trim() {
+                    "" => example.push_str("#\n"),
+                    line => example.push_str(&format!("# {}\n", line)),
+                }
+            }
+            let rules = match (var.required, var.value_type) {
+                (true, ValueType::String) => Some("Required.".to_string()),
+                (true, value_type) => Some(format!("Required. Type: {}.", value_type)),
+                (false, ValueType::String) => None,
+                (false, value_type) => Some(format!("Type: {}.", value_type)),
+            };
+            if let Some(rules) = rules {
+                example.push_str(&format!("# {}\n", rules));
+            }
+            if let Some(pattern) = &var.pattern {
+                example.push_str(&format!("# Must match: {}\n", pattern));
+            }
+            example.push_str(&format!("{}=\n", var.name));
+        }
+        example
+    }
+}
+
+impl SchemaVar {
+    /// Reads the `[[var]]` table `entry` of a schema.
+    fn parse(entry: toml::Value) -> Result<Self, EnvEncError> {
+        let toml::Value::Table(mut entry) = entry else {
+            return Err(invalid("each var must be a table"));
+        };
+        let name = match entry.remove("name") {
+            Some(toml::Value::String(name)) => name,
+            Some(_) => return Err(invalid("the name of a var must be a string")),
+            None => return Err(invalid("a var has no name")),
+        };
+        let mut var = SchemaVar {
+            name,
+            required: false,
+            value_type: ValueType::default(),
+            pattern: None,
+            description: None,
+        };
+        for (key, value) in entry {
+            let wrong_type = |expected: &str| {
+                invalid(format!("'{}' of {} must be a {}", key, var.name, expected))
+            };
+            match (key.as_str(), value) {
+                ("required", toml::Value::Boolean(value)) => var.required = value,
+                ("type", toml::Value::String(value)) => var.value_type = value.parse()?,
+                ("pattern", toml::Value::String(value)) => {
+                    let pattern = Regex::new(&value).map_err(|err| {
+                        invalid(format!("the pattern '{}' is not valid: {}", value, err))
+                    })?;
+                    var.pattern = Some(pattern);
+                }
+                ("description", toml::Value::String(value)) => var.description = Some(value),
+                ("required", _) => return Err(wrong_type("boolean")),
+                ("type" | "pattern" | "description", _) => return Err(wrong_type("string")),
+                (key, _) => {
+                    return Err(invalid(format!(
+                        "unknown key '{}' in the var {}",
+                        key, var.name
+                    )))
+                }
+            }
+        }
+        Ok(var)
+    }
+
+    /// Returns the name of the variable.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns whether the variable must be set.
+    pub fn required(&self) -> bool {
+        self.required
+    }
+
+    /// Returns the type of the value.
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    /// Returns the pattern the value must match, if any.
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_ref().map(Regex::as_str)
+    }
+
+    /// Returns the description of the variable, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Returns the rule of the schema `value` breaks, if any.
+    fn check(&self, value: Option<&str>) -> Option<SchemaRule> {
+        let value = match value {
+            None if self.required => return Some(SchemaRule::Missing),
+            None => return None,
+            Some("") if self.required => return Some(SchemaRule::Empty),
+            Some(value) => value,
+        };
+        if !self.value_type.accepts(value) {
+            return Some(SchemaRule::Type(self.value_type));
+        }
+        match &self.pattern {
+            Some(pattern) if !pattern.is_match(value) => {
+                Some(SchemaRule::Pattern(pattern.as_str().to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ValueType {
+    /// Returns the name of the type in a schema.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValueType::String => "string",
+            ValueType::Int => "int",
+            ValueType::Bool => "bool",
+            ValueType::Url => "url",
+        }
+    }
+
+    /// Returns `true` if `value` is of this type.
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            ValueType::String => true,
+            ValueType::Int => value.trim().parse::<i64>().is_ok(),
+            ValueType::Bool => parse_bool("", value).is_ok(),
+            ValueType::Url => is_url(value),
+        }
+    }
+}
+
+impl std::str::FromStr for ValueType {
+    type Err = EnvEncError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        [
+            ValueType::String,
+            ValueType::Int,
+            ValueType::Bool,
+            ValueType::Url,
+        ]
+        .into_iter()
+        .find(|value_type| value_type.name() == name)
+        .ok_or_else(|| {
+            invalid(format!(
+                "unknown type '{}', expected string, int, bool, or url",
+                name
+            ))
+        })
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.var, self.rule)
+    }
+}
+
+impl fmt::Display for SchemaRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaRule::Missing => write!(f, "is required but not set"),
+            SchemaRule::Empty => write!(f, "is required but empty"),
+            SchemaRule::Type(value_type) => write!(f, "is not a valid {}", value_type),
+            SchemaRule::Pattern(pattern) => write!(f, "does not match the pattern {}", pattern),
+        }
+    }
+}
+
+/// Checks the decrypted variables `env` against `schema`, and returns the rules they break, in
+/// the order of the schema. Variables the schema does not declare are not checked.
+///
+/// Violations name the variable and the rule but never the value, so that they can be logged
+/// or printed by CI. See `Schema` for an example.
+pub fn validate_against_schema(env: &DecryptedEnv, schema: &Schema) -> Vec<Violation> {
+    schema
+        .vars
+        .iter()
+        .filter_map(|var| {
+            var.check(env.get(&var.name)).map(|rule| Violation {
+                var: var.name.clone(),
+                rule,
+            })
+        })
+        .collect()
+}
+
+/// Returns `true` if `value` is a URL with a scheme, such as `postgres://db/app`, or
+/// `mailto:ops@example.com`, without whitespace.
+fn is_url(value: &str) -> bool {
+    let Some((scheme, rest)) = value.split_once(':') else {
+        return false;
+    };
+    let mut scheme_chars = scheme.chars();
+    scheme_chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic())
+        && scheme_chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        && !rest.is_empty()
+        && rest != "//"
+        && !value.chars().any(char::is_whitespace)
+}
+
+/// Returns an `EnvEncError::InvalidSchema` for `reason`.
+fn invalid(reason: impl Into<String>) -> EnvEncError {
+    EnvEncError::InvalidSchema {
+        reason: reason.into(),
+    }
+}
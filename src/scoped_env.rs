@@ -0,0 +1,221 @@
+use crate::{
+    decrypt_entries, decrypt_file, key_ring::Keys, CipherType, DecryptedEnv, EnvEncError,
+    DEFAULT_ENV_FILE,
+};
+use std::{collections::HashMap, env, ffi::OsString, marker::PhantomData, path::Path};
+
+/// Variables set in the process environment, which are restored to their previous values, or
+/// removed if they did not exist, when this is dropped.
+pub(crate) struct EnvRestore {
+    previous: Vec<(String, Option<OsString>)>,
+}
+
+impl EnvRestore {
+    /// Sets `vars` in the process environment, recording the values they replace.
+    pub(crate) fn set<'a>(vars: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut restore = EnvRestore {
+            previous: Vec::new(),
+        };
+        for (name, value) in vars {
+            // Recorded before setting, so that a panic in `set_var` still restores the
+            // variables set so far.
+            restore.previous.push((name.to_string(), env::var_os(name)));
+            env::set_var(name, value);
+        }
+        restore
+    }
+
+    /// Returns the names of the variables that will be restored.
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.previous.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Keeps the variables as they are set now, instead of restoring them.
+    fn forget(mut self) {
+        self.previous.clear();
+    }
+}
+
+impl Drop for EnvRestore {
+    fn drop(&mut self) {
+        for (name, previous) in self.previous.drain(..).rev() {
+            match previous {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+    }
+}
+
+/// Decrypts the variables of `.env`, sets them in the process environment for the duration
+/// of `f`, and then restores the environment.
+///
+/// This behaves like `with_decrypted_env_from` on `.env` in the current working directory.
+///
+/// # Errors
+///
+/// Returns the same errors as `with_decrypted_env_from`.
+pub fn with_decrypted_env<T>(
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+    f: impl FnOnce(&DecryptedEnv) -> T,
+) -> Result<T, EnvEncError> {
+    with_decrypted_env_from(DEFAULT_ENV_FILE, cipher_type, key, f)
+}
+
+/// Decrypts the variables of the env file at `path`, sets them in the process environment
+/// for the duration of `f`, and then restores the environment.
+///
+/// `f` receives the decrypted variables. Afterwards, every variable that was set is removed,
+/// or restored to its previous value if it already existed, also when `f` panics. Nothing is
+/// set unless every variable decrypts.
+///
+/// The process environment is shared by all threads: other threads see the variables while
+/// `f` runs, and a thread that changes one of them meanwhile has its change undone. Call this
+/// where no other thread touches the environment, such as during startup.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::KeyMismatch` if the file was written with another key,
+/// `EnvEncError::InvalidDataKey` if its data key cannot be unwrapped with `key`,
+/// `EnvEncError::DecryptionFailed` or `EnvEncError::InvalidUtf8` for the first variable that
+/// could not be decrypted, and `EnvEncError::Io` if the file exists but cannot be read. `f` is
+/// not called in that case.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, set_enc_env_in, with_decrypted_env_from, CipherType};
+/// use std::collections::BTreeMap;
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "SCOPED_API_KEY", "super_secret_api_key", cipher_type, &key)?;
+/// set_enc_env_in(&path, "SCOPED_REGION", "eu-west-1", cipher_type, &key)?;
+///
+/// // A variable that already exists is restored rather than removed.
+/// std::env::set_var("SCOPED_REGION", "us-east-1");
+/// let before: BTreeMap<_, _> = std::env::vars_os().collect();
+///
+/// let region = with_decrypted_env_from(&path, cipher_type, &key, |env| {
+///     assert_eq!(std::env::var("SCOPED_API_KEY").as_deref(), Ok("super_secret_api_key"));
+///     assert_eq!(env.get("SCOPED_REGION"), Some("eu-west-1"));
+///     std::env::var("SCOPED_REGION")
+/// })??;
+/// assert_eq!(region, "eu-west-1");
+/// assert_eq!(std::env::vars_os().collect::<BTreeMap<_, _>>(), before);
+///
+/// // The environment is restored when the closure panics, too.
+/// let result = std::panic::catch_unwind(|| {
+///     with_decrypted_env_from(&path, cipher_type, &key, |_| panic!("operation failed"))
+/// });
+/// assert!(result.is_err());
+/// assert_eq!(std::env::vars_os().collect::<BTreeMap<_, _>>(), before);
+/// assert_eq!(std::env::var("SCOPED_REGION")?, "us-east-1");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn with_decrypted_env_from<T>(
+    path: impl AsRef<Path>,
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+    f: impl FnOnce(&DecryptedEnv) -> T,
+) -> Result<T, EnvEncError> {
+    let (decrypted, report) = decrypt_file(path.as_ref(), cipher_type, key.as_ref())?;
+    report.into_result()?;
+    let env = DecryptedEnv::from(decrypted);
+    let _restore = EnvRestore::set(env.iter());
+    Ok(f(&env))
+}
+
+/// Decrypted variables set in the process environment by `decrypt_env_guarded`, which are
+/// removed again, or restored to their previous values if they already existed, when the
+/// guard is dropped.
+///
+/// The process environment is shared by all threads, and changing it while another thread
+/// reads it is not thread-safe on every platform. The guard is therefore neither `Send` nor
+/// `Sync`, so it is dropped on the thread that created it, and should be created and dropped
+/// where no other thread touches the environment.
+#[must_use = "the variables are removed again when the guard is dropped"]
+pub struct DecryptGuard {
+    restore: EnvRestore,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl DecryptGuard {
+    /// Returns the names of the variables the guard set, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.restore.names()
+    }
+
+    /// Keeps the decrypted variables in the process environment for good, like `decrypt_env`.
+    pub fn leak(self) {
+        self.restore.forget();
+    }
+}
+
+impl std::fmt::Debug for DecryptGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DecryptGuard")
+            .field("names", &self.names().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Decrypts the provided environment variables and sets them in the current process
+/// environment until the returned guard is dropped.
+///
+/// Unlike `decrypt_env`, nothing is set unless every variable decrypts. Call
+/// `DecryptGuard::leak` to keep the variables after all.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::DecryptionFailed` or `EnvEncError::InvalidUtf8` for the first variable
+/// that could not be decrypted.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{decrypt_env_guarded, keys_generation, read_env_enc_from, set_enc_env_in, CipherType};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "GUARD_API_KEY", "super_secret_api_key", cipher_type, &key)?;
+/// set_enc_env_in(&path, "GUARD_REGION", "eu-west-1", cipher_type, &key)?;
+///
+/// std::env::set_var("GUARD_REGION", "us-east-1");
+/// let env_vars = read_env_enc_from(&path)?;
+/// let before = std::env::var("GUARD_API_KEY").ok();
+///
+/// {
+///     let guard = decrypt_env_guarded(env_vars.clone(), cipher_type, &key)?;
+///     assert_eq!(guard.names().collect::<Vec<_>>(), ["GUARD_API_KEY", "GUARD_REGION"]);
+///     assert_eq!(std::env::var("GUARD_API_KEY")?, "super_secret_api_key");
+///     assert_eq!(std::env::var("GUARD_REGION")?, "eu-west-1");
+/// }
+/// // The prior value of a variable that already existed is restored.
+/// assert_eq!(std::env::var("GUARD_REGION")?, "us-east-1");
+/// assert_eq!(std::env::var("GUARD_API_KEY").ok(), before);
+///
+/// // A leaked guard keeps the variables.
+/// decrypt_env_guarded(env_vars, cipher_type, &key)?.leak();
+/// assert_eq!(std::env::var("GUARD_REGION")?, "eu-west-1");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn decrypt_env_guarded(
+    env_vars: HashMap<String, String>,
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+) -> Result<DecryptGuard, EnvEncError> {
+    let (decrypted, report) =
+        decrypt_entries(env_vars, &cipher_type, Keys::Single(key.as_ref()), false);
+    report.into_result()?;
+    let env = DecryptedEnv::from(decrypted);
+    Ok(DecryptGuard {
+        restore: EnvRestore::set(env.iter()),
+        _not_send: PhantomData,
+    })
+}
@@ -0,0 +1,246 @@
+use crate::{
+    env_file::{
+        canonical_entries, lock_env_file, read_contents, write_atomic, DEFAULT_LOCK_TIMEOUT,
+    },
+    EnvEncError,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::Signer;
+use rand::rngs::OsRng;
+use std::{path::Path, str::FromStr};
+
+/// The prefix of the comment line that records the signature of an env file.
+const SIGNATURE_PREFIX: &str = "# envenc signature:";
+
+/// The context string the signed serialization of the entries starts with.
+const SIGNATURE_CONTEXT: &str = "envenc signature v1\n";
+
+/// An Ed25519 signing key that signs env files with `sign_env_file`.
+///
+/// The `Debug` output shows only the verifying key.
+#[derive(Clone)]
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+impl SigningKey {
+    /// Generates a random signing key.
+    pub fn generate() -> Self {
+        SigningKey(ed25519_dalek::SigningKey::generate(&mut OsRng))
+    }
+
+    /// Creates a signing key from its 32-byte seed.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        SigningKey(ed25519_dalek::SigningKey::from_bytes(&bytes))
+    }
+
+    /// Returns the 32-byte seed of the signing key, for example to store it in a key file.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// Returns the key that verifies the signatures made with this key.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(self.0.verifying_key())
+    }
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("verifying_key", &self.verifying_key())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The Ed25519 key that checks env file signatures with `verify_env_file`.
+///
+/// A verifying key is not secret: it is shared as the base64 string written by `Display` and
+/// parsed by `FromStr`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey(ed25519_dalek::VerifyingKey);
+
+impl VerifyingKey {
+    /// Creates a verifying key from its 32 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EnvEncError::InvalidVerifyingKey` if the bytes are not a valid Ed25519 point.
+    pub fn from_bytes(bytes: [u8; 32]) -> Result<Self, EnvEncError> {
+        ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+            .map(VerifyingKey)
+            .map_err(|_| EnvEncError::InvalidVerifyingKey {
+                key: STANDARD.encode(bytes),
+            })
+    }
+
+    /// Returns the 32 bytes of the verifying key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        self.0.as_bytes()
+    }
+}
+
+impl std::fmt::Display for VerifyingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", STANDARD.encode(self.as_bytes()))
+    }
+}
+
+impl std::fmt::Debug for VerifyingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("VerifyingKey")
+            .field(&self.to_string())
+            .finish()
+    }
+}
+
+impl FromStr for VerifyingKey {
+    type Err = EnvEncError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        STANDARD
+            .decode(s.trim())
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .and_then(|bytes| VerifyingKey::from_bytes(bytes).ok())
+            .ok_or_else(|| EnvEncError::InvalidVerifyingKey { key: s.to_string() })
+    }
+}
+
+/// Signs the entries of the env file at `path` with `signing_key`, recording the signature in
+/// a `# envenc signature: <base64>` line at the end of the file.
+///
+/// AEAD protects each value on its own, but cannot tell when a variable is added, removed, or
+/// an older copy of the whole file is restored. The signature covers every `KEY=value` line in
+/// file order, so reordering entries, which changes which of two duplicates wins, is detected
+/// too. Comments and blank lines are not covered.
+///
+/// An existing signature line is replaced. Any later change to the entries invalidates the
+/// signature, so sign the file again after writing to it.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::LockTimeout` if another writer holds the lock on the file, and
+/// `EnvEncError::Io` if it cannot be read or written.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, set_enc_env_in, sign_env_file, verify_env_file, CipherType, EnvEncError, SigningKey};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "SIGNED_API_KEY", "super_secret_api_key", cipher_type, &key)?;
+/// set_enc_env_in(&path, "SIGNED_REGION", "eu-west-1", cipher_type, &key)?;
+///
+/// let signing_key = SigningKey::generate();
+/// let verifying_key = signing_key.verifying_key();
+/// sign_env_file(&path, &signing_key)?;
+/// verify_env_file(&path, &verifying_key)?;
+/// let signed = std::fs::read_to_string(&path)?;
+/// assert!(signed.lines().last().unwrap().starts_with("# envenc signature: "));
+///
+/// // Comments are not covered.
+/// std::fs::write(&path, format!("# Production secrets\n{}", signed))?;
+/// verify_env_file(&path, &verifying_key)?;
+///
+/// let lines: Vec<&str> = signed.lines().collect();
+/// let (header, api_key, region, signature) = (lines[0], lines[1], lines[2], lines[3]);
+/// let tampered = [
+///     // An added line.
+///     [header, api_key, region, "DEBUG=1", signature].join("\n"),
+///     // A removed line.
+///     [header, api_key, signature].join("\n"),
+///     // Reordered lines.
+///     [header, region, api_key, signature].join("\n"),
+///     // A value copied from an older file.
+///     [header, api_key, "SIGNED_REGION=ENC[v2:AES256GCM:c3RhbGUgdmFsdWUgZnJvbSBiZWZvcmU=]", signature].join("\n"),
+/// ];
+/// for contents in tampered {
+///     std::fs::write(&path, contents)?;
+///     assert!(matches!(verify_env_file(&path, &verifying_key), Err(EnvEncError::InvalidSignature { .. })));
+/// }
+///
+/// // A file signed with another key, or not signed at all, fails too.
+/// std::fs::write(&path, &signed)?;
+/// let other_key = SigningKey::generate().verifying_key();
+/// assert!(matches!(verify_env_file(&path, &other_key), Err(EnvEncError::InvalidSignature { .. })));
+/// std::fs::write(&path, [header, api_key, region].join("\n"))?;
+/// assert!(matches!(verify_env_file(&path, &verifying_key), Err(EnvEncError::MissingSignature { .. })));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn sign_env_file(path: impl AsRef<Path>, signing_key: &SigningKey) -> Result<(), EnvEncError> {
+    let path = path.as_ref();
+    let _lock = lock_env_file(path, DEFAULT_LOCK_TIMEOUT)?;
+    let contents = read_contents(path)?.unwrap_or_default();
+    let signature = signing_key
+        .0
+        .sign(&canonical_entries(SIGNATURE_CONTEXT, &contents));
+
+    let mut signed: String = contents
+        .split_inclusive('\n')
+        .filter(|line| !line.starts_with(SIGNATURE_PREFIX))
+        .collect();
+    if !signed.is_empty() && !signed.ends_with('\n') {
+        signed.push('\n');
+    }
+    signed.push_str(&format!(
+        "{} {}\n",
+        SIGNATURE_PREFIX,
+        STANDARD.encode(signature.to_bytes())
+    ));
+    write_atomic(path, &signed)
+}
+
+/// Checks the signature written by `sign_env_file` over the entries of the env file at `path`.
+/// See `sign_env_file` for an example.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::MissingSignature` if the file has no signature line,
+/// `EnvEncError::InvalidSignature` if the signature was not made by the signing key of
+/// `verifying_key` over the current entries, and `EnvEncError::Io` if the file cannot be read.
+pub fn verify_env_file(
+    path: impl AsRef<Path>,
+    verifying_key: &VerifyingKey,
+) -> Result<(), EnvEncError> {
+    let path = path.as_ref();
+    verify_contents(
+        path,
+        &read_contents(path)?.unwrap_or_default(),
+        verifying_key,
+    )
+}
+
+/// Returns `true` if `contents` has the signature line written by `sign_env_file`.
+pub(crate) fn has_signature(contents: &str) -> bool {
+    contents
+        .lines()
+        .any(|line| line.starts_with(SIGNATURE_PREFIX))
+}
+
+/// Checks the signature of `contents`, read from the env file at `path`.
+pub(crate) fn verify_contents(
+    path: &Path,
+    contents: &str,
+    verifying_key: &VerifyingKey,
+) -> Result<(), EnvEncError> {
+    let invalid = || EnvEncError::InvalidSignature {
+        path: path.to_path_buf(),
+    };
+    let signature = contents
+        .lines()
+        .find_map(|line| line.strip_prefix(SIGNATURE_PREFIX))
+        .ok_or_else(|| EnvEncError::MissingSignature {
+            path: path.to_path_buf(),
+        })?;
+    let signature = STANDARD
+        .decode(signature.trim())
+        .ok()
+        .and_then(|bytes| ed25519_dalek::Signature::from_slice(&bytes).ok())
+        .ok_or_else(invalid)?;
+    verifying_key
+        .0
+        .verify_strict(&canonical_entries(SIGNATURE_CONTEXT, contents), &signature)
+        .map_err(|_| invalid())
+}
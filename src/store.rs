@@ -0,0 +1,229 @@
+use crate::{
+    env_file::{
+        check_unchanged, content_hash, lock_env_file, read_contents, write_atomic_bytes,
+        ContentHash, WriteOptions, DEFAULT_LOCK_TIMEOUT,
+    },
+    EnvDocument, EnvEncError,
+};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// Where an `EnvEnc` session loads its env file from and saves it to, set with
+/// `EnvEncBuilder::store`.
+///
+/// A store only moves whole documents: the session parses, decrypts, and edits them, and
+/// checks before every save that the document it loads is still the one it read last, failing
+/// with `EnvEncError::ConcurrentModification` otherwise. A store that can load or save
+/// atomically, such as with a version check, makes that check race-free.
+///
+/// `FileStore` keeps the document in a file, and `MemoryStore` in memory, which suits tests.
+/// Other backends implement the three methods over their own client, serializing the document
+/// with `read_env_enc_from_reader` and `write_env_to_writer`. Errors of the backend are
+/// returned as `EnvEncError::Io`, with `std::io::Error::other`.
+///
+/// # Example
+///
+/// A store over an object in a bucket, as an S3 or Redis client would provide it; here the
+/// bucket is a map in memory:
+///
+/// ```
+/// use envenc::{
+///     keys_generation, read_env_enc_from_reader, write_env_to_writer, CipherType, EnvDocument,
+///     EnvEnc, EnvEncError, EnvStore,
+/// };
+/// use std::{collections::HashMap, sync::{Arc, Mutex}};
+///
+/// #[derive(Clone, Default)]
+/// struct BucketStore {
+///     bucket: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+///     object: String,
+/// }
+///
+/// impl EnvStore for BucketStore {
+///     fn load(&self) -> Result<EnvDocument, EnvEncError> {
+///         let bucket = self.bucket.lock().map_err(|_| std::io::Error::other("bucket poisoned"))?;
+///         match bucket.get(&self.object) {
+///             Some(bytes) => read_env_enc_from_reader(bytes.as_slice()),
+///             None => Ok(EnvDocument::default()),
+///         }
+///     }
+///
+///     fn save(&self, document: &EnvDocument) -> Result<(), EnvEncError> {
+///         let mut bytes = Vec::new();
+///         write_env_to_writer(&mut bytes, document)?;
+///         let mut bucket = self.bucket.lock().map_err(|_| std::io::Error::other("bucket poisoned"))?;
+///         bucket.insert(self.object.clone(), bytes);
+///         Ok(())
+///     }
+///
+///     fn exists(&self) -> Result<bool, EnvEncError> {
+///         let bucket = self.bucket.lock().map_err(|_| std::io::Error::other("bucket poisoned"))?;
+///         Ok(bucket.contains_key(&self.object))
+///     }
+/// }
+///
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// let store = BucketStore { object: "billing/.env".to_string(), ..BucketStore::default() };
+/// assert!(!store.exists()?);
+///
+/// let builder = EnvEnc::builder().cipher(cipher_type).key(key).store(Box::new(store.clone()));
+/// builder.clone().build()?.set("API_KEY", "sk_live_123")?;
+/// assert!(store.exists()?);
+/// assert!(store.load()?.get("API_KEY").unwrap().starts_with("ENC[v2:AES256GCM:"));
+/// assert_eq!(builder.build()?.get("API_KEY")?.as_deref(), Some("sk_live_123"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait EnvStore: Send + Sync {
+    /// Returns the document held by the store, or an empty one if it holds none yet.
+    fn load(&self) -> Result<EnvDocument, EnvEncError>;
+
+    /// Replaces the document held by the store with `document`.
+    fn save(&self, document: &EnvDocument) -> Result<(), EnvEncError>;
+
+    /// Returns whether the store holds a document.
+    fn exists(&self) -> Result<bool, EnvEncError>;
+}
+
+/// A store that keeps the document in the env file at a path.
+///
+/// Saves replace the file atomically, keeping its permissions, under the same lock as the
+/// other writers of the crate. A session built with `EnvEncBuilder::env_file` reads the file
+/// directly instead, which also supports sealed env files and holds the lock for the whole
+/// read-modify-write cycle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    /// Returns a store over the env file at `path`, which need not exist yet.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileStore { path: path.into() }
+    }
+
+    /// Returns the path of the env file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl EnvStore for FileStore {
+    fn load(&self) -> Result<EnvDocument, EnvEncError> {
+        Ok(EnvDocument::parse(
+            read_contents(&self.path)?.unwrap_or_default(),
+        ))
+    }
+
+    fn save(&self, document: &EnvDocument) -> Result<(), EnvEncError> {
+        let _lock = lock_env_file(&self.path, DEFAULT_LOCK_TIMEOUT)?;
+        write_atomic_bytes(&self.path, document.as_str().as_bytes())
+    }
+
+    fn exists(&self) -> Result<bool, EnvEncError> {
+        Ok(self.path.try_exists()?)
+    }
+}
+
+/// A store that keeps the document in memory, so that tests never touch the disk.
+///
+/// Clones share the same document, so a test can hand one clone to a session and inspect the
+/// document through another.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, CipherType, EnvEnc, EnvStore, MemoryStore};
+///
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// let store = MemoryStore::with_contents("# Settings\nLOG_LEVEL=debug\n");
+///
+/// let mut session = EnvEnc::builder().cipher(cipher_type).key(key).store(Box::new(store.clone())).build()?;
+/// session.set("API_KEY", "sk_live_123")?;
+///
+/// let contents = store.contents().unwrap();
+/// assert!(contents.starts_with("# Settings\nLOG_LEVEL=debug\nAPI_KEY=ENC[v2:AES256GCM:"));
+/// assert!(!contents.contains("sk_live_123"));
+/// assert!(!MemoryStore::new().exists()?);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    contents: Arc<Mutex<Option<String>>>,
+}
+
+impl MemoryStore {
+    /// Returns a store that holds no document yet.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Returns a store that holds the env file `contents`.
+    pub fn with_contents(contents: impl Into<String>) -> Self {
+        MemoryStore {
+            contents: Arc::new(Mutex::new(Some(contents.into()))),
+        }
+    }
+
+    /// Returns the text of the document held by the store, if any.
+    pub fn contents(&self) -> Option<String> {
+        self.lock().clone()
+    }
+
+    /// Locks the document, which a panicking writer cannot leave half-written.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<String>> {
+        self.contents.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl EnvStore for MemoryStore {
+    fn load(&self) -> Result<EnvDocument, EnvEncError> {
+        Ok(EnvDocument::parse(self.lock().clone().unwrap_or_default()))
+    }
+
+    fn save(&self, document: &EnvDocument) -> Result<(), EnvEncError> {
+        *self.lock() = Some(document.as_str().to_string());
+        Ok(())
+    }
+
+    fn exists(&self) -> Result<bool, EnvEncError> {
+        Ok(self.lock().is_some())
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemoryStore").finish_non_exhaustive()
+    }
+}
+
+/// Replaces the document held by `store` with what `update` returns for its text, if
+/// anything, as `rewrite_env_file` does for a file. `path` names the env file in errors.
+/// Returns the hash of the text of the document afterwards.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::ConcurrentModification` if the text no longer hashes to
+/// `options.expected`, the error of `update`, and the errors of the store.
+pub(crate) fn rewrite_store(
+    store: &dyn EnvStore,
+    path: &Path,
+    options: WriteOptions,
+    update: impl FnOnce(&str) -> Result<Option<String>, EnvEncError>,
+) -> Result<ContentHash, EnvEncError> {
+    let document = store.load()?;
+    check_unchanged(path, document.as_str(), options.expected)?;
+    match update(document.as_str())? {
+        Some(updated) => {
+            let updated = options.line_ending.apply(&updated);
+            let hash = content_hash(&updated);
+            store.save(&EnvDocument::parse(updated))?;
+            Ok(hash)
+        }
+        None => Ok(content_hash(document.as_str())),
+    }
+}
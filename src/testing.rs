@@ -0,0 +1,89 @@
+//! Test doubles for the extension points of this crate.
+
+use crate::{random_bytes, CipherType, EnvCipher, KeyProvider, KeyProviderError};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// The associated data data keys are wrapped with by `InMemoryKeyProvider`.
+const WRAP_AAD: &[u8] = b"envenc in-memory key provider";
+
+/// A `KeyProvider` that keeps a random master key in memory, for tests that would otherwise
+/// need a key management service.
+///
+/// Data keys are wrapped with AES-256-GCM. Clones share the master key, and an unavailable
+/// provider fails every call with `KeyProviderError::Network`, like a provider that cannot be
+/// reached. See `KeyProvider` for an example.
+#[derive(Clone)]
+pub struct InMemoryKeyProvider {
+    id: String,
+    master_key: Arc<Vec<u8>>,
+    unavailable: Arc<AtomicBool>,
+}
+
+impl InMemoryKeyProvider {
+    /// Creates a provider with a fresh random master key, recorded in env files as `id`.
+    pub fn new(id: impl Into<String>) -> Self {
+        InMemoryKeyProvider {
+            id: id.into(),
+            master_key: Arc::new(random_bytes(CipherType::AES256GCM.key_size())),
+            unavailable: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Makes every call of this provider and its clones fail as if it could not be reached.
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.unavailable.store(unavailable, Ordering::SeqCst);
+    }
+
+    /// Returns `KeyProviderError::Network` if the provider is unavailable.
+    fn check_available(&self) -> Result<(), KeyProviderError> {
+        if self.unavailable.load(Ordering::SeqCst) {
+            return Err(KeyProviderError::Network(format!(
+                "{} is unavailable",
+                self.id
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl KeyProvider for InMemoryKeyProvider {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn wrap(&self, data_key: &[u8]) -> Result<Vec<u8>, KeyProviderError> {
+        self.check_available()?;
+        let cipher = CipherType::AES256GCM;
+        let mut wrapped = random_bytes(cipher.nonce_size());
+        let ciphertext = cipher
+            .seal(&self.master_key, &wrapped, data_key, WRAP_AAD)
+            .map_err(|err| KeyProviderError::Other(err.to_string()))?;
+        wrapped.extend_from_slice(&ciphertext);
+        Ok(wrapped)
+    }
+
+    fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>, KeyProviderError> {
+        self.check_available()?;
+        let cipher = CipherType::AES256GCM;
+        if wrapped.len() < cipher.nonce_size() {
+            return Err(KeyProviderError::InvalidCiphertext);
+        }
+        let (nonce, ciphertext) = wrapped.split_at(cipher.nonce_size());
+        cipher
+            .open(&self.master_key, nonce, ciphertext, WRAP_AAD)
+            .map_err(|_| KeyProviderError::InvalidCiphertext)
+    }
+}
+
+impl std::fmt::Debug for InMemoryKeyProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("InMemoryKeyProvider")
+            .field("id", &self.id)
+            .field("master_key", &"[REDACTED]")
+            .field("unavailable", &self.unavailable.load(Ordering::SeqCst))
+            .finish()
+    }
+}
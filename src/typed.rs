@@ -0,0 +1,217 @@
+use crate::EnvEncError;
+use std::{collections::HashMap, env, fmt::Display, str::FromStr};
+
+/// The values `read_env_bool` reads as `true`, compared without regard to case.
+const TRUE_VALUES: [&str; 4] = ["true", "1", "yes", "on"];
+
+/// The values `read_env_bool` reads as `false`, compared without regard to case.
+const FALSE_VALUES: [&str; 4] = ["false", "0", "no", "off"];
+
+/// Reads the decrypted environment variable `var_name` and parses it as a `T`, such as a port,
+/// a count, or an address.
+///
+/// Surrounding whitespace is trimmed before parsing. `DecryptedEnv::parse` and
+/// `LazyEnv::parse` read values the same way without going through the process environment.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::MissingVar` if the variable is not set, and
+/// `EnvEncError::InvalidValue` if it cannot be parsed. The error names the variable and carries
+/// the message of the parse error, but never the value, which may be a secret.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{read_env_bool, read_env_parse, EnvEncError};
+/// use std::net::IpAddr;
+///
+/// std::env::set_var("READ_ENV_PARSE_PORT", "8080");
+/// std::env::set_var("READ_ENV_PARSE_HOST", "127.0.0.1");
+/// std::env::set_var("READ_ENV_PARSE_DEBUG", "Yes");
+/// assert_eq!(read_env_parse::<u16>("READ_ENV_PARSE_PORT")?, 8080);
+/// assert!(read_env_parse::<IpAddr>("READ_ENV_PARSE_HOST")?.is_loopback());
+/// assert!(read_env_bool("READ_ENV_PARSE_DEBUG")?);
+///
+/// std::env::set_var("READ_ENV_PARSE_WORKERS", "sk_live_123");
+/// let err = read_env_parse::<u32>("READ_ENV_PARSE_WORKERS").unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "the value of environment variable 'READ_ENV_PARSE_WORKERS' is invalid: invalid digit found in string"
+/// );
+/// assert!(matches!(
+///     read_env_parse::<u16>("READ_ENV_PARSE_MISSING"),
+///     Err(EnvEncError::MissingVar { var }) if var == "READ_ENV_PARSE_MISSING"
+/// ));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn read_env_parse<T>(var_name: &str) -> Result<T, EnvEncError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    parse_value(var_name, &read_var(var_name)?)
+}
+
+/// Reads the decrypted environment variable `var_name` as a flag. `true`, `1`, `yes`, and `on`
+/// are `true`, and `false`, `0`, `no`, and `off` are `false`, in any case and with surrounding
+/// whitespace trimmed.
+///
+/// See `read_env_parse` for an example.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::MissingVar` if the variable is not set, and
+/// `EnvEncError::InvalidValue` if it is set to anything else.
+pub fn read_env_bool(var_name: &str) -> Result<bool, EnvEncError> {
+    parse_bool(var_name, &read_var(var_name)?)
+}
+
+/// Reads the decrypted environment variable `var_name` and deserializes it from JSON, such as
+/// a list of hosts or a small service account document.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::MissingVar` if the variable is not set, and
+/// `EnvEncError::InvalidValue` if it is not JSON of the shape of `T`. The error gives the
+/// line and column where parsing failed, but none of the value.
+///
+/// # Example
+///
+/// ```
+/// use envenc::read_env_json;
+/// use std::collections::HashMap;
+///
+/// std::env::set_var("READ_ENV_JSON_REPLICAS", r#"{"eu": "db-eu.internal", "us": "db-us.internal"}"#);
+/// let replicas: HashMap<String, String> = read_env_json("READ_ENV_JSON_REPLICAS")?;
+/// assert_eq!(replicas["eu"], "db-eu.internal");
+///
+/// std::env::set_var("READ_ENV_JSON_PORTS", r#"["sk_live_123"]"#);
+/// let err = read_env_json::<Vec<u16>>("READ_ENV_JSON_PORTS").unwrap_err();
+/// assert!(!err.to_string().contains("sk_live_123"), "{}", err);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg(feature = "serde")]
+pub fn read_env_json<T: serde::de::DeserializeOwned>(var_name: &str) -> Result<T, EnvEncError> {
+    parse_json(var_name, &read_var(var_name)?)
+}
+
+/// Reads the decrypted environment variables `var_names`, failing if any of them is not set,
+/// so that a service refuses to start rather than failing when it first uses a secret.
+///
+/// Every name is checked before failing, so that one error reports all the variables that
+/// are missing. `EnvEnc::require` checks the env file of a session instead of the process
+/// environment.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::MissingVars` with the names of the variables that are not set, and
+/// `EnvEncError::InvalidValue` if a value is not valid unicode.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{require_envs, EnvEncError};
+///
+/// std::env::set_var("REQUIRE_ENVS_DATABASE_URL", "postgres://localhost/db");
+/// let values = require_envs(&["REQUIRE_ENVS_DATABASE_URL"])?;
+/// assert_eq!(values["REQUIRE_ENVS_DATABASE_URL"], "postgres://localhost/db");
+///
+/// let err = require_envs(&["REQUIRE_ENVS_SMTP_URL", "REQUIRE_ENVS_DATABASE_URL", "REQUIRE_ENVS_API_KEY"]).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "required environment variables are not set: REQUIRE_ENVS_API_KEY, REQUIRE_ENVS_SMTP_URL"
+/// );
+/// assert!(matches!(err, EnvEncError::MissingVars { vars } if vars.len() == 2));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn require_envs(var_names: &[&str]) -> Result<HashMap<String, String>, EnvEncError> {
+    let mut values = HashMap::new();
+    let mut missing = Vec::new();
+    for &var_name in var_names {
+        match read_var(var_name) {
+            Ok(value) => {
+                values.insert(var_name.to_string(), value);
+            }
+            Err(EnvEncError::MissingVar { var }) => missing.push(var),
+            Err(err) => return Err(err),
+        }
+    }
+    missing_vars(missing)?;
+    Ok(values)
+}
+
+/// Returns `EnvEncError::MissingVars` for the variables `missing`, if there are any.
+pub(crate) fn missing_vars(mut missing: Vec<String>) -> Result<(), EnvEncError> {
+    if missing.is_empty() {
+        return Ok(());
+    }
+    missing.sort();
+    missing.dedup();
+    Err(EnvEncError::MissingVars { vars: missing })
+}
+
+/// Returns the value of the variable `var_name` of the process environment.
+fn read_var(var_name: &str) -> Result<String, EnvEncError> {
+    match env::var(var_name) {
+        Ok(value) => Ok(value),
+        Err(env::VarError::NotPresent) => Err(EnvEncError::MissingVar {
+            var: var_name.to_string(),
+        }),
+        Err(env::VarError::NotUnicode(_)) => Err(invalid_value(var_name, "not valid unicode")),
+    }
+}
+
+/// Parses `value`, the value of `var_name`, as a `T`.
+pub(crate) fn parse_value<T>(var_name: &str, value: &str) -> Result<T, EnvEncError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|err: T::Err| invalid_value(var_name, err))
+}
+
+/// Parses `value`, the value of `var_name`, as a flag, as `read_env_bool` does.
+pub(crate) fn parse_bool(var_name: &str, value: &str) -> Result<bool, EnvEncError> {
+    let value = value.trim();
+    let is = |values: &[&str]| values.iter().any(|known| known.eq_ignore_ascii_case(value));
+    match (is(&TRUE_VALUES), is(&FALSE_VALUES)) {
+        (true, _) => Ok(true),
+        (_, true) => Ok(false),
+        _ => Err(invalid_value(
+            var_name,
+            "expected true, false, 1, 0, yes, no, on, or off",
+        )),
+    }
+}
+
+/// Deserializes `value`, the value of `var_name`, from JSON, as `read_env_json` does.
+#[cfg(feature = "serde")]
+pub(crate) fn parse_json<T: serde::de::DeserializeOwned>(
+    var_name: &str,
+    value: &str,
+) -> Result<T, EnvEncError> {
+    use serde_json::error::Category;
+
+    serde_json::from_str(value).map_err(|err| {
+        // The message of a serde_json error may quote part of the value, so only its kind and
+        // position are kept.
+        let kind = match err.classify() {
+            Category::Syntax | Category::Io => "invalid JSON",
+            Category::Data => "JSON of the wrong shape",
+            Category::Eof => "JSON that ends early",
+        };
+        let reason = format!("{} at line {} column {}", kind, err.line(), err.column());
+        invalid_value(var_name, reason)
+    })
+}
+
+/// Returns an `EnvEncError::InvalidValue` for `var_name`, for `reason`.
+fn invalid_value(var_name: &str, reason: impl Display) -> EnvEncError {
+    EnvEncError::InvalidValue {
+        var: var_name.to_string(),
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{
+    data_key::{unwrap_data_key, KeyProtection},
+    decrypt_entries,
+    env_file::{parse_entries, read_contents},
+    file_mac::{check_mac, has_mac},
+    fingerprint::{check_fingerprint, is_header},
+    sealed,
+    signature::has_signature,
+    CipherType, EnvEncError, Keys, SkipReason,
+};
+use std::{collections::HashMap, io, path::Path};
+
+/// The outcome of one integrity check of an env file, in a `VerifyReport`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// The env file does not carry what the check verifies, so there is nothing to check.
+    #[default]
+    Absent,
+    /// The check passed.
+    Valid,
+    /// The check failed.
+    Invalid,
+    /// The env file carries what the check verifies, but it was not checked. A signature is
+    /// only checked against a verifying key, with `verify_env_file`.
+    Unchecked,
+}
+
+impl CheckStatus {
+    /// Returns the name of the status, as shown by `Display`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CheckStatus::Absent => "absent",
+            CheckStatus::Valid => "valid",
+            CheckStatus::Invalid => "invalid",
+            CheckStatus::Unchecked => "unchecked",
+        }
+    }
+}
+
+impl std::fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The health of an env file, as checked by `verify_env`. It holds no decrypted value.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// The number of variables stored in the env file.
+    pub variables: usize,
+    /// Whether the env file is a sealed env file.
+    pub sealed: bool,
+    /// Whether the key fingerprint recorded in the header matches the key.
+    pub fingerprint: CheckStatus,
+    /// Whether the data key of the env file, if it has one, can be unwrapped with the key.
+    pub data_key: CheckStatus,
+    /// Whether the MAC footer matches the entries of the env file.
+    pub mac: CheckStatus,
+    /// Whether the env file carries a signature. It is never `Valid` or `Invalid` unless set
+    /// so by the caller after `verify_env_file`.
+    pub signature: CheckStatus,
+    /// Variables that decrypted successfully.
+    pub decrypted: Vec<String>,
+    /// Variables that were not decrypted because their stored value is not an encrypted
+    /// payload, such as key material, or needs another key.
+    pub skipped: Vec<(String, SkipReason)>,
+    /// Variables whose stored value is not encrypted, such as `RUST_LOG=info`.
+    pub plain: Vec<String>,
+    /// Variables that failed to decrypt or whose envelope is malformed, as
+    /// `EnvEncError::DecryptionFailed`, `EnvEncError::InvalidUtf8`, or
+    /// `EnvEncError::InvalidEnvelope`. A sealed env file that cannot be opened is reported here
+    /// as a whole.
+    pub failed: Vec<EnvEncError>,
+}
+
+impl VerifyReport {
+    /// Returns `true` if no check is `Invalid` and every encrypted value decrypts.
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+            && [self.fingerprint, self.data_key, self.mac, self.signature]
+                .iter()
+                .all(|status| *status != CheckStatus::Invalid)
+    }
+}
+
+/// Checks the env file at `path` against `key` without printing, returning, or setting any
+/// decrypted value, for health checks in CI or before a deploy.
+///
+/// Every check runs even when an earlier one fails, so a single report shows whether the key
+/// fingerprint matches, whether the data key unwraps, whether the MAC footer verifies, and
+/// which variables decrypt. Use `VerifyReport::is_ok` to gate on the result.
+///
+/// # Errors
+///
+/// Returns `EnvEncError::Io` if the file does not exist or cannot be read. Problems with the
+/// contents of the file are reported in the returned `VerifyReport` instead.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, set_enc_env_in, set_plain_env_in, verify_env, CheckStatus, CipherType, EnvEncError, Overwrite};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "VERIFY_API_KEY", "super_secret_api_key", cipher_type, &key)?;
+/// set_enc_env_in(&path, "VERIFY_REGION", "eu-west-1", cipher_type, &key)?;
+/// set_plain_env_in(&path, "RUST_LOG", "info", Overwrite::No)?;
+///
+/// let report = verify_env(&path, cipher_type, &key)?;
+/// assert!(report.is_ok());
+/// assert_eq!(report.variables, 3);
+/// assert_eq!(report.plain, ["RUST_LOG"]);
+/// assert_eq!(report.fingerprint, CheckStatus::Valid);
+/// assert_eq!(report.decrypted, ["VERIFY_API_KEY", "VERIFY_REGION"]);
+///
+/// // A damaged value is reported by name.
+/// let contents = std::fs::read_to_string(&path)?;
+/// let damaged = contents.replace("VERIFY_REGION=ENC[v2:AES256GCM:", "VERIFY_REGION=ENC[v2:AES256GCM:AAAA");
+/// std::fs::write(&path, damaged)?;
+/// let report = verify_env(&path, cipher_type, &key)?;
+/// assert!(!report.is_ok());
+/// assert_eq!(report.decrypted, ["VERIFY_API_KEY"]);
+/// assert!(matches!(&report.failed[..], [EnvEncError::DecryptionFailed { var }] if var == "VERIFY_REGION"));
+///
+/// // So is the wrong key.
+/// std::fs::write(&path, contents)?;
+/// let (wrong_key, _) = keys_generation(cipher_type)?;
+/// let report = verify_env(&path, cipher_type, &wrong_key)?;
+/// assert_eq!(report.fingerprint, CheckStatus::Invalid);
+/// assert_eq!(report.failed.len(), 2);
+///
+/// assert!(matches!(verify_env(dir.path().join("missing.env"), cipher_type, &key), Err(EnvEncError::Io(_))));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn verify_env(
+    path: impl AsRef<Path>,
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+) -> Result<VerifyReport, EnvEncError> {
+    let path = path.as_ref();
+    let key = key.as_ref();
+    if let Some(contents) = sealed::read_sealed(path)? {
+        let mut report = VerifyReport {
+            sealed: true,
+            ..VerifyReport::default()
+        };
+        match sealed::open(path, &contents, &cipher_type, key) {
+            Ok(vars) => {
+                report.variables = vars.len();
+                report.decrypted = vars.into_keys().collect();
+            }
+            Err(err) => report.failed.push(err),
+        }
+        return Ok(report);
+    }
+    let contents = read_contents(path)?.ok_or_else(|| {
+        EnvEncError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", path.display()),
+        ))
+    })?;
+
+    let mut report = VerifyReport::default();
+    let data_key = unwrap_data_key(path, &contents, &cipher_type, &[KeyProtection::Key(key)]);
+    let value_key = match &data_key {
+        Ok(Some(data_key)) => {
+            report.data_key = CheckStatus::Valid;
+            data_key.as_slice()
+        }
+        Ok(None) => key,
+        Err(_) => {
+            report.data_key = CheckStatus::Invalid;
+            key
+        }
+    };
+    report.fingerprint = match contents.lines().any(is_header) {
+        false => CheckStatus::Absent,
+        true => match check_fingerprint(&contents, &[value_key]) {
+            Ok(()) => CheckStatus::Valid,
+            Err(_) => CheckStatus::Invalid,
+        },
+    };
+    report.mac = match (has_mac(&contents), check_mac(path, &contents, &[value_key])) {
+        (false, _) => CheckStatus::Absent,
+        (true, Ok(())) => CheckStatus::Valid,
+        (true, Err(_)) => CheckStatus::Invalid,
+    };
+    if has_signature(&contents) {
+        report.signature = CheckStatus::Unchecked;
+    }
+
+    let env_vars = parse_entries(&contents)
+        .into_iter()
+        .map(|(_, name, value)| (name, value))
+        .collect::<HashMap<_, _>>();
+    report.variables = env_vars.len();
+    let (_, decrypt_report) =
+        decrypt_entries(env_vars, &cipher_type, Keys::Single(value_key), false);
+    report.decrypted = decrypt_report.decrypted;
+    report.skipped = decrypt_report.skipped;
+    report.plain = decrypt_report.plain;
+    report.failed = decrypt_report.failed;
+    Ok(report)
+}
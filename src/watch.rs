@@ -0,0 +1,293 @@
+use crate::{CipherType, DecryptedEnv, EnvEnc, EnvEncError, Key, SecretValue};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How long the env file must stay unchanged after a write before it is read again, so that
+/// an editor saving in several steps produces a single `EnvChange`.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The variables that changed between two versions of a watched env file, as passed to the
+/// callback of `watch_env`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvChange {
+    /// The variables that were not set before, with their values.
+    pub added: DecryptedEnv,
+    /// The variables whose value changed, with their new values.
+    pub changed: DecryptedEnv,
+    /// The names of the variables that are no longer set, sorted.
+    pub removed: Vec<String>,
+}
+
+impl EnvChange {
+    /// Returns `true` if no variable was added, changed, or removed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A running watch on an env file, returned by `watch_env`. Dropping it stops the watch and
+/// waits for its thread to finish.
+pub struct EnvWatcher {
+    path: PathBuf,
+    watcher: Option<RecommendedWatcher>,
+    messages: Sender<Message>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// What the thread of an `EnvWatcher` receives.
+enum Message {
+    /// An event of the watched directory.
+    Event(notify::Result<Event>),
+    /// The watcher was dropped.
+    Stop,
+}
+
+/// Watches the env file at `path`, and calls `callback` on a background thread with the
+/// variables that were added, changed, or removed whenever the file is rewritten, so that a
+/// service picks up rotated credentials without restarting.
+///
+/// The file is decrypted with `key` when the watch starts, and again after each change once
+/// it has been left alone for 100 milliseconds, so that several writes in quick succession are
+/// delivered as one change. A version of the file only replaces the previous one if every
+/// value decrypts: a file that does not exist, cannot be parsed, or holds a value that does not
+/// decrypt is logged as a warning and skipped, and the next change is compared with the last
+/// version that was delivered. Values that are not encrypted are passed through as they are.
+///
+/// The directory of the file is watched rather than the file itself, so that the atomic
+/// renames of envenc and of most editors are seen. The process environment is not touched;
+/// call `std::env::set_var` from the callback to update it.
+///
+/// # Errors
+///
+/// Returns the errors of `EnvEncBuilder::build` and `EnvEncError::DecryptionFailed` if the
+/// file cannot be decrypted when the watch starts, and `EnvEncError::Io` if its directory
+/// cannot be watched.
+///
+/// # Example
+///
+/// ```
+/// use envenc::{keys_generation, remove_enc_env_in, set_enc_env_in, upsert_enc_env_in, watch_env, CipherType, Overwrite, RemoveOptions};
+/// use std::{sync::mpsc, time::Duration};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("secrets.env");
+/// let cipher_type = CipherType::AES256GCM;
+/// let (key, _) = keys_generation(cipher_type)?;
+/// set_enc_env_in(&path, "DATABASE_URL", "postgres://app:old@db/app", cipher_type, &key)?;
+/// set_enc_env_in(&path, "LEGACY_TOKEN", "retired", cipher_type, &key)?;
+///
+/// let (changes, received) = mpsc::channel();
+/// let watcher = watch_env(&path, cipher_type, &key, move |change| changes.send(change).unwrap())?;
+///
+/// // Rotate the credentials in a single rewrite of the file.
+/// let mut contents = std::fs::read_to_string(&path)?;
+/// let rotated = dir.path().join("rotated.env");
+/// std::fs::write(&rotated, &contents)?;
+/// upsert_enc_env_in(&rotated, "DATABASE_URL", "postgres://app:new@db/app", cipher_type, &key, Overwrite::Yes)?;
+/// set_enc_env_in(&rotated, "CACHE_URL", "redis://cache", cipher_type, &key)?;
+/// remove_enc_env_in(&rotated, "LEGACY_TOKEN", RemoveOptions::default())?;
+/// std::fs::rename(&rotated, &path)?;
+///
+/// let change = received.recv_timeout(Duration::from_secs(10))?;
+/// assert_eq!(change.changed.get("DATABASE_URL"), Some("postgres://app:new@db/app"));
+/// assert_eq!(change.added.get("CACHE_URL"), Some("redis://cache"));
+/// assert_eq!(change.removed, ["LEGACY_TOKEN"]);
+///
+/// // A value that does not decrypt with the key is not delivered.
+/// let (other_key, _) = keys_generation(cipher_type)?;
+/// let other = dir.path().join("other.env");
+/// set_enc_env_in(&other, "BROKEN", "value", cipher_type, &other_key)?;
+/// let broken = std::fs::read_to_string(&other)?;
+/// contents.push_str(broken.lines().find(|line| line.starts_with("BROKEN=")).unwrap());
+/// std::fs::write(&path, contents + "\n")?;
+/// assert!(received.recv_timeout(Duration::from_millis(500)).is_err());
+///
+/// // Dropping the watcher stops it.
+/// drop(watcher);
+/// assert!(received.recv().is_err());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn watch_env<F>(
+    path: impl AsRef<Path>,
+    cipher_type: CipherType,
+    key: impl AsRef<[u8]>,
+    callback: F,
+) -> Result<EnvWatcher, EnvEncError>
+where
+    F: FnMut(EnvChange) + Send + 'static,
+{
+    let path = path.as_ref().to_path_buf();
+    let key = Key::from_bytes(key.as_ref());
+    let current = load(&path, cipher_type, &key)?;
+
+    let (messages, received) = mpsc::channel();
+    let events = messages.clone();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = events.send(Message::Event(event));
+    })
+    .map_err(watch_error)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(watch_error)?;
+
+    let watched = path.clone();
+    let thread = thread::Builder::new()
+        .name("envenc-watch".to_string())
+        .spawn(move || run(&watched, cipher_type, &key, current, &received, callback))?;
+    log::info!("watching {}", path.display());
+    Ok(EnvWatcher {
+        path,
+        watcher: Some(watcher),
+        messages,
+        thread: Some(thread),
+    })
+}
+
+/// Receives the events of the directory of the env file at `path` until the watcher is
+/// dropped, delivering each change of the file to `callback`.
+fn run(
+    path: &Path,
+    cipher_type: CipherType,
+    key: &Key,
+    mut current: BTreeMap<String, SecretValue>,
+    received: &Receiver<Message>,
+    mut callback: impl FnMut(EnvChange),
+) {
+    let name = path.file_name().map(OsString::from);
+    loop {
+        match received.recv() {
+            Ok(Message::Event(Ok(event))) if touches(&event, name.as_deref()) => {}
+            Ok(Message::Event(Ok(_))) => continue,
+            Ok(Message::Event(Err(err))) => {
+                log::warn!("watching {} failed: {}", path.display(), err);
+                continue;
+            }
+            Ok(Message::Stop) | Err(_) => return,
+        }
+        // Wait for the writes to settle.
+        loop {
+            match received.recv_timeout(DEBOUNCE) {
+                Ok(Message::Event(_)) => {}
+                Err(RecvTimeoutError::Timeout) => break,
+                Ok(Message::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        if !path.exists() {
+            log::warn!(
+                "{} was removed; waiting for it to come back",
+                path.display()
+            );
+            continue;
+        }
+        let next = match load(path, cipher_type, key) {
+            Ok(next) => next,
+            Err(err) => {
+                log::warn!(
+                    "{} changed but cannot be decrypted; kept the previous values: {}",
+                    path.display(),
+                    err
+                );
+                continue;
+            }
+        };
+        let change = diff(&current, &next);
+        current = next;
+        if !change.is_empty() {
+            log::info!("{} changed", path.display());
+            callback(change);
+        }
+    }
+}
+
+/// Returns `true` if `event` may have changed the file named `name`. Reads, including those of
+/// the watcher itself, are not changes.
+fn touches(event: &Event, name: Option<&std::ffi::OsStr>) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+        && event
+            .paths
+            .iter()
+            .any(|path| path.file_name().is_some() && path.file_name() == name)
+}
+
+/// Decrypts every variable of the env file at `path`, failing if any of them does not decrypt.
+fn load(
+    path: &Path,
+    cipher_type: CipherType,
+    key: &Key,
+) -> Result<BTreeMap<String, SecretValue>, EnvEncError> {
+    let session = EnvEnc::builder()
+        .cipher(cipher_type)
+        .key(key.as_bytes())
+        .env_file(path)
+        .build()?;
+    let (decrypted, report) = session.decrypt_entries();
+    report.into_result()?;
+    Ok(decrypted.into_iter().collect())
+}
+
+/// Returns the variables that differ between `previous` and `next`.
+fn diff(
+    previous: &BTreeMap<String, SecretValue>,
+    next: &BTreeMap<String, SecretValue>,
+) -> EnvChange {
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for (name, value) in next {
+        match previous.get(name) {
+            None => added.push((name.clone(), value.clone())),
+            Some(old) if old != value => changed.push((name.clone(), value.clone())),
+            Some(_) => {}
+        }
+    }
+    EnvChange {
+        added: added.into_iter().collect(),
+        changed: changed.into_iter().collect(),
+        removed: previous
+            .keys()
+            .filter(|name| !next.contains_key(*name))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Returns `err` of the file watcher as an `EnvEncError::Io`.
+fn watch_error(err: notify::Error) -> EnvEncError {
+    match err.kind {
+        notify::ErrorKind::Io(err) => EnvEncError::Io(err),
+        _ => EnvEncError::Io(io::Error::other(err)),
+    }
+}
+
+impl Drop for EnvWatcher {
+    fn drop(&mut self) {
+        let _ = self.messages.send(Message::Stop);
+        drop(self.watcher.take());
+        if let Some(thread) = self.thread.take() {
+            // A callback that drops the watcher cannot wait for its own thread.
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+        log::debug!("stopped watching {}", self.path.display());
+    }
+}
+
+impl std::fmt::Debug for EnvWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("EnvWatcher")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
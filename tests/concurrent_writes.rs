@@ -0,0 +1,95 @@
+use envenc::{
+    keys_generation, read_env_enc_from, set_enc_env_many_in, set_plain_env_in, upsert_enc_env_in,
+    CipherType, Overwrite, SetOutcome,
+};
+use std::{
+    path::Path,
+    sync::{Barrier, Mutex},
+};
+
+const THREADS: usize = 48;
+const ROUNDS: usize = 5;
+
+/// Runs `write` for every index from `THREADS` threads at once, released together.
+fn hammer(write: impl Fn(usize) + Sync) {
+    let barrier = Barrier::new(THREADS);
+    std::thread::scope(|scope| {
+        for index in 0..THREADS {
+            let (barrier, write) = (&barrier, &write);
+            scope.spawn(move || {
+                barrier.wait();
+                write(index);
+            });
+        }
+    });
+}
+
+fn stored_names(path: &Path) -> Vec<String> {
+    let mut names: Vec<String> = read_env_enc_from(path).unwrap().into_keys().collect();
+    names.sort();
+    names
+}
+
+fn expected_names(prefix: &str) -> Vec<String> {
+    let mut names: Vec<String> = (0..THREADS)
+        .map(|index| format!("{}_{}", prefix, index))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn concurrent_upserts_keep_every_variable() {
+    let cipher_type = CipherType::AES256GCM;
+    let (key, _) = keys_generation(cipher_type).unwrap();
+    for _ in 0..ROUNDS {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        hammer(|index| {
+            let var_name = format!("UPSERT_{}", index);
+            upsert_enc_env_in(&path, &var_name, "value", cipher_type, &key, Overwrite::Yes)
+                .unwrap();
+        });
+        assert_eq!(stored_names(&path), expected_names("UPSERT"));
+    }
+}
+
+#[test]
+fn concurrent_batches_keep_every_variable() {
+    let cipher_type = CipherType::ChaCha20Poly1305;
+    let (key, _) = keys_generation(cipher_type).unwrap();
+    for _ in 0..ROUNDS {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        hammer(|index| {
+            let (first, second) = (format!("FIRST_{}", index), format!("SECOND_{}", index));
+            let vars = [(first.as_str(), "one"), (second.as_str(), "two")];
+            let outcomes = set_enc_env_many_in(&path, &vars, cipher_type, &key).unwrap();
+            assert_eq!(outcomes, [SetOutcome::Created, SetOutcome::Created]);
+        });
+        let mut expected = [expected_names("FIRST"), expected_names("SECOND")].concat();
+        expected.sort();
+        assert_eq!(stored_names(&path), expected);
+    }
+}
+
+#[test]
+fn concurrent_plain_sets_create_a_variable_once() {
+    for _ in 0..ROUNDS {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        let outcomes = Mutex::new(Vec::new());
+        hammer(|index| {
+            let value = format!("value_{}", index);
+            let outcome = set_plain_env_in(&path, "SHARED", &value, Overwrite::No).unwrap();
+            outcomes.lock().unwrap().push((outcome, value));
+        });
+        let outcomes = outcomes.into_inner().unwrap();
+        let created: Vec<_> = outcomes
+            .iter()
+            .filter(|(outcome, _)| *outcome == SetOutcome::Created)
+            .collect();
+        assert_eq!(created.len(), 1);
+        assert_eq!(read_env_enc_from(&path).unwrap()["SHARED"], created[0].1);
+    }
+}
@@ -0,0 +1,118 @@
+use envenc::{keys_generation, CipherType, EnvEnc, EnvEncBuilder, EnvEncError};
+use std::path::Path;
+
+const FOOTER: &str = "# envenc mac:";
+
+/// Writes three variables through a session that adds a MAC footer.
+fn protected_file(path: &Path) -> EnvEncBuilder {
+    let cipher_type = CipherType::AES256GCM;
+    let (key, _) = keys_generation(cipher_type).unwrap();
+    let builder = EnvEnc::builder()
+        .cipher(cipher_type)
+        .key(key)
+        .env_file(path)
+        .file_mac(true);
+    let mut session = builder.clone().build().unwrap();
+    for (var_name, value) in [
+        ("API_KEY", "sk_live_123"),
+        ("REGION", "eu"),
+        ("TOKEN", "t0k"),
+    ] {
+        session.set(var_name, value).unwrap();
+    }
+    builder
+}
+
+/// Rewrites the lines of the env file with `tamper`, keeping the trailing newline.
+fn tamper(path: &Path, tamper: impl FnOnce(&mut Vec<String>)) {
+    let contents = std::fs::read_to_string(path).unwrap();
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    tamper(&mut lines);
+    std::fs::write(path, lines.join("\n") + "\n").unwrap();
+}
+
+fn assert_rejected(builder: EnvEncBuilder) {
+    let result = builder.build();
+    assert!(
+        matches!(result, Err(EnvEncError::IntegrityError { .. })),
+        "expected an IntegrityError, got {:?}",
+        result.map(|_| ())
+    );
+}
+
+fn entry_index(lines: &[String], var_name: &str) -> usize {
+    lines
+        .iter()
+        .position(|line| line.starts_with(&format!("{}=", var_name)))
+        .unwrap()
+}
+
+#[test]
+fn untouched_file_verifies() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".env");
+    let builder = protected_file(&path);
+    let session = builder.build().unwrap();
+    assert_eq!(session.get("REGION").unwrap().as_deref(), Some("eu"));
+}
+
+#[test]
+fn removed_entry_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".env");
+    let builder = protected_file(&path);
+    tamper(&path, |lines| {
+        lines.remove(entry_index(lines, "REGION"));
+    });
+    assert_rejected(builder);
+}
+
+#[test]
+fn changed_value_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".env");
+    let builder = protected_file(&path);
+    tamper(&path, |lines| {
+        let token = entry_index(lines, "TOKEN");
+        let region = entry_index(lines, "REGION");
+        let value = lines[region].split_once('=').unwrap().1.to_string();
+        lines[token] = format!("TOKEN={}", value);
+    });
+    assert_rejected(builder);
+}
+
+#[test]
+fn reordered_entries_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".env");
+    let builder = protected_file(&path);
+    tamper(&path, |lines| {
+        let first = entry_index(lines, "API_KEY");
+        let last = entry_index(lines, "TOKEN");
+        lines.swap(first, last);
+    });
+    assert_rejected(builder);
+}
+
+#[test]
+fn removed_footer_is_rejected_when_required() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".env");
+    let builder = protected_file(&path);
+    tamper(&path, |lines| {
+        lines.retain(|line| !line.starts_with(FOOTER));
+        lines.remove(entry_index(lines, "REGION"));
+    });
+    assert_rejected(builder.clone());
+
+    // A session that does not require the footer reads the file and can restore it.
+    builder
+        .clone()
+        .file_mac(false)
+        .build()
+        .unwrap()
+        .add_file_mac()
+        .unwrap();
+    let session = builder.build().unwrap();
+    assert_eq!(session.get("REGION").unwrap(), None);
+}
@@ -0,0 +1,4 @@
+export API_KEY='sk_live_123'
+export DATABASE_URL='postgres://app:p@ss=w'"'"'rd@db:5432/app'
+export GREETING='it'"'"'s a "quoted" \ backslash'
+export UNICODE='Grüße, 世界 🌍'
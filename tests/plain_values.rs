@@ -0,0 +1,83 @@
+use envenc::{
+    decrypt_env, decrypt_env_to_map, encrypt, keys_generation, set_enc_env_in, set_plain_env_in,
+    CipherType, EnvEnc, Key, LazyEnv, Nonce, Overwrite,
+};
+use std::collections::HashMap;
+
+const GIT_SHA: &str = "3f786850e387550fdab836ed7e6dc881de23001b";
+
+#[test]
+fn hex_settings_are_passed_through_as_plain_text() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".env");
+    let cipher_type = CipherType::AES256GCM;
+    let (key, _) = keys_generation(cipher_type).unwrap();
+    set_enc_env_in(
+        &path,
+        "PLAIN_TEST_API_KEY",
+        "sk_live_123",
+        cipher_type,
+        &key,
+    )
+    .unwrap();
+    set_plain_env_in(&path, "PLAIN_TEST_GIT_SHA", GIT_SHA, Overwrite::No).unwrap();
+
+    let env_vars = envenc::read_env_enc_from(&path).unwrap();
+    let report = decrypt_env(env_vars.clone(), cipher_type, &key, &[]).unwrap();
+    assert!(report.failed.is_empty());
+    assert_eq!(report.plain, ["PLAIN_TEST_GIT_SHA"]);
+    assert_eq!(std::env::var("PLAIN_TEST_GIT_SHA").unwrap(), GIT_SHA);
+
+    let map = decrypt_env_to_map(env_vars.clone(), cipher_type, &key).unwrap();
+    assert_eq!(map["PLAIN_TEST_GIT_SHA"], GIT_SHA);
+    assert_eq!(map["PLAIN_TEST_API_KEY"], "sk_live_123");
+
+    let session = EnvEnc::builder()
+        .cipher(cipher_type)
+        .key(key.as_slice())
+        .env_file(&path)
+        .build()
+        .unwrap();
+    assert_eq!(
+        session.decrypt_to_map().unwrap()["PLAIN_TEST_GIT_SHA"],
+        GIT_SHA
+    );
+
+    let lazy = LazyEnv::new(env_vars, cipher_type, &key).unwrap();
+    let value = lazy.get("PLAIN_TEST_GIT_SHA").unwrap().unwrap();
+    assert_eq!(value.expose_secret(), GIT_SHA);
+}
+
+#[test]
+fn legacy_hex_values_are_decrypted_only_when_enabled() {
+    let cipher_type = CipherType::ChaCha20Poly1305;
+    let (key, nonce) = keys_generation(cipher_type).unwrap();
+    let typed_key = Key::new(cipher_type, key.clone()).unwrap();
+    let typed_nonce = Nonce::new(cipher_type, nonce.clone()).unwrap();
+    let ciphertext = encrypt(cipher_type, &typed_key, &typed_nonce, b"legacy").unwrap();
+    let stored = format!("{}{}", hex::encode(&nonce), hex::encode(ciphertext));
+    let env_vars = HashMap::from([("LEGACY_TEST_TOKEN".to_string(), stored.clone())]);
+
+    let lazy = LazyEnv::new(env_vars.clone(), cipher_type, &key).unwrap();
+    let value = lazy.get("LEGACY_TEST_TOKEN").unwrap().unwrap();
+    assert_eq!(value.expose_secret(), stored);
+
+    let lazy = LazyEnv::new(env_vars, cipher_type, &key)
+        .unwrap()
+        .legacy_hex(true);
+    let value = lazy.get("LEGACY_TEST_TOKEN").unwrap().unwrap();
+    assert_eq!(value.expose_secret(), "legacy");
+
+    // With legacy values enabled, hex that does not decrypt is reported rather than exported.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".env");
+    std::fs::write(&path, format!("LEGACY_TEST_SHA={}\n", GIT_SHA)).unwrap();
+    let session = EnvEnc::builder()
+        .cipher(cipher_type)
+        .key(key.as_slice())
+        .env_file(&path)
+        .legacy_hex(true)
+        .build()
+        .unwrap();
+    assert!(session.decrypt_to_map().is_err());
+}
@@ -0,0 +1,70 @@
+use envenc::{
+    keys_generation, set_enc_env_in, set_enc_env_many_in, CipherType, EnvEnc, EnvEncError,
+};
+use std::{fs::File, path::Path, time::Duration};
+
+fn stored_file(path: &Path) -> (CipherType, Vec<u8>) {
+    let cipher_type = CipherType::AES256GCM;
+    let (key, _) = keys_generation(cipher_type).unwrap();
+    set_enc_env_in(path, "API_KEY", "sk_live_123", cipher_type, &key).unwrap();
+    (cipher_type, key)
+}
+
+#[test]
+fn held_lock_times_out_and_leaves_the_file_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".env");
+    let (cipher_type, key) = stored_file(&path);
+    let before = std::fs::read(&path).unwrap();
+    let mut session = EnvEnc::builder()
+        .cipher(cipher_type)
+        .key(key)
+        .env_file(&path)
+        .lock_timeout(Duration::from_millis(50))
+        .build()
+        .unwrap();
+
+    // Another process holding the lock on the sibling lock file.
+    let lock = File::create(dir.path().join("..env.lock")).unwrap();
+    lock.lock().unwrap();
+    let result = session.set("REGION", "eu");
+    assert!(matches!(result, Err(EnvEncError::LockTimeout { .. })));
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+
+    drop(lock);
+    session.set("REGION", "eu").unwrap();
+    assert_eq!(session.get("REGION").unwrap().as_deref(), Some("eu"));
+}
+
+#[test]
+fn failed_write_leaves_the_file_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".env");
+    let (cipher_type, key) = stored_file(&path);
+    let before = std::fs::read(&path).unwrap();
+
+    // Make creating the temporary file fail by putting a directory in its place.
+    let temporary = dir.path().join("..env.tmp");
+    std::fs::create_dir(&temporary).unwrap();
+    let vars = [("REGION", "eu"), ("TOKEN", "t0k")];
+    let result = set_enc_env_many_in(&path, &vars, cipher_type, &key);
+    assert!(matches!(result, Err(EnvEncError::Io(_))));
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+
+    let mut session = EnvEnc::builder()
+        .cipher(cipher_type)
+        .key(key)
+        .env_file(&path)
+        .build()
+        .unwrap();
+    assert!(matches!(
+        session.set("REGION", "eu"),
+        Err(EnvEncError::Io(_))
+    ));
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+
+    // The session did not record the failed write, so it still writes once the file can be.
+    std::fs::remove_dir(&temporary).unwrap();
+    session.set("REGION", "eu").unwrap();
+    assert_eq!(session.get("REGION").unwrap().as_deref(), Some("eu"));
+}